@@ -94,10 +94,10 @@ fn manifest_declared_version() -> Option<String> {
         }
 
         let value = parts.next()?.split('#').next()?.trim();
-        if let Some(stripped) = value.strip_prefix('"') {
-            if let Some(stripped) = stripped.strip_suffix('"') {
-                return Some(stripped.to_string());
-            }
+        if let Some(stripped) = value.strip_prefix('"')
+            && let Some(stripped) = stripped.strip_suffix('"')
+        {
+            return Some(stripped.to_string());
         }
 
         return None;
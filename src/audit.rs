@@ -0,0 +1,457 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow};
+use console::style;
+use reqwest::Client;
+
+use crate::download::SUBMODULE_MARKER;
+use crate::github::types::GitTreeEntryType;
+use crate::github::{fetch_git_tree, fetch_repository_info, parse_github_url};
+use crate::http::CachePolicy;
+use crate::lfs::parse_lfs_pointer;
+use crate::progress::format_bytes;
+use crate::rate_limit::RateLimitTracker;
+use crate::types::RequestKind;
+
+/// Something in a downloaded tree that a plain download did not materialize
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    LfsPointer {
+        path: String,
+        size: u64,
+    },
+    Submodule {
+        path: String,
+        url: Option<String>,
+        commit: Option<String>,
+    },
+    /// A symlink present in the repository but missing locally
+    SkippedSymlink {
+        path: String,
+    },
+    DanglingSymlink {
+        path: String,
+        target: String,
+    },
+}
+
+/// Scan `dir` and print which additional fetches it needs to be complete.
+///
+/// Without `source`, only local evidence is used (LFS pointers, `.gitmodules`, broken
+/// links). With the URL the tree was downloaded from, the repository listing is
+/// compared too, which also finds skipped symlinks and submodules.
+pub async fn run_audit(
+    client: &Client,
+    dir: &Path,
+    source: Option<&str>,
+    token: Option<&str>,
+) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+
+    let mut findings = scan_local(dir)?;
+    if let Some(url) = source {
+        let remote = scan_remote(client, dir, url, token).await?;
+        merge_findings(&mut findings, remote);
+    }
+
+    print_report(dir, &findings, source.is_some());
+    Ok(())
+}
+
+fn scan_local(dir: &Path) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut placeholders = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+
+    while let Some(current) = stack.pop() {
+        for entry in fs::read_dir(&current)
+            .with_context(|| format!("failed to read directory {}", current.display()))?
+        {
+            let entry = entry.with_context(|| {
+                format!("failed to read directory entry in {}", current.display())
+            })?;
+            let path = entry.path();
+            let relative = relative_display(dir, &path);
+            if entry.file_name() == ".git" {
+                continue;
+            }
+
+            let metadata = fs::symlink_metadata(&path)
+                .with_context(|| format!("failed to inspect {}", path.display()))?;
+
+            if metadata.file_type().is_symlink() {
+                if fs::metadata(&path).is_err() {
+                    let target = fs::read_link(&path)
+                        .map(|target| target.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    findings.push(Finding::DanglingSymlink {
+                        path: relative,
+                        target,
+                    });
+                }
+                continue;
+            }
+
+            if metadata.is_dir() {
+                stack.push(path);
+                continue;
+            }
+
+            if entry.file_name() == SUBMODULE_MARKER {
+                let contents = fs::read_to_string(&path).unwrap_or_default();
+                placeholders.push(Finding::Submodule {
+                    path: relative_display(dir, path.parent().unwrap_or(dir)),
+                    url: None,
+                    commit: placeholder_commit(&contents),
+                });
+                continue;
+            }
+
+            if entry.file_name() == ".gitmodules" {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let base = path.parent().unwrap_or(dir);
+                for (sub_path, url) in parse_gitmodules(&contents) {
+                    if !is_populated(&base.join(&sub_path)) {
+                        findings.push(Finding::Submodule {
+                            path: relative_display(dir, &base.join(&sub_path)),
+                            url,
+                            commit: None,
+                        });
+                    }
+                }
+                continue;
+            }
+
+            if metadata.len() <= 1024
+                && let Ok(content) = fs::read(&path)
+                && let Some(pointer) = parse_lfs_pointer(&content)
+            {
+                findings.push(Finding::LfsPointer {
+                    path: relative,
+                    size: pointer.size,
+                });
+            }
+        }
+    }
+
+    // Submodules listed in `.gitmodules` take the commit from their placeholder
+    merge_findings(&mut findings, placeholders);
+    Ok(findings)
+}
+
+/// The commit recorded in a submodule placeholder written by the REST strategy
+fn placeholder_commit(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("commit: "))
+        .map(str::trim)
+        .filter(|sha| *sha != "unknown")
+        .map(str::to_string)
+}
+
+async fn scan_remote(
+    client: &Client,
+    dir: &Path,
+    url: &str,
+    token: Option<&str>,
+) -> Result<Vec<Finding>> {
+    let mut request = parse_github_url(url)?;
+    if request.kind == RequestKind::Blob {
+        return Err(anyhow!("--source must point at a directory, not a file"));
+    }
+    if request.branch.is_empty() {
+        request.branch = fetch_repository_info(client, &request.owner, &request.repo, token)
+            .await
+            .context("failed to fetch repository information")?
+            .default_branch;
+    }
+
+    let rate_limit = Arc::new(RateLimitTracker::default());
+    let tree = fetch_git_tree(client, &request, token, rate_limit, CachePolicy::default()).await?;
+
+    let mut findings = Vec::new();
+    for entry in tree.tree {
+        let local = dir.join(&entry.path);
+        match entry.entry_type {
+            GitTreeEntryType::Commit if !is_populated(&local) => {
+                findings.push(Finding::Submodule {
+                    path: entry.path,
+                    url: None,
+                    commit: entry.sha,
+                });
+            }
+            GitTreeEntryType::Blob
+                if entry.mode.as_deref() == Some("120000")
+                    && fs::symlink_metadata(&local).is_err() =>
+            {
+                findings.push(Finding::SkippedSymlink { path: entry.path });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(findings)
+}
+
+/// Add remote findings, combining submodule details found both locally and remotely
+fn merge_findings(findings: &mut Vec<Finding>, remote: Vec<Finding>) {
+    for finding in remote {
+        if let Finding::Submodule {
+            path, commit: sha, ..
+        } = &finding
+            && let Some(Finding::Submodule { commit, .. }) = findings.iter_mut().find(
+                |existing| matches!(existing, Finding::Submodule { path: p, .. } if p == path),
+            )
+        {
+            if sha.is_some() {
+                commit.clone_from(sha);
+            }
+            continue;
+        }
+        findings.push(finding);
+    }
+}
+
+/// Extract `(path, url)` pairs from a `.gitmodules` file
+fn parse_gitmodules(contents: &str) -> Vec<(String, Option<String>)> {
+    let mut modules: Vec<(Option<String>, Option<String>)> = Vec::new();
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with("[submodule") {
+            modules.push((None, None));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let Some(current) = modules.last_mut() else {
+            continue;
+        };
+        match key.trim() {
+            "path" => current.0 = Some(value.trim().to_string()),
+            "url" => current.1 = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    modules
+        .into_iter()
+        .filter_map(|(path, url)| path.map(|path| (path, url)))
+        .collect()
+}
+
+/// A submodule counts as fetched when its directory exists and has content
+fn is_populated(path: &Path) -> bool {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .any(|entry| entry.file_name() != SUBMODULE_MARKER)
+        })
+        .unwrap_or(false)
+}
+
+fn relative_display(base: &Path, path: &Path) -> String {
+    path.strip_prefix(base)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Flags that would fetch the missing content on a re-run
+fn suggested_flags(findings: &[Finding]) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    if findings
+        .iter()
+        .any(|f| matches!(f, Finding::LfsPointer { .. }))
+    {
+        flags.push("--lfs");
+    }
+    if findings
+        .iter()
+        .any(|f| matches!(f, Finding::SkippedSymlink { .. }))
+    {
+        flags.push("--symlinks follow");
+    }
+    flags
+}
+
+fn print_report(dir: &Path, findings: &[Finding], compared_with_source: bool) {
+    if findings.is_empty() {
+        eprintln!(
+            "{} No external references found in {}",
+            style("✓").green().bold(),
+            dir.display()
+        );
+        if !compared_with_source {
+            eprintln!("  Pass --source <URL> to also detect skipped symlinks and submodules.");
+        }
+        return;
+    }
+
+    let mut sections: BTreeMap<u8, (&str, Vec<String>)> = BTreeMap::new();
+    for finding in findings {
+        let (order, title, line) = match finding {
+            Finding::LfsPointer { path, size } => (
+                0,
+                "Git LFS pointers (rerun with --lfs)",
+                format!("{} ({})", path, format_bytes(*size)),
+            ),
+            Finding::SkippedSymlink { path } => (
+                1,
+                "Skipped symlinks (rerun with --symlinks follow or --symlinks create)",
+                path.clone(),
+            ),
+            Finding::Submodule { path, url, commit } => (
+                2,
+                "Submodules (not fetched by ghdl; download them separately)",
+                format!(
+                    "{}{}{}",
+                    path,
+                    url.as_deref()
+                        .map(|url| format!(" → {}", url))
+                        .unwrap_or_default(),
+                    commit
+                        .as_deref()
+                        .map(|sha| format!(" @ {}", &sha[..sha.len().min(12)]))
+                        .unwrap_or_default()
+                ),
+            ),
+            Finding::DanglingSymlink { path, target } => (
+                3,
+                "Broken symlinks (target missing)",
+                format!("{} -> {}", path, target),
+            ),
+        };
+        sections
+            .entry(order)
+            .or_insert_with(|| (title, Vec::new()))
+            .1
+            .push(line);
+    }
+
+    eprintln!(
+        "{} {} incomplete item(s) in {}",
+        style("!").yellow().bold(),
+        findings.len(),
+        dir.display()
+    );
+    for (title, mut lines) in sections.into_values() {
+        lines.sort();
+        eprintln!("\n{} ({})", style(title).bold(), lines.len());
+        for line in lines {
+            eprintln!("  {}", line);
+        }
+    }
+
+    let flags = suggested_flags(findings);
+    if !flags.is_empty() {
+        eprintln!("\nSuggested flags: {}", style(flags.join(" ")).cyan());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gitmodules() {
+        let contents = r#"
+[submodule "vendor/lib"]
+	path = vendor/lib
+	url = https://github.com/owner/lib.git
+[submodule "docs"]
+    path=docs/theme
+[submodule "broken"]
+	url = https://example.com/no-path.git
+"#;
+        assert_eq!(
+            parse_gitmodules(contents),
+            vec![
+                (
+                    "vendor/lib".to_string(),
+                    Some("https://github.com/owner/lib.git".to_string())
+                ),
+                ("docs/theme".to_string(), None),
+            ]
+        );
+        assert!(parse_gitmodules("").is_empty());
+    }
+
+    #[test]
+    fn test_scan_local_reads_submodule_placeholders() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(".gitmodules"),
+            "[submodule \"lib\"]\n\tpath = vendor/lib\n\turl = https://github.com/o/lib.git\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("vendor/lib")).unwrap();
+        fs::write(
+            dir.path().join("vendor/lib").join(SUBMODULE_MARKER),
+            "vendor/lib is a git submodule; ghdl does not download submodules.\ncommit: abc123\n",
+        )
+        .unwrap();
+
+        // The placeholder does not count as a populated checkout, and adds the commit
+        assert_eq!(
+            scan_local(dir.path()).unwrap(),
+            vec![Finding::Submodule {
+                path: "vendor/lib".to_string(),
+                url: Some("https://github.com/o/lib.git".to_string()),
+                commit: Some("abc123".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_merge_findings_and_suggested_flags() {
+        let mut findings = vec![
+            Finding::Submodule {
+                path: "vendor/lib".to_string(),
+                url: Some("https://github.com/owner/lib.git".to_string()),
+                commit: None,
+            },
+            Finding::LfsPointer {
+                path: "assets/logo.psd".to_string(),
+                size: 10,
+            },
+        ];
+        merge_findings(
+            &mut findings,
+            vec![
+                Finding::Submodule {
+                    path: "vendor/lib".to_string(),
+                    url: None,
+                    commit: Some("abc123".to_string()),
+                },
+                Finding::SkippedSymlink {
+                    path: "current".to_string(),
+                },
+            ],
+        );
+
+        // The remote commit fills in the locally known submodule instead of duplicating it
+        assert_eq!(findings.len(), 3);
+        assert_eq!(
+            findings[0],
+            Finding::Submodule {
+                path: "vendor/lib".to_string(),
+                url: Some("https://github.com/owner/lib.git".to_string()),
+                commit: Some("abc123".to_string()),
+            }
+        );
+        assert_eq!(
+            suggested_flags(&findings),
+            vec!["--lfs", "--symlinks follow"]
+        );
+        assert!(suggested_flags(&[]).is_empty());
+    }
+}
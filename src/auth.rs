@@ -0,0 +1,446 @@
+use std::io::{self, IsTerminal, Read};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow};
+use console::{Term, style};
+use keyring::Entry;
+use log::debug;
+use reqwest::Client;
+use reqwest::header::ACCEPT;
+use serde::Deserialize;
+
+use crate::cli::AuthAction;
+use crate::config::ProviderConfig;
+use crate::github::{
+    Endpoints, RateLimitResource, TokenDetails, describe_reset, fetch_authenticated_user,
+    fetch_core_rate_limit,
+};
+
+/// Keyring service the tokens are filed under, one entry per host
+const KEYRING_SERVICE: &str = "ghdl";
+
+/// Scope requested by `auth login --web`: read access to private repositories
+const DEVICE_FLOW_SCOPE: &str = "repo";
+
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Client ID of ghdl's public OAuth app on github.com. Device flow uses no client
+/// secret, so the ID is not confidential; release builds set `GHDL_OAUTH_CLIENT_ID`.
+const DEFAULT_OAUTH_CLIENT_ID: Option<&str> = option_env!("GHDL_OAUTH_CLIENT_ID");
+
+/// OAuth app `auth login --web` authorizes on `host`: the configured one, else ghdl's
+/// own app, which is registered on github.com only
+fn oauth_client_id<'a>(provider: &'a ProviderConfig, host: &str) -> Option<&'a str> {
+    provider
+        .oauth_client_id
+        .as_deref()
+        .or(DEFAULT_OAUTH_CLIENT_ID.filter(|_| host == "github.com"))
+}
+
+fn keyring_entry(host: &str) -> Result<Entry> {
+    Entry::new(KEYRING_SERVICE, host)
+        .map_err(|err| anyhow!("the system keyring is unavailable: {}", err))
+}
+
+/// Token saved by `ghdl auth login` for `host`. A missing entry or an unavailable
+/// keyring (e.g. no Secret Service on a headless machine) yields `None`.
+pub fn stored_token(host: &str) -> Option<String> {
+    let entry = match keyring_entry(host) {
+        Ok(entry) => entry,
+        Err(err) => {
+            debug!("Not reading a stored token: {:#}", err);
+            return None;
+        }
+    };
+    match entry.get_password() {
+        Ok(token) => Some(token).filter(|token| !token.trim().is_empty()),
+        Err(keyring::Error::NoEntry) => None,
+        Err(err) => {
+            debug!("Could not read the stored token for {}: {}", host, err);
+            None
+        }
+    }
+}
+
+/// Run `ghdl auth login/status/logout/check` against the configured provider's host.
+/// `resolve_token` finds the token a download would use, for `check`.
+pub fn run_auth_command(
+    action: AuthAction,
+    provider: &ProviderConfig,
+    client: &Client,
+    endpoints: &Endpoints,
+    resolve_token: impl FnOnce() -> Result<Option<String>>,
+) -> Result<()> {
+    let host = provider.host();
+    match action {
+        AuthAction::Login { with_token, web } => {
+            let token = if web {
+                let client_id = oauth_client_id(provider, &host).ok_or_else(|| {
+                    anyhow!("`auth login --web` on {} needs the oauth_client_id of an OAuth app with device flow enabled in the config file", host)
+                })?;
+                block_on(endpoints, device_flow_token(client, &host, client_id))?
+            } else {
+                read_token(with_token)?
+            };
+            let login = block_on(endpoints, fetch_authenticated_user(client, &token))?.login;
+            keyring_entry(&host)?
+                .set_password(&token)
+                .with_context(|| format!("failed to store the token for {}", host))?;
+            eprintln!(
+                "{} Logged in to {} as {}",
+                style("✓").green().bold(),
+                host,
+                login
+            );
+        }
+        AuthAction::Status => {
+            let token = stored_token(&host)
+                .ok_or_else(|| anyhow!("not logged in to {}; run `ghdl auth login`", host))?;
+            let login = block_on(endpoints, fetch_authenticated_user(client, &token))
+                .with_context(|| format!("the token stored for {} does not work", host))?
+                .login;
+            eprintln!(
+                "{} Logged in to {} as {} (token in the system keyring)",
+                style("✓").green().bold(),
+                host,
+                login
+            );
+        }
+        AuthAction::Check => {
+            let token = resolve_token()?.ok_or_else(|| {
+                anyhow!(
+                    "no token found for {}; pass --token, set GITHUB_TOKEN, or run `ghdl auth login`",
+                    host
+                )
+            })?;
+            let (details, rate_limit) = block_on(endpoints, async {
+                let details = fetch_authenticated_user(client, &token)
+                    .await
+                    .with_context(|| format!("the token for {} does not work", host))?;
+                let rate_limit = fetch_core_rate_limit(client, Some(&token)).await?;
+                Ok::<_, anyhow::Error>((details, rate_limit))
+            })?;
+
+            println!("Token for {}:", host);
+            println!("  Account:    {}", details.login);
+            println!("  Type:       {}", token_kind(&token));
+            if let Some(scopes) = &details.scopes {
+                let scopes = if scopes.is_empty() {
+                    "none".to_string()
+                } else {
+                    scopes.join(", ")
+                };
+                println!("  Scopes:     {}", scopes);
+            }
+            println!(
+                "  Expires:    {}",
+                details.expires.as_deref().unwrap_or("never")
+            );
+            if let Some(sso) = &details.sso {
+                println!("  SSO:        {}", sso);
+            }
+            println!(
+                "  Rate limit: {}/{} remaining, resets {}",
+                rate_limit.remaining,
+                rate_limit.limit,
+                describe_reset(rate_limit.reset)
+            );
+            for note in token_notes(&token, &details, &rate_limit) {
+                eprintln!("{} {}", style("!").yellow().bold(), note);
+            }
+        }
+        AuthAction::Logout => match keyring_entry(&host)?.delete_credential() {
+            Ok(()) => eprintln!("{} Logged out of {}", style("✓").green().bold(), host),
+            Err(keyring::Error::NoEntry) => eprintln!("Not logged in to {}", host),
+            Err(err) => {
+                return Err(anyhow!("failed to remove the token for {}: {}", host, err));
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Kind of token, told apart by GitHub's token prefixes
+fn token_kind(token: &str) -> &'static str {
+    let token = token.trim();
+    if token.starts_with("github_pat_") {
+        "fine-grained personal access token"
+    } else if token.starts_with("ghp_") {
+        "classic personal access token"
+    } else if token.starts_with("gho_") {
+        "OAuth app token"
+    } else if token.starts_with("ghu_") {
+        "GitHub App user token"
+    } else if token.starts_with("ghs_") {
+        "GitHub App installation token"
+    } else {
+        "unknown"
+    }
+}
+
+/// Problems worth knowing about before a long download starts
+fn token_notes(token: &str, details: &TokenDetails, rate_limit: &RateLimitResource) -> Vec<String> {
+    let mut notes = Vec::new();
+    if let Some(scopes) = &details.scopes
+        && !scopes.iter().any(|scope| scope == "repo")
+    {
+        notes.push(
+            "without the `repo` scope, private repositories cannot be downloaded".to_string(),
+        );
+    }
+    if token.trim().starts_with("github_pat_") {
+        notes.push(
+            "fine-grained permissions are not reported by the API; the token needs read access to Contents on each repository"
+                .to_string(),
+        );
+    }
+    if details
+        .sso
+        .as_deref()
+        .is_some_and(|sso| sso.starts_with("required"))
+    {
+        notes.push(
+            "the token is not authorized for an organization's SAML SSO; open the URL above to authorize it"
+                .to_string(),
+        );
+    }
+    if rate_limit.remaining == 0 {
+        notes.push(format!(
+            "the API quota is used up until it resets {}",
+            describe_reset(rate_limit.reset)
+        ));
+    }
+    notes
+}
+
+/// Token to log in with: all of stdin with `--with-token` or when stdin is not a
+/// terminal, otherwise a hidden prompt
+fn read_token(with_token: bool) -> Result<String> {
+    let token = if with_token || !io::stdin().is_terminal() {
+        let mut input = String::new();
+        io::stdin()
+            .read_to_string(&mut input)
+            .context("failed to read the token from stdin")?;
+        input
+    } else {
+        eprint!("Paste a GitHub token: ");
+        Term::stderr()
+            .read_secure_line()
+            .context("failed to read the token")?
+    };
+
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        return Err(anyhow!("no token given"));
+    }
+    Ok(token)
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+/// Answer to one poll of the access token endpoint
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TokenPoll {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+    interval: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum PollOutcome {
+    Token(String),
+    Pending,
+    /// Poll less often, every this many seconds
+    SlowDown(u64),
+    Failed(String),
+}
+
+impl TokenPoll {
+    fn outcome(self, interval: u64) -> PollOutcome {
+        if let Some(token) = self.access_token.filter(|token| !token.is_empty()) {
+            return PollOutcome::Token(token);
+        }
+        match self.error.as_deref() {
+            Some("authorization_pending") => PollOutcome::Pending,
+            Some("slow_down") => PollOutcome::SlowDown(self.interval.unwrap_or(interval + 5)),
+            Some("expired_token") => {
+                PollOutcome::Failed("the code expired before it was entered".to_string())
+            }
+            Some("access_denied") => {
+                PollOutcome::Failed("the authorization was denied".to_string())
+            }
+            error => PollOutcome::Failed(
+                self.error_description
+                    .or(error.map(str::to_string))
+                    .unwrap_or_else(|| "no token in the answer".to_string()),
+            ),
+        }
+    }
+}
+
+/// Obtain a token through GitHub's OAuth device flow: show a code for the user to
+/// enter in the browser, then poll until they have authorized the app
+async fn device_flow_token(client: &Client, host: &str, client_id: &str) -> Result<String> {
+    let login_base = format!("https://{}/login", host);
+    let code: DeviceCode = client
+        .post(format!("{}/device/code", login_base))
+        .header(ACCEPT, "application/json")
+        .form(&[("client_id", client_id), ("scope", DEVICE_FLOW_SCOPE)])
+        .send()
+        .await
+        .context("failed to start the device authorization")?
+        .error_for_status()
+        .context("GitHub refused to start the device authorization; check oauth_client_id")?
+        .json()
+        .await
+        .context("failed to parse the device authorization")?;
+
+    eprintln!(
+        "Open {} and enter the code {}",
+        style(&code.verification_uri).cyan(),
+        style(&code.user_code).bold()
+    );
+    eprintln!("Waiting for the authorization...");
+
+    let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+    let mut interval = code.interval.max(1);
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        if Instant::now() >= deadline {
+            return Err(anyhow!(
+                "the code expired before it was entered; run `ghdl auth login --web` again"
+            ));
+        }
+
+        let poll: TokenPoll = client
+            .post(format!("{}/oauth/access_token", login_base))
+            .header(ACCEPT, "application/json")
+            .form(&[
+                ("client_id", client_id),
+                ("device_code", code.device_code.as_str()),
+                ("grant_type", DEVICE_GRANT_TYPE),
+            ])
+            .send()
+            .await
+            .context("failed to poll for the access token")?
+            .json()
+            .await
+            .context("failed to parse the access token answer")?;
+        match poll.outcome(interval) {
+            PollOutcome::Token(token) => return Ok(token),
+            PollOutcome::Pending => {}
+            PollOutcome::SlowDown(seconds) => interval = seconds,
+            PollOutcome::Failed(reason) => {
+                return Err(anyhow!("device authorization failed: {}", reason));
+            }
+        }
+    }
+}
+
+fn block_on<T, E: Into<anyhow::Error>>(
+    endpoints: &Endpoints,
+    future: impl Future<Output = std::result::Result<T, E>>,
+) -> Result<T> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to build async runtime")?
+        .block_on(endpoints.scope(future))
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oauth_client_id_prefers_config() {
+        let mut provider = ProviderConfig::default();
+        assert_eq!(
+            oauth_client_id(&provider, "github.com"),
+            DEFAULT_OAUTH_CLIENT_ID
+        );
+        assert_eq!(oauth_client_id(&provider, "ghe.example.com"), None);
+        provider.oauth_client_id = Some("Iv1.configured".to_string());
+        assert_eq!(
+            oauth_client_id(&provider, "ghe.example.com"),
+            Some("Iv1.configured")
+        );
+    }
+
+    #[test]
+    fn test_token_notes() {
+        let quota = |remaining| RateLimitResource {
+            limit: 5000,
+            remaining,
+            used: 5000 - remaining,
+            reset: 0,
+        };
+        let details = |scopes: Option<&[&str]>, sso: Option<&str>| TokenDetails {
+            login: "octocat".to_string(),
+            scopes: scopes.map(|scopes| scopes.iter().map(|s| s.to_string()).collect()),
+            expires: None,
+            sso: sso.map(str::to_string),
+        };
+
+        assert_eq!(token_kind("ghp_abc"), "classic personal access token");
+        assert_eq!(
+            token_kind("github_pat_abc"),
+            "fine-grained personal access token"
+        );
+        assert_eq!(token_kind("secret"), "unknown");
+
+        let full = details(Some(&["repo", "read:org"]), None);
+        assert!(token_notes("ghp_abc", &full, &quota(4000)).is_empty());
+
+        let notes = token_notes("ghp_abc", &details(Some(&["gist"]), None), &quota(0));
+        assert_eq!(notes.len(), 2);
+        assert!(notes[0].contains("`repo` scope"));
+        assert!(notes[1].contains("used up"));
+
+        let sso = details(None, Some("required; url=https://github.com/orgs/acme/sso"));
+        let notes = token_notes("github_pat_abc", &sso, &quota(4000));
+        assert_eq!(notes.len(), 2);
+        assert!(notes[1].contains("SSO"));
+    }
+
+    #[test]
+    fn test_token_poll_outcome() {
+        let poll = |json: &str| serde_json::from_str::<TokenPoll>(json).unwrap();
+
+        assert_eq!(
+            poll(r#"{"access_token":"gho_abc","token_type":"bearer","scope":"repo"}"#).outcome(5),
+            PollOutcome::Token("gho_abc".to_string())
+        );
+        assert_eq!(
+            poll(r#"{"error":"authorization_pending"}"#).outcome(5),
+            PollOutcome::Pending
+        );
+        assert_eq!(
+            poll(r#"{"error":"slow_down","interval":10}"#).outcome(5),
+            PollOutcome::SlowDown(10)
+        );
+        assert_eq!(
+            poll(r#"{"error":"slow_down"}"#).outcome(5),
+            PollOutcome::SlowDown(10)
+        );
+        assert!(matches!(
+            poll(r#"{"error":"access_denied"}"#).outcome(5),
+            PollOutcome::Failed(_)
+        ));
+        assert_eq!(
+            poll(r#"{"error":"incorrect_client_credentials","error_description":"The client_id is not valid."}"#)
+                .outcome(5),
+            PollOutcome::Failed("The client_id is not valid.".to_string())
+        );
+    }
+}
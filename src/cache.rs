@@ -1,17 +1,22 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::SystemTime;
 
 use anyhow::{Context, Result, anyhow};
-use log::{debug, info};
+use console::style;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+use crate::cli::CacheAction;
+use crate::progress::format_bytes;
 use crate::utils::system_time_to_secs;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedResponse {
     pub url: String,
     pub body: Vec<u8>,
@@ -20,17 +25,50 @@ pub struct CachedResponse {
     pub timestamp: u64,
 }
 
+/// Version of the on-disk format of cached responses. Entries written by older
+/// versions are migrated when read; entries from newer versions are ignored.
+pub const CACHE_SCHEMA_VERSION: u32 = 2;
+
+/// Suffix of the sidecar file holding the SHA-256 of a cached archive
+const CHECKSUM_SUFFIX: &str = ".sha256";
+
+/// A cached response as stored on disk, with its format version and a checksum of
+/// the body. Version-1 entries were bare `CachedResponse`s and read as version 0.
 #[derive(Debug, Serialize, Deserialize)]
+struct StoredResponse {
+    #[serde(default)]
+    schema: u32,
+    #[serde(default)]
+    checksum: Option<String>,
+    #[serde(flatten)]
+    response: CachedResponse,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Bookkeeping for a file download in progress, kept in the downloads cache so an
+/// interrupted run only resumes a partial file it can vouch for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PartialDownload {
     pub url: String,
     pub path: PathBuf,
+    /// Bytes known to be on disk when the record was last written
     pub downloaded_bytes: u64,
     pub total_bytes: Option<u64>,
+    /// Blob SHA of the complete file, when known
+    #[serde(default)]
+    pub sha: Option<String>,
+    /// ETag of the response, sent as `If-Range` when resuming
+    #[serde(default)]
+    pub etag: Option<String>,
     pub timestamp: u64,
 }
 
-fn cache_base_dir() -> Result<PathBuf> {
-    let base = env::var_os("XDG_CACHE_HOME")
+/// `$XDG_CACHE_HOME/ghdl` or `~/.cache/ghdl`, whether or not it is usable
+fn preferred_cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
         .map(PathBuf::from)
         .filter(|p| !p.as_os_str().is_empty())
         .or_else(|| {
@@ -38,11 +76,102 @@ fn cache_base_dir() -> Result<PathBuf> {
                 .map(PathBuf::from)
                 .map(|home| home.join(".cache"))
         })
-        .ok_or_else(|| {
-            anyhow!("Unable to determine cache directory (set XDG_CACHE_HOME or HOME)")
-        })?;
+        .map(|base| base.join("ghdl"))
+}
 
-    Ok(base.join("ghdl"))
+/// Cache directory used when the preferred one cannot be written
+fn fallback_cache_dir() -> PathBuf {
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "user".to_string());
+    env::temp_dir().join(format!("ghdl-cache-{}", user))
+}
+
+fn is_writable_dir(dir: &Path) -> bool {
+    fs::create_dir_all(dir).is_ok() && tempfile::tempfile_in(dir).is_ok()
+}
+
+/// Whether `dir`, a predictable path in the shared temp dir, is safe to cache in.
+/// Cached clones keep the token in their remote URL and cached archives and
+/// responses are trusted when read back, so the directory must be a real directory
+/// (not a symlink) owned by us and closed to everyone else. A missing one is
+/// created with mode 0700; one another user planted is refused.
+#[cfg(unix)]
+fn is_private_dir(dir: &Path) -> bool {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    if let Err(err) = fs::DirBuilder::new().mode(0o700).create(dir)
+        && err.kind() != io::ErrorKind::AlreadyExists
+    {
+        return false;
+    }
+    let Ok(metadata) = fs::symlink_metadata(dir) else {
+        return false;
+    };
+    if !metadata.is_dir() || metadata.permissions().mode() & 0o077 != 0 {
+        return false;
+    }
+    // A file we create carries our uid, which std cannot report directly
+    match tempfile::tempfile_in(dir).and_then(|probe| probe.metadata()) {
+        Ok(probe) => probe.uid() == metadata.uid(),
+        Err(_) => false,
+    }
+}
+
+/// The temp dir is per-user on Windows, so writability is all that matters there
+#[cfg(not(unix))]
+fn is_private_dir(dir: &Path) -> bool {
+    is_writable_dir(dir)
+}
+
+/// Preferred directory and what it resolved to, so the probe runs once per location
+static RESOLVED_BASE: Mutex<Option<(Option<PathBuf>, Option<PathBuf>)>> = Mutex::new(None);
+
+/// Root of all caches. A read-only or missing preferred location (common in
+/// locked-down containers) falls back to a private directory under the system temp
+/// dir with a warning; when that is unusable too, this fails and ghdl runs without
+/// a cache.
+fn cache_base_dir() -> Result<PathBuf> {
+    let preferred = preferred_cache_dir();
+    let mut resolved = RESOLVED_BASE.lock().unwrap_or_else(|err| err.into_inner());
+    let base = match resolved.as_ref() {
+        Some((key, base)) if *key == preferred => base.clone(),
+        _ => {
+            let base = resolve_cache_dir(preferred.as_deref(), &fallback_cache_dir());
+            *resolved = Some((preferred, base.clone()));
+            base
+        }
+    };
+    base.ok_or_else(|| anyhow!("no writable cache directory is available"))
+}
+
+fn resolve_cache_dir(preferred: Option<&Path>, fallback: &Path) -> Option<PathBuf> {
+    if let Some(dir) = preferred
+        && is_writable_dir(dir)
+    {
+        return Some(dir.to_path_buf());
+    }
+
+    let reason = preferred
+        .map(|dir| format!("cache directory {} is not writable", dir.display()))
+        .unwrap_or_else(|| "no cache directory is configured (set XDG_CACHE_HOME or HOME)".into());
+    if is_private_dir(fallback) {
+        warn!("{}; caching in {} instead", reason, fallback.display());
+        Some(fallback.to_path_buf())
+    } else {
+        warn!(
+            "{}, and {} is not a private directory of ours; continuing without a cache",
+            reason,
+            fallback.display()
+        );
+        None
+    }
+}
+
+/// Whether any cache directory can be used; `false` means the run should behave as
+/// if `--no-cache` were given
+pub fn cache_available() -> bool {
+    cache_base_dir().is_ok()
 }
 
 pub fn responses_cache_dir() -> Result<PathBuf> {
@@ -56,7 +185,6 @@ pub fn responses_cache_dir() -> Result<PathBuf> {
     Ok(dir)
 }
 
-#[allow(dead_code)]
 pub fn downloads_cache_dir() -> Result<PathBuf> {
     let dir = cache_base_dir()?.join("downloads");
     fs::create_dir_all(&dir).with_context(|| {
@@ -75,6 +203,80 @@ pub fn repos_cache_dir() -> Result<PathBuf> {
     Ok(dir)
 }
 
+/// Directory of file contents keyed by git blob SHA, shared by all repositories
+pub fn blobs_cache_dir() -> Result<PathBuf> {
+    let dir = cache_base_dir()?.join("blobs");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create blobs cache directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Directory of the last rate-limit snapshot seen for each token
+pub fn rate_limits_cache_dir() -> Result<PathBuf> {
+    let dir = cache_base_dir()?.join("rate-limits");
+    fs::create_dir_all(&dir).with_context(|| {
+        format!(
+            "failed to create rate-limit cache directory {}",
+            dir.display()
+        )
+    })?;
+    Ok(dir)
+}
+
+fn blob_path(sha: &str) -> Result<PathBuf> {
+    if sha.len() < 40 || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("invalid blob SHA '{}'", sha));
+    }
+    Ok(blobs_cache_dir()?.join(sha.to_ascii_lowercase()))
+}
+
+/// Copy a cached blob to `target`, returning `false` when the blob is not cached
+pub fn restore_blob(sha: &str, target: &Path) -> Result<bool> {
+    let path = blob_path(sha)?;
+    match fs::copy(&path, target) {
+        Ok(_) => {
+            debug!("Restored blob {} to {}", sha, target.display());
+            Ok(true)
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(anyhow!(
+            "failed to copy cached blob {} to {}: {}",
+            sha,
+            target.display(),
+            err
+        )),
+    }
+}
+
+/// Store a downloaded file's contents under its blob SHA
+pub fn store_blob(sha: &str, source: &Path) -> Result<()> {
+    let path = blob_path(sha)?;
+    if path.exists() {
+        return Ok(());
+    }
+
+    // Copy to a unique temporary name first so concurrent runs never see a partial blob
+    let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::copy(source, &tmp_path)
+        .with_context(|| format!("failed to copy {} into the blob cache", source.display()))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("failed to persist cached blob {}", path.display()))?;
+    Ok(())
+}
+
+/// Drop a cached blob, e.g. after it failed verification
+pub fn remove_blob(sha: &str) -> Result<()> {
+    let path = blob_path(sha)?;
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(anyhow!(
+            "failed to remove cached blob {}: {}",
+            path.display(),
+            err
+        )),
+        _ => Ok(()),
+    }
+}
+
 fn cache_key(url: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(url.as_bytes());
@@ -82,6 +284,31 @@ fn cache_key(url: &str) -> String {
 }
 
 pub fn load_cached_response(url: &str, ttl_secs: u64) -> Result<Option<CachedResponse>> {
+    let Some(cached) = load_cached_entry(url)? else {
+        return Ok(None);
+    };
+
+    let now = system_time_to_secs(SystemTime::now());
+    if now - cached.timestamp > ttl_secs {
+        debug!(
+            "Cached response for {} expired (age: {}s, ttl: {}s)",
+            url,
+            now - cached.timestamp,
+            ttl_secs
+        );
+        return Ok(None);
+    }
+
+    debug!(
+        "Using cached response for {} (age: {}s)",
+        url,
+        now - cached.timestamp
+    );
+    Ok(Some(cached))
+}
+
+/// Load a cached response regardless of its age, e.g. to revalidate a stale entry
+pub fn load_cached_entry(url: &str) -> Result<Option<CachedResponse>> {
     let key = cache_key(url);
     let path = responses_cache_dir()?.join(format!("{}.json", key));
 
@@ -97,35 +324,57 @@ pub fn load_cached_response(url: &str, ttl_secs: u64) -> Result<Option<CachedRes
         }
     };
 
-    let cached: CachedResponse = match serde_json::from_reader(file) {
-        Ok(cached) => cached,
+    let stored: StoredResponse = match serde_json::from_reader(io::BufReader::new(file)) {
+        Ok(stored) => stored,
         Err(err) => {
-            debug!(
-                "Unable to parse cached response file {}; ignoring: {}",
+            warn!(
+                "Removing unreadable cached response {}: {}",
                 path.display(),
                 err
             );
+            purge_cache_file(&path);
             return Ok(None);
         }
     };
 
-    let now = system_time_to_secs(SystemTime::now());
-    if now - cached.timestamp > ttl_secs {
-        debug!(
-            "Cached response for {} expired (age: {}s, ttl: {}s)",
-            url,
-            now - cached.timestamp,
-            ttl_secs
-        );
-        return Ok(None);
+    match stored.schema {
+        0 => {
+            // Written before entries carried a version; store it again in the current format
+            debug!(
+                "Migrating cached response {} to the current format",
+                path.display()
+            );
+            if let Err(err) = save_cached_response(&stored.response) {
+                debug!("Unable to migrate {}: {}", path.display(), err);
+            }
+        }
+        CACHE_SCHEMA_VERSION => {
+            if stored.checksum.as_deref() != Some(sha256_hex(&stored.response.body).as_str()) {
+                warn!(
+                    "Removing corrupted cached response {} (checksum mismatch)",
+                    path.display()
+                );
+                purge_cache_file(&path);
+                return Ok(None);
+            }
+        }
+        newer => {
+            debug!(
+                "Ignoring cached response {} written in newer format {}",
+                path.display(),
+                newer
+            );
+            return Ok(None);
+        }
     }
 
-    debug!(
-        "Using cached response for {} (age: {}s)",
-        url,
-        now - cached.timestamp
-    );
-    Ok(Some(cached))
+    Ok(Some(stored.response))
+}
+
+fn purge_cache_file(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        debug!("Unable to remove {}: {}", path.display(), err);
+    }
 }
 
 pub fn save_cached_response(cached: &CachedResponse) -> Result<()> {
@@ -140,7 +389,12 @@ pub fn save_cached_response(cached: &CachedResponse) -> Result<()> {
         )
     })?;
 
-    serde_json::to_writer(&mut file, cached)
+    let stored = StoredResponse {
+        schema: CACHE_SCHEMA_VERSION,
+        checksum: Some(sha256_hex(&cached.body)),
+        response: cached.clone(),
+    };
+    serde_json::to_writer(&mut file, &stored)
         .with_context(|| format!("failed to write cached response to {}", tmp_path.display()))?;
 
     file.flush()
@@ -158,44 +412,555 @@ pub fn save_cached_response(cached: &CachedResponse) -> Result<()> {
     Ok(())
 }
 
+fn partial_record_path(target: &Path) -> Result<PathBuf> {
+    let absolute = std::path::absolute(target)
+        .with_context(|| format!("failed to resolve {}", target.display()))?;
+    let key = cache_key(&absolute.to_string_lossy());
+    Ok(downloads_cache_dir()?.join(format!("{}.json", key)))
+}
+
+/// Load the record of an interrupted download into `target`, if any
+pub fn load_partial_download(target: &Path) -> Result<Option<PartialDownload>> {
+    let path = partial_record_path(target)?;
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(anyhow!(
+                "failed to read partial download record {}: {}",
+                path.display(),
+                err
+            ));
+        }
+    };
+
+    match serde_json::from_slice(&contents) {
+        Ok(record) => Ok(Some(record)),
+        Err(err) => {
+            debug!(
+                "Ignoring unreadable partial download record {}: {}",
+                path.display(),
+                err
+            );
+            let _ = fs::remove_file(&path);
+            Ok(None)
+        }
+    }
+}
+
+pub fn save_partial_download(record: &PartialDownload) -> Result<()> {
+    let path = partial_record_path(&record.path)?;
+    let tmp_path = path.with_extension("json.tmp");
+    let contents = serde_json::to_vec(record).context("failed to serialize download record")?;
+    fs::write(&tmp_path, contents)
+        .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("failed to persist {}", path.display()))?;
+    Ok(())
+}
+
+/// Forget the partial download of `target`, once it completed or was discarded
+pub fn remove_partial_download(target: &Path) -> Result<()> {
+    let path = partial_record_path(target)?;
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(anyhow!(
+            "failed to remove partial download record {}: {}",
+            path.display(),
+            err
+        )),
+        _ => Ok(()),
+    }
+}
+
 pub fn clear_all_caches() -> Result<()> {
+    info!(
+        "Clearing all cached data from {}",
+        cache_base_dir()?.display()
+    );
+    clear_caches(&CacheSection::ALL)?;
+    info!("All caches cleared successfully");
+    Ok(())
+}
+
+/// Top-level areas of the cache directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheSection {
+    /// Cached API responses, keyed by URL
+    Responses,
+    /// Git clones and zip archives
+    Repos,
+    /// Partial downloads kept for resuming
+    Downloads,
+    /// File contents keyed by git blob SHA
+    Blobs,
+}
+
+impl CacheSection {
+    pub const ALL: [CacheSection; 4] = [
+        CacheSection::Responses,
+        CacheSection::Repos,
+        CacheSection::Downloads,
+        CacheSection::Blobs,
+    ];
+
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            CacheSection::Responses => "responses",
+            CacheSection::Repos => "repos",
+            CacheSection::Downloads => "downloads",
+            CacheSection::Blobs => "blobs",
+        }
+    }
+}
+
+/// One file or clone in the cache
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub section: CacheSection,
+    pub path: PathBuf,
+    /// The cached URL for responses, the file or directory name otherwise
+    pub label: String,
+    pub size: u64,
+    /// Most recent modification time of anything in the entry, in Unix seconds
+    pub modified: u64,
+}
+
+/// Remove the given cache sections entirely
+pub fn clear_caches(sections: &[CacheSection]) -> Result<()> {
+    let base = cache_base_dir()?;
+
+    for section in sections {
+        let dir = base.join(section.dir_name());
+        if dir.exists() {
+            fs::remove_dir_all(&dir).with_context(|| {
+                format!(
+                    "failed to remove {} cache {}",
+                    section.dir_name(),
+                    dir.display()
+                )
+            })?;
+            info!("Cleared {} cache", section.dir_name());
+        }
+    }
+
+    Ok(())
+}
+
+/// List the entries of the given sections, without creating missing directories
+pub fn list_cache_entries(sections: &[CacheSection]) -> Result<Vec<CacheEntry>> {
     let base = cache_base_dir()?;
+    let mut entries = Vec::new();
+
+    for &section in sections {
+        let dir = base.join(section.dir_name());
+        let read_dir = match fs::read_dir(&dir) {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(anyhow!(
+                    "failed to read cache directory {}: {}",
+                    dir.display(),
+                    err
+                ));
+            }
+        };
+
+        for entry in read_dir {
+            let path = entry
+                .with_context(|| format!("failed to read entry in {}", dir.display()))?
+                .path();
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if name.starts_with(ACCESS_INDEX) || name.ends_with(CHECKSUM_SUFFIX) {
+                continue;
+            }
+            let label = match section {
+                CacheSection::Responses => cached_response_url(&path).unwrap_or(name),
+                _ => name,
+            };
+            let (size, modified) = disk_usage(&path)?;
+            entries.push(CacheEntry {
+                section,
+                path,
+                label,
+                size,
+                modified,
+            });
+        }
+    }
 
-    info!("Clearing all cached data from {}", base.display());
+    Ok(entries)
+}
 
-    let responses_dir = base.join("responses");
-    if responses_dir.exists() {
-        fs::remove_dir_all(&responses_dir).with_context(|| {
-            format!(
-                "failed to remove responses cache {}",
-                responses_dir.display()
-            )
-        })?;
-        info!("Cleared response cache");
+/// Delete every cached response, clone, and archive belonging to `owner/repo`
+pub fn remove_repo_entries(owner: &str, repo: &str) -> Result<Vec<CacheEntry>> {
+    let mut removed = Vec::new();
+
+    for entry in list_cache_entries(&CacheSection::ALL)? {
+        if !entry_matches_repo(entry.section, &entry.label, owner, repo) {
+            continue;
+        }
+        remove_cache_path(&entry.path)?;
+        debug!("Removed cache entry {}", entry.path.display());
+        removed.push(entry);
     }
 
-    let downloads_dir = base.join("downloads");
-    if downloads_dir.exists() {
-        fs::remove_dir_all(&downloads_dir).with_context(|| {
-            format!(
-                "failed to remove downloads cache {}",
-                downloads_dir.display()
-            )
-        })?;
-        info!("Cleared downloads cache");
+    Ok(removed)
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(CHECKSUM_SUFFIX);
+    PathBuf::from(name)
+}
+
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Record the SHA-256 of a cached file next to it, so later runs can detect corruption
+pub fn write_checksum(path: &Path) -> Result<()> {
+    let checksum = file_sha256(path)?;
+    fs::write(checksum_path(path), checksum)
+        .with_context(|| format!("failed to write checksum for {}", path.display()))
+}
+
+/// Check a cached file against its recorded checksum. Files cached before checksums
+/// existed are accepted when `legacy_check` vouches for them, which records a
+/// checksum for next time. A file that fails either check is deleted.
+pub fn verify_cached_file(path: &Path, legacy_check: impl FnOnce(&Path) -> bool) -> Result<bool> {
+    let valid = match fs::read_to_string(checksum_path(path)) {
+        Ok(expected) => file_sha256(path)? == expected.trim(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let valid = legacy_check(path);
+            if valid {
+                debug!(
+                    "Recording checksum for legacy cache entry {}",
+                    path.display()
+                );
+                write_checksum(path)?;
+            }
+            valid
+        }
+        Err(err) => {
+            return Err(anyhow!(
+                "failed to read checksum of {}: {}",
+                path.display(),
+                err
+            ));
+        }
+    };
+
+    if !valid {
+        warn!("Removing corrupted cache entry {}", path.display());
+        remove_cache_path(path)?;
+    }
+    Ok(valid)
+}
+
+/// Delete a cache entry together with its checksum sidecar
+fn remove_cache_path(path: &Path) -> Result<()> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.with_context(|| format!("failed to remove {}", path.display()))?;
+    let _ = fs::remove_file(checksum_path(path));
+    Ok(())
+}
+
+/// File in the repos cache recording when each clone or archive was last used
+const ACCESS_INDEX: &str = ".access.json";
+
+/// Record that the repos cache entry `name` was just used
+pub fn record_repo_access(name: &str) -> Result<()> {
+    let dir = repos_cache_dir()?;
+    let mut index = load_access_index(&dir);
+    index.insert(name.to_string(), system_time_to_secs(SystemTime::now()));
+    save_access_index(&dir, &index)
+}
+
+/// Evict the least recently used clones and archives until the repos cache fits in
+/// `max_bytes`. Entries used at or after `keep_since` (Unix seconds) are never evicted,
+/// so the current run cannot remove what it just downloaded.
+pub fn enforce_repo_cache_limit(max_bytes: u64, keep_since: u64) -> Result<Vec<String>> {
+    let dir = repos_cache_dir()?;
+    let mut index = load_access_index(&dir);
+
+    let mut entries = Vec::new();
+    for entry in list_cache_entries(&[CacheSection::Repos])? {
+        let last_access = index.get(&entry.label).copied().unwrap_or(entry.modified);
+        entries.push((entry.label, entry.size, last_access));
     }
 
-    let repos_dir = base.join("repos");
-    if repos_dir.exists() {
-        fs::remove_dir_all(&repos_dir)
-            .with_context(|| format!("failed to remove repos cache {}", repos_dir.display()))?;
-        info!("Cleared repos cache");
+    let evicted = select_evictions(&entries, max_bytes, keep_since);
+    for name in &evicted {
+        remove_cache_path(&dir.join(name)).context("failed to evict cache entry")?;
+        info!("Evicted {} from the repos cache", name);
     }
 
-    info!("All caches cleared successfully");
+    // Drop index records for entries that no longer exist
+    index.retain(|name, _| {
+        !evicted.contains(name) && entries.iter().any(|(entry, _, _)| entry == name)
+    });
+    save_access_index(&dir, &index)?;
+
+    Ok(evicted)
+}
+
+/// Pick `(name, size, last_access)` entries to evict, oldest first, until the total
+/// size is at most `max_bytes`
+fn select_evictions(
+    entries: &[(String, u64, u64)],
+    max_bytes: u64,
+    keep_since: u64,
+) -> Vec<String> {
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    let mut candidates: Vec<&(String, u64, u64)> = entries
+        .iter()
+        .filter(|(_, _, last_access)| *last_access < keep_since)
+        .collect();
+    candidates.sort_by_key(|(_, _, last_access)| *last_access);
+
+    let mut evicted = Vec::new();
+    for (name, size, _) in candidates {
+        if total <= max_bytes {
+            break;
+        }
+        total = total.saturating_sub(*size);
+        evicted.push(name.clone());
+    }
+    evicted
+}
+
+fn load_access_index(dir: &Path) -> BTreeMap<String, u64> {
+    fs::read(dir.join(ACCESS_INDEX))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save_access_index(dir: &Path, index: &BTreeMap<String, u64>) -> Result<()> {
+    let path = dir.join(ACCESS_INDEX);
+    let tmp_path = dir.join(format!("{}.tmp", ACCESS_INDEX));
+    let bytes = serde_json::to_vec(index).context("failed to serialize cache access index")?;
+    fs::write(&tmp_path, bytes)
+        .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, &path).with_context(|| format!("failed to persist {}", path.display()))
+}
+
+/// Parse a size such as `2GiB`, `500M`, `1.5g`, or `1048576` into bytes
+pub fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        other => return Err(format!("unknown size unit '{}'", other)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+fn cached_response_url(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let cached: CachedResponse = serde_json::from_reader(io::BufReader::new(file)).ok()?;
+    Some(cached.url)
+}
+
+/// Total size and newest modification time of a file or directory tree
+fn disk_usage(path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| format!("failed to inspect {}", path.display()))?;
+    let mut size = metadata.len();
+    let mut modified = metadata.modified().map(system_time_to_secs).unwrap_or(0);
+
+    if metadata.is_dir() {
+        size = 0;
+        for entry in
+            fs::read_dir(path).with_context(|| format!("failed to read {}", path.display()))?
+        {
+            let entry = entry.with_context(|| format!("failed to read {}", path.display()))?;
+            let (child_size, child_modified) = disk_usage(&entry.path())?;
+            size += child_size;
+            modified = modified.max(child_modified);
+        }
+    }
+
+    Ok((size, modified))
+}
+
+/// Whether a cache entry belongs to `owner/repo`.
+///
+/// Clones and archives are named `{owner}-{repo}-{hash}`; responses are matched on
+/// the API or github.com path of their URL.
+fn entry_matches_repo(section: CacheSection, label: &str, owner: &str, repo: &str) -> bool {
+    let owner = owner.to_ascii_lowercase();
+    let repo = repo.to_ascii_lowercase();
+    let label = label.to_ascii_lowercase();
+
+    match section {
+        CacheSection::Repos => {
+            let name = label.strip_suffix(".zip").unwrap_or(&label);
+            name.strip_prefix(&format!("{}-{}-", owner, repo))
+                .is_some_and(|hash| hash.len() == 8 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        }
+        CacheSection::Responses => {
+            let Ok(url) = url::Url::parse(&label) else {
+                return false;
+            };
+            let path = url.path().trim_end_matches('/');
+            [
+                format!("/repos/{}/{}", owner, repo),
+                format!("/{}/{}", owner, repo),
+            ]
+            .iter()
+            .any(|prefix| {
+                path == prefix
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+        }
+        // Downloads are not tracked per repository, and blobs are shared between them
+        CacheSection::Downloads | CacheSection::Blobs => false,
+    }
+}
+
+/// Execute a `cache` subcommand
+pub fn run_cache_command(action: CacheAction) -> Result<()> {
+    match action {
+        CacheAction::Ls => {
+            let entries = list_cache_entries(&CacheSection::ALL)?;
+            print_cache_listing(&entries, system_time_to_secs(SystemTime::now()));
+        }
+        CacheAction::Rm { repo } => {
+            let (owner, name) = repo
+                .split_once('/')
+                .filter(|(owner, name)| {
+                    !owner.is_empty() && !name.is_empty() && !name.contains('/')
+                })
+                .ok_or_else(|| anyhow!("expected a repository as OWNER/REPO, got '{}'", repo))?;
+            let removed = remove_repo_entries(owner, name.trim_end_matches(".git"))?;
+            if removed.is_empty() {
+                eprintln!("No cache entries found for {}", repo);
+            } else {
+                let freed: u64 = removed.iter().map(|entry| entry.size).sum();
+                eprintln!(
+                    "{} Removed {} cache entr{} for {} ({})",
+                    style("✓").green().bold(),
+                    removed.len(),
+                    if removed.len() == 1 { "y" } else { "ies" },
+                    repo,
+                    format_bytes(freed)
+                );
+            }
+        }
+        CacheAction::Clear {
+            responses,
+            repos,
+            downloads,
+            blobs,
+        } => {
+            let selected: Vec<CacheSection> = [
+                (CacheSection::Responses, responses),
+                (CacheSection::Repos, repos),
+                (CacheSection::Downloads, downloads),
+                (CacheSection::Blobs, blobs),
+            ]
+            .into_iter()
+            .filter_map(|(section, selected)| selected.then_some(section))
+            .collect();
+            if selected.is_empty() {
+                clear_all_caches()?;
+            } else {
+                clear_caches(&selected)?;
+            }
+            eprintln!("{} Cache cleared", style("✓").green().bold());
+        }
+    }
+    Ok(())
+}
+
+/// Entry count and size of each cache section, for `ghdl stats`
+pub fn print_cache_summary() -> Result<()> {
+    let entries = list_cache_entries(&CacheSection::ALL)?;
+    println!(
+        "{} ({})",
+        style("Cache").bold(),
+        cache_base_dir()?.display()
+    );
+    for section in CacheSection::ALL {
+        let (count, size) = entries
+            .iter()
+            .filter(|entry| entry.section == section)
+            .fold((0usize, 0u64), |(count, size), entry| {
+                (count + 1, size + entry.size)
+            });
+        println!(
+            "  {:<10} {} entr{}, {}",
+            section.dir_name(),
+            count,
+            if count == 1 { "y" } else { "ies" },
+            format_bytes(size)
+        );
+    }
     Ok(())
 }
 
+fn print_cache_listing(entries: &[CacheEntry], now: u64) {
+    for section in CacheSection::ALL {
+        let mut rows: Vec<&CacheEntry> = entries
+            .iter()
+            .filter(|entry| entry.section == section)
+            .collect();
+        rows.sort_by_key(|entry| std::cmp::Reverse(entry.modified));
+        let total: u64 = rows.iter().map(|entry| entry.size).sum();
+
+        println!(
+            "{} ({} entr{}, {})",
+            style(section.dir_name()).bold(),
+            rows.len(),
+            if rows.len() == 1 { "y" } else { "ies" },
+            format_bytes(total)
+        );
+        for entry in rows {
+            println!(
+                "  {:>10}  {:>4}  {}",
+                format_bytes(entry.size),
+                format_age(now.saturating_sub(entry.modified)),
+                entry.label
+            );
+        }
+    }
+}
+
+/// Compact age such as `45s`, `12m`, `3h`, or `9d`
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -292,6 +1057,75 @@ mod tests {
         cleanup_test_cache_dir(test_dir);
     }
 
+    #[test]
+    #[serial]
+    fn test_cached_response_migration_and_corruption() {
+        let test_dir = setup_test_cache_dir();
+        let url = "https://example.com/legacy";
+        let path = responses_cache_dir()
+            .expect("Failed to create cache dir")
+            .join(format!("{}.json", cache_key(url)));
+        let legacy = CachedResponse {
+            url: url.to_string(),
+            body: b"[1,2,3]".to_vec(),
+            etag: None,
+            last_modified: None,
+            timestamp: system_time_to_secs(SystemTime::now()),
+        };
+
+        // An entry in the pre-versioning format is read and rewritten with a checksum
+        fs::write(&path, serde_json::to_vec(&legacy).unwrap()).unwrap();
+        assert!(load_cached_entry(url).unwrap().is_some());
+        let stored: StoredResponse = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(stored.schema, CACHE_SCHEMA_VERSION);
+        assert_eq!(stored.checksum, Some(sha256_hex(b"[1,2,3]")));
+
+        // A body that no longer matches its checksum is purged
+        let mut corrupted = stored;
+        corrupted.response.body = b"[1,2,4]".to_vec();
+        fs::write(&path, serde_json::to_vec(&corrupted).unwrap()).unwrap();
+        assert!(load_cached_entry(url).unwrap().is_none());
+        assert!(!path.exists());
+
+        // So is an entry that is not valid JSON at all
+        fs::write(&path, b"{\"url\": ").unwrap();
+        assert!(load_cached_entry(url).unwrap().is_none());
+        assert!(!path.exists());
+
+        // Entries from a newer format are left alone
+        corrupted.schema = CACHE_SCHEMA_VERSION + 1;
+        fs::write(&path, serde_json::to_vec(&corrupted).unwrap()).unwrap();
+        assert!(load_cached_entry(url).unwrap().is_none());
+        assert!(path.exists());
+
+        cleanup_test_cache_dir(test_dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_verify_cached_file() {
+        let test_dir = setup_test_cache_dir();
+        let path = repos_cache_dir().unwrap().join("owner-repo-12345678.zip");
+
+        // Legacy entries are vouched for by the caller and get a checksum recorded
+        fs::write(&path, b"archive").unwrap();
+        assert!(verify_cached_file(&path, |_| true).unwrap());
+        assert!(checksum_path(&path).exists());
+        assert!(verify_cached_file(&path, |_| false).unwrap());
+        // Sidecars are not listed as entries of their own
+        assert_eq!(list_cache_entries(&[CacheSection::Repos]).unwrap().len(), 1);
+
+        fs::write(&path, b"archivf").unwrap();
+        assert!(!verify_cached_file(&path, |_| true).unwrap());
+        assert!(!path.exists() && !checksum_path(&path).exists());
+
+        fs::write(&path, b"truncated").unwrap();
+        assert!(!verify_cached_file(&path, |_| false).unwrap());
+        assert!(!path.exists());
+
+        cleanup_test_cache_dir(test_dir);
+    }
+
     #[test]
     #[serial]
     fn test_load_cached_response_not_found() {
@@ -380,6 +1214,234 @@ mod tests {
         cleanup_test_cache_dir(test_dir);
     }
 
+    #[test]
+    fn test_entry_matches_repo() {
+        let cases = [
+            (CacheSection::Repos, "owner-repo-1a2b3c4d", true),
+            (CacheSection::Repos, "Owner-Repo-1a2b3c4d.zip", true),
+            (CacheSection::Repos, "owner-repo-extra-1a2b3c4d", false),
+            (CacheSection::Repos, "owner-repo2-1a2b3c4d", false),
+            (
+                CacheSection::Responses,
+                "https://api.github.com/repos/owner/repo/contents/src?ref=main",
+                true,
+            ),
+            (
+                CacheSection::Responses,
+                "https://api.github.com/repos/owner/repo",
+                true,
+            ),
+            (
+                CacheSection::Responses,
+                "https://api.github.com/repos/owner/repository/contents",
+                false,
+            ),
+            (
+                CacheSection::Responses,
+                "https://github.com/owner/repo/archive/refs/heads/main.zip",
+                true,
+            ),
+            (CacheSection::Responses, "not a url", false),
+            (CacheSection::Downloads, "owner-repo-1a2b3c4d", false),
+        ];
+
+        for (section, label, expected) in cases {
+            assert_eq!(
+                entry_matches_repo(section, label, "owner", "repo"),
+                expected,
+                "{:?} {}",
+                section,
+                label
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_cache_dir_falls_back() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let fallback = dir.path().join("ghdl-cache-user");
+        assert_eq!(
+            resolve_cache_dir(Some(dir.path()), &fallback),
+            Some(dir.path().to_path_buf())
+        );
+
+        // A directory that cannot be created (its parent is a file) falls back to temp
+        let blocker = dir.path().join("file");
+        fs::write(&blocker, b"").unwrap();
+        let unwritable = blocker.join("ghdl");
+        assert!(!is_writable_dir(&unwritable));
+        assert_eq!(
+            resolve_cache_dir(Some(&unwritable), &fallback),
+            Some(fallback.clone())
+        );
+        assert_eq!(resolve_cache_dir(None, &fallback), Some(fallback));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fallback_cache_dir_must_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let fallback = dir.path().join("ghdl-cache-user");
+        assert!(is_private_dir(&fallback));
+        let mode = fs::metadata(&fallback).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // A directory others can read or write is refused rather than reused
+        fs::set_permissions(&fallback, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(!is_private_dir(&fallback));
+        assert_eq!(resolve_cache_dir(None, &fallback), None);
+
+        // So is a symlink, even to a private directory
+        fs::set_permissions(&fallback, fs::Permissions::from_mode(0o700)).unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&fallback, &link).unwrap();
+        assert!(!is_private_dir(&link));
+    }
+
+    #[test]
+    #[serial]
+    fn test_partial_download_records() {
+        let test_dir = setup_test_cache_dir();
+        let target = test_dir.join("out").join("file.bin");
+        let record = PartialDownload {
+            url: "https://raw.githubusercontent.com/o/r/main/file.bin".to_string(),
+            path: target.clone(),
+            downloaded_bytes: 512,
+            total_bytes: Some(2048),
+            sha: Some("ce013625030ba8dba906f756967f9e9ca394464a".to_string()),
+            etag: Some("\"abc\"".to_string()),
+            timestamp: 1,
+        };
+
+        assert_eq!(load_partial_download(&target).expect("load"), None);
+        save_partial_download(&record).expect("Failed to save record");
+        assert_eq!(load_partial_download(&target).expect("load"), Some(record));
+
+        remove_partial_download(&target).expect("Failed to remove record");
+        assert_eq!(load_partial_download(&target).expect("load"), None);
+        remove_partial_download(&target).expect("removing twice is fine");
+
+        cleanup_test_cache_dir(test_dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_store_and_restore_blob() {
+        let test_dir = setup_test_cache_dir();
+        let sha = "ce013625030ba8dba906f756967f9e9ca394464a";
+        let source = test_dir.join("source.txt");
+        let target = test_dir.join("target.txt");
+        fs::create_dir_all(&test_dir).expect("Failed to create test dir");
+        fs::write(&source, b"hello\n").expect("write");
+
+        assert!(!restore_blob(sha, &target).expect("restore should not fail"));
+        store_blob(sha, &source).expect("Failed to store blob");
+        assert!(restore_blob(sha, &target).expect("Failed to restore blob"));
+        assert_eq!(fs::read(&target).expect("read"), b"hello\n");
+
+        remove_blob(sha).expect("Failed to remove blob");
+        assert!(!restore_blob(sha, &target).expect("restore should not fail"));
+        assert!(restore_blob("../../etc/passwd", &target).is_err());
+
+        cleanup_test_cache_dir(test_dir);
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1048576"), Ok(1_048_576));
+        assert_eq!(parse_byte_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_byte_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_byte_size("1.5g"), Ok(3 << 29));
+        assert_eq!(parse_byte_size("10 kb"), Ok(10 << 10));
+        assert!(parse_byte_size("lots").is_err());
+        assert!(parse_byte_size("5 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_select_evictions() {
+        let entries = vec![
+            ("new".to_string(), 40, 300),
+            ("old".to_string(), 30, 100),
+            ("middle".to_string(), 50, 200),
+        ];
+
+        // Already under the cap
+        assert!(select_evictions(&entries, 120, 1000).is_empty());
+        // Oldest entries go first until the total fits
+        assert_eq!(select_evictions(&entries, 100, 1000), vec!["old"]);
+        assert_eq!(select_evictions(&entries, 60, 1000), vec!["old", "middle"]);
+        // Entries used during this run are kept even if the cap cannot be met
+        assert_eq!(select_evictions(&entries, 0, 200), vec!["old"]);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(0), "0s");
+        assert_eq!(format_age(59), "59s");
+        assert_eq!(format_age(60), "1m");
+        assert_eq!(format_age(7200), "2h");
+        assert_eq!(format_age(86400 * 3 + 5), "3d");
+    }
+
+    #[test]
+    #[serial]
+    fn test_list_and_remove_repo_entries() {
+        let test_dir = setup_test_cache_dir();
+
+        let repos_dir = repos_cache_dir().expect("Failed to create repos dir");
+        let clone = repos_dir.join("owner-repo-1a2b3c4d");
+        fs::create_dir_all(clone.join(".git")).expect("Failed to create clone dir");
+        fs::write(clone.join(".git/HEAD"), b"ref: refs/heads/main\n").expect("write");
+        fs::write(clone.join("README.md"), b"hello").expect("write");
+        fs::write(repos_dir.join("other-repo-1a2b3c4d.zip"), b"zip").expect("write");
+
+        save_cached_response(&CachedResponse {
+            url: "https://api.github.com/repos/owner/repo/contents".to_string(),
+            body: b"[]".to_vec(),
+            etag: None,
+            last_modified: None,
+            timestamp: system_time_to_secs(SystemTime::now()),
+        })
+        .expect("Failed to save cache");
+
+        let entries = list_cache_entries(&CacheSection::ALL).expect("Failed to list");
+        assert_eq!(entries.len(), 3);
+        let clone_entry = entries
+            .iter()
+            .find(|entry| entry.label == "owner-repo-1a2b3c4d")
+            .expect("clone should be listed");
+        assert_eq!(clone_entry.size, 26);
+        assert!(
+            entries
+                .iter()
+                .any(|entry| entry.section == CacheSection::Responses
+                    && entry.label == "https://api.github.com/repos/owner/repo/contents")
+        );
+
+        let removed = remove_repo_entries("owner", "repo").expect("Failed to remove");
+        assert_eq!(removed.len(), 2);
+        assert!(!clone.exists());
+        assert!(repos_dir.join("other-repo-1a2b3c4d.zip").exists());
+
+        record_repo_access("other-repo-1a2b3c4d.zip").expect("Failed to record access");
+        assert_eq!(
+            list_cache_entries(&[CacheSection::Repos])
+                .expect("Failed to list")
+                .len(),
+            1,
+            "the access index is not a cache entry"
+        );
+        let evicted = enforce_repo_cache_limit(0, u64::MAX).expect("Failed to enforce limit");
+        assert_eq!(evicted, vec!["other-repo-1a2b3c4d.zip"]);
+
+        clear_caches(&[CacheSection::Repos]).expect("Failed to clear repos");
+        assert!(!repos_dir.exists());
+
+        cleanup_test_cache_dir(test_dir);
+    }
+
     #[test]
     fn test_cached_response_serialization() {
         let cached = CachedResponse {
@@ -1,6 +1,14 @@
 use std::path::PathBuf;
 
-use clap::{ArgAction, Parser, ValueEnum};
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand, ValueEnum};
+
+use crate::cache::parse_byte_size;
+use crate::download::lock::DEFAULT_LOCKFILE;
+use crate::download::manifest::DEFAULT_MANIFEST;
+use crate::download::watch::parse_interval;
+use crate::http::parse_jitter;
+use crate::shard::Shard;
 
 pub const VERSION: &str = env!("GHDL_VERSION");
 pub const LONG_VERSION: &str = env!("GHDL_LONG_VERSION");
@@ -18,64 +26,725 @@ pub enum DownloadStrategy {
     Auto,
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Replace symlinks with the contents of the file they point to.
+    Follow,
+    /// Create real symlinks (falls back to copying where the platform refuses).
+    Create,
+    /// Skip symlinks with a warning.
+    #[default]
+    Skip,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GitTransport {
+    /// Clone over HTTPS, authenticating with the token when one is available.
+    #[default]
+    Https,
+    /// Clone over SSH (`git@github.com:owner/repo.git`) using the local SSH agent and keys.
+    Ssh,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// Aligned columns: type, size, abbreviated SHA, and path.
+    Table,
+    /// A JSON array of `{path, type, size, sha}` objects.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RateFormat {
+    /// One row per quota: limit, used, remaining, and when it resets.
+    Table,
+    /// `{authenticated, resources}`, with each quota's `limit`, `used`, `remaining`, and `reset` (Unix seconds) by resource name.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ProgressOutput {
+    /// Progress bars on the terminal.
+    Bars,
+    /// One JSON event per line (`start`, `file_start`, `chunk_done`, `file_done`, `finish`).
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines with a timestamp, level, and module.
+    Text,
+    /// One JSON object per record: `{timestamp, level, target, message}`.
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TagVerification {
+    /// Warn when the tag is unsigned or its signature does not verify.
+    Warn,
+    /// Refuse to download unless the tag's signature verifies.
+    Require,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Keep the existing file and leave the remote one out.
+    Skip,
+    /// Replace the existing file (the same as `--force`).
+    Overwrite,
+    /// Move the existing file to `<name>.bak`, then write the new one.
+    Backup,
+    /// Keep the existing file and write the new one as `<stem>.1.<ext>` (or the next free number).
+    Rename,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Download files or directories (the same as `ghdl <URL>...`)
+    Get {
+        /// GitHub folder URLs to download from (e.g. https://github.com/owner/repo/tree/branch/path)
+        #[arg(value_name = "URL", required_unless_present = "input_file")]
+        urls: Vec<String>,
+
+        #[command(flatten)]
+        download: Box<DownloadArgs>,
+    },
+
+    /// Download every source listed in a gdl.toml manifest into its destination and lock the commits they resolved to
+    Install {
+        /// Manifest listing the sources
+        #[arg(long, value_name = "FILE", default_value = DEFAULT_MANIFEST, env = "GHDL_MANIFEST")]
+        manifest: PathBuf,
+
+        /// Move sources whose ref has new commits and rewrite gdl.lock, instead of failing
+        #[arg(long)]
+        update: bool,
+
+        #[command(flatten)]
+        download: Box<DownloadArgs>,
+    },
+
+    /// Update ghdl to the latest release
+    Update {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Display GitHub API rate limit information for every quota (core, search, graphql, ...)
+    Rate {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = RateFormat::Table)]
+        format: RateFormat,
+    },
+
+    /// Show a repository's visibility, size, and default branch, and check the URL's ref and path
+    Info {
+        /// GitHub URL to check (e.g. https://github.com/owner/repo/tree/branch/path)
+        #[arg(value_name = "URL")]
+        url: String,
+    },
+
+    /// List the files and directories behind a URL without downloading them
+    Ls {
+        /// GitHub URL to list (e.g. https://github.com/owner/repo/tree/branch/path)
+        #[arg(value_name = "URL")]
+        url: String,
+
+        /// List the whole tree below the URL instead of one level
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
+
+    /// Report LFS pointers, submodules, and symlinks a downloaded tree is missing
+    Audit {
+        /// Directory produced by an earlier download
+        #[arg(value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+
+        /// URL the directory was downloaded from, to also detect skipped symlinks and submodules
+        #[arg(long, value_name = "URL")]
+        source: Option<String>,
+    },
+
+    /// Store a GitHub token in the system keyring, or check or remove the stored one
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+
+    /// Inspect or prune the local cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Show cache usage, or with --telemetry the locally recorded usage counters
+    Stats {
+        /// Show the opt-in usage counters instead of cache usage
+        #[arg(long)]
+        telemetry: bool,
+
+        /// Send the aggregate counters to the configured telemetry_endpoint
+        #[arg(long, requires = "telemetry", conflicts_with = "reset")]
+        upload: bool,
+
+        /// Delete the recorded counters
+        #[arg(long, requires = "telemetry")]
+        reset: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuthAction {
+    /// Check a token and store it in the system keyring; later runs use it automatically
+    Login {
+        /// Read the token from stdin instead of prompting for it
+        #[arg(long)]
+        with_token: bool,
+
+        /// Authorize in the browser with GitHub's device flow instead of pasting a token
+        #[arg(long, conflicts_with = "with_token")]
+        web: bool,
+    },
+
+    /// Show which account the stored token belongs to
+    Status,
+
+    /// Check the token a download would use: account, scopes, SSO, expiry, and rate limit
+    Check,
+
+    /// Remove the stored token from the system keyring
+    Logout,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// List cached responses, repositories, and downloads with their size and age
+    Ls,
+
+    /// Remove every cached entry belonging to a repository
+    Rm {
+        /// Repository whose entries should be removed
+        #[arg(value_name = "OWNER/REPO")]
+        repo: String,
+    },
+
+    /// Remove cached data; without flags every section is cleared
+    Clear {
+        /// Clear cached API responses
+        #[arg(long)]
+        responses: bool,
+
+        /// Clear cached git clones and zip archives
+        #[arg(long)]
+        repos: bool,
+
+        /// Clear partial downloads
+        #[arg(long)]
+        downloads: bool,
+
+        /// Clear file contents cached by blob SHA
+        #[arg(long)]
+        blobs: bool,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(
     name = "ghdl",
     author,
     version = VERSION,
     long_version = LONG_VERSION,
-    about = "Download files or directories from a GitHub repository using the REST API or git."
+    about = "Download files or directories from a GitHub repository using the REST API or git.",
+    subcommand_negates_reqs = true
 )]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// GitHub folder URLs to download from (e.g. https://github.com/owner/repo/tree/branch/path)
     #[arg(
         value_name = "URL",
         num_args = 1..,
-        required_unless_present_any = ["self_update", "check_update", "clear_cache", "api_rate"]
+        required_unless_present_any = ["self_update", "check_update", "clear_cache", "api_rate", "input_file"]
     )]
     pub urls: Vec<String>,
 
-    /// Update ghdl to the latest release and exit
-    #[arg(long)]
+    #[command(flatten)]
+    pub download: DownloadArgs,
+
+    /// Former spelling of `ghdl update`
+    #[arg(long, hide = true)]
     pub self_update: bool,
 
-    /// Check for a newer ghdl release and exit without installing it
-    #[arg(long)]
+    /// Former spelling of `ghdl update --check`
+    #[arg(long, hide = true)]
     pub check_update: bool,
 
-    /// Display GitHub API rate limit information and exit
-    #[arg(long)]
+    /// Former spelling of `ghdl rate`
+    #[arg(long, hide = true)]
     pub api_rate: bool,
 
-    /// Output directory to place the downloaded files (defaults depend on the request)
-    #[arg(short = 'o', long)]
-    pub output: Option<PathBuf>,
+    /// Former spelling of `ghdl cache clear`
+    #[arg(long, hide = true)]
+    pub clear_cache: bool,
 
     /// GitHub personal access token (falls back to GITHUB_TOKEN or GH_TOKEN env vars)
-    #[arg(long)]
+    #[arg(long, global = true, env = "GHDL_TOKEN", hide_env_values = true)]
     pub token: Option<String>,
 
+    /// Without any other token, use the one the GitHub CLI (`gh auth login`) stored
+    #[arg(long, global = true, env = "GHDL_GH_AUTH", value_parser = BoolishValueParser::new())]
+    pub gh_auth: bool,
+
     /// Increase logging verbosity (-v for info, -vv for debug, -vvv for trace)
-    #[arg(short = 'v', long = "verbose", action = ArgAction::Count)]
+    #[arg(short = 'v', long = "verbose", global = true, action = ArgAction::Count)]
     pub verbose: u8,
 
+    /// Format of log records on stderr (`text` or `json`)
+    #[arg(long, global = true, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Text, env = "GHDL_LOG_FORMAT")]
+    pub log_format: LogFormat,
+
+    /// Also write every log record down to trace level to FILE, whatever the verbosity
+    #[arg(long, global = true, value_name = "FILE", env = "GHDL_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Send every request through this proxy, e.g. http://host:3128 or socks5://host:1080 (default: the *_proxy environment variables)
+    #[arg(long, global = true, value_name = "URL", env = "GHDL_PROXY")]
+    pub proxy: Option<String>,
+
+    /// Also trust the root certificates in this PEM bundle, e.g. a corporate proxy's CA
+    #[arg(long, global = true, value_name = "FILE", env = "GHDL_CACERT")]
+    pub cacert: Option<PathBuf>,
+
+    /// Skip TLS certificate verification entirely (unsafe; prefer --cacert)
+    #[arg(long, global = true, env = "GHDL_INSECURE", value_parser = BoolishValueParser::new())]
+    pub insecure: bool,
+
+    /// Give up on a connection that is not established within this many seconds (0 disables)
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        default_value_t = 30,
+        env = "GHDL_CONNECT_TIMEOUT"
+    )]
+    pub connect_timeout: u64,
+
+    /// Give up on any request, including a whole file or archive download, after this many seconds (0 disables)
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        default_value_t = 0,
+        env = "GHDL_TIMEOUT"
+    )]
+    pub timeout: u64,
+}
+
+/// Options of a download, given with `ghdl get` or the bare `ghdl <URL>` form
+#[derive(Args, Debug)]
+#[command(group = ArgGroup::new("syncing").args(["sync", "watch"]).multiple(true))]
+pub struct DownloadArgs {
+    /// Output directory to place the downloaded files (defaults depend on the request)
+    #[arg(short = 'o', long, env = "GHDL_OUTPUT")]
+    pub output: Option<PathBuf>,
+
+    /// Also download the URLs listed in this file, one per line (`-` reads stdin); blank lines and lines starting with # are skipped
+    #[arg(short = 'i', long, value_name = "FILE", env = "GHDL_INPUT_FILE")]
+    pub input_file: Option<PathBuf>,
+
     /// Maximum number of files to download concurrently
-    #[arg(short = 'p', long, value_name = "N", default_value_t = 4)]
+    #[arg(
+        short = 'p',
+        long,
+        value_name = "N",
+        default_value_t = 4,
+        env = "GHDL_PARALLEL"
+    )]
     pub parallel: usize,
 
+    /// Maximum number of directory listings to fetch concurrently (defaults to --parallel)
+    #[arg(long, value_name = "N", env = "GHDL_LISTING_PARALLEL")]
+    pub listing_parallel: Option<usize>,
+
+    /// Keep downloading the other URLs when one fails, print a summary, and exit non-zero at the end
+    #[arg(short = 'k', long, env = "GHDL_KEEP_GOING", value_parser = BoolishValueParser::new())]
+    pub keep_going: bool,
+
+    /// Number of URLs to download at once; each still downloads up to --parallel files
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        env = "GHDL_JOBS"
+    )]
+    pub jobs: usize,
+
     /// Preferred download strategy (`api`, `git`, or `auto`)
-    #[arg(short = 's', long, value_enum, default_value_t = DownloadStrategy::Auto)]
+    #[arg(short = 's', long, value_enum, default_value_t = DownloadStrategy::Auto, env = "GHDL_STRATEGY")]
     pub strategy: DownloadStrategy,
 
     /// Disable HTTP response caching and download resume
-    #[arg(long)]
+    #[arg(long, env = "GHDL_NO_CACHE", value_parser = BoolishValueParser::new())]
     pub no_cache: bool,
 
-    /// Clear all cached data and exit
-    #[arg(long)]
-    pub clear_cache: bool,
+    /// Reuse cached API responses for this many seconds (default: 3600, or `cache_ttl` in the config file)
+    #[arg(long, value_name = "SECS", env = "GHDL_CACHE_TTL")]
+    pub cache_ttl: Option<u64>,
+
+    /// Evict the least recently used clones and archives once the repos cache exceeds SIZE (e.g. 2GiB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size, env = "GHDL_CACHE_MAX_SIZE")]
+    pub cache_max_size: Option<u64>,
 
     /// Force overwrite existing files without prompting
-    #[arg(long, short = 'f')]
+    #[arg(long, short = 'f', env = "GHDL_FORCE", value_parser = BoolishValueParser::new())]
     pub force: bool,
+
+    /// What to do with files that already exist, instead of asking (overrides `--force` and `--sync`)
+    #[arg(long, value_enum, value_name = "MODE", env = "GHDL_ON_CONFLICT")]
+    pub on_conflict: Option<OnConflict>,
+
+    /// Only download files that are missing or differ from the remote (by git blob SHA), replacing changed files without prompting
+    #[arg(long, env = "GHDL_SYNC", value_parser = BoolishValueParser::new())]
+    pub sync: bool,
+
+    /// Skip existing files that already match the remote (by size and git blob SHA); the others are only replaced as `--force` or `--on-conflict` allow
+    #[arg(long, env = "GHDL_UPDATE_ONLY", value_parser = BoolishValueParser::new())]
+    pub update_only: bool,
+
+    /// Record the commit each URL resolved to and the blob SHA of every file in this lockfile (default: gdl.lock), keeping other URLs' entries
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = DEFAULT_LOCKFILE,
+        conflicts_with_all = ["locked", "dry_run", "offline", "watch", "local_repo"],
+        env = "GHDL_WRITE_LOCK",
+    )]
+    pub write_lock: Option<PathBuf>,
+
+    /// Fail unless every URL still resolves to the commit and files recorded in this lockfile (default: gdl.lock)
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = DEFAULT_LOCKFILE,
+        conflicts_with_all = ["offline", "watch", "local_repo"],
+        env = "GHDL_LOCKED",
+    )]
+    pub locked: Option<PathBuf>,
+
+    /// Keep running and sync again whenever a new commit touches the requested path, checking this often (e.g. 30s, 5m, 1h); implies --sync
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, conflicts_with_all = ["dry_run", "offline", "checksums"], env = "GHDL_WATCH")]
+    pub watch: Option<std::time::Duration>,
+
+    /// With --sync, delete local files below the output directory that the remote path no longer has, making it an exact mirror
+    #[arg(long, requires = "syncing", conflicts_with = "shard", env = "GHDL_DELETE", value_parser = BoolishValueParser::new())]
+    pub delete: bool,
+
+    /// Write a .gdl-source.json into each downloaded directory recording its URL, ref, resolved commit, strategy, and download time
+    #[arg(long, env = "GHDL_PROVENANCE", value_parser = BoolishValueParser::new())]
+    pub provenance: bool,
+
+    /// Show a desktop notification when the run finishes or fails
+    #[arg(long, env = "GHDL_NOTIFY", value_parser = BoolishValueParser::new())]
+    pub notify: bool,
+
+    /// Set each file's modification time to the date of its last commit
+    #[arg(long, env = "GHDL_PRESERVE_MTIME", value_parser = BoolishValueParser::new())]
+    pub preserve_mtime: bool,
+
+    /// Treat an empty repository or directory as an error instead of a no-op
+    #[arg(long, env = "GHDL_FAIL_ON_EMPTY", value_parser = BoolishValueParser::new())]
+    pub fail_on_empty: bool,
+
+    /// How to materialize symlinks found in the repository (`follow`, `create`, or `skip`)
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = SymlinkPolicy::Skip, env = "GHDL_SYMLINKS")]
+    pub symlinks: SymlinkPolicy,
+
+    /// Transport used by the git strategy (`https` or `ssh`)
+    #[arg(long, value_enum, value_name = "TRANSPORT", default_value_t = GitTransport::Https, env = "GHDL_GIT_TRANSPORT")]
+    pub git_transport: GitTransport,
+
+    /// Commits of history the git strategy fetches; 0 fetches all of it
+    #[arg(long, value_name = "N", default_value_t = 1, env = "GHDL_GIT_DEPTH")]
+    pub git_depth: u32,
+
+    /// Partial-clone filter for the git strategy (e.g. `blob:none`, `tree:0`,
+    /// `blob:limit=1m`); `none` clones every object
+    #[arg(
+        long,
+        value_name = "FILTER",
+        default_value = "blob:none",
+        env = "GHDL_GIT_FILTER"
+    )]
+    pub git_filter: String,
+
+    /// Clone into the output directory and keep its .git, checking out only the
+    /// requested paths, to keep working in the repository afterwards (git strategy only)
+    #[arg(long, conflicts_with_all = ["local_repo", "dry_run", "offline", "watch", "shard"], env = "GHDL_KEEP_GIT", value_parser = BoolishValueParser::new())]
+    pub keep_git: bool,
+
+    /// Replace Git LFS pointer files with the content they reference
+    #[arg(long, env = "GHDL_LFS", value_parser = BoolishValueParser::new())]
+    pub lfs: bool,
+
+    /// Download only partition I of N of the file list, so several runs can split a large tree
+    #[arg(long, value_name = "I/N", env = "GHDL_SHARD")]
+    pub shard: Option<Shard>,
+
+    /// Check the signature of the requested tag as verified by GitHub (`warn` or `require`)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        conflicts_with = "local_repo",
+        env = "GHDL_VERIFY_TAG"
+    )]
+    pub verify_tag: Option<TagVerification>,
+
+    /// Export from an existing local clone instead of downloading (no network access)
+    #[arg(long, value_name = "PATH", env = "GHDL_LOCAL_REPO")]
+    pub local_repo: Option<PathBuf>,
+
+    /// Serve everything from the response, repository, and archive caches without network access
+    #[arg(long, conflicts_with_all = ["verify_tag", "lfs", "no_cache"], env = "GHDL_OFFLINE", value_parser = BoolishValueParser::new())]
+    pub offline: bool,
+
+    /// Use a throwaway cache, skip the update check and usage counters, and remove everything on exit
+    #[arg(long, conflicts_with_all = ["offline", "cache_max_size"], env = "GHDL_EPHEMERAL", value_parser = BoolishValueParser::new())]
+    pub ephemeral: bool,
+
+    /// Print why each download strategy was tried or skipped, as JSON lines on stdout
+    #[arg(long, env = "GHDL_EXPLAIN", value_parser = BoolishValueParser::new())]
+    pub explain: bool,
+
+    /// Only print warnings and errors: no stage messages, progress bars, or final summary
+    #[arg(short = 'q', long, env = "GHDL_QUIET", value_parser = BoolishValueParser::new())]
+    pub quiet: bool,
+
+    /// Hide the progress bars but keep the other status messages
+    #[arg(long, env = "GHDL_NO_PROGRESS", value_parser = BoolishValueParser::new())]
+    pub no_progress: bool,
+
+    /// How to report download progress: `bars`, or `json` events on stdout (or --progress-file)
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ProgressOutput::Bars, env = "GHDL_PROGRESS")]
+    pub progress: ProgressOutput,
+
+    /// Write JSON progress events to this file instead of stdout, e.g. /dev/fd/3 (implies --progress json)
+    #[arg(long, value_name = "PATH", env = "GHDL_PROGRESS_FILE")]
+    pub progress_file: Option<PathBuf>,
+
+    /// Check files written by the git and zip strategies against the blob SHA-1s of the git tree (the default)
+    #[arg(long, overrides_with = "no_verify", env = "GHDL_VERIFY", value_parser = BoolishValueParser::new())]
+    pub verify: bool,
+
+    /// On Windows, keep file names Windows cannot store instead of renaming them (e.g. `a:b` to `a_b`, `aux.c` to `aux_.c`)
+    #[arg(long, env = "GHDL_NO_SANITIZE_NAMES", value_parser = BoolishValueParser::new())]
+    pub no_sanitize_names: bool,
+
+    /// Keep files from git checkouts and archives without comparing them to the git tree
+    #[arg(long, overrides_with = "verify", env = "GHDL_NO_VERIFY", value_parser = BoolishValueParser::new())]
+    pub no_verify: bool,
+
+    /// Write the SHA-256 of every downloaded file to this file, in `sha256sum` format
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "dry_run",
+        env = "GHDL_CHECKSUMS"
+    )]
+    pub checksums: Option<PathBuf>,
+
+    /// List the files that would be downloaded, their targets, and the total size, then exit without writing anything
+    #[arg(long, conflicts_with = "local_repo", env = "GHDL_DRY_RUN", value_parser = BoolishValueParser::new())]
+    pub dry_run: bool,
+
+    /// Cap the combined download speed of all files and archives, in bytes per second (e.g. 500K, 2M)
+    #[arg(long, value_name = "RATE", value_parser = parse_byte_size, env = "GHDL_LIMIT_RATE")]
+    pub limit_rate: Option<u64>,
+
+    /// Stop after sending this many GitHub API requests (retries included), so an unattended job cannot use up a shared token's quota; `auto` then falls back to its other strategies
+    #[arg(long, value_name = "N", env = "GHDL_MAX_API_CALLS")]
+    pub max_api_calls: Option<u64>,
+
+    /// Download files of at least --chunk-threshold as this many parallel range requests (1 disables)
+    #[arg(long, value_name = "N", default_value_t = 4, env = "GHDL_CHUNKS")]
+    pub chunks: usize,
+
+    /// Minimum size of a file downloaded in chunks (e.g. 64MiB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size, default_value = "64MiB", env = "GHDL_CHUNK_THRESHOLD")]
+    pub chunk_threshold: u64,
+
+    /// Do not record usage counters for this run, even if the config file enables telemetry
+    #[arg(long, env = "GHDL_NO_TELEMETRY", value_parser = BoolishValueParser::new())]
+    pub no_telemetry: bool,
+
+    /// Print a tree of the downloaded directories with file counts and sizes, DEPTH levels deep
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "2",
+        env = "GHDL_TREE",
+    )]
+    pub tree: Option<usize>,
+
+    /// With the zip strategy, extract the repository's tar.gz archive while it downloads instead of caching the whole archive first
+    #[arg(long, conflicts_with = "offline", env = "GHDL_STREAM_ARCHIVE", value_parser = BoolishValueParser::new())]
+    pub stream_archive: bool,
+
+    /// Retry a file when its download receives no data for this many seconds (0 disables)
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 30,
+        env = "GHDL_STALL_TIMEOUT"
+    )]
+    pub stall_timeout: u64,
+
+    /// Abort and retry a file or archive whose download averages less than this many bytes per second over --speed-time (e.g. 10K)
+    #[arg(long, value_name = "RATE", value_parser = parse_byte_size, env = "GHDL_SPEED_LIMIT")]
+    pub speed_limit: Option<u64>,
+
+    /// Period over which --speed-limit is averaged, in seconds
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 30,
+        requires = "speed_limit",
+        env = "GHDL_SPEED_TIME"
+    )]
+    pub speed_time: u64,
+
+    /// Retry requests and downloads that fail with a dropped connection, a timeout, a stall, or a 5xx answer this many times (0 disables)
+    #[arg(long, value_name = "N", default_value_t = 3, env = "GHDL_RETRIES")]
+    pub retries: usize,
+
+    /// Wait before the first retry in milliseconds, doubled for each following one
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 1000,
+        env = "GHDL_RETRY_DELAY"
+    )]
+    pub retry_delay: u64,
+
+    /// Fraction of each retry wait that is randomized, from 0 to 1
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.5, value_parser = parse_jitter, env = "GHDL_RETRY_JITTER")]
+    pub retry_jitter: f64,
+}
+
+impl DownloadArgs {
+    /// Telemetry names of the options this run changed from their defaults
+    pub fn features(&self) -> Vec<&'static str> {
+        [
+            ("input_file", self.input_file.is_some()),
+            ("jobs", self.jobs > 1),
+            ("keep_going", self.keep_going),
+            ("no_sanitize_names", self.no_sanitize_names),
+            ("no_cache", self.no_cache),
+            ("force", self.force),
+            ("on_conflict", self.on_conflict.is_some()),
+            ("sync", self.sync),
+            ("update_only", self.update_only),
+            ("delete", self.delete),
+            ("provenance", self.provenance),
+            ("notify", self.notify),
+            ("watch", self.watch.is_some()),
+            ("write_lock", self.write_lock.is_some()),
+            ("locked", self.locked.is_some()),
+            ("preserve_mtime", self.preserve_mtime),
+            ("fail_on_empty", self.fail_on_empty),
+            ("symlinks", self.symlinks != SymlinkPolicy::Skip),
+            ("git_transport:ssh", self.git_transport == GitTransport::Ssh),
+            ("git_depth", self.git_depth != 1),
+            ("git_filter", self.git_filter != "blob:none"),
+            ("keep_git", self.keep_git),
+            ("lfs", self.lfs),
+            ("shard", self.shard.is_some()),
+            ("verify_tag", self.verify_tag.is_some()),
+            ("local_repo", self.local_repo.is_some()),
+            ("explain", self.explain),
+            ("quiet", self.quiet),
+            ("no_progress", self.no_progress),
+            (
+                "progress:json",
+                self.progress == ProgressOutput::Json || self.progress_file.is_some(),
+            ),
+            ("dry_run", self.dry_run),
+            ("checksums", self.checksums.is_some()),
+            ("offline", self.offline),
+            ("tree", self.tree.is_some()),
+            ("cache_max_size", self.cache_max_size.is_some()),
+            ("limit_rate", self.limit_rate.is_some()),
+            ("max_api_calls", self.max_api_calls.is_some()),
+            ("stream_archive", self.stream_archive),
+            ("no_verify", self.no_verify),
+            ("no_retries", self.retries == 0),
+            ("speed_limit", self.speed_limit.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(feature, used)| used.then_some(feature))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn env_names(command: &clap::Command, names: &mut Vec<String>) {
+        for arg in command.get_arguments() {
+            if let Some(name) = arg.get_env() {
+                names.push(name.to_string_lossy().into_owned());
+            }
+        }
+        for subcommand in command.get_subcommands() {
+            env_names(subcommand, names);
+        }
+    }
+
+    #[test]
+    fn test_download_features() {
+        let cli = Cli::try_parse_from([
+            "ghdl",
+            "get",
+            "--lfs",
+            "--jobs",
+            "4",
+            "https://github.com/o/r",
+        ])
+        .unwrap();
+        let Some(Command::Get { download, .. }) = cli.command else {
+            panic!("expected the get subcommand");
+        };
+        assert_eq!(download.features(), ["jobs", "lfs"]);
+    }
+
+    #[test]
+    fn test_env_variables_share_one_prefix() {
+        let mut names = Vec::new();
+        env_names(&Cli::command(), &mut names);
+        assert!(names.contains(&"GHDL_PARALLEL".to_string()));
+        let stray: Vec<_> = names
+            .iter()
+            .filter(|name| !name.starts_with("GHDL_"))
+            .collect();
+        assert!(
+            stray.is_empty(),
+            "variables without the GHDL_ prefix: {stray:?}"
+        );
+    }
 }
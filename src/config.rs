@@ -0,0 +1,511 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, anyhow};
+use log::{debug, warn};
+use serde::Deserialize;
+
+/// Settings read from `config.toml`. Command-line flags take precedence.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    /// Seconds a cached API response is reused (`--cache-ttl`)
+    pub cache_ttl: Option<u64>,
+    /// Record anonymous usage counters locally (off unless set to `true`)
+    pub telemetry: Option<bool>,
+    /// Where `ghdl stats --telemetry --upload` sends the aggregate counters
+    pub telemetry_endpoint: Option<String>,
+    /// Per-provider settings, keyed by provider name (`[providers.github]`)
+    pub providers: BTreeMap<String, ProviderConfig>,
+    /// Tokens keyed by host (`github.com`, `ghe.example.com`), used for requests to
+    /// that host when `--token` is absent
+    pub tokens: BTreeMap<String, String>,
+}
+
+/// Connection defaults for one hosting provider
+#[derive(Debug, Default, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// REST API base URL, e.g. `https://ghe.example.com/api/v3`
+    pub api_url: Option<String>,
+    /// User agent sent with every request
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every request
+    pub headers: BTreeMap<String, String>,
+    /// Environment variables checked, in order, for a token when `--token` is absent
+    pub token_env: Option<Vec<String>>,
+    /// Shell command printing a token, run when neither `--token` nor `token_env` supplies one
+    pub token_cmd: Option<String>,
+    /// Ask the GitHub CLI (`gh auth token`) for a token when nothing else supplies one
+    pub gh_auth: Option<bool>,
+    /// Client ID of the OAuth app `ghdl auth login --web` authorizes
+    pub oauth_client_id: Option<String>,
+    /// Warn once the remaining API quota drops to this many requests
+    pub rate_limit_warn: Option<u64>,
+}
+
+/// Providers ghdl knows how to talk to
+const KNOWN_PROVIDERS: [&str; 1] = ["github"];
+
+impl Config {
+    /// Load the configuration file, or defaults when there is none.
+    ///
+    /// `GHDL_CONFIG` names the file explicitly; otherwise
+    /// `$XDG_CONFIG_HOME/ghdl/config.toml` (or `~/.config/ghdl/config.toml`) is used.
+    pub fn load() -> Result<Self> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if env::var_os("GHDL_CONFIG").is_some() {
+                    return Err(anyhow!("config file {} does not exist", path.display()));
+                }
+                return Ok(Self::default());
+            }
+            Err(err) => {
+                return Err(anyhow!(
+                    "failed to read config file {}: {}",
+                    path.display(),
+                    err
+                ));
+            }
+        };
+
+        debug!("Loading configuration from {}", path.display());
+        Self::parse(&contents).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        for name in config.providers.keys() {
+            if !KNOWN_PROVIDERS.contains(&name.as_str()) {
+                warn!(
+                    "Ignoring settings for unsupported provider '{}' (supported: {})",
+                    name,
+                    KNOWN_PROVIDERS.join(", ")
+                );
+            }
+        }
+        Ok(config)
+    }
+
+    /// Settings for GitHub, empty when the file has no `[providers.github]` table
+    pub fn github(&self) -> ProviderConfig {
+        self.providers.get("github").cloned().unwrap_or_default()
+    }
+
+    /// Token configured for `host` in the `[tokens]` table
+    pub fn token_for_host(&self, host: &str) -> Option<String> {
+        self.tokens
+            .get(host)
+            .or_else(|| {
+                self.tokens
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(host))
+                    .map(|(_, token)| token)
+            })
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+impl ProviderConfig {
+    /// Token from the first non-empty variable in `token_env`, or from
+    /// `GITHUB_TOKEN`/`GH_TOKEN` when the list is not configured
+    pub fn token_from_env(&self) -> Option<String> {
+        let default_vars = ["GITHUB_TOKEN".to_string(), "GH_TOKEN".to_string()];
+        self.token_env
+            .as_deref()
+            .unwrap_or(&default_vars)
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    /// Run `token_cmd` (e.g. `op read op://vault/github/token`) and return its trimmed
+    /// output. The caller resolves the token once at startup and reuses it for the
+    /// rest of the process, so the command runs at most once per invocation.
+    pub fn token_from_cmd(&self) -> Result<Option<String>> {
+        let Some(command) = self.token_cmd.as_deref().filter(|c| !c.trim().is_empty()) else {
+            return Ok(None);
+        };
+
+        debug!("Running token_cmd to obtain a GitHub token");
+        // stdin and stderr stay attached so password managers can prompt
+        let output = shell_command(command)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("failed to run token_cmd `{}`", command))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "token_cmd `{}` exited with {}",
+                command,
+                output.status
+            ));
+        }
+
+        let token = String::from_utf8(output.stdout)
+            .map_err(|_| anyhow!("token_cmd `{}` printed invalid UTF-8", command))?
+            .trim()
+            .to_string();
+        if token.is_empty() {
+            return Err(anyhow!("token_cmd `{}` printed no token", command));
+        }
+        Ok(Some(token))
+    }
+
+    /// Token of the GitHub CLI login for this provider's host: the output of
+    /// `gh auth token`, or the `oauth_token` in gh's `hosts.yml` when `gh` cannot
+    /// run. `None` when the user is not logged in; failures are only logged.
+    pub fn token_from_gh(&self) -> Option<String> {
+        let host = self.host();
+        match Command::new("gh")
+            .args(["auth", "token", "--hostname", &host])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if !token.is_empty() {
+                    debug!("Using the GitHub CLI token for {}", host);
+                    return Some(token);
+                }
+            }
+            Ok(output) => debug!("`gh auth token` exited with {}", output.status),
+            Err(err) => debug!("Could not run `gh auth token`: {}", err),
+        }
+
+        let path = gh_config_dir()?.join("hosts.yml");
+        let contents = fs::read_to_string(&path).ok()?;
+        let token = parse_gh_hosts(&contents, &host);
+        if token.is_some() {
+            debug!("Using the token for {} from {}", host, path.display());
+        }
+        token
+    }
+
+    /// Password of the `.netrc` entry (`$NETRC`, or `~/.netrc`) for the API host,
+    /// or failing that the web host, as curl and git's netrc helper would send it
+    pub fn token_from_netrc(&self) -> Option<String> {
+        let path = netrc_path()?;
+        let contents = fs::read_to_string(&path).ok()?;
+        let hosts = [self.api_host(), self.host()];
+        let (host, token) = hosts
+            .iter()
+            .find_map(|host| parse_netrc(&contents, host).map(|token| (host, token)))?;
+        debug!("Using the token for {} from {}", host, path.display());
+        Some(token)
+    }
+
+    /// Host of the REST API: `api.github.com`, or the host of `api_url`
+    fn api_host(&self) -> String {
+        self.api_url
+            .as_deref()
+            .and_then(|url| reqwest::Url::parse(url).ok())
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| "api.github.com".to_string())
+    }
+
+    /// Web host of this provider: `github.com`, or the host of a GitHub Enterprise
+    /// `api_url`. Stored credentials are keyed by it.
+    pub fn host(&self) -> String {
+        match self.api_host() {
+            host if host == "api.github.com" => "github.com".to_string(),
+            host => host,
+        }
+    }
+}
+
+/// `oauth_token` of `host` in gh's `hosts.yml`, i.e. its active account. Recent gh
+/// versions keep tokens in the system keyring instead, where only `gh auth token`
+/// can reach them.
+fn parse_gh_hosts(contents: &str, host: &str) -> Option<String> {
+    let mut in_host = false;
+    // Indentation of the host's own keys; deeper ones belong to `users`
+    let mut key_indent = None;
+    for line in contents.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if indent == 0 {
+            in_host = line
+                .trim_end()
+                .trim_end_matches(':')
+                .trim_matches(['"', '\''])
+                == host;
+            key_indent = None;
+            continue;
+        }
+        if !in_host || *key_indent.get_or_insert(indent) != indent {
+            continue;
+        }
+        if let Some(value) = line.trim().strip_prefix("oauth_token:") {
+            let token = value.trim().trim_matches(['"', '\'']);
+            return (!token.is_empty()).then(|| token.to_string());
+        }
+    }
+    None
+}
+
+/// Password of the `machine` entry for `host` in a `.netrc` file. `default`
+/// entries are ignored: their password was not meant for GitHub.
+fn parse_netrc(contents: &str, host: &str) -> Option<String> {
+    let mut in_macro = false;
+    let mut in_host = false;
+    let mut pending: Option<&str> = None;
+    for line in contents.lines() {
+        // A macro definition runs until the next empty line
+        if in_macro {
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        for word in line.split_whitespace() {
+            if word.starts_with('#') {
+                break;
+            }
+            match pending.take() {
+                Some("machine") => in_host = word == host,
+                Some("password") if in_host => return Some(word.to_string()),
+                Some(_) => {}
+                None => match word {
+                    "machine" | "login" | "password" | "account" => pending = Some(word),
+                    "default" => in_host = false,
+                    "macdef" => {
+                        in_macro = true;
+                        break;
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+    None
+}
+
+/// `$NETRC`, or `.netrc` (`_netrc` on Windows) in the home directory
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NETRC").filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    let name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+    Some(PathBuf::from(home).join(name))
+}
+
+/// gh's configuration directory: `$GH_CONFIG_DIR`, `$XDG_CONFIG_HOME/gh`, or `~/.config/gh`
+fn gh_config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("GH_CONFIG_DIR").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| !p.as_os_str().is_empty())
+        .or_else(|| {
+            env::var_os("HOME")
+                .map(PathBuf::from)
+                .map(|home| home.join(".config"))
+        })
+        .map(|base| base.join("gh"))
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("GHDL_CONFIG").filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| !p.as_os_str().is_empty())
+        .or_else(|| {
+            env::var_os("HOME")
+                .map(PathBuf::from)
+                .map(|home| home.join(".config"))
+        })
+        .map(|base| base.join("ghdl").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse("cache_ttl = 86400\n").unwrap().cache_ttl,
+            Some(86400)
+        );
+        assert!(Config::parse("cache_ttl = \"soon\"").is_err());
+        assert_eq!(
+            Config::parse("telemetry = true\n").unwrap().telemetry,
+            Some(true)
+        );
+
+        let config = Config::parse(
+            r#"
+[providers.github]
+api_url = "https://ghe.example.com/api/v3"
+user_agent = "build-bot/1.0"
+token_env = ["GHE_TOKEN"]
+token_cmd = "op read op://vault/github/token"
+gh_auth = true
+oauth_client_id = "Iv1.0123456789abcdef"
+rate_limit_warn = 200
+
+[providers.github.headers]
+X-Request-Source = "ci"
+
+[providers.gitlab]
+api_url = "https://gitlab.example.com/api/v4"
+"#,
+        )
+        .unwrap();
+        let github = config.github();
+        assert_eq!(
+            github.api_url.as_deref(),
+            Some("https://ghe.example.com/api/v3")
+        );
+        assert_eq!(github.user_agent.as_deref(), Some("build-bot/1.0"));
+        assert_eq!(github.token_env, Some(vec!["GHE_TOKEN".to_string()]));
+        assert_eq!(
+            github.token_cmd.as_deref(),
+            Some("op read op://vault/github/token")
+        );
+        assert_eq!(github.gh_auth, Some(true));
+        assert_eq!(
+            github.oauth_client_id.as_deref(),
+            Some("Iv1.0123456789abcdef")
+        );
+        assert_eq!(github.rate_limit_warn, Some(200));
+        assert_eq!(
+            github.headers.get("X-Request-Source").map(String::as_str),
+            Some("ci")
+        );
+        // Other providers are kept but unused
+        assert!(config.providers.contains_key("gitlab"));
+        assert_eq!(Config::default().github(), ProviderConfig::default());
+
+        let config = Config::parse(
+            r#"
+[tokens]
+"github.com" = "ghp_public"
+"GHE.example.com" = "ghp_enterprise"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.token_for_host("github.com").as_deref(),
+            Some("ghp_public")
+        );
+        assert_eq!(
+            config.token_for_host("ghe.example.com").as_deref(),
+            Some("ghp_enterprise")
+        );
+        assert_eq!(config.token_for_host("gitlab.com"), None);
+
+        // Typos inside a provider table are reported instead of silently ignored
+        assert!(Config::parse("[providers.github]\napi-url = \"x\"").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_token_from_cmd() {
+        let provider = |cmd: &str| ProviderConfig {
+            token_cmd: Some(cmd.to_string()),
+            ..ProviderConfig::default()
+        };
+
+        assert_eq!(ProviderConfig::default().token_from_cmd().unwrap(), None);
+        assert_eq!(
+            provider("printf ' ghp_secret\\n'")
+                .token_from_cmd()
+                .unwrap(),
+            Some("ghp_secret".to_string())
+        );
+        assert!(provider("true").token_from_cmd().is_err());
+        assert!(provider("exit 3").token_from_cmd().is_err());
+    }
+
+    #[test]
+    fn test_gh_token() {
+        let hosts = r#"
+github.com:
+    users:
+        octocat:
+            oauth_token: gho_user
+    oauth_token: gho_active
+    user: octocat
+"ghe.example.com":
+    oauth_token: 'gho_enterprise'
+"#;
+        assert_eq!(
+            parse_gh_hosts(hosts, "github.com"),
+            Some("gho_active".to_string())
+        );
+        assert_eq!(
+            parse_gh_hosts(hosts, "ghe.example.com"),
+            Some("gho_enterprise".to_string())
+        );
+        assert_eq!(parse_gh_hosts(hosts, "other.example.com"), None);
+
+        let provider = |api_url: &str| ProviderConfig {
+            api_url: Some(api_url.to_string()),
+            ..ProviderConfig::default()
+        };
+        assert_eq!(ProviderConfig::default().host(), "github.com");
+        assert_eq!(ProviderConfig::default().api_host(), "api.github.com");
+        assert_eq!(provider("https://api.github.com").host(), "github.com");
+        assert_eq!(
+            provider("https://ghe.example.com/api/v3").host(),
+            "ghe.example.com"
+        );
+    }
+
+    #[test]
+    fn test_parse_netrc() {
+        let netrc = "\
+# build machine credentials
+machine example.com login ci password hunter2
+macdef init
+machine api.github.com password inside-a-macro
+
+machine api.github.com
+    login x-access-token
+    password ghp_api # the token
+machine ghe.example.com login bot password ghp_enterprise
+default login anonymous password guest
+";
+        assert_eq!(
+            parse_netrc(netrc, "api.github.com"),
+            Some("ghp_api".to_string())
+        );
+        assert_eq!(
+            parse_netrc(netrc, "ghe.example.com"),
+            Some("ghp_enterprise".to_string())
+        );
+        assert_eq!(parse_netrc(netrc, "github.com"), None);
+    }
+}
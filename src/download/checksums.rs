@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+
+use crate::cache::file_sha256;
+use crate::error::{Context, Result};
+use crate::progress::ProgressSink;
+
+/// Collects the SHA-256 of every file a strategy writes (`--checksums`) and forwards
+/// all events to the sink it wraps. Each file is hashed as soon as it is written,
+/// while it is still in the page cache.
+pub struct ChecksumSink {
+    inner: Option<Arc<dyn ProgressSink>>,
+    /// Absolute target path -> hex digest, or why hashing failed
+    entries: Mutex<BTreeMap<PathBuf, std::result::Result<String, String>>>,
+}
+
+impl ChecksumSink {
+    pub fn new(inner: Option<Arc<dyn ProgressSink>>) -> Self {
+        Self {
+            inner,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Write the checksums in `sha256sum` format, sorted by path. Paths are relative
+    /// to the manifest's directory when the files are below it, so `sha256sum -c`
+    /// can be run from there.
+    pub fn write_manifest(&self, manifest: &Path) -> Result<()> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| anyhow!("checksum list is poisoned"))?;
+        let manifest = std::path::absolute(manifest)
+            .with_context(|| format!("invalid checksum file path {}", manifest.display()))?;
+        let base = manifest.parent().unwrap_or(Path::new("/"));
+
+        let mut contents = String::new();
+        for (target, digest) in entries.iter() {
+            let digest = digest
+                .as_ref()
+                .map_err(|err| anyhow!("failed to hash {}: {}", target.display(), err))?;
+            let path = target.strip_prefix(base).unwrap_or(target);
+            contents.push_str(&format!("{}  {}\n", digest, path.display()));
+        }
+        std::fs::write(&manifest, contents)
+            .with_context(|| format!("failed to write checksum file {}", manifest.display()))
+    }
+}
+
+impl ProgressSink for ChecksumSink {
+    fn start(&self, files: usize, bytes: u64) {
+        if let Some(inner) = &self.inner {
+            inner.start(files, bytes);
+        }
+    }
+
+    fn file_start(&self, path: &str, target: &Path, size: Option<u64>) {
+        if let Some(inner) = &self.inner {
+            inner.file_start(path, target, size);
+        }
+    }
+
+    fn chunk_done(&self, path: &str, index: usize, count: usize, bytes: u64) {
+        if let Some(inner) = &self.inner {
+            inner.chunk_done(path, index, count, bytes);
+        }
+    }
+
+    fn bytes(&self, downloaded: u64) {
+        if let Some(inner) = &self.inner {
+            inner.bytes(downloaded);
+        }
+    }
+
+    fn file_done(&self, path: &str, target: &Path, size: Option<u64>) {
+        // Symlinks are recorded as links, not as the files they point to
+        if !target.is_symlink() {
+            let digest = file_sha256(target).map_err(|err| format!("{:#}", err));
+            let target = std::path::absolute(target).unwrap_or_else(|_| target.to_path_buf());
+            if let Ok(mut entries) = self.entries.lock() {
+                entries.insert(target, digest);
+            }
+        }
+        if let Some(inner) = &self.inner {
+            inner.file_done(path, target, size);
+        }
+    }
+
+    fn finish(&self, files: usize, bytes: u64) {
+        if let Some(inner) = &self.inner {
+            inner.finish(files, bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("out")).unwrap();
+        let b = dir.path().join("out/b.txt");
+        let a = dir.path().join("out/a.txt");
+        std::fs::write(&b, "hello\n").unwrap();
+        std::fs::write(&a, "").unwrap();
+
+        let sink = ChecksumSink::new(None);
+        sink.file_done("b.txt", &b, Some(6));
+        sink.file_done("a.txt", &a, Some(0));
+        let manifest = dir.path().join("SHA256SUMS");
+        sink.write_manifest(&manifest).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&manifest).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  out/a.txt\n\
+             5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03  out/b.txt\n"
+        );
+
+        sink.file_done("gone.txt", &dir.path().join("out/gone.txt"), None);
+        assert!(sink.write_manifest(&manifest).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use indicatif::MultiProgress;
+use reqwest::Client;
+
+use crate::cli::DownloadStrategy;
+use crate::config::ProviderConfig;
+use crate::download::download_github_paths;
+use crate::error::Result;
+use crate::filter::PathFilter;
+use crate::github::Endpoints;
+use crate::http::{ClientOptions, LowSpeedLimit, RetryPolicy, TokenBucket, build_client};
+use crate::progress::ProgressSink;
+use crate::rate_limit::RateLimitTracker;
+use crate::types::{DownloadContext, DownloadOptions};
+
+/// Downloads GitHub URLs with one client, rate-limit tracker, and set of options.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), ghdl::GdlError> {
+/// let downloader = ghdl::Downloader::builder()
+///     .token("ghp_...")
+///     .strategy(ghdl::DownloadStrategy::Api)
+///     .parallel(8)
+///     .cache(false)
+///     .build()?;
+/// downloader
+///     .download("https://github.com/owner/repo/tree/main/docs", None)
+///     .await
+/// # }
+/// ```
+pub struct Downloader<'a> {
+    ctx: DownloadContext,
+    strategy: DownloadStrategy,
+    options: DownloadOptions<'a>,
+}
+
+impl<'a> Downloader<'a> {
+    pub fn builder() -> DownloaderBuilder<'a> {
+        DownloaderBuilder::default()
+    }
+
+    /// Download one URL into `output`, or a directory named after the requested path
+    pub async fn download(&self, url: &str, output: Option<&Path>) -> Result<()> {
+        self.download_all(&[url.to_string()], output).await
+    }
+
+    /// Download several URLs, in order unless [`DownloaderBuilder::jobs`] allows more
+    /// than one at once. Paths of the same repository and ref share one git checkout
+    /// when git is the strategy used.
+    pub async fn download_all(&self, urls: &[String], output: Option<&Path>) -> Result<()> {
+        // Each URL's mirror would delete the files of the others
+        if self.options.delete && urls.len() > 1 {
+            return Err(anyhow!("--delete mirrors a single URL; run ghdl once per URL").into());
+        }
+        let output = output.map(Path::to_path_buf);
+        self.ctx
+            .endpoints
+            .scope(download_github_paths(
+                &self.ctx,
+                urls,
+                output.as_ref(),
+                self.strategy,
+                &self.options,
+            ))
+            .await
+    }
+
+    /// Download one URL, keeping only the files `filter` selects
+    pub async fn download_filtered(
+        &self,
+        url: &str,
+        output: Option<&Path>,
+        filter: Option<PathFilter>,
+    ) -> Result<()> {
+        let options = DownloadOptions {
+            filter,
+            ..self.options.clone()
+        };
+        let output = output.map(Path::to_path_buf);
+        self.ctx
+            .endpoints
+            .scope(download_github_paths(
+                &self.ctx,
+                &[url.to_string()],
+                output.as_ref(),
+                self.strategy,
+                &options,
+            ))
+            .await
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.ctx.client
+    }
+
+    pub fn rate_limit(&self) -> &Arc<RateLimitTracker> {
+        &self.ctx.rate_limit
+    }
+
+    /// Where this downloader's requests go; run other API calls through
+    /// [`Endpoints::scope`] to send them there too
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.ctx.endpoints
+    }
+
+    pub fn options(&self) -> &DownloadOptions<'a> {
+        &self.options
+    }
+}
+
+/// Settings for a [`Downloader`]; anything left unset keeps the command line's default
+#[derive(Default)]
+pub struct DownloaderBuilder<'a> {
+    client: Option<Client>,
+    rate_limit: Option<Arc<RateLimitTracker>>,
+    multi: Option<MultiProgress>,
+    strategy: Option<DownloadStrategy>,
+    parallel: Option<usize>,
+    listing_parallel: Option<usize>,
+    jobs: Option<usize>,
+    bandwidth: Option<Arc<TokenBucket>>,
+    retry: Option<RetryPolicy>,
+    endpoints: Endpoints,
+    options: DownloadOptions<'a>,
+}
+
+impl<'a> DownloaderBuilder<'a> {
+    /// HTTP client for every request; by default one with ghdl's user agent
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn token(mut self, token: &'a str) -> Self {
+        self.options.token = Some(token);
+        self
+    }
+
+    pub fn strategy(mut self, strategy: DownloadStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// Files downloaded at once (at least 1)
+    pub fn parallel(mut self, parallel: usize) -> Self {
+        self.parallel = Some(parallel);
+        self
+    }
+
+    /// Directory listings fetched at once; defaults to `parallel`
+    pub fn listing_parallel(mut self, listing_parallel: usize) -> Self {
+        self.listing_parallel = Some(listing_parallel);
+        self
+    }
+
+    /// URLs downloaded at once by [`Downloader::download_all`] (at least 1)
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Read and write the API response cache (on by default)
+    pub fn cache(mut self, enabled: bool) -> Self {
+        self.options.no_cache = !enabled;
+        self
+    }
+
+    /// How long cached API responses are reused
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.options.cache_ttl = ttl;
+        self
+    }
+
+    /// Replace existing files without asking
+    pub fn force(mut self, force: bool) -> Self {
+        self.options.force = force;
+        self
+    }
+
+    /// Abort and retry a file whose stream delivers no data for this long; `None`
+    /// waits forever
+    pub fn stall_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.stall_timeout = timeout;
+        self
+    }
+
+    /// Abort and retry a file whose stream stays below this speed
+    pub fn low_speed_limit(mut self, limit: Option<LowSpeedLimit>) -> Self {
+        self.options.low_speed_limit = limit;
+        self
+    }
+
+    /// Cap the combined speed of this downloader's streams, in bytes per second;
+    /// `None` removes the cap
+    pub fn limit_rate(mut self, bytes_per_sec: Option<u64>) -> Self {
+        self.bandwidth = bytes_per_sec.map(|rate| Arc::new(TokenBucket::new(rate)));
+        self
+    }
+
+    /// How transient failures are retried; give a [`rate_limit`](Self::rate_limit)
+    /// tracker its own policy with [`RateLimitTracker::with_retry_policy`] instead
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Print no status messages
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.options.quiet = quiet;
+        self
+    }
+
+    /// Replace characters and names Windows can't store in output file names (on by
+    /// default on Windows only)
+    pub fn sanitize_names(mut self, sanitize: bool) -> Self {
+        self.options.sanitize_names = sanitize;
+        self
+    }
+
+    /// REST API base URL, e.g. a mirror or a local mock server, like `GHDL_API_BASE`
+    /// for the command line. Only this downloader's requests use it.
+    pub fn api_base(mut self, url: &str) -> Self {
+        self.endpoints = self.endpoints.with_api(url);
+        self
+    }
+
+    /// Base URL that repository archives and LFS objects are downloaded from, in
+    /// place of `https://github.com`. Only this downloader's requests use it.
+    pub fn archive_base(mut self, url: &str) -> Self {
+        self.endpoints = self.endpoints.with_archive(url);
+        self
+    }
+
+    /// Both base URLs at once
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: Arc<RateLimitTracker>) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Where the clone and archive transfer bars are drawn
+    pub fn multi_progress(mut self, multi: MultiProgress) -> Self {
+        self.multi = Some(multi);
+        self
+    }
+
+    pub fn progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.options.progress = Some(sink);
+        self
+    }
+
+    /// Start from these options; the token, cache, force, and progress setters
+    /// called afterwards still apply
+    pub fn options(mut self, options: DownloadOptions<'a>) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn build(self) -> Result<Downloader<'a>> {
+        let client = match self.client {
+            Some(client) => client,
+            None => build_client(&ProviderConfig::default(), &ClientOptions::default())?,
+        };
+        let rate_limit = match (self.rate_limit, self.retry) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "retry_policy can't be combined with rate_limit; use RateLimitTracker::with_retry_policy"
+                ).into());
+            }
+            (Some(rate_limit), None) => rate_limit,
+            (None, retry) => {
+                Arc::new(RateLimitTracker::default().with_retry_policy(retry.unwrap_or_default()))
+            }
+        };
+        let parallel = self.parallel.unwrap_or(4).max(1);
+        let mut ctx = DownloadContext::new(
+            client,
+            rate_limit,
+            self.multi.unwrap_or_default(),
+            parallel,
+            self.listing_parallel.unwrap_or(parallel).max(1),
+            self.jobs.unwrap_or(1).max(1),
+        );
+        ctx.endpoints = self.endpoints;
+        ctx.bandwidth = self.bandwidth;
+        Ok(Downloader {
+            ctx,
+            strategy: self.strategy.unwrap_or(DownloadStrategy::Auto),
+            options: self.options,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults_and_overrides() {
+        let downloader = Downloader::builder().build().unwrap();
+        assert_eq!(downloader.strategy, DownloadStrategy::Auto);
+        assert_eq!(downloader.ctx.parallel, 4);
+        assert_eq!(downloader.ctx.listing_parallel, 4);
+        assert_eq!(downloader.ctx.jobs, 1);
+        assert!(!downloader.options().no_cache);
+        assert!(downloader.ctx.bandwidth.is_none());
+        assert_eq!(downloader.ctx.rate_limit.retry, RetryPolicy::default());
+        assert!(!downloader.options().quiet);
+
+        let downloader = Downloader::builder()
+            .token("secret")
+            .strategy(DownloadStrategy::Zip)
+            .parallel(0)
+            .cache(false)
+            .force(true)
+            .stall_timeout(None)
+            .limit_rate(Some(1024 * 1024))
+            .retry_policy(RetryPolicy {
+                retries: 0,
+                ..RetryPolicy::default()
+            })
+            .quiet(true)
+            .sanitize_names(true)
+            .low_speed_limit(Some(LowSpeedLimit {
+                bytes_per_sec: 1024,
+                time: Duration::from_secs(10),
+            }))
+            .build()
+            .unwrap();
+        assert_eq!(downloader.strategy, DownloadStrategy::Zip);
+        assert_eq!(downloader.ctx.parallel, 1);
+        assert_eq!(downloader.options().token, Some("secret"));
+        assert!(downloader.options().no_cache && downloader.options().force);
+        let limits = downloader.ctx.stream_limits(downloader.options());
+        assert_eq!(limits.stall_timeout, None);
+        assert_eq!(
+            limits.low_speed.map(|limit| limit.bytes_per_sec),
+            Some(1024)
+        );
+        assert!(limits.bandwidth.is_some());
+        assert_eq!(downloader.ctx.rate_limit.retry.retries, 0);
+        assert!(downloader.options().quiet && downloader.options().sanitize_names);
+
+        assert!(
+            Downloader::builder()
+                .rate_limit(Arc::default())
+                .retry_policy(RetryPolicy::default())
+                .build()
+                .is_err()
+        );
+    }
+}
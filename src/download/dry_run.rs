@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use crate::cli::DownloadStrategy;
+use crate::download::{
+    RestPlan, auto_strategy_order, describe_strategy, is_whole_repo, plan_via_rest,
+};
+use crate::error::Result;
+use crate::git::git_available;
+use crate::paths::format_path_for_log;
+use crate::progress::format_bytes;
+use crate::types::{DownloadContext, DownloadOptions, RequestInfo};
+
+/// Print what downloading `url` would write, and where, without writing anything.
+///
+/// Git and zip only learn the file list by transferring the repository, so every
+/// strategy is planned from the REST API listing; the files they write are the same.
+pub async fn print_dry_run(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    let plan = plan_via_rest(ctx, request, url, output, options).await?;
+    let strategy = match strategy {
+        DownloadStrategy::Auto => auto_strategy_order(git_available(), is_whole_repo(request))[0],
+        strategy => strategy,
+    };
+    print!("{}", render_plan(url, &plan, describe_strategy(strategy)));
+    Ok(())
+}
+
+/// One `SIZE  PATH -> TARGET` line per file, then the totals. Targets that already
+/// exist are marked, since a real run would need `--force` to replace them.
+fn render_plan(url: &str, plan: &RestPlan, strategy: &str) -> String {
+    let sizes: Vec<String> = plan
+        .tasks
+        .iter()
+        .map(|task| task.size.map(format_bytes).unwrap_or_else(|| "?".into()))
+        .collect();
+    let width = sizes.iter().map(String::len).max().unwrap_or(0);
+
+    let mut out = format!("Dry run for {} ({}):\n", url, strategy);
+    for (task, size) in plan.tasks.iter().zip(&sizes) {
+        out.push_str(&format!(
+            "  {:>width$}  {} -> {}{}\n",
+            size,
+            task.item.path,
+            format_path_for_log(&task.target_path),
+            if task.target_path.exists() {
+                " (exists)"
+            } else {
+                ""
+            }
+        ));
+    }
+    let total: u64 = plan.tasks.iter().filter_map(|task| task.size).sum();
+    out.push_str(&format!(
+        "Would write {} file(s), {}, to {}\n",
+        plan.tasks.len(),
+        format_bytes(total),
+        plan.target_display
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::types::GitHubContent;
+    use crate::types::DownloadTask;
+
+    #[test]
+    fn test_render_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("existing.txt"), "old").unwrap();
+        let task = |path: &str, size: Option<u64>| DownloadTask {
+            item: serde_json::from_value::<GitHubContent>(serde_json::json!({
+                "name": path, "path": path, "url": "", "type": "file"
+            }))
+            .unwrap(),
+            target_path: dir.path().join(path),
+            size,
+            link_target: None,
+            placeholder: None,
+        };
+        let plan = RestPlan {
+            output_dir: dir.path().to_path_buf(),
+            target_display: dir.path().display().to_string(),
+            tasks: vec![task("existing.txt", Some(2048)), task("new.txt", None)],
+        };
+
+        let rendered = render_plan("https://github.com/o/r", &plan, "REST API download");
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(
+            lines[0],
+            "Dry run for https://github.com/o/r (REST API download):"
+        );
+        assert!(lines[1].ends_with("existing.txt (exists)"));
+        assert!(lines[1].contains(&format_bytes(2048)));
+        assert!(lines[2].trim_start().starts_with("? "));
+        assert!(lines[2].ends_with("new.txt"));
+        assert_eq!(
+            lines[3],
+            format!(
+                "Would write 2 file(s), {}, to {}",
+                format_bytes(2048),
+                dir.path().display()
+            )
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use log::info;
+use serde::Serialize;
+
+use crate::cli::DownloadStrategy;
+use crate::error::{ErrorKind, GdlError};
+use crate::http::{is_stalled, is_too_slow};
+use crate::types::DownloadOptions;
+
+/// What happened to a strategy while handling one URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Skipped,
+    Attempted,
+    Failed,
+    Succeeded,
+}
+
+/// Version of the `--explain` output, described by `docs/explain.schema.json`.
+/// Adding a field keeps the version; renaming or removing one bumps it.
+pub const EXPLAIN_SCHEMA_VERSION: u32 = 1;
+
+/// One step of the strategy selection, emitted as a JSON line with `--explain`
+#[derive(Debug, Serialize)]
+pub struct Decision<'a> {
+    pub schema: u32,
+    pub url: &'a str,
+    pub strategy: &'static str,
+    pub action: Action,
+    /// Stable reason code, e.g. `git_missing`, `fallback`, `rate_limited`
+    pub reason: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl<'a> Decision<'a> {
+    pub fn new(
+        url: &'a str,
+        strategy: DownloadStrategy,
+        action: Action,
+        reason: &'static str,
+    ) -> Self {
+        Self {
+            schema: EXPLAIN_SCHEMA_VERSION,
+            url,
+            strategy: strategy_name(strategy),
+            action,
+            reason,
+            detail: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Record a failed attempt, classifying the error into a reason code
+    pub fn failure(url: &'a str, strategy: DownloadStrategy, err: &GdlError) -> Self {
+        Self::new(url, strategy, Action::Failed, classify_failure(err))
+            .with_detail(format!("{:#}", err))
+    }
+}
+
+/// Log a decision at info level (`-v`) and print it as JSON on stdout with `--explain`
+pub fn explain(options: &DownloadOptions<'_>, decision: Decision<'_>) {
+    if let Some(telemetry) = options.telemetry {
+        telemetry.decision(&decision);
+    }
+    info!(
+        "strategy {} {:?} for {}: {}{}",
+        decision.strategy,
+        decision.action,
+        decision.url,
+        decision.reason,
+        decision
+            .detail
+            .as_deref()
+            .map(|detail| format!(" ({})", detail))
+            .unwrap_or_default()
+    );
+    if options.explain
+        && let Ok(line) = serde_json::to_string(&decision)
+    {
+        println!("{}", line);
+    }
+}
+
+/// Reason code for the strategy `auto` tries first
+pub fn auto_order_reason(git_available: bool, whole_repo: bool) -> &'static str {
+    match (git_available, whole_repo) {
+        (true, _) => "git_available",
+        (false, true) => "whole_repository_prefers_zip",
+        (false, false) => "path_prefers_api",
+    }
+}
+
+pub(crate) fn strategy_name(strategy: DownloadStrategy) -> &'static str {
+    match strategy {
+        DownloadStrategy::Api => "api",
+        DownloadStrategy::Git => "git",
+        DownloadStrategy::Zip => "zip",
+        DownloadStrategy::Auto => "auto",
+    }
+}
+
+/// Map an error to a reason code. Failures of the `git` executable only carry their
+/// cause in its output, so an error the engine didn't recognise is then inspected as
+/// text.
+pub fn classify_failure(err: &GdlError) -> &'static str {
+    match err.cause() {
+        GdlError::EmptyDownload(_) => return "empty_result",
+        GdlError::Offline(_) => return "offline",
+        GdlError::GitMissing(_) => return "git_missing",
+        _ => {}
+    }
+    match err.kind() {
+        ErrorKind::RateLimited => return "rate_limited",
+        ErrorKind::NotFound => return "not_found",
+        ErrorKind::AuthRequired => return "unauthorized",
+        _ => {}
+    }
+    if is_stalled(err) {
+        return "stalled";
+    }
+    if is_too_slow(err) {
+        return "too_slow";
+    }
+
+    let text = format!("{:#}", err).to_lowercase();
+    if text.contains("rate limit") {
+        "rate_limited"
+    } else if text.contains("404") || text.contains("not found") {
+        "not_found"
+    } else if text.contains("401") || text.contains("403") {
+        "unauthorized"
+    } else {
+        "error"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{LowSpeedLimit, SlowStream, StalledStream};
+    use crate::types::{EmptyDownload, RequestInfo, RequestKind};
+    use anyhow::anyhow;
+    use std::time::Duration;
+
+    #[test]
+    fn test_classify_failure() {
+        let request = RequestInfo {
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            branch: "main".to_string(),
+            path: "docs".to_string(),
+            has_trailing_slash: false,
+            kind: RequestKind::Tree,
+        };
+        let cases = [
+            (
+                GdlError::from(EmptyDownload::for_request(&request)),
+                "empty_result",
+            ),
+            (
+                GdlError::from(StalledStream {
+                    idle: Duration::from_secs(30),
+                }),
+                "stalled",
+            ),
+            (
+                GdlError::from(SlowStream {
+                    received: 10,
+                    limit: LowSpeedLimit {
+                        bytes_per_sec: 1000,
+                        time: Duration::from_secs(30),
+                    },
+                }),
+                "too_slow",
+            ),
+            (
+                anyhow!(
+                    "GitHub request listing exceeded rate limit after 3 attempts (status 403 Forbidden)"
+                )
+                .into(),
+                "rate_limited",
+            ),
+            (
+                anyhow!("git fetch exited with status 128: remote: Repository not found").into(),
+                "not_found",
+            ),
+            (
+                anyhow!("GitHub request failed with status 401 Unauthorized").into(),
+                "unauthorized",
+            ),
+            (
+                GdlError::GitMissing("git executable not found in PATH".into()),
+                "git_missing",
+            ),
+            (
+                GdlError::Offline("offline: no cached zip archive of o/r:main".into())
+                    .context("zip download failed"),
+                "offline",
+            ),
+            (
+                GdlError::AuthRequired("Bad credentials".into()),
+                "unauthorized",
+            ),
+            (anyhow!("connection reset by peer").into(), "error"),
+        ];
+
+        for (err, expected) in cases {
+            assert_eq!(classify_failure(&err), expected, "{:#}", err);
+        }
+    }
+
+    #[test]
+    fn test_decision_json() {
+        let decision = Decision::new(
+            "https://github.com/o/r/tree/main/docs",
+            DownloadStrategy::Git,
+            Action::Skipped,
+            "git_missing",
+        );
+        assert_eq!(
+            serde_json::to_string(&decision).unwrap(),
+            r#"{"schema":1,"url":"https://github.com/o/r/tree/main/docs","strategy":"git","action":"skipped","reason":"git_missing"}"#
+        );
+
+        let decision = Decision::new("u", DownloadStrategy::Zip, Action::Attempted, "fallback")
+            .with_detail("after git");
+        assert!(
+            serde_json::to_string(&decision)
+                .unwrap()
+                .ends_with(r#""reason":"fallback","detail":"after git"}"#)
+        );
+
+        assert_eq!(auto_order_reason(true, true), "git_available");
+        assert_eq!(
+            auto_order_reason(false, true),
+            "whole_repository_prefers_zip"
+        );
+        assert_eq!(auto_order_reason(false, false), "path_prefers_api");
+    }
+
+    #[test]
+    fn test_decision_matches_schema() {
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../../docs/explain.schema.json")).unwrap();
+        assert_eq!(
+            schema["properties"]["schema"]["const"],
+            EXPLAIN_SCHEMA_VERSION
+        );
+        let properties = schema["properties"].as_object().unwrap();
+        let required: Vec<&str> = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|name| name.as_str().unwrap())
+            .collect();
+
+        let strategies = [
+            DownloadStrategy::Api,
+            DownloadStrategy::Git,
+            DownloadStrategy::Zip,
+            DownloadStrategy::Auto,
+        ];
+        let actions = [
+            Action::Skipped,
+            Action::Attempted,
+            Action::Failed,
+            Action::Succeeded,
+        ];
+        for (strategy, action) in strategies.into_iter().zip(actions) {
+            let decision = Decision::new("u", strategy, action, "fallback").with_detail("d");
+            let value = serde_json::to_value(&decision).unwrap();
+            let object = value.as_object().unwrap();
+
+            // Every emitted field is documented, and every required one is emitted
+            for (key, field) in object {
+                let property = properties
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{} missing", key));
+                if let Some(allowed) = property.get("enum") {
+                    assert!(allowed.as_array().unwrap().contains(field), "{}", field);
+                }
+            }
+            for name in &required {
+                assert!(object.contains_key(*name), "{} not emitted", name);
+            }
+        }
+    }
+}
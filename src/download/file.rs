@@ -1,34 +1,111 @@
-use std::io;
+use std::io::{self, SeekFrom};
 use std::path::Path;
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use anyhow::{Context, Result, anyhow};
-use futures::StreamExt;
+use anyhow::anyhow;
 use log::{debug, warn};
-use reqwest::header::{ACCEPT, AUTHORIZATION, RANGE};
-use reqwest::{Client, StatusCode};
+use reqwest::header::{ACCEPT, AUTHORIZATION, ETAG, IF_RANGE, RANGE};
+use reqwest::{Client, Response, StatusCode};
 use sha1::{Digest, Sha1};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
 
+use crate::cache::{
+    PartialDownload, load_partial_download, remove_partial_download, save_partial_download,
+};
+use crate::error::{Context, Result};
+use crate::github::api_base;
 use crate::github::types::GitHubContent;
-use crate::http::send_github_request;
+use crate::http::{
+    StreamLimits, StreamWatch, describe_delay, is_transient, next_chunk, send_github_request,
+};
+use crate::progress::{DownloadProgress, PROGRESS_STEP, format_bytes};
 use crate::rate_limit::RateLimitTracker;
+use crate::types::{DownloadContext, DownloadOptions, RequestInfo};
+use crate::utils::system_time_to_secs;
 
 pub async fn download_file(
-    client: &Client,
+    ctx: &DownloadContext,
     item: &GitHubContent,
-    token: Option<&str>,
     target_path: &Path,
-    rate_limit: &Arc<RateLimitTracker>,
-    no_cache: bool,
+    options: &DownloadOptions<'_>,
+    progress: &Mutex<DownloadProgress>,
+) -> Result<()> {
+    let policy = ctx.rate_limit.retry;
+    let mut attempt = 1;
+    loop {
+        let result = download_file_attempt(ctx, item, target_path, options, progress).await;
+        if result.is_err() {
+            progress.lock().await.forget_bytes(&item.path);
+        }
+
+        match result {
+            Err(err) if is_transient(&err) && attempt <= policy.retries => {
+                // Unless --no-cache is set, the retry resumes from the partial file
+                let delay = policy.delay(attempt);
+                warn!(
+                    "Download of {} failed ({}), retrying in {} ({}/{})...",
+                    item.path,
+                    err.root_cause(),
+                    describe_delay(delay),
+                    attempt,
+                    policy.retries
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Reports a stream's bytes to the progress every [`PROGRESS_STEP`]; the rest is
+/// counted when the file completes
+struct StreamedBytes<'a> {
+    progress: &'a Mutex<DownloadProgress>,
+    path: &'a str,
+    pending: u64,
+}
+
+impl<'a> StreamedBytes<'a> {
+    fn new(progress: &'a Mutex<DownloadProgress>, path: &'a str) -> Self {
+        Self {
+            progress,
+            path,
+            pending: 0,
+        }
+    }
+
+    async fn add(&mut self, bytes: usize) {
+        self.pending += bytes as u64;
+        if self.pending >= PROGRESS_STEP {
+            self.progress
+                .lock()
+                .await
+                .record_bytes(self.path, self.pending);
+            self.pending = 0;
+        }
+    }
+}
+
+async fn download_file_attempt(
+    ctx: &DownloadContext,
+    item: &GitHubContent,
+    target_path: &Path,
+    options: &DownloadOptions<'_>,
+    progress: &Mutex<DownloadProgress>,
 ) -> Result<()> {
+    let (client, rate_limit) = (&ctx.client, &ctx.rate_limit);
+    let (token, no_cache) = (options.token, options.no_cache);
+    let limits = ctx.stream_limits(options);
     let url = item.download_url.as_ref().unwrap_or(&item.url);
 
     // Check for partial download to resume
-    let (start_byte, partial_file) = if !no_cache {
-        check_partial_download(target_path, item.size).await?
+    let (start_byte, partial_file, resume_etag) = if !no_cache {
+        check_partial_download(target_path, url, item).await?
     } else {
-        (0, None)
+        (0, None, None)
     };
 
     let mut request_builder = if item.download_url.is_some() {
@@ -46,6 +123,10 @@ pub async fn download_file(
     // Add Range header for resume
     if start_byte > 0 {
         request_builder = request_builder.header(RANGE, format!("bytes={}-", start_byte));
+        // The server answers with the whole file if it changed since the partial was written
+        if let Some(etag) = &resume_etag {
+            request_builder = request_builder.header(IF_RANGE, etag.as_str());
+        }
         debug!(
             "Resuming download of {} from byte {}",
             item.path, start_byte
@@ -89,22 +170,33 @@ pub async fn download_file(
         let response = send_github_request(&fresh_request, rate_limit, &context)
             .await
             .with_context(|| format!("failed to download {}", item.path))?;
+        if !no_cache {
+            record_partial_download(target_path, url, item, 0, &response);
+        }
 
         let mut file = tokio::fs::File::create(target_path)
             .await
             .with_context(|| format!("failed to create file {}", target_path.display()))?;
         let mut stream = response.bytes_stream();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.with_context(|| format!("failed to read data for {}", item.path))?;
+        let mut watch = StreamWatch::new(&limits);
+        let mut streamed = StreamedBytes::new(progress, &item.path);
+        while let Some(chunk) = next_chunk(&mut stream, &mut watch)
+            .await
+            .with_context(|| format!("failed to read data for {}", item.path))?
+        {
             file.write_all(&chunk)
                 .await
                 .with_context(|| format!("failed to write content to {}", target_path.display()))?;
+            streamed.add(chunk.len()).await;
         }
         file.flush().await.with_context(|| {
             format!("failed to flush downloaded file {}", target_path.display())
         })?;
     } else {
+        if !no_cache {
+            record_partial_download(target_path, url, item, start_byte, &response);
+        }
+
         // Use existing file handle or create new one
         let mut file = if let Some(pf) = partial_file {
             pf
@@ -116,17 +208,27 @@ pub async fn download_file(
 
         let mut stream = response.bytes_stream();
 
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.with_context(|| format!("failed to read data for {}", item.path))?;
+        let mut watch = StreamWatch::new(&limits);
+        let mut streamed = StreamedBytes::new(progress, &item.path);
+        while let Some(chunk) = next_chunk(&mut stream, &mut watch)
+            .await
+            .with_context(|| format!("failed to read data for {}", item.path))?
+        {
             file.write_all(&chunk)
                 .await
                 .with_context(|| format!("failed to write content to {}", target_path.display()))?;
+            streamed.add(chunk.len()).await;
         }
         file.flush().await.with_context(|| {
             format!("failed to flush downloaded file {}", target_path.display())
         })?;
     }
 
+    // The file is complete; whatever the hash check says, it is no longer resumable
+    if !no_cache && let Err(err) = remove_partial_download(target_path) {
+        debug!("{:#}", err);
+    }
+
     // Verify file hash if available
     if let Some(ref expected_sha) = item.sha {
         debug!("Verifying hash for {}", item.path);
@@ -135,7 +237,8 @@ pub async fn download_file(
             return Err(anyhow!(
                 "Hash verification failed for {}: file may be corrupted",
                 item.path
-            ));
+            )
+            .into());
         }
         debug!("Hash verification passed for {}", item.path);
     }
@@ -143,7 +246,275 @@ pub async fn download_file(
     Ok(())
 }
 
-fn calculate_git_blob_sha1(content: &[u8]) -> String {
+/// Split `total` bytes into at most `count` contiguous inclusive ranges
+pub fn chunk_ranges(total: u64, count: usize) -> Vec<(u64, u64)> {
+    let count = (count.max(1) as u64).min(total.max(1));
+    let chunk = total.div_ceil(count);
+    (0..count)
+        .map(|index| index * chunk)
+        .take_while(|&start| start < total)
+        .map(|start| (start, (start + chunk).min(total) - 1))
+        .collect()
+}
+
+/// Download a large file as several concurrent `Range` requests, each writing its own
+/// region of a preallocated file. Fails without falling back when the server ignores
+/// ranges, so the caller can retry with a single stream.
+pub async fn download_file_chunked(
+    ctx: &DownloadContext,
+    item: &GitHubContent,
+    target_path: &Path,
+    options: &DownloadOptions<'_>,
+    progress: &Arc<Mutex<DownloadProgress>>,
+) -> Result<()> {
+    let total = item
+        .size
+        .ok_or_else(|| anyhow!("size of {} is unknown", item.path))?;
+    let url = item.download_url.as_ref().unwrap_or(&item.url);
+    let ranges = chunk_ranges(total, options.chunks);
+
+    if !options.no_cache
+        && let Err(err) = remove_partial_download(target_path)
+    {
+        debug!("{:#}", err);
+    }
+    let file = tokio::fs::File::create(target_path)
+        .await
+        .with_context(|| format!("failed to create file {}", target_path.display()))?;
+    file.set_len(total)
+        .await
+        .with_context(|| format!("failed to preallocate {}", target_path.display()))?;
+    drop(file);
+
+    debug!(
+        "Downloading {} in {} chunks of up to {}",
+        item.path,
+        ranges.len(),
+        format_bytes(ranges[0].1 - ranges[0].0 + 1)
+    );
+    let count = ranges.len();
+    let result = futures::future::try_join_all(ranges.into_iter().enumerate().map(
+        |(index, range)| async move {
+            let policy = ctx.rate_limit.retry;
+            let mut attempt = 1;
+            loop {
+                match download_range(ctx, item, url, target_path, range, options).await {
+                    Err(err) if is_transient(&err) && attempt <= policy.retries => {
+                        let delay = policy.delay(attempt);
+                        warn!(
+                            "Chunk {}/{} of {} failed ({}), retrying in {} ({}/{})...",
+                            index + 1,
+                            count,
+                            item.path,
+                            err.root_cause(),
+                            describe_delay(delay),
+                            attempt,
+                            policy.retries
+                        );
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    Err(err) => return Err(err),
+                    Ok(()) => break,
+                }
+            }
+            progress
+                .lock()
+                .await
+                .record_chunk(&item.path, index, count, range.1 - range.0 + 1);
+            Ok(())
+        },
+    ))
+    .await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_file(target_path).await;
+        return Err(err);
+    }
+
+    if let Some(expected_sha) = item.sha.as_deref()
+        && !verify_file_hash(target_path, expected_sha).await?
+    {
+        let _ = tokio::fs::remove_file(target_path).await;
+        return Err(anyhow!(
+            "Hash verification failed for {}: chunks may be corrupted",
+            item.path
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Fetch one inclusive byte range of `url` into the same region of `target_path`
+async fn download_range(
+    ctx: &DownloadContext,
+    item: &GitHubContent,
+    url: &str,
+    target_path: &Path,
+    (start, end): (u64, u64),
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    let (client, rate_limit) = (&ctx.client, &ctx.rate_limit);
+    let mut request_builder = client
+        .get(url)
+        .header(RANGE, format!("bytes={}-{}", start, end));
+    if item.download_url.is_none() {
+        request_builder = request_builder.header(ACCEPT, "application/vnd.github.v3.raw");
+    }
+    if let Some(token) = options.token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    let context = format!("downloading bytes {}-{} of {}", start, end, item.path);
+    let response = send_github_request(&request_builder, rate_limit, &context)
+        .await
+        .with_context(|| format!("failed to download {}", item.path))?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(anyhow!(
+            "server ignored the range request for {} (status {})",
+            item.path,
+            response.status()
+        )
+        .into());
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(target_path)
+        .await
+        .with_context(|| format!("failed to open {}", target_path.display()))?;
+    file.seek(SeekFrom::Start(start))
+        .await
+        .with_context(|| format!("failed to seek in {}", target_path.display()))?;
+
+    let expected = end - start + 1;
+    let mut written = 0u64;
+    let mut stream = response.bytes_stream();
+    let mut watch = StreamWatch::new(&ctx.stream_limits(options));
+    while let Some(chunk) = next_chunk(&mut stream, &mut watch)
+        .await
+        .with_context(|| format!("failed to read data for {}", item.path))?
+    {
+        written += chunk.len() as u64;
+        if written > expected {
+            return Err(anyhow!(
+                "server sent more than the requested range for {}",
+                item.path
+            )
+            .into());
+        }
+        file.write_all(&chunk)
+            .await
+            .with_context(|| format!("failed to write content to {}", target_path.display()))?;
+    }
+    file.flush()
+        .await
+        .with_context(|| format!("failed to flush downloaded file {}", target_path.display()))?;
+
+    if written != expected {
+        return Err(anyhow!(
+            "range {}-{} of {} ended after {} of {} bytes",
+            start,
+            end,
+            item.path,
+            written,
+            expected
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Request `item`'s raw content from the git blob API, returning its SHA and the response
+async fn request_blob<'a>(
+    client: &Client,
+    request: &RequestInfo,
+    item: &'a GitHubContent,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+) -> Result<(&'a str, Response)> {
+    let sha = item
+        .sha
+        .as_deref()
+        .ok_or_else(|| anyhow!("no blob SHA known for {}", item.path))?;
+    let url = format!(
+        "{}/repos/{}/{}/git/blobs/{}",
+        api_base(),
+        request.owner,
+        request.repo,
+        sha
+    );
+    let mut request_builder = client
+        .get(&url)
+        .header(ACCEPT, "application/vnd.github.raw");
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    let context = format!("downloading blob {} for {}", sha, item.path);
+    let response = send_github_request(&request_builder, rate_limit, &context)
+        .await
+        .with_context(|| format!("failed to download {} via the blob API", item.path))?;
+    Ok((sha, response))
+}
+
+/// Read `item`'s content into memory through the git blob API (the overwrite prompt's diff)
+pub async fn fetch_blob_content(
+    client: &Client,
+    request: &RequestInfo,
+    item: &GitHubContent,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+) -> Result<Vec<u8>> {
+    let (_, response) = request_blob(client, request, item, token, rate_limit).await?;
+    let content = response
+        .bytes()
+        .await
+        .with_context(|| format!("failed to read blob data for {}", item.path))?;
+    Ok(content.to_vec())
+}
+
+/// Download a file's content through the git blob API, an alternative to the raw
+/// download URL when that fails (e.g. CDN lag right after a push)
+pub async fn download_blob(
+    client: &Client,
+    request: &RequestInfo,
+    item: &GitHubContent,
+    token: Option<&str>,
+    target_path: &Path,
+    rate_limit: &Arc<RateLimitTracker>,
+    limits: &StreamLimits,
+) -> Result<()> {
+    let (sha, response) = request_blob(client, request, item, token, rate_limit).await?;
+    let mut file = tokio::fs::File::create(target_path)
+        .await
+        .with_context(|| format!("failed to create file {}", target_path.display()))?;
+    let mut stream = response.bytes_stream();
+    let mut watch = StreamWatch::new(limits);
+    while let Some(chunk) = next_chunk(&mut stream, &mut watch)
+        .await
+        .with_context(|| format!("failed to read blob data for {}", item.path))?
+    {
+        file.write_all(&chunk)
+            .await
+            .with_context(|| format!("failed to write content to {}", target_path.display()))?;
+    }
+    file.flush()
+        .await
+        .with_context(|| format!("failed to flush downloaded file {}", target_path.display()))?;
+
+    if !verify_file_hash(target_path, sha).await? {
+        let _ = tokio::fs::remove_file(target_path).await;
+        return Err(anyhow!(
+            "Hash verification failed for {} downloaded via the blob API",
+            item.path
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn calculate_git_blob_sha1(content: &[u8]) -> String {
     let mut hasher = Sha1::new();
     let header = format!("blob {}\0", content.len());
     hasher.update(header.as_bytes());
@@ -151,7 +522,7 @@ fn calculate_git_blob_sha1(content: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
-async fn verify_file_hash(path: &Path, expected_sha: &str) -> Result<bool> {
+pub async fn verify_file_hash(path: &Path, expected_sha: &str) -> Result<bool> {
     let content = tokio::fs::read(path).await.with_context(|| {
         format!(
             "failed to read file {} for hash verification",
@@ -163,54 +534,60 @@ async fn verify_file_hash(path: &Path, expected_sha: &str) -> Result<bool> {
     Ok(calculated_sha == expected_sha)
 }
 
-async fn check_partial_download(
+/// Remember which download a partial file belongs to, so a later run resumes it only
+/// if the URL, expected content, and size still match
+fn record_partial_download(
     target_path: &Path,
-    expected_size: Option<u64>,
-) -> Result<(u64, Option<tokio::fs::File>)> {
-    // Check if file already exists
-    match tokio::fs::metadata(target_path).await {
-        Ok(metadata) => {
-            if metadata.is_file() {
-                let size = metadata.len();
-
-                // If we know the expected size, validate the partial file
-                if let Some(expected) = expected_size {
-                    if size >= expected {
-                        // File is complete or larger than expected, delete and start fresh
-                        debug!(
-                            "Existing file at {} is complete or larger than expected ({} >= {}), replacing",
-                            target_path.display(),
-                            size,
-                            expected
-                        );
-                        let _ = tokio::fs::remove_file(target_path).await;
-                        return Ok((0, None));
-                    }
-                }
+    url: &str,
+    item: &GitHubContent,
+    downloaded_bytes: u64,
+    response: &Response,
+) {
+    let record = PartialDownload {
+        url: url.to_string(),
+        path: target_path.to_path_buf(),
+        downloaded_bytes,
+        total_bytes: item.size,
+        sha: item.sha.clone(),
+        etag: response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        timestamp: system_time_to_secs(SystemTime::now()),
+    };
+    if let Err(err) = save_partial_download(&record) {
+        debug!(
+            "Unable to record partial download of {}: {:#}",
+            item.path, err
+        );
+    }
+}
 
-                if size > 0 {
-                    // Open file in append mode for resume
-                    let file = tokio::fs::OpenOptions::new()
-                        .write(true)
-                        .append(true)
-                        .open(target_path)
-                        .await
-                        .with_context(|| {
-                            format!("failed to open partial file {}", target_path.display())
-                        })?;
-
-                    debug!(
-                        "Found partial download at {}, {} bytes",
-                        target_path.display(),
-                        size
-                    );
-                    return Ok((size, Some(file)));
-                }
-            }
-        }
+/// Whether a partial file of `size` bytes can be resumed according to its record
+fn can_resume(record: &PartialDownload, url: &str, item: &GitHubContent, size: u64) -> bool {
+    record.url == url
+        && record.sha == item.sha
+        && record.total_bytes == item.size
+        // The partial file only grows; a shorter one was truncated or replaced
+        && size >= record.downloaded_bytes
+        && item.size.is_none_or(|expected| size < expected)
+}
+
+/// Find a resumable partial file at `target_path`. Files without a matching record in
+/// the downloads cache (a previous complete download, another ref's content, or a
+/// file written by someone else) are replaced instead of appended to.
+async fn check_partial_download(
+    target_path: &Path,
+    url: &str,
+    item: &GitHubContent,
+) -> Result<(u64, Option<tokio::fs::File>, Option<String>)> {
+    let size = match tokio::fs::metadata(target_path).await {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        Ok(_) => return Ok((0, None, None)),
         Err(e) if e.kind() == io::ErrorKind::NotFound => {
             // File doesn't exist, start fresh
-            return Ok((0, None));
+            return Ok((0, None, None));
         }
         Err(e) => {
             return Err(e).with_context(|| {
@@ -220,7 +597,89 @@ async fn check_partial_download(
                 )
             });
         }
+    };
+
+    let record = match load_partial_download(target_path) {
+        Ok(record) => record,
+        Err(err) => {
+            debug!("{:#}", err);
+            None
+        }
+    };
+    let Some(record) = record.filter(|record| size > 0 && can_resume(record, url, item, size))
+    else {
+        debug!(
+            "Existing file at {} is not a resumable partial download, replacing",
+            target_path.display()
+        );
+        return Ok((0, None, None));
+    };
+
+    // Open file in append mode for resume
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .append(true)
+        .open(target_path)
+        .await
+        .with_context(|| format!("failed to open partial file {}", target_path.display()))?;
+
+    debug!(
+        "Found partial download at {}, {} bytes",
+        target_path.display(),
+        size
+    );
+    Ok((size, Some(file), record.etag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::types::ContentType;
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert_eq!(
+            chunk_ranges(100, 4),
+            vec![(0, 24), (25, 49), (50, 74), (75, 99)]
+        );
+        assert_eq!(chunk_ranges(10, 3), vec![(0, 3), (4, 7), (8, 9)]);
+        // Never more chunks than bytes, and at least one
+        assert_eq!(chunk_ranges(2, 8), vec![(0, 0), (1, 1)]);
+        assert_eq!(chunk_ranges(5, 0), vec![(0, 4)]);
     }
 
-    Ok((0, None))
+    #[test]
+    fn test_can_resume() {
+        let url = "https://raw.githubusercontent.com/o/r/main/file.bin";
+        let item = GitHubContent {
+            name: "file.bin".to_string(),
+            path: "file.bin".to_string(),
+            url: url.to_string(),
+            size: Some(100),
+            download_url: Some(url.to_string()),
+            content_type: ContentType::File,
+            sha: Some("ce013625030ba8dba906f756967f9e9ca394464a".to_string()),
+        };
+        let record = PartialDownload {
+            url: url.to_string(),
+            path: "file.bin".into(),
+            downloaded_bytes: 40,
+            total_bytes: Some(100),
+            sha: item.sha.clone(),
+            etag: None,
+            timestamp: 0,
+        };
+
+        assert!(can_resume(&record, url, &item, 40));
+        assert!(can_resume(&record, url, &item, 99));
+        // Complete, truncated, or from another URL or blob: start over
+        assert!(!can_resume(&record, url, &item, 100));
+        assert!(!can_resume(&record, url, &item, 10));
+        assert!(!can_resume(&record, "https://example.com/other", &item, 50));
+        let changed = PartialDownload {
+            sha: Some("0000000000000000000000000000000000000000".to_string()),
+            ..record.clone()
+        };
+        assert!(!can_resume(&changed, url, &item, 50));
+    }
 }
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::download::Downloader;
+use crate::error::{Context, Result};
+use crate::github::{fetch_blob_shas, fetch_commit_sha, parse_github_url};
+use crate::http::CachePolicy;
+use crate::types::RequestKind;
+
+/// Lockfile written by `--write-lock` and checked by `--locked` when no path is given
+pub const DEFAULT_LOCKFILE: &str = "gdl.lock";
+
+const LOCKFILE_VERSION: u32 = 1;
+
+/// Mismatched files named in an error before the rest are only counted
+const MAX_LISTED: usize = 5;
+
+/// The commits and blobs a set of URLs resolved to, for reproducible downloads
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    pub sources: Vec<LockedSource>,
+}
+
+/// What one URL resolved to: the commit of its ref and the blob SHA of every
+/// regular file below its path, keyed by repository path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedSource {
+    pub url: String,
+    pub commit: String,
+    pub files: BTreeMap<String, String>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            sources: Vec::new(),
+        }
+    }
+}
+
+impl Lockfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("failed to read lockfile {}", path.display()))?;
+        let lockfile: Lockfile = serde_json::from_slice(&data)
+            .with_context(|| format!("failed to parse lockfile {}", path.display()))?;
+        if lockfile.version != LOCKFILE_VERSION {
+            return Err(anyhow!(
+                "lockfile {} has unsupported version {}",
+                path.display(),
+                lockfile.version
+            )
+            .into());
+        }
+        Ok(lockfile)
+    }
+
+    /// Write the lockfile, keeping the entries of URLs this run did not download
+    pub fn update(path: &Path, sources: Vec<LockedSource>) -> Result<()> {
+        let mut lockfile = if path.exists() {
+            Self::load(path)?
+        } else {
+            Self::default()
+        };
+        for source in sources {
+            match lockfile
+                .sources
+                .iter_mut()
+                .find(|locked| locked.url == source.url)
+            {
+                Some(locked) => *locked = source,
+                None => lockfile.sources.push(source),
+            }
+        }
+        lockfile.save(path)
+    }
+
+    /// Write the lockfile with its sources sorted by URL
+    pub fn save(mut self, path: &Path) -> Result<()> {
+        self.sources.sort_by(|a, b| a.url.cmp(&b.url));
+        let mut data = serde_json::to_string_pretty(&self).context("failed to encode lockfile")?;
+        data.push('\n');
+        std::fs::write(path, data)
+            .with_context(|| format!("failed to write lockfile {}", path.display()))
+    }
+
+    /// Fail unless every source is recorded with the same commit and files
+    pub fn check(&self, path: &Path, sources: &[LockedSource]) -> Result<()> {
+        for source in sources {
+            let locked = self
+                .sources
+                .iter()
+                .find(|locked| locked.url == source.url)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "{} is not in lockfile {}; record it with --write-lock",
+                        source.url,
+                        path.display()
+                    )
+                })?;
+            if locked.commit != source.commit {
+                return Err(anyhow!(
+                    "{} now resolves to commit {}, but {} locks it at {}; update the lockfile with --write-lock",
+                    source.url,
+                    source.commit,
+                    path.display(),
+                    locked.commit
+                ).into());
+            }
+            let changed = changed_files(&locked.files, &source.files);
+            if !changed.is_empty() {
+                let mut listed: Vec<&str> = changed.iter().take(MAX_LISTED).copied().collect();
+                if changed.len() > MAX_LISTED {
+                    listed.push("...");
+                }
+                return Err(anyhow!(
+                    "{} file(s) of {} differ from lockfile {}: {}",
+                    changed.len(),
+                    source.url,
+                    path.display(),
+                    listed.join(", ")
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Paths that are missing on either side or have a different blob SHA
+fn changed_files<'a>(
+    locked: &'a BTreeMap<String, String>,
+    current: &'a BTreeMap<String, String>,
+) -> Vec<&'a str> {
+    let mut changed: Vec<&str> = locked
+        .iter()
+        .filter(|(path, sha)| current.get(*path) != Some(sha))
+        .map(|(path, _)| path.as_str())
+        .chain(
+            current
+                .keys()
+                .filter(|path| !locked.contains_key(*path))
+                .map(String::as_str),
+        )
+        .collect();
+    changed.sort_unstable();
+    changed
+}
+
+/// Resolve each URL's ref to a commit and list the blobs below its path at that
+/// commit. Costs up to two API requests per URL; the ref is always revalidated.
+pub async fn resolve_sources(
+    downloader: &Downloader<'_>,
+    urls: &[String],
+) -> Result<Vec<LockedSource>> {
+    let options = downloader.options();
+    let cache = CachePolicy {
+        ttl: Duration::ZERO,
+        ..options.cache_policy()
+    };
+    let mut sources = Vec::with_capacity(urls.len());
+    for url in urls {
+        let mut request = parse_github_url(url)?;
+        let commit = downloader
+            .endpoints()
+            .scope(fetch_commit_sha(
+                downloader.client(),
+                &request,
+                options.token,
+                downloader.rate_limit(),
+                cache,
+            ))
+            .await
+            .with_context(|| format!("failed to resolve the commit of {}", url))?;
+
+        // A tree listed by commit SHA never changes, so a cached one is fine
+        request.branch = commit.clone();
+        let files = downloader
+            .endpoints()
+            .scope(fetch_blob_shas(
+                downloader.client(),
+                &request,
+                options.token,
+                Arc::clone(downloader.rate_limit()),
+                options.cache_policy(),
+            ))
+            .await
+            .with_context(|| format!("failed to list the files of {}", url))?
+            .into_iter()
+            // A file URL is listed through its directory
+            .filter(|(path, _)| request.kind == RequestKind::Tree || *path == request.path)
+            .collect();
+
+        sources.push(LockedSource {
+            url: url.clone(),
+            commit,
+            files,
+        });
+    }
+    Ok(sources)
+}
+
+/// Fail when a ref moved while its files were downloading, since the files may
+/// then come from either commit
+pub async fn ensure_unmoved(downloader: &Downloader<'_>, sources: &[LockedSource]) -> Result<()> {
+    let options = downloader.options();
+    let cache = CachePolicy {
+        ttl: Duration::ZERO,
+        ..options.cache_policy()
+    };
+    for source in sources {
+        let request = parse_github_url(&source.url)?;
+        let commit = downloader
+            .endpoints()
+            .scope(fetch_commit_sha(
+                downloader.client(),
+                &request,
+                options.token,
+                downloader.rate_limit(),
+                cache,
+            ))
+            .await?;
+        if commit != source.commit {
+            return Err(anyhow!(
+                "{} moved from commit {} to {} during the download; run ghdl again",
+                source.url,
+                source.commit,
+                commit
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(url: &str, commit: &str, files: &[(&str, &str)]) -> LockedSource {
+        LockedSource {
+            url: url.to_string(),
+            commit: commit.to_string(),
+            files: files
+                .iter()
+                .map(|(path, sha)| (path.to_string(), sha.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_lockfile_update_and_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DEFAULT_LOCKFILE);
+        let docs = source(
+            "https://github.com/o/r/tree/main/docs",
+            "c1",
+            &[("docs/a", "1")],
+        );
+        let src = source(
+            "https://github.com/o/r/tree/main/src",
+            "c1",
+            &[("src/b", "2")],
+        );
+        Lockfile::update(&path, vec![src.clone(), docs.clone()]).unwrap();
+
+        // Updating one URL keeps the other
+        let docs_v2 = source(
+            "https://github.com/o/r/tree/main/docs",
+            "c2",
+            &[("docs/a", "3"), ("docs/new", "4")],
+        );
+        Lockfile::update(&path, vec![docs_v2.clone()]).unwrap();
+        let lockfile = Lockfile::load(&path).unwrap();
+        assert_eq!(lockfile.sources, vec![docs_v2.clone(), src.clone()]);
+
+        assert!(lockfile.check(&path, &[docs_v2, src]).is_ok());
+        let err = lockfile.check(&path, &[docs]).unwrap_err();
+        assert!(err.to_string().contains("locks it at c2"), "{}", err);
+
+        let tampered = source(
+            "https://github.com/o/r/tree/main/docs",
+            "c2",
+            &[("docs/a", "5"), ("docs/new", "4"), ("docs/extra", "6")],
+        );
+        let err = lockfile.check(&path, &[tampered]).unwrap_err();
+        assert!(err.to_string().ends_with(": docs/a, docs/extra"), "{}", err);
+
+        let unknown = source("https://github.com/o/other", "c1", &[]);
+        assert!(lockfile.check(&path, &[unknown]).is_err());
+    }
+}
@@ -1,25 +1,52 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::anyhow;
+use console::style;
 use futures::stream::{self, StreamExt, TryStreamExt};
-use log::{info, warn};
-use reqwest::Client;
+use log::{debug, info, warn};
 use tokio::sync::Mutex;
 
+use crate::cache::{remove_blob, restore_blob, store_blob};
 use crate::cli::DownloadStrategy;
-use crate::download::{collect_download_tasks, download_file};
-use crate::git::{download_via_git, ensure_git_available, git_available};
+use crate::download::dry_run::print_dry_run;
+use crate::download::explain::{Action, Decision, auto_order_reason, explain, strategy_name};
+use crate::download::mtime::apply_commit_mtime;
+use crate::download::preflight::verify_ref;
+use crate::download::provenance::{Provenance, resolve_commit};
+use crate::download::signature::verify_tag_signature;
+use crate::download::sync::{mirror_output, skip_unchanged};
+use crate::download::{
+    collect_download_tasks, download_blob, download_file, download_file_chunked,
+    fetch_blob_content, verify_file_hash,
+};
+use crate::error::{Context, GdlError, Result};
+use crate::filter::select_matching;
+use crate::git::{
+    GitTarget, download_many_via_git, download_via_git, download_via_local_repo,
+    ensure_git_available, git_available,
+};
+use crate::github::types::GitHubContent;
 use crate::github::{
-    build_file_inventory, fetch_github_contents, fetch_repository_info, parse_github_url,
+    build_file_inventory, fetch_default_branch, fetch_github_contents, parse_github_url,
+};
+use crate::lfs::fetch_lfs_objects;
+use crate::overwrite::resolve_conflicts;
+use crate::paths::{
+    compute_base_and_default_output, describe_download_target, determine_paths,
+    disambiguate_targets, ensure_directory, output_root,
 };
-use crate::overwrite::{check_overwrite_permission, collect_target_paths};
-use crate::paths::{describe_download_target, determine_paths, ensure_directory};
-use crate::progress::{DownloadProgress, format_bytes};
-use crate::rate_limit::RateLimitTracker;
-use crate::types::{DownloadContext, DownloadOptions, DownloadTask, RequestInfo};
-use crate::zip::download_via_zip;
+use crate::progress::{DownloadProgress, format_bytes, status};
+use crate::shard::{report_empty_shard, select_shard};
+use crate::tree::print_tree;
+use crate::types::{
+    DownloadContext, DownloadOptions, DownloadTask, EmptyDownload, RequestInfo, RequestKind,
+    is_empty_download,
+};
+use crate::utils::{create_symlink, remove_symlinks_below};
+use crate::zip::{download_many_via_zip, download_via_zip, extract_file_from_cached_zip};
 
 pub async fn download_github_path(
     ctx: &DownloadContext,
@@ -28,150 +55,373 @@ pub async fn download_github_path(
     strategy: DownloadStrategy,
     options: &DownloadOptions<'_>,
 ) -> Result<()> {
-    let mut request = parse_github_url(url)?;
+    let request = parse_github_url(url)?;
     log::debug!("Parsed request info: {:?}", request);
 
+    let result = match options.local_repo {
+        // A local clone needs no network access; refs resolve against the clone itself
+        Some(local_repo) => {
+            match download_via_local_repo(ctx, &request, url, output, local_repo, options).await {
+                Ok((branch, commit)) => {
+                    report_tree(&request, output, options);
+                    let request = RequestInfo { branch, ..request };
+                    record_provenance(&request, url, output, Some(commit), "local", options)
+                }
+                Err(err) => Err(err),
+            }
+        }
+        None => download_remote(ctx, request, url, output, strategy, options).await,
+    };
+
+    settle(result, options)
+}
+
+/// Download every URL, up to `ctx.jobs` at once. URLs naming the same repository and
+/// ref share a single git checkout (configured with all of their paths) when git is
+/// the strategy to use, or a single zip archive with the zip strategy.
+pub async fn download_github_paths(
+    ctx: &DownloadContext,
+    urls: &[String],
+    output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    let shares_source = match strategy {
+        DownloadStrategy::Git => true,
+        // A streamed archive is read once per URL and never saved to share
+        DownloadStrategy::Zip => !options.stream_archive,
+        DownloadStrategy::Auto => git_available(),
+        DownloadStrategy::Api => false,
+    };
+    if options.keep_going && urls.len() > 1 {
+        return download_each(ctx, urls, output, strategy, options).await;
+    }
+    if urls.len() < 2 || options.local_repo.is_some() || options.dry_run || !shares_source {
+        // Dry-run listings are printed whole, one URL after the other
+        let jobs = if options.dry_run { 1 } else { ctx.jobs };
+        return stream::iter(urls.iter().map(Ok))
+            .try_for_each_concurrent(jobs, |url| {
+                download_github_path(ctx, url, output, strategy, options)
+            })
+            .await;
+    }
+
+    let mut targets = Vec::with_capacity(urls.len());
+    for url in urls {
+        let request = parse_github_url(url)?;
+        match prepare_remote_request(ctx, request, strategy, options).await {
+            Ok(request) => targets.push(GitTarget {
+                request,
+                url: url.clone(),
+            }),
+            Err(err) => settle(Err(err), options)?,
+        }
+    }
+
+    stream::iter(group_by_checkout(targets).into_iter().map(Ok))
+        .try_for_each_concurrent(ctx.jobs, |group| {
+            download_checkout_group(ctx, group, output, strategy, options)
+        })
+        .await
+}
+
+/// Download every URL on its own, even after some fail (`--keep-going`), then
+/// summarize. Fails with the first failure once all URLs have been tried.
+async fn download_each(
+    ctx: &DownloadContext,
+    urls: &[String],
+    output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    let jobs = if options.dry_run { 1 } else { ctx.jobs };
+    let outcomes: Vec<(&String, Result<()>)> = stream::iter(urls)
+        .map(|url| async move {
+            let result = download_github_path(ctx, url, output, strategy, options).await;
+            // The error itself is listed in the summary
+            if result.is_err() {
+                warn!("{} failed; continuing with the other URLs", url);
+            }
+            (url, result)
+        })
+        .buffered(jobs)
+        .collect()
+        .await;
+
+    let failed = outcomes
+        .iter()
+        .filter(|(_, result)| result.is_err())
+        .count();
+    status(
+        options.quiet,
+        format!(
+            "\n{} {} of {} URL(s) downloaded",
+            if failed == 0 {
+                style("✓").green().bold()
+            } else {
+                style("✗").red().bold()
+            },
+            outcomes.len() - failed,
+            outcomes.len()
+        ),
+    );
+    let mut first_err = None;
+    for (url, result) in outcomes {
+        if let Err(err) = result {
+            status(
+                options.quiet,
+                format!("  {} {}: {:#}", style("✗").red(), url, err),
+            );
+            first_err.get_or_insert(err);
+        }
+    }
+    let summary = format!("{} of {} URL(s) failed", failed, urls.len());
+    match first_err {
+        // When every URL failed, the first failure names the exit status
+        Some(err) if failed == urls.len() => Err(err.context(summary)),
+        Some(err) => Err(err.context(GdlError::PartialFailure(summary))),
+        None => Ok(()),
+    }
+}
+
+/// Download the targets sharing one repository and ref with a single checkout (or zip
+/// archive), falling back per target under `auto` when the checkout fails
+async fn download_checkout_group(
+    ctx: &DownloadContext,
+    group: Vec<GitTarget>,
+    output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    if group.len() == 1 {
+        let target = &group[0];
+        let result =
+            run_prepared(ctx, &target.request, &target.url, output, strategy, options).await;
+        settle(result, options)?;
+        return Ok(());
+    }
+
+    // Auto only groups targets when git is available
+    let shared = match strategy {
+        DownloadStrategy::Zip => DownloadStrategy::Zip,
+        _ => DownloadStrategy::Git,
+    };
+    let source = match shared {
+        DownloadStrategy::Zip => "zip archive",
+        _ => "git checkout",
+    };
+    let first = &group[0].request;
+    info!(
+        "Sharing one {} of {}/{}:{} for {} paths",
+        source,
+        first.owner,
+        first.repo,
+        first.branch,
+        group.len()
+    );
+    for target in &group {
+        explain(
+            options,
+            Decision::new(&target.url, shared, Action::Attempted, "shared_checkout")
+                .with_detail(format!("{} paths share one {}", group.len(), source)),
+        );
+    }
+    let results = match shared {
+        DownloadStrategy::Zip => download_many_via_zip(ctx, group.clone(), output, options).await,
+        _ => download_many_via_git(ctx, group.clone(), output, options).await,
+    };
+    match results {
+        Ok(results) => {
+            for (target, result) in group.iter().zip(results) {
+                explain_outcome(options, &target.url, shared, &result);
+                let result = match result {
+                    Ok(()) => {
+                        finish_download(ctx, &target.request, &target.url, output, shared, options)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                };
+                settle(result, options)?;
+            }
+        }
+        Err(err) if strategy == DownloadStrategy::Auto => {
+            warn!(
+                "{} failed ({}); falling back for each path...",
+                describe_strategy(DownloadStrategy::Git),
+                err
+            );
+            for target in &group {
+                explain(
+                    options,
+                    Decision::failure(&target.url, DownloadStrategy::Git, &err),
+                );
+                // Git already failed for this checkout; go straight to the others
+                let order = auto_strategy_order(false, is_whole_repo(&target.request));
+                let result = run_with_fallback(
+                    ctx,
+                    &target.request,
+                    &target.url,
+                    output,
+                    &order,
+                    "fallback",
+                    options,
+                )
+                .await;
+                let result = match result {
+                    Ok(used) => {
+                        finish_download(ctx, &target.request, &target.url, output, used, options)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                };
+                settle(result, options)?;
+            }
+        }
+        Err(err) => {
+            for target in &group {
+                explain(options, Decision::failure(&target.url, shared, &err));
+            }
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Group targets sharing a repository and ref, keeping first-appearance order
+fn group_by_checkout(targets: Vec<GitTarget>) -> Vec<Vec<GitTarget>> {
+    let mut groups: Vec<Vec<GitTarget>> = Vec::new();
+    for target in targets {
+        let key = |request: &RequestInfo| {
+            (
+                request.owner.to_lowercase(),
+                request.repo.to_lowercase(),
+                request.branch.clone(),
+            )
+        };
+        match groups
+            .iter_mut()
+            .find(|group| key(&group[0].request) == key(&target.request))
+        {
+            Some(group) => group.push(target),
+            None => groups.push(vec![target]),
+        }
+    }
+    groups
+}
+
+/// Turn an empty download into a notice unless `--fail-on-empty` is set
+fn settle(result: Result<()>, options: &DownloadOptions<'_>) -> Result<()> {
+    match result {
+        Err(err) if is_empty_download(&err) && !options.fail_on_empty => {
+            status(
+                options.quiet,
+                format!("{} Nothing to download: {}", style("ℹ").cyan(), err),
+            );
+            Ok(())
+        }
+        other => other,
+    }
+}
+
+async fn download_remote(
+    ctx: &DownloadContext,
+    request: RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    let request = prepare_remote_request(ctx, request, strategy, options).await?;
+    run_prepared(ctx, &request, url, output, strategy, options).await
+}
+
+/// Resolve the default branch and run the up-front ref and signature checks
+async fn prepare_remote_request(
+    ctx: &DownloadContext,
+    mut request: RequestInfo,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<RequestInfo> {
     // If branch is empty, we need to fetch the default branch
-    if request.branch.is_empty() {
+    let explicit_ref = !request.branch.is_empty();
+    if !explicit_ref {
         log::debug!(
             "Fetching default branch for {}/{}",
             request.owner,
             request.repo
         );
-        let repo_info =
-            fetch_repository_info(&ctx.client, &request.owner, &request.repo, options.token)
-                .await
-                .context("failed to fetch repository information")?;
-        request.branch = repo_info.default_branch;
+        let branch = fetch_default_branch(
+            &ctx.client,
+            &request.owner,
+            &request.repo,
+            options.token,
+            &ctx.rate_limit,
+            options.cache_policy(),
+        )
+        .await;
+        request.branch = match branch {
+            Ok(branch) => branch,
+            Err(_) if options.offline => {
+                return Err(GdlError::Offline(format!(
+                    "offline: the default branch of {}/{} is unknown; include the branch in the URL (e.g. /tree/main/...)",
+                    request.owner, request.repo
+                )));
+            }
+            Err(err) => return Err(err.context("failed to fetch repository information")),
+        };
         log::debug!("Using default branch: {}", request.branch);
     }
 
+    if strategy == DownloadStrategy::Git {
+        ensure_git_available()?;
+    }
+
+    // Default branches exist by definition; explicit refs are checked once up front
+    // so a typo yields one clear error instead of a failure from every strategy.
+    if explicit_ref && !options.offline {
+        verify_ref(ctx, &mut request, options).await?;
+    }
+    verify_tag_signature(ctx, &mut request, options).await?;
+
+    Ok(request)
+}
+
+async fn run_prepared(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    if options.dry_run {
+        return print_dry_run(ctx, request, url, output, strategy, options).await;
+    }
+
     let start_time = Instant::now();
     let result = match strategy {
-        DownloadStrategy::Api => download_via_rest(ctx, &request, url, output, options).await,
-        DownloadStrategy::Git => {
-            ensure_git_available()?;
-            download_via_git(&request, url, output, options, &ctx.multi).await
-        }
-        DownloadStrategy::Zip => {
-            download_via_zip(
-                &ctx.client,
-                &request,
-                url,
-                output,
-                Arc::clone(&ctx.rate_limit),
-                options,
-                &ctx.multi,
-            )
-            .await
-        }
         DownloadStrategy::Auto => {
-            // Prefer git if available, otherwise choose based on request type
-            if git_available() {
-                // Git available: try git first, then zip, then API
-                match download_via_git(&request, url, output, options, &ctx.multi).await {
-                    Ok(()) => Ok(()),
-                    Err(git_err) => {
-                        warn!(
-                            "Git sparse checkout failed ({}); attempting zip archive download...",
-                            git_err
-                        );
-                        match download_via_zip(
-                            &ctx.client,
-                            &request,
-                            url,
-                            output,
-                            Arc::clone(&ctx.rate_limit),
-                            options,
-                            &ctx.multi,
-                        )
-                        .await
-                        {
-                            Ok(()) => Ok(()),
-                            Err(zip_err) => {
-                                warn!(
-                                    "Zip download failed ({}); attempting REST API download...",
-                                    zip_err
-                                );
-                                match download_via_rest(
-                                    ctx,
-                                    &request,
-                                    url,
-                                    output,
-                                    options,
-                                )
-                                .await
-                                {
-                                    Ok(()) => Ok(()),
-                                    Err(api_err) => Err(git_err.context(format!(
-                                        "zip fallback failed: {}; REST API fallback also failed: {}",
-                                        zip_err, api_err
-                                    ))),
-                                }
-                            }
-                        }
-                    }
-                }
-            } else {
-                // Git not available: use zip for whole repo, API for specific paths
-                let is_whole_repo = request.path.is_empty() || request.path == "/";
-
-                if is_whole_repo {
-                    // Whole repo: try zip first, then API
-                    match download_via_zip(
-                        &ctx.client,
-                        &request,
-                        url,
-                        output,
-                        Arc::clone(&ctx.rate_limit),
-                        options,
-                        &ctx.multi,
-                    )
-                    .await
-                    {
-                        Ok(()) => Ok(()),
-                        Err(zip_err) => {
-                            warn!(
-                                "Zip download failed ({}); attempting REST API download...",
-                                zip_err
-                            );
-                            match download_via_rest(ctx, &request, url, output, options).await {
-                                Ok(()) => Ok(()),
-                                Err(api_err) => Err(zip_err.context(format!(
-                                    "REST API fallback also failed: {}",
-                                    api_err
-                                ))),
-                            }
-                        }
-                    }
-                } else {
-                    // Specific path: try API first, then zip
-                    match download_via_rest(ctx, &request, url, output, options).await {
-                        Ok(()) => Ok(()),
-                        Err(api_err) => {
-                            warn!(
-                                "REST API download failed ({}); attempting zip archive download...",
-                                api_err
-                            );
-                            match download_via_zip(
-                                &ctx.client,
-                                &request,
-                                url,
-                                output,
-                                Arc::clone(&ctx.rate_limit),
-                                options,
-                                &ctx.multi,
-                            )
-                            .await
-                            {
-                                Ok(()) => Ok(()),
-                                Err(zip_err) => Err(api_err
-                                    .context(format!("zip fallback also failed: {}", zip_err))),
-                            }
-                        }
-                    }
-                }
+            let git_available = git_available();
+            let whole_repo = is_whole_repo(request);
+            if !git_available {
+                explain(
+                    options,
+                    Decision::new(url, DownloadStrategy::Git, Action::Skipped, "git_missing"),
+                );
             }
+            let order = auto_strategy_order(git_available, whole_repo);
+            let reason = auto_order_reason(git_available, whole_repo);
+            run_with_fallback(ctx, request, url, output, &order, reason, options).await
+        }
+        _ => {
+            explain(
+                options,
+                Decision::new(url, strategy, Action::Attempted, "requested"),
+            );
+            let result = run_strategy(ctx, request, url, output, strategy, options).await;
+            explain_outcome(options, url, strategy, &result);
+            result.map(|()| strategy)
         }
     };
 
@@ -182,42 +432,254 @@ pub async fn download_github_path(
         elapsed.as_secs_f64()
     );
 
-    result
+    match result {
+        Ok(used) => finish_download(ctx, request, url, output, used, options).await,
+        Err(err) => Err(err),
+    }
 }
 
-async fn download_via_rest(
+/// Report a finished download: its layout with `--tree`, its source with `--provenance`
+async fn finish_download(
     ctx: &DownloadContext,
     request: &RequestInfo,
     url: &str,
     output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
     options: &DownloadOptions<'_>,
 ) -> Result<()> {
-    let contents = fetch_github_contents(
+    report_tree(request, output, options);
+    if !options.provenance || request.kind == RequestKind::Blob {
+        return Ok(());
+    }
+    let commit = resolve_commit(ctx, request, url, options).await;
+    record_provenance(
+        request,
+        url,
+        output,
+        commit,
+        strategy_name(strategy),
+        options,
+    )
+}
+
+/// Write `.gdl-source.json` into a finished directory download when `--provenance` is set
+fn record_provenance(
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    commit: Option<String>,
+    strategy: &str,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    if !options.provenance || request.kind == RequestKind::Blob {
+        return Ok(());
+    }
+    let dir = tree_dir(request, output);
+    Provenance::new(request, url, &dir, commit, strategy).write(&dir)
+}
+
+/// The directory a tree request was downloaded into
+fn tree_dir(request: &RequestInfo, output: Option<&PathBuf>) -> PathBuf {
+    output
+        .cloned()
+        .unwrap_or_else(|| compute_base_and_default_output(request, false, None).1)
+}
+
+/// Print the layout of a finished directory download when `--tree` is set
+fn report_tree(request: &RequestInfo, output: Option<&PathBuf>, options: &DownloadOptions<'_>) {
+    let Some(depth) = options.tree else {
+        return;
+    };
+    if request.kind == RequestKind::Blob {
+        return;
+    }
+
+    let dir = tree_dir(request, output);
+    if let Err(err) = print_tree(&dir, depth) {
+        warn!("Unable to summarize {}: {:#}", dir.display(), err);
+    }
+}
+
+pub fn is_whole_repo(request: &RequestInfo) -> bool {
+    request.path.is_empty() || request.path == "/"
+}
+
+/// Order in which the `auto` strategy tries concrete strategies.
+///
+/// With git available: git → zip → API. Without git, whole repositories prefer the
+/// zip archive while specific paths prefer the REST API.
+pub fn auto_strategy_order(git_available: bool, whole_repo: bool) -> Vec<DownloadStrategy> {
+    if git_available {
+        vec![
+            DownloadStrategy::Git,
+            DownloadStrategy::Zip,
+            DownloadStrategy::Api,
+        ]
+    } else if whole_repo {
+        vec![DownloadStrategy::Zip, DownloadStrategy::Api]
+    } else {
+        vec![DownloadStrategy::Api, DownloadStrategy::Zip]
+    }
+}
+
+pub fn describe_strategy(strategy: DownloadStrategy) -> &'static str {
+    match strategy {
+        DownloadStrategy::Api => "REST API download",
+        DownloadStrategy::Git => "git sparse checkout",
+        DownloadStrategy::Zip => "zip archive download",
+        DownloadStrategy::Auto => "automatic strategy selection",
+    }
+}
+
+/// Report how an attempt ended
+fn explain_outcome(
+    options: &DownloadOptions<'_>,
+    url: &str,
+    strategy: DownloadStrategy,
+    result: &Result<()>,
+) {
+    let decision = match result {
+        Ok(()) => Decision::new(url, strategy, Action::Succeeded, "ok"),
+        Err(err) => Decision::failure(url, strategy, err),
+    };
+    explain(options, decision);
+}
+
+/// Try each strategy in `order`; `first_reason` explains why the first one leads
+async fn run_with_fallback(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    order: &[DownloadStrategy],
+    first_reason: &'static str,
+    options: &DownloadOptions<'_>,
+) -> Result<DownloadStrategy> {
+    let mut failures: Vec<(DownloadStrategy, GdlError)> = Vec::new();
+
+    for (idx, &candidate) in order.iter().enumerate() {
+        let reason = if idx == 0 { first_reason } else { "fallback" };
+        explain(
+            options,
+            Decision::new(url, candidate, Action::Attempted, reason),
+        );
+        let result = run_strategy(ctx, request, url, output, candidate, options).await;
+        explain_outcome(options, url, candidate, &result);
+        match result {
+            Ok(()) => return Ok(candidate),
+            // An empty path is a definitive answer; other strategies would agree
+            Err(err) if is_empty_download(&err) => {
+                for &skipped in &order[idx + 1..] {
+                    explain(
+                        options,
+                        Decision::new(url, skipped, Action::Skipped, "empty_result"),
+                    );
+                }
+                return Err(err);
+            }
+            Err(err) => {
+                if let Some(&next) = order.get(idx + 1) {
+                    warn!(
+                        "{} failed ({}); attempting {}...",
+                        describe_strategy(candidate),
+                        err,
+                        describe_strategy(next)
+                    );
+                }
+                failures.push((candidate, err));
+            }
+        }
+    }
+
+    let mut failures = failures.into_iter();
+    let (_, first_err) = failures
+        .next()
+        .ok_or_else(|| anyhow!("no download strategy available"))?;
+    let fallback_errors: Vec<String> = failures
+        .map(|(candidate, err)| {
+            format!(
+                "{} fallback also failed: {}",
+                describe_strategy(candidate),
+                err
+            )
+        })
+        .collect();
+
+    if fallback_errors.is_empty() {
+        Err(first_err)
+    } else {
+        Err(first_err.context(fallback_errors.join("; ")))
+    }
+}
+
+async fn run_strategy(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    strategy: DownloadStrategy,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    match strategy {
+        DownloadStrategy::Api => download_via_rest(ctx, request, url, output, options).await,
+        DownloadStrategy::Git => download_via_git(ctx, request, url, output, options).await,
+        DownloadStrategy::Zip => download_via_zip(ctx, request, url, output, options).await,
+        DownloadStrategy::Auto => Err(anyhow!("auto is not a concrete download strategy").into()),
+    }
+}
+
+/// Files the REST API strategy would write, after filter and `--shard` selection
+pub struct RestPlan {
+    pub output_dir: PathBuf,
+    /// The output directory, or the target file for a single-file download
+    pub target_display: String,
+    pub tasks: Vec<DownloadTask>,
+}
+
+/// Enumerate what `url` resolves to and where each file would be written, without
+/// downloading any file contents
+pub async fn plan_via_rest(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    options: &DownloadOptions<'_>,
+) -> Result<RestPlan> {
+    let contents = match fetch_github_contents(
         &ctx.client,
         request,
         &request.path,
         options.token,
         Arc::clone(&ctx.rate_limit),
-        options.no_cache,
+        options.cache_policy(),
     )
     .await
-    .with_context(|| format!("unable to fetch GitHub contents for {}", url))?;
+    {
+        Ok(contents) => contents,
+        Err(err) if matches!(err.cause(), GdlError::EmptyRepository(_)) => {
+            return Err(EmptyDownload::for_request(request).into());
+        }
+        Err(err) => {
+            return Err(err.context(format!("unable to fetch GitHub contents for {}", url)));
+        }
+    };
 
     if contents.is_empty() {
-        return Err(anyhow!("No contents returned for the requested path"));
+        return Err(EmptyDownload::for_request(request).into());
     }
 
     let (base_path, default_output_dir) = determine_paths(request, &contents);
-    let output_dir = output.cloned().unwrap_or(default_output_dir);
+    let output_dir = output_root(output.cloned().unwrap_or(default_output_dir));
 
-    let target_display = describe_download_target(&output_dir, &base_path, &contents)?;
+    let target_display =
+        describe_download_target(&output_dir, &base_path, &contents, options.sanitize_names)?;
     let file_inventory = build_file_inventory(
         &ctx.client,
         request,
         options.token,
         &contents,
         Arc::clone(&ctx.rate_limit),
-        options.no_cache,
+        options.cache_policy(),
     )
     .await
     .with_context(|| {
@@ -233,7 +695,52 @@ async fn download_via_rest(
             }
         )
     })?;
-    ensure_directory(&output_dir)?;
+
+    let download_tasks = collect_download_tasks(
+        ctx,
+        request,
+        &output_dir,
+        &base_path,
+        contents,
+        &file_inventory,
+        options,
+    )
+    .await?;
+
+    if download_tasks.is_empty() {
+        return Err(EmptyDownload::for_request(request).into());
+    }
+    let download_tasks = disambiguate_targets(download_tasks, |task| &task.item.path);
+
+    Ok(RestPlan {
+        output_dir,
+        target_display,
+        tasks: select_shard(
+            select_matching(download_tasks, options.filter.as_ref(), |task| {
+                &task.item.path
+            }),
+            options.shard,
+            |task| &task.item.path,
+        ),
+    })
+}
+
+async fn download_via_rest(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    let RestPlan {
+        output_dir,
+        target_display,
+        tasks: download_tasks,
+    } = plan_via_rest(ctx, request, url, output, options).await?;
+    if download_tasks.is_empty() {
+        report_empty_shard(options.shard, options.quiet);
+        return Ok(());
+    }
 
     info!(
         "Downloading from {}/{}:{}:{} into {}",
@@ -247,28 +754,42 @@ async fn download_via_rest(
         },
         target_display
     );
+    ensure_directory(&output_dir)?;
 
-    let total_files = file_inventory.len();
-    let total_bytes = file_inventory.values().filter_map(|meta| meta.size).sum();
-    let download_tasks = collect_download_tasks(
-        ctx,
-        request,
-        &output_dir,
-        &base_path,
-        contents,
-        &file_inventory,
-        options,
-    )
-    .await?;
+    let keep: Option<HashSet<PathBuf>> = (options.delete && request.kind == RequestKind::Tree)
+        .then(|| {
+            download_tasks
+                .iter()
+                .map(|task| task.target_path.clone())
+                .collect()
+        });
+    let download_tasks = if options.sync || options.update_only {
+        skip_unchanged(download_tasks, |task| task.item.sha.clone())
+    } else {
+        download_tasks
+    };
+    if download_tasks.is_empty() {
+        return mirror_output(&output_dir, keep.as_ref());
+    }
 
-    // Check for file overwrites before proceeding
-    let target_paths = collect_target_paths(&download_tasks);
-    check_overwrite_permission(&target_paths, options.force)?;
+    // Check for file overwrites before proceeding; --sync replaces changed files
+    let download_tasks = resolve_conflicts(
+        download_tasks,
+        options.on_conflict,
+        options.force || options.sync,
+        &ctx.prompt,
+        |task| incoming_content(ctx, request, task, options),
+    )?;
+    if download_tasks.is_empty() {
+        return mirror_output(&output_dir, keep.as_ref());
+    }
+    let total_files = download_tasks.len();
+    let total_bytes = download_tasks.iter().filter_map(|task| task.size).sum();
 
-    let progress = Arc::new(Mutex::new(DownloadProgress::with_multi_progress(
+    let progress = Arc::new(Mutex::new(DownloadProgress::new(
         total_files,
         total_bytes,
-        Some(&ctx.multi),
+        options.progress.clone(),
     )));
 
     log::debug!(
@@ -277,14 +798,22 @@ async fn download_via_rest(
         format_bytes(total_bytes)
     );
 
+    let lfs_candidates: Vec<(String, PathBuf)> = if options.lfs {
+        download_tasks
+            .iter()
+            .map(|task| (task.item.path.clone(), task.target_path.clone()))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
     download_all_files(
-        &ctx.client,
-        options.token,
+        ctx,
+        request,
+        &output_dir,
         download_tasks,
         Arc::clone(&progress),
-        ctx.parallel,
-        Arc::clone(&ctx.rate_limit),
-        options.no_cache,
+        options,
     )
     .await?;
 
@@ -294,6 +823,18 @@ async fn download_via_rest(
         (guard.downloaded_files, guard.downloaded_bytes)
     };
 
+    if options.lfs {
+        fetch_lfs_objects(
+            &ctx.client,
+            request,
+            options.token,
+            &lfs_candidates,
+            &ctx.multi,
+        )
+        .await?;
+    }
+    mirror_output(&output_dir, keep.as_ref())?;
+
     info!(
         "Finished downloading {} file(s) ({} total) from {}.",
         downloaded_files,
@@ -304,24 +845,47 @@ async fn download_via_rest(
     Ok(())
 }
 
+/// Fetch a file's remote content for the overwrite prompt's diff. The prompt already
+/// holds this worker thread, so the request runs on it; a single-threaded runtime
+/// cannot spare the thread and gets no diff.
+fn incoming_content(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    task: &DownloadTask,
+    options: &DownloadOptions<'_>,
+) -> Option<Vec<u8>> {
+    if let Some(text) = &task.placeholder {
+        return Some(text.clone().into_bytes());
+    }
+    let handle = tokio::runtime::Handle::current();
+    if handle.runtime_flavor() != tokio::runtime::RuntimeFlavor::MultiThread {
+        return None;
+    }
+    let fetch = fetch_blob_content(
+        &ctx.client,
+        request,
+        &task.item,
+        options.token,
+        &ctx.rate_limit,
+    );
+    tokio::task::block_in_place(|| handle.block_on(fetch))
+        .map_err(|err| warn!("Unable to fetch {} for a diff: {:#}", task.item.path, err))
+        .ok()
+}
+
 async fn download_all_files(
-    client: &Client,
-    token: Option<&str>,
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    output_dir: &Path,
     tasks: Vec<DownloadTask>,
     progress: Arc<Mutex<DownloadProgress>>,
-    parallel: usize,
-    rate_limit: Arc<RateLimitTracker>,
-    no_cache: bool,
+    options: &DownloadOptions<'_>,
 ) -> Result<()> {
-    let effective_parallel = parallel.max(1);
+    let effective_parallel = ctx.parallel.max(1);
 
     stream::iter(tasks.into_iter().map(|task| {
-        let http_client = client.clone();
         let progress = Arc::clone(&progress);
-        let rate_limit = Arc::clone(&rate_limit);
-        async move {
-            download_single_file(http_client, token, task, progress, rate_limit, no_cache).await
-        }
+        async move { download_single_file(ctx, request, output_dir, task, progress, options).await }
     }))
     .buffer_unordered(effective_parallel)
     .try_collect::<Vec<_>>()
@@ -330,19 +894,23 @@ async fn download_all_files(
 }
 
 async fn download_single_file(
-    client: Client,
-    token: Option<&str>,
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    output_dir: &Path,
     task: DownloadTask,
     progress: Arc<Mutex<DownloadProgress>>,
-    rate_limit: Arc<RateLimitTracker>,
-    no_cache: bool,
+    options: &DownloadOptions<'_>,
 ) -> Result<()> {
     let DownloadTask {
         item,
         target_path,
         size,
+        link_target,
+        placeholder,
     } = task;
 
+    // A link left by an earlier --symlinks create run must not redirect the write
+    remove_symlinks_below(output_dir, &target_path)?;
     if let Some(parent) = target_path.parent() {
         tokio::fs::create_dir_all(parent)
             .await
@@ -354,10 +922,241 @@ async fn download_single_file(
         guard.log_start(&item.path, &target_path, size);
     }
 
-    download_file(&client, &item, token, &target_path, &rate_limit, no_cache).await?;
+    if let Some(text) = placeholder {
+        tokio::fs::write(&target_path, text)
+            .await
+            .with_context(|| format!("failed to write {}", target_path.display()))?;
+        progress
+            .lock()
+            .await
+            .record_download(&item.path, &target_path, size);
+        return Ok(());
+    }
+    if let Some(link_target) = link_target {
+        create_symlink(&link_target, &target_path)?;
+        progress
+            .lock()
+            .await
+            .record_download(&item.path, &target_path, size);
+        return Ok(());
+    }
+
+    // Raw downloads do not count against the API quota; contents API downloads and
+    // per-file commit lookups do, so those slow down as the quota drains
+    let _permit = if item.download_url.is_none() || options.preserve_mtime {
+        Some(ctx.rate_limit.throttle(ctx.parallel).await)
+    } else {
+        None
+    };
+
+    if !restore_cached_blob(&item, &target_path, options).await {
+        if options.offline {
+            return Err(GdlError::Offline(format!(
+                "offline: contents of {} are not in the blob cache",
+                item.path
+            )));
+        }
+
+        let chunked = use_chunks(&item, options)
+            && match download_file_chunked(ctx, &item, &target_path, options, &progress).await {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!(
+                        "Chunked download of {} failed ({}); downloading it as a single stream",
+                        item.path,
+                        err.root_cause()
+                    );
+                    progress.lock().await.forget_bytes(&item.path);
+                    false
+                }
+            };
+
+        if !chunked {
+            let result = download_file(ctx, &item, &target_path, options, &progress).await;
+            if let Err(err) = result {
+                retry_file_elsewhere(ctx, request, &item, &target_path, options, err).await?;
+            }
+        }
+
+        if !options.no_cache
+            && let Some(sha) = item.sha.as_deref()
+            && let Err(err) = store_blob(sha, &target_path)
+        {
+            debug!("Unable to cache blob for {}: {:#}", item.path, err);
+        }
+    }
+
+    if options.preserve_mtime {
+        apply_commit_mtime(
+            &ctx.client,
+            request,
+            &item.path,
+            &target_path,
+            options.token,
+            &ctx.rate_limit,
+            options.cache_policy(),
+        )
+        .await;
+    }
+
     {
         let mut guard = progress.lock().await;
         guard.record_download(&item.path, &target_path, size);
     }
     Ok(())
 }
+
+/// Whether a file is large enough to be fetched as parallel range requests
+fn use_chunks(item: &GitHubContent, options: &DownloadOptions<'_>) -> bool {
+    options.chunks > 1
+        && item
+            .size
+            .is_some_and(|size| size >= options.chunk_threshold)
+}
+
+/// Copy the file from the blob cache when its SHA is known, verifying the copy.
+/// Returns `false` when the file still has to be downloaded.
+async fn restore_cached_blob(
+    item: &GitHubContent,
+    target_path: &std::path::Path,
+    options: &DownloadOptions<'_>,
+) -> bool {
+    let Some(sha) = item.sha.as_deref() else {
+        return false;
+    };
+    if options.no_cache {
+        return false;
+    }
+
+    match restore_blob(sha, target_path) {
+        Ok(true) if verify_file_hash(target_path, sha).await.unwrap_or(false) => {
+            debug!("Reused cached blob {} for {}", sha, item.path);
+            true
+        }
+        Ok(true) => {
+            warn!(
+                "Cached blob {} is corrupt; downloading {} again",
+                sha, item.path
+            );
+            let _ = remove_blob(sha);
+            let _ = tokio::fs::remove_file(target_path).await;
+            false
+        }
+        Ok(false) => false,
+        Err(err) => {
+            debug!("Unable to use blob cache for {}: {:#}", item.path, err);
+            false
+        }
+    }
+}
+
+/// Recover a single failed file through the blob API, then from a cached zip archive
+/// of the same ref, instead of failing the whole run or switching strategy.
+async fn retry_file_elsewhere(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    item: &GitHubContent,
+    target_path: &std::path::Path,
+    options: &DownloadOptions<'_>,
+    err: GdlError,
+) -> Result<()> {
+    // Without a blob SHA neither source can be fetched or verified
+    let Some(sha) = item.sha.as_deref() else {
+        return Err(err);
+    };
+
+    warn!(
+        "Download of {} failed ({}); retrying via the blob API...",
+        item.path,
+        err.root_cause()
+    );
+    let blob_err = match download_blob(
+        &ctx.client,
+        request,
+        item,
+        options.token,
+        target_path,
+        &ctx.rate_limit,
+        &ctx.stream_limits(options),
+    )
+    .await
+    {
+        Ok(()) => return Ok(()),
+        Err(blob_err) => blob_err,
+    };
+
+    match extract_file_from_cached_zip(request, &item.path, target_path) {
+        Ok(true) if verify_file_hash(target_path, sha).await.unwrap_or(false) => {
+            info!("Recovered {} from the cached zip archive", item.path);
+            return Ok(());
+        }
+        Ok(true) => {
+            let _ = tokio::fs::remove_file(target_path).await;
+            debug!("Cached zip archive holds an outdated {}", item.path);
+        }
+        Ok(false) => debug!("No cached zip archive contains {}", item.path),
+        Err(zip_err) => debug!("Unable to read cached zip archive: {:#}", zip_err),
+    }
+
+    Err(err.context(format!("blob API fallback also failed: {:#}", blob_err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_strategy_order() {
+        // Git available: git first regardless of request type
+        assert_eq!(
+            auto_strategy_order(true, true),
+            vec![
+                DownloadStrategy::Git,
+                DownloadStrategy::Zip,
+                DownloadStrategy::Api
+            ]
+        );
+        assert_eq!(auto_strategy_order(true, false)[0], DownloadStrategy::Git);
+
+        // Without git: whole repo prefers zip, specific paths prefer the API
+        assert_eq!(
+            auto_strategy_order(false, true),
+            vec![DownloadStrategy::Zip, DownloadStrategy::Api]
+        );
+        assert_eq!(
+            auto_strategy_order(false, false),
+            vec![DownloadStrategy::Api, DownloadStrategy::Zip]
+        );
+    }
+
+    #[test]
+    fn test_group_by_checkout() {
+        let target = |owner: &str, branch: &str, path: &str| GitTarget {
+            request: RequestInfo {
+                owner: owner.to_string(),
+                repo: "repo".to_string(),
+                branch: branch.to_string(),
+                path: path.to_string(),
+                has_trailing_slash: false,
+                kind: RequestKind::Tree,
+            },
+            url: format!("https://github.com/{}/repo/tree/{}/{}", owner, branch, path),
+        };
+
+        let groups = group_by_checkout(vec![
+            target("octo", "main", "docs"),
+            target("other", "main", "docs"),
+            target("Octo", "main", "src"),
+            target("octo", "v1", "docs"),
+        ]);
+
+        let paths: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|group| group.iter().map(|t| t.request.path.as_str()).collect())
+            .collect();
+        // Owner matching is case-insensitive; refs must match exactly
+        assert_eq!(paths, vec![vec!["docs", "src"], vec!["docs"], vec!["docs"]]);
+        assert_eq!(groups[2][0].request.branch, "v1");
+        assert!(group_by_checkout(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+use console::style;
+use log::info;
+use serde::Deserialize;
+
+use crate::download::Downloader;
+use crate::download::lock::{DEFAULT_LOCKFILE, Lockfile, ensure_unmoved, resolve_sources};
+use crate::error::{Context, Result};
+use crate::filter::PathFilter;
+use crate::progress::status;
+
+/// Manifest `ghdl install` reads when no path is given
+pub const DEFAULT_MANIFEST: &str = "gdl.toml";
+
+/// The sources a project vendors, read from `gdl.toml`
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default, rename = "source")]
+    pub sources: Vec<ManifestSource>,
+}
+
+/// One `[[source]]` table: a path of a repository and where it goes
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestSource {
+    /// `owner/repo`
+    pub repo: String,
+    /// Branch, tag, or commit; the default branch when absent
+    #[serde(rename = "ref")]
+    pub reference: Option<String>,
+    /// Directory or file in the repository; the whole repository when empty
+    #[serde(default)]
+    pub path: String,
+    /// Destination, relative to the manifest's directory
+    pub dest: PathBuf,
+    /// Globs of files to keep, relative to `path`
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Globs of files to leave out, relative to `path`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read manifest {}", path.display()))?;
+        let manifest: Manifest = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse manifest {}", path.display()))?;
+        if manifest.sources.is_empty() {
+            return Err(anyhow!("manifest {} lists no [[source]] tables", path.display()).into());
+        }
+        for source in &manifest.sources {
+            source
+                .url()
+                .with_context(|| format!("invalid source in manifest {}", path.display()))?;
+        }
+        manifest
+            .check_dests()
+            .with_context(|| format!("invalid dest in manifest {}", path.display()))?;
+        Ok(manifest)
+    }
+
+    /// Every dest must be a directory of its own below the manifest's: `--delete`
+    /// mirrors each source into its dest, so a dest holding the project (or lying
+    /// outside it) or one nested in another's would delete files it does not own.
+    fn check_dests(&self) -> Result<()> {
+        let mut dests: Vec<(PathBuf, &ManifestSource)> = Vec::new();
+        for source in &self.sources {
+            let mut dest = PathBuf::new();
+            for component in source.dest.components() {
+                match component {
+                    Component::Normal(part) => dest.push(part),
+                    Component::CurDir => {}
+                    Component::ParentDir => {
+                        return Err(anyhow!(
+                            "dest '{}' of {} must not contain '..'",
+                            source.dest.display(),
+                            source.repo
+                        )
+                        .into());
+                    }
+                    Component::RootDir | Component::Prefix(_) => {
+                        return Err(anyhow!(
+                            "dest '{}' of {} must be relative to the manifest's directory",
+                            source.dest.display(),
+                            source.repo
+                        )
+                        .into());
+                    }
+                }
+            }
+            if dest.as_os_str().is_empty() {
+                return Err(anyhow!(
+                    "dest '{}' of {} is the manifest's own directory; give it a subdirectory",
+                    source.dest.display(),
+                    source.repo
+                )
+                .into());
+            }
+            if let Some((other, other_source)) = dests
+                .iter()
+                .find(|(other, _)| dest.starts_with(other) || other.starts_with(&dest))
+            {
+                return Err(anyhow!(
+                    "dests '{}' of {} and '{}' of {} overlap; give each source its own directory",
+                    other.display(),
+                    other_source.repo,
+                    dest.display(),
+                    source.repo
+                )
+                .into());
+            }
+            dests.push((dest, source));
+        }
+        Ok(())
+    }
+}
+
+impl ManifestSource {
+    /// The GitHub URL this source stands for
+    pub fn url(&self) -> Result<String> {
+        let repo = self.repo.trim_matches('/');
+        if repo.split('/').count() != 2 || repo.split('/').any(str::is_empty) {
+            return Err(anyhow!("repo '{}' must be written as owner/repo", self.repo).into());
+        }
+        let path = self.path.trim_matches('/');
+        match (&self.reference, path.is_empty()) {
+            (None, true) => Ok(format!("https://github.com/{}", repo)),
+            (Some(reference), true) => {
+                Ok(format!("https://github.com/{}/tree/{}", repo, reference))
+            }
+            (Some(reference), false) => Ok(format!(
+                "https://github.com/{}/tree/{}/{}",
+                repo, reference, path
+            )),
+            (None, false) => Err(anyhow!(
+                "source {} sets path '{}' without a ref; add ref = \"main\" (or the branch to track)",
+                repo,
+                path
+            ).into()),
+        }
+    }
+
+    fn filter(&self) -> Option<PathFilter> {
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return None;
+        }
+        Some(PathFilter {
+            root: self.path.trim_matches('/').to_string(),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+        })
+    }
+}
+
+/// Download every source of the manifest at `manifest_path` into its destination.
+///
+/// The commits the sources resolved to are recorded in a `gdl.lock` next to the
+/// manifest. Once that exists, a source whose ref has moved fails the install until
+/// `update` is set, which moves the lockfile to the new commits.
+pub async fn install(
+    downloader: &Downloader<'_>,
+    manifest_path: &Path,
+    update: bool,
+) -> Result<()> {
+    let manifest = Manifest::load(manifest_path)?;
+    let base = manifest_path.parent().unwrap_or(Path::new(""));
+    let lock_path = base.join(DEFAULT_LOCKFILE);
+    let options = downloader.options();
+
+    let urls = manifest
+        .sources
+        .iter()
+        .map(ManifestSource::url)
+        .collect::<Result<Vec<_>>>()?;
+    // Without network access the refs cannot be resolved, so the lock is left alone
+    let locking = !options.offline;
+    let mut resolved = if locking {
+        resolve_sources(downloader, &urls).await?
+    } else {
+        Vec::new()
+    };
+    // Files the filters leave out cannot make an install stale
+    for (source, locked) in manifest.sources.iter().zip(resolved.iter_mut()) {
+        if let Some(filter) = source.filter() {
+            locked.files.retain(|path, _| filter.matches(path));
+        }
+    }
+    if locking && !update && lock_path.exists() {
+        Lockfile::load(&lock_path)?
+            .check(&lock_path, &resolved)
+            .context(
+                "the manifest's sources have changed; run `ghdl install --update` to refresh them",
+            )?;
+    }
+
+    for (source, url) in manifest.sources.iter().zip(&urls) {
+        let dest = base.join(&source.dest);
+        status(
+            downloader.options().quiet,
+            format!("{} {} → {}", style("↓").cyan().bold(), url, dest.display()),
+        );
+        downloader
+            .download_filtered(url, Some(&dest), source.filter())
+            .await
+            .with_context(|| format!("failed to install {}", url))?;
+    }
+
+    if locking && !options.dry_run {
+        ensure_unmoved(downloader, &resolved).await?;
+        Lockfile {
+            sources: resolved,
+            ..Lockfile::default()
+        }
+        .save(&lock_path)?;
+        info!("Wrote {}", lock_path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[source]]
+            repo = "owner/docs"
+            ref = "v1.2"
+            path = "guide"
+            dest = "vendor/guide"
+            include = ["*.md"]
+
+            [[source]]
+            repo = "owner/config"
+            dest = "vendor/config"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.sources.len(), 2);
+        assert_eq!(
+            manifest.sources[0].url().unwrap(),
+            "https://github.com/owner/docs/tree/v1.2/guide"
+        );
+        assert_eq!(manifest.sources[0].filter().unwrap().root, "guide");
+        assert_eq!(
+            manifest.sources[1].url().unwrap(),
+            "https://github.com/owner/config"
+        );
+        assert_eq!(manifest.sources[1].filter(), None);
+
+        let source = ManifestSource {
+            path: "docs".into(),
+            reference: None,
+            ..manifest.sources[1].clone()
+        };
+        assert!(source.url().is_err());
+        assert!(
+            toml::from_str::<Manifest>(
+                "[[source]]\nrepo = \"o/r\"\ndest = \"d\"\nbranch = \"x\"\n"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_check_dests() {
+        let manifest = |dests: &[&str]| Manifest {
+            sources: dests
+                .iter()
+                .map(|dest| ManifestSource {
+                    repo: "owner/repo".into(),
+                    reference: None,
+                    path: String::new(),
+                    dest: PathBuf::from(dest),
+                    include: Vec::new(),
+                    exclude: Vec::new(),
+                })
+                .collect(),
+        };
+        assert!(
+            manifest(&["vendor/a", "vendor/b", "./third_party"])
+                .check_dests()
+                .is_ok()
+        );
+        assert!(manifest(&["vendor/ab", "vendor/a"]).check_dests().is_ok());
+
+        // Nested or identical dests would mirror away each other's files
+        for dests in [
+            &["vendor", "vendor/guide"][..],
+            &["vendor/guide", "vendor"],
+            &["vendor", "./vendor/"],
+        ] {
+            let err = manifest(dests).check_dests().unwrap_err();
+            assert!(err.to_string().contains("overlap"), "{:?}: {}", dests, err);
+        }
+
+        // So would a dest holding the whole project, or lying outside it
+        for dest in [".", "", "..", "vendor/../..", "/tmp/vendor"] {
+            assert!(manifest(&[dest]).check_dests().is_err(), "{}", dest);
+        }
+    }
+}
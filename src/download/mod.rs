@@ -1,7 +1,21 @@
+pub mod checksums;
+pub mod downloader;
+pub mod dry_run;
+pub mod explain;
 pub mod file;
+pub mod lock;
 pub mod manager;
+pub mod manifest;
+pub mod mtime;
+pub mod preflight;
+pub mod provenance;
+pub mod signature;
+pub mod sync;
 pub mod tasks;
+pub mod verify;
+pub mod watch;
 
+pub use downloader::{Downloader, DownloaderBuilder};
 pub use file::*;
 pub use manager::*;
 pub use tasks::*;
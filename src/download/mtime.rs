@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::stream::{self, StreamExt};
+use log::{debug, warn};
+use reqwest::Client;
+
+use crate::github::fetch_last_commit_time;
+use crate::http::CachePolicy;
+use crate::rate_limit::RateLimitTracker;
+use crate::types::RequestInfo;
+use crate::utils::set_file_mtime;
+
+/// Commit lookups in flight at once when stamping extracted files
+const PARALLEL_LOOKUPS: usize = 8;
+
+/// Set the modification time of `target` to the date of the last commit touching
+/// `item_path`. Failures are logged and leave the current time in place.
+pub async fn apply_commit_mtime(
+    client: &Client,
+    request: &RequestInfo,
+    item_path: &str,
+    target: &Path,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+    cache: CachePolicy,
+) {
+    let commit_time =
+        fetch_last_commit_time(client, request, item_path, token, rate_limit, cache).await;
+
+    match commit_time {
+        Ok(Some(secs)) => {
+            if let Err(err) = set_file_mtime(target, secs) {
+                warn!("Unable to preserve mtime for {}: {}", item_path, err);
+            }
+        }
+        Ok(None) => debug!("No commit found for {}; leaving mtime unchanged", item_path),
+        Err(err) => warn!(
+            "Unable to fetch last commit date for {}: {}",
+            item_path, err
+        ),
+    }
+}
+
+/// Stamp files extracted from an archive with their own last commit dates. Every
+/// archive entry carries the date of the ref's commit, which is all a file keeps
+/// when its lookup fails.
+pub async fn apply_commit_times(
+    client: &Client,
+    request: &RequestInfo,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+    cache: CachePolicy,
+    files: &[(String, PathBuf)],
+) {
+    stream::iter(files)
+        .for_each_concurrent(PARALLEL_LOOKUPS, |(item_path, target)| {
+            apply_commit_mtime(client, request, item_path, target, token, rate_limit, cache)
+        })
+        .await;
+}
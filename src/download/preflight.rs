@@ -0,0 +1,231 @@
+use log::debug;
+use tokio::task::spawn_blocking;
+
+use crate::error::{Context, GdlError, Result};
+use crate::git::{git_available, list_remote_refs, repo_url_for_transport};
+use crate::github::{fetch_ref_names, ref_exists};
+use crate::types::{DownloadContext, DownloadOptions, EmptyDownload, RequestInfo, RequestKind};
+
+/// Maximum number of ref names listed in a "not found" error
+const MAX_SUGGESTIONS: usize = 10;
+
+#[derive(Debug, PartialEq, Eq)]
+enum RefResolution {
+    Found,
+    /// The branch name contains slashes that were parsed as part of the path
+    Adjusted {
+        branch: String,
+        path: String,
+    },
+    Missing,
+}
+
+/// Verify the requested ref exists before any strategy runs.
+///
+/// Uses `git ls-remote` when git is available (no API quota), otherwise a single
+/// commits API lookup. Branch and tag names are only listed when the ref is missing.
+/// Inconclusive checks (network or auth failures) never block the download, but a
+/// repository the API cannot find is reported as such.
+pub async fn verify_ref(
+    ctx: &DownloadContext,
+    request: &mut RequestInfo,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    if looks_like_commit_sha(&request.branch) {
+        debug!(
+            "Skipping ref preflight for commit-like ref {}",
+            request.branch
+        );
+        return Ok(());
+    }
+
+    let remote_refs = if git_available() {
+        let repo_url = repo_url_for_transport(
+            ctx.endpoints.archive(),
+            &request.owner,
+            &request.repo,
+            options.token,
+            options.git_transport,
+        )?;
+        match spawn_blocking(move || list_remote_refs(&repo_url)).await {
+            Ok(Ok(refs)) => Some(refs),
+            Ok(Err(err)) => {
+                debug!("git ls-remote preflight failed: {}", err);
+                None
+            }
+            Err(err) => {
+                debug!("git ls-remote preflight task failed: {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let refs = match remote_refs {
+        Some(refs) => refs,
+        None => {
+            let exists = ref_exists(
+                &ctx.client,
+                &request.owner,
+                &request.repo,
+                &request.branch,
+                options.token,
+                &ctx.rate_limit,
+            )
+            .await;
+            match exists {
+                Some(true) => return Ok(()),
+                None => {
+                    debug!("Ref preflight for {} was inconclusive", request.branch);
+                    return Ok(());
+                }
+                // A missing or inaccessible repository fails here rather than looking
+                // like one without any refs
+                Some(false) => fetch_ref_names(
+                    &ctx.client,
+                    &request.owner,
+                    &request.repo,
+                    options.token,
+                    &ctx.rate_limit,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "unable to look up '{}' in {}/{}",
+                        request.branch, request.owner, request.repo
+                    )
+                })?,
+            }
+        }
+    };
+
+    if refs.is_empty() {
+        // A repository without any branches has no commits yet
+        return Err(EmptyDownload::for_request(request).into());
+    }
+
+    match resolve_ref(&refs, &request.branch, &request.path) {
+        RefResolution::Found => Ok(()),
+        RefResolution::Adjusted { branch, path } => {
+            debug!(
+                "Interpreting ref as {} (path {}) based on remote refs",
+                branch, path
+            );
+            request.branch = branch;
+            request.path = path;
+            if request.path.is_empty() && request.kind == RequestKind::Blob {
+                request.kind = RequestKind::Tree;
+            }
+            Ok(())
+        }
+        RefResolution::Missing => Err(GdlError::NotFound(format!(
+            "branch or tag '{}' not found in {}/{} (available: {})",
+            request.branch,
+            request.owner,
+            request.repo,
+            format_available(&refs)
+        ))),
+    }
+}
+
+fn looks_like_commit_sha(reference: &str) -> bool {
+    (7..=40).contains(&reference.len()) && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Match the parsed ref against known names, allowing branch names that contain slashes
+fn resolve_ref(refs: &[String], branch: &str, path: &str) -> RefResolution {
+    if refs.iter().any(|name| name == branch) {
+        return RefResolution::Found;
+    }
+
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut candidate = branch.to_string();
+    for (idx, segment) in segments.iter().enumerate() {
+        candidate.push('/');
+        candidate.push_str(segment);
+        if refs.iter().any(|name| name == &candidate) {
+            return RefResolution::Adjusted {
+                branch: candidate,
+                path: segments[idx + 1..].join("/"),
+            };
+        }
+    }
+
+    RefResolution::Missing
+}
+
+fn format_available(refs: &[String]) -> String {
+    if refs.is_empty() {
+        return "none".to_string();
+    }
+
+    let mut listed = refs
+        .iter()
+        .take(MAX_SUGGESTIONS)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if refs.len() > MAX_SUGGESTIONS {
+        listed.push_str(&format!(", … ({} more)", refs.len() - MAX_SUGGESTIONS));
+    }
+    listed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_resolve_ref() {
+        let refs = names(&["main", "feature/login", "release/v1/final", "v1.0"]);
+
+        assert_eq!(resolve_ref(&refs, "main", "src"), RefResolution::Found);
+        assert_eq!(resolve_ref(&refs, "v1.0", ""), RefResolution::Found);
+
+        // Branch names with slashes get split off the path
+        assert_eq!(
+            resolve_ref(&refs, "feature", "login/src/app.rs"),
+            RefResolution::Adjusted {
+                branch: "feature/login".to_string(),
+                path: "src/app.rs".to_string()
+            }
+        );
+        assert_eq!(
+            resolve_ref(&refs, "release", "v1/final"),
+            RefResolution::Adjusted {
+                branch: "release/v1/final".to_string(),
+                path: String::new()
+            }
+        );
+
+        assert_eq!(resolve_ref(&refs, "develop", "src"), RefResolution::Missing);
+        assert_eq!(resolve_ref(&[], "main", ""), RefResolution::Missing);
+    }
+
+    #[test]
+    fn test_looks_like_commit_sha() {
+        assert!(looks_like_commit_sha("a1b2c3d"));
+        assert!(looks_like_commit_sha(
+            "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+        ));
+        assert!(!looks_like_commit_sha("main"));
+        assert!(!looks_like_commit_sha("abc12")); // too short
+        assert!(!looks_like_commit_sha("deadbeefg"));
+    }
+
+    #[test]
+    fn test_format_available() {
+        assert_eq!(format_available(&[]), "none");
+        assert_eq!(format_available(&names(&["dev", "main"])), "dev, main");
+
+        let many: Vec<String> = (0..12).map(|i| format!("b{:02}", i)).collect();
+        let listed = format_available(&many);
+        assert!(listed.starts_with("b00, b01"));
+        assert!(listed.ends_with("… (2 more)"));
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::PKG_VERSION;
+use crate::error::{Context, Result};
+use crate::git::utils::is_full_commit_sha;
+use crate::github::fetch_commit_sha;
+use crate::types::{DownloadContext, DownloadOptions, RequestInfo};
+use crate::utils::{format_utc_timestamp, system_time_to_secs};
+
+/// Written into each downloaded directory by `--provenance`
+pub const PROVENANCE_FILE: &str = ".gdl-source.json";
+
+/// Where a vendored directory came from, and the command that refreshes it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub url: String,
+    pub owner: String,
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub path: String,
+    /// `None` when the ref could not be resolved, e.g. offline without a cached answer
+    pub commit: Option<String>,
+    pub downloaded_at: String,
+    /// The strategy that produced the files: `api`, `git`, `zip`, or `local`
+    pub strategy: String,
+    pub tool: String,
+    pub update_command: String,
+}
+
+impl Provenance {
+    pub fn new(
+        request: &RequestInfo,
+        url: &str,
+        dir: &Path,
+        commit: Option<String>,
+        strategy: &str,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            owner: request.owner.clone(),
+            repo: request.repo.clone(),
+            reference: request.branch.clone(),
+            path: request.path.clone(),
+            commit,
+            downloaded_at: format_utc_timestamp(system_time_to_secs(std::time::SystemTime::now())),
+            strategy: strategy.to_string(),
+            tool: format!("ghdl {}", PKG_VERSION),
+            update_command: format!(
+                "ghdl --sync --delete --provenance --output '{}' '{}'",
+                dir.display(),
+                url
+            ),
+        }
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(PROVENANCE_FILE);
+        let mut data = serde_json::to_string_pretty(self).context("failed to encode provenance")?;
+        data.push('\n');
+        std::fs::write(&path, data)
+            .with_context(|| format!("failed to write provenance file {}", path.display()))
+    }
+}
+
+/// The commit `request` points at; a ref that is already a full SHA needs no request.
+/// Failures only cost the field, since the files are downloaded by then.
+pub async fn resolve_commit(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    options: &DownloadOptions<'_>,
+) -> Option<String> {
+    let branch = &request.branch;
+    if is_full_commit_sha(branch) {
+        return Some(branch.to_lowercase());
+    }
+    match fetch_commit_sha(
+        &ctx.client,
+        request,
+        options.token,
+        &ctx.rate_limit,
+        options.cache_policy(),
+    )
+    .await
+    {
+        Ok(commit) => {
+            debug!("{} is at commit {}", url, commit);
+            Some(commit)
+        }
+        Err(err) => {
+            warn!("Unable to resolve the commit of {}: {:#}", url, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::RequestKind;
+
+    #[test]
+    fn test_write_provenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let request = RequestInfo {
+            owner: "o".into(),
+            repo: "r".into(),
+            branch: "main".into(),
+            path: "docs".into(),
+            has_trailing_slash: false,
+            kind: RequestKind::Tree,
+        };
+        let url = "https://github.com/o/r/tree/main/docs";
+        let provenance = Provenance::new(&request, url, dir.path(), Some("abc".into()), "git");
+        provenance.write(dir.path()).unwrap();
+
+        let data = std::fs::read_to_string(dir.path().join(PROVENANCE_FILE)).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(value["ref"], "main");
+        assert_eq!(value["commit"], "abc");
+        assert_eq!(value["strategy"], "git");
+        assert!(
+            value["update_command"]
+                .as_str()
+                .unwrap()
+                .ends_with(&format!("'{}'", url))
+        );
+        assert_eq!(
+            serde_json::from_str::<Provenance>(&data).unwrap(),
+            provenance
+        );
+    }
+}
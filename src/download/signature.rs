@@ -0,0 +1,135 @@
+use anyhow::anyhow;
+use console::style;
+use log::{debug, warn};
+
+use crate::cli::TagVerification;
+use crate::error::Result;
+use crate::github::{TagSignature, fetch_tag_signature};
+use crate::progress::status;
+use crate::types::{DownloadContext, DownloadOptions, RequestInfo};
+
+/// Outcome of checking a tag against the requested verification mode
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Verified(String),
+    Warning(String),
+}
+
+/// Check the requested tag's signature when `--verify-tag` is set.
+///
+/// GitHub's `verification` object is used as-is, so any signature GitHub can verify
+/// (GPG, SSH, S/MIME) is accepted. The request is then pinned to the tag's commit, so
+/// every strategy downloads what was checked even if a branch shares the tag's name
+/// or the tag moves.
+pub async fn verify_tag_signature(
+    ctx: &DownloadContext,
+    request: &mut RequestInfo,
+    options: &DownloadOptions<'_>,
+) -> Result<()> {
+    let Some(mode) = options.verify_tag else {
+        return Ok(());
+    };
+
+    let signature = fetch_tag_signature(
+        &ctx.client,
+        &request.owner,
+        &request.repo,
+        &request.branch,
+        options.token,
+        &ctx.rate_limit,
+    )
+    .await?;
+
+    match evaluate(mode, &request.branch, signature.as_ref())? {
+        Verdict::Verified(message) => {
+            status(options.quiet, format!("{} {}", style("✓").green(), message))
+        }
+        Verdict::Warning(message) => warn!("{}", message),
+    }
+    if let Some(signature) = signature {
+        debug!(
+            "Pinning tag {} to commit {}",
+            request.branch, signature.commit
+        );
+        request.branch = signature.commit;
+    }
+    Ok(())
+}
+
+fn evaluate(mode: TagVerification, tag: &str, signature: Option<&TagSignature>) -> Result<Verdict> {
+    let problem = match signature {
+        None => format!("'{}' is not a tag, so it has no signature to verify", tag),
+        Some(signature) if signature.verification.verified => {
+            return Ok(Verdict::Verified(format!(
+                "Signature of tag {} verified by GitHub",
+                tag
+            )));
+        }
+        Some(signature) => {
+            let subject = if signature.annotated {
+                format!("tag {}", tag)
+            } else {
+                format!("commit of lightweight tag {}", tag)
+            };
+            format!(
+                "signature of {} is not verified ({})",
+                subject, signature.verification.reason
+            )
+        }
+    };
+
+    match mode {
+        TagVerification::Warn => Ok(Verdict::Warning(problem)),
+        TagVerification::Require => Err(anyhow!("refusing to download: {}", problem).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::types::SignatureVerification;
+
+    fn signature(annotated: bool, verified: bool, reason: &str) -> TagSignature {
+        TagSignature {
+            annotated,
+            commit: "a1".repeat(20),
+            verification: SignatureVerification {
+                verified,
+                reason: reason.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let valid = signature(true, true, "valid");
+        let unsigned = signature(true, false, "unsigned");
+        let lightweight = signature(false, false, "unknown_key");
+
+        // Verified tags pass in either mode
+        assert!(matches!(
+            evaluate(TagVerification::Require, "v1", Some(&valid)).unwrap(),
+            Verdict::Verified(_)
+        ));
+        assert!(matches!(
+            evaluate(TagVerification::Warn, "v1", Some(&valid)).unwrap(),
+            Verdict::Verified(_)
+        ));
+
+        // Warn mode reports problems without failing
+        assert_eq!(
+            evaluate(TagVerification::Warn, "v1", Some(&unsigned)).unwrap(),
+            Verdict::Warning("signature of tag v1 is not verified (unsigned)".to_string())
+        );
+        assert!(matches!(
+            evaluate(TagVerification::Warn, "main", None).unwrap(),
+            Verdict::Warning(_)
+        ));
+
+        // Require mode refuses unsigned tags, lightweight tags with bad commits, and non-tags
+        let err = evaluate(TagVerification::Require, "v2", Some(&lightweight)).unwrap_err();
+        assert!(err.to_string().contains("commit of lightweight tag v2"));
+        assert!(evaluate(TagVerification::Require, "v1", Some(&unsigned)).is_err());
+        assert!(evaluate(TagVerification::Require, "main", None).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info};
+
+use crate::download::calculate_git_blob_sha1;
+use crate::download::lock::DEFAULT_LOCKFILE;
+use crate::download::manifest::DEFAULT_MANIFEST;
+use crate::download::provenance::PROVENANCE_FILE;
+use crate::download::tasks::SUBMODULE_MARKER;
+use crate::error::{Context, Result};
+use crate::overwrite::TargetPath;
+
+/// Git blob SHA of the regular file at `path`; `None` when it is missing, a
+/// symlink, or unreadable, so the file is written as usual
+pub fn local_blob_sha(path: &Path) -> Option<String> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    fs::read(path)
+        .ok()
+        .map(|content| calculate_git_blob_sha1(&content))
+}
+
+/// Drop the tasks whose target already holds the blob they would write (`--sync`,
+/// `--update-only`). `expected` gives a task's blob SHA, or `None` when it is unknown;
+/// such tasks are kept, as are those whose target does not exist yet. A target whose
+/// size differs from a known task size is kept without being hashed.
+pub fn skip_unchanged<T: TargetPath>(
+    tasks: Vec<T>,
+    mut expected: impl FnMut(&T) -> Option<String>,
+) -> Vec<T> {
+    let total = tasks.len();
+    let changed: Vec<T> = tasks
+        .into_iter()
+        .filter(|task| {
+            let path = task.path();
+            let Ok(metadata) = fs::metadata(path) else {
+                return true;
+            };
+            if task.size() > 0 && metadata.len() != task.size() {
+                debug!("{} differs in size", path.display());
+                return true;
+            }
+            let unchanged = expected(task).is_some_and(|sha| local_blob_sha(path) == Some(sha));
+            if unchanged {
+                debug!("{} is up to date", path.display());
+            }
+            !unchanged
+        })
+        .collect();
+
+    let skipped = total - changed.len();
+    if skipped > 0 && changed.is_empty() {
+        info!("All {} file(s) are already up to date", skipped);
+    } else if skipped > 0 {
+        info!(
+            "Skipping {} unchanged file(s); {} to download",
+            skipped,
+            changed.len()
+        );
+    }
+    changed
+}
+
+/// Staging directories of the tar.gz stream live in the output directory
+const STAGING_PREFIX: &str = ".ghdl-staging-";
+
+/// Never deleted by `--delete`: a git repository (e.g. a `--keep-git` clone or an
+/// `-o .` inside a checkout) and the files ghdl itself writes
+const PROTECTED_NAMES: [&str; 5] = [
+    ".git",
+    PROVENANCE_FILE,
+    DEFAULT_LOCKFILE,
+    DEFAULT_MANIFEST,
+    SUBMODULE_MARKER,
+];
+
+/// Apply `--delete` to `root` once every file of the download is written; `keep` is
+/// only set with `--delete`. A download that fails or is cancelled midway never
+/// gets here, so it removes nothing.
+pub fn mirror_output(root: &Path, keep: Option<&HashSet<PathBuf>>) -> Result<()> {
+    if let Some(keep) = keep {
+        delete_extraneous(root, keep)?;
+    }
+    Ok(())
+}
+
+/// Remove the files below `root` that are not in `keep` (`--delete`), then the
+/// directories that leaves empty, so `root` mirrors the remote path. Staging
+/// directories and [`PROTECTED_NAMES`] are kept. Returns the number of files removed.
+pub fn delete_extraneous(root: &Path, keep: &HashSet<PathBuf>) -> Result<usize> {
+    let removed = delete_below(root, keep)?;
+    if removed > 0 {
+        info!(
+            "Deleted {} file(s) no longer present upstream from {}",
+            removed,
+            root.display()
+        );
+    }
+    Ok(removed)
+}
+
+fn delete_below(dir: &Path, keep: &HashSet<PathBuf>) -> Result<usize> {
+    let mut removed = 0;
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read directory {}", dir.display()))?
+    {
+        let entry = entry
+            .with_context(|| format!("failed to read directory entry in {}", dir.display()))?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(STAGING_PREFIX) || PROTECTED_NAMES.contains(&name.as_ref()) {
+            continue;
+        }
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("failed to inspect {}", path.display()))?;
+
+        if file_type.is_dir() {
+            removed += delete_below(&path, keep)?;
+            let empty = fs::read_dir(&path)
+                .map(|mut entries| entries.next().is_none())
+                .unwrap_or(false);
+            if empty && !keep.contains(&path) {
+                fs::remove_dir(&path)
+                    .with_context(|| format!("failed to remove directory {}", path.display()))?;
+            }
+        } else if !keep.contains(&path) {
+            debug!("Deleting {}", path.display());
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to delete {}", path.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Task(PathBuf, u64, Option<String>);
+
+    impl TargetPath for Task {
+        fn path(&self) -> &Path {
+            &self.0
+        }
+
+        fn size(&self) -> u64 {
+            self.1
+        }
+
+        fn set_path(&mut self, path: PathBuf) {
+            self.0 = path;
+        }
+    }
+
+    #[test]
+    fn test_skip_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let same = dir.path().join("same.txt");
+        let changed = dir.path().join("changed.txt");
+        let unknown = dir.path().join("unknown.txt");
+        let missing = dir.path().join("missing.txt");
+        let resized = dir.path().join("resized.txt");
+        for path in [&same, &changed, &unknown, &resized] {
+            fs::write(path, "hello\n").unwrap();
+        }
+        let hello = calculate_git_blob_sha1(b"hello\n");
+        assert_eq!(hello, "ce013625030ba8dba906f756967f9e9ca394464a");
+
+        let tasks = vec![
+            Task(same.clone(), 6, Some(hello.clone())),
+            Task(changed.clone(), 4, Some(calculate_git_blob_sha1(b"bye\n"))),
+            Task(unknown.clone(), 0, None),
+            Task(missing.clone(), 6, Some(hello.clone())),
+            // A size mismatch is decisive without hashing
+            Task(resized.clone(), 7, Some(hello)),
+        ];
+        let kept = skip_unchanged(tasks, |task| task.2.clone());
+        let kept: Vec<&PathBuf> = kept.iter().map(|task| &task.0).collect();
+        assert_eq!(kept, vec![&changed, &unknown, &missing, &resized]);
+    }
+
+    #[test]
+    fn test_delete_extraneous() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in ["keep.txt", "old.txt", "sub/keep.txt", "gone/old.txt"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        fs::create_dir(root.join(".ghdl-staging-123")).unwrap();
+        fs::write(root.join(PROVENANCE_FILE), "{}").unwrap();
+        for path in [".git/HEAD", "gdl.lock", "gdl.toml", "sub/.git"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+
+        let keep: HashSet<PathBuf> = ["keep.txt", "sub/keep.txt"]
+            .iter()
+            .map(|path| root.join(path))
+            .collect();
+        assert_eq!(delete_extraneous(root, &keep).unwrap(), 2);
+
+        assert!(root.join("keep.txt").exists());
+        assert!(root.join("sub/keep.txt").exists());
+        assert!(!root.join("old.txt").exists());
+        assert!(!root.join("gone").exists());
+        assert!(root.join(".ghdl-staging-123").exists());
+        assert!(root.join(PROVENANCE_FILE).exists());
+        assert!(root.join(".git/HEAD").exists());
+        assert!(root.join("gdl.lock").exists());
+        assert!(root.join("gdl.toml").exists());
+        assert!(root.join("sub/.git").exists());
+
+        // Without --delete nothing is removed
+        fs::write(root.join("old.txt"), "x").unwrap();
+        mirror_output(root, None).unwrap();
+        assert!(root.join("old.txt").exists());
+    }
+}
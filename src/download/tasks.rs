@@ -1,15 +1,21 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
 use futures::stream::{self, StreamExt, TryStreamExt};
 use log::{debug, warn};
 
+use crate::cli::SymlinkPolicy;
+use crate::download::{calculate_git_blob_sha1, fetch_blob_content};
+use crate::error::{Context, GdlError, Result};
 use crate::github::fetch_github_contents;
 use crate::github::types::{ContentType, GitHubContent};
 use crate::paths::relative_path;
 use crate::types::{DownloadContext, DownloadOptions, DownloadTask, FileMetadata, RequestInfo};
+use crate::utils::{resolve_created_link, resolve_link_target};
+
+/// File written inside the directory of a submodule, which the REST strategy does not fetch
+pub const SUBMODULE_MARKER: &str = ".ghdl-submodule";
 
 pub async fn collect_download_tasks(
     ctx: &DownloadContext,
@@ -37,23 +43,43 @@ async fn collect_download_tasks_inner(
 ) -> Result<Vec<DownloadTask>> {
     let mut tasks = Vec::new();
     let mut directories = Vec::new();
+    let mut links = Vec::new();
 
     for item in contents {
         match item.content_type {
             ContentType::File => {
-                let relative = relative_path(base_path, &item)?;
+                let relative = relative_path(base_path, &item, options.sanitize_names)?;
                 let target_path = output_dir.join(&relative);
                 let size = files.get(&item.path).and_then(|meta| meta.size);
                 tasks.push(DownloadTask {
                     item,
                     target_path,
                     size,
+                    link_target: None,
+                    placeholder: None,
                 });
             }
             ContentType::Dir => {
                 directories.push(item);
             }
-            ContentType::Symlink | ContentType::Submodule | ContentType::Other => {
+            ContentType::Symlink if options.symlinks != SymlinkPolicy::Skip => {
+                links.push(item);
+            }
+            ContentType::Symlink => {
+                warn!(
+                    "Skipping symlink {} (use --symlinks to download it).",
+                    item.path
+                );
+            }
+            ContentType::Submodule => {
+                tasks.push(submodule_placeholder(
+                    output_dir,
+                    base_path,
+                    &item,
+                    options.sanitize_names,
+                )?);
+            }
+            ContentType::Other => {
                 warn!(
                     "Skipping unsupported content type {:?} at {}",
                     item.content_type, item.path
@@ -62,28 +88,44 @@ async fn collect_download_tasks_inner(
         }
     }
 
+    let listing_parallel = ctx.listing_parallel.max(1);
+
+    if !links.is_empty() {
+        let link_tasks = stream::iter(
+            links
+                .into_iter()
+                .map(|item| symlink_task(ctx, request, output_dir, base_path, item, options)),
+        )
+        .buffer_unordered(listing_parallel)
+        .try_collect::<Vec<_>>()
+        .await?;
+        tasks.extend(link_tasks.into_iter().flatten());
+    }
+
     if directories.is_empty() {
         return Ok(tasks);
     }
 
-    let listing_parallel = ctx.parallel.max(1);
-
     let sub_results = stream::iter(directories.into_iter().map(|dir_entry| {
         let http_client = ctx.client.clone();
         let dir_path = dir_entry.path.clone();
         let rate_limit = Arc::clone(&ctx.rate_limit);
         async move {
             debug!("Enumerating directory {}", dir_path);
-            let sub_contents = fetch_github_contents(
-                &http_client,
-                request,
-                &dir_path,
-                options.token,
-                Arc::clone(&rate_limit),
-                options.no_cache,
-            )
-            .await
-            .with_context(|| format!("unable to fetch contents of {}", dir_path))?;
+            let sub_contents = {
+                // Released before recursing so nested listings cannot starve each other
+                let _permit = rate_limit.throttle(listing_parallel).await;
+                fetch_github_contents(
+                    &http_client,
+                    request,
+                    &dir_path,
+                    options.token,
+                    Arc::clone(&rate_limit),
+                    options.cache_policy(),
+                )
+                .await
+                .with_context(|| format!("unable to fetch contents of {}", dir_path))?
+            };
 
             // Create a temporary context for recursive calls
             let sub_ctx = DownloadContext {
@@ -91,6 +133,11 @@ async fn collect_download_tasks_inner(
                 rate_limit,
                 multi: ctx.multi.clone(),
                 parallel: ctx.parallel,
+                listing_parallel: ctx.listing_parallel,
+                jobs: ctx.jobs,
+                endpoints: ctx.endpoints.clone(),
+                bandwidth: ctx.bandwidth.clone(),
+                prompt: ctx.prompt.clone(),
             };
 
             collect_download_tasks_inner(
@@ -115,3 +162,256 @@ async fn collect_download_tasks_inner(
 
     Ok(tasks)
 }
+
+/// Turn a symlink into a task per the `--symlinks` policy: a link to create, or a
+/// download of the file it points to. Links that cannot be followed are skipped.
+async fn symlink_task(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    output_dir: &Path,
+    base_path: &Path,
+    item: GitHubContent,
+    options: &DownloadOptions<'_>,
+) -> Result<Option<DownloadTask>> {
+    if options.offline {
+        warn!("offline: skipping symlink {}", item.path);
+        return Ok(None);
+    }
+
+    // A symlink's blob holds the path it points to
+    let target = {
+        let _permit = ctx.rate_limit.throttle(ctx.listing_parallel.max(1)).await;
+        fetch_blob_content(&ctx.client, request, &item, options.token, &ctx.rate_limit)
+            .await
+            .with_context(|| format!("unable to read the target of symlink {}", item.path))?
+    };
+    let target = String::from_utf8_lossy(&target).into_owned();
+    let target_path = output_dir.join(relative_path(base_path, &item, options.sanitize_names)?);
+
+    let skip = |reason: &str| {
+        warn!("Skipping symlink {} -> {}: {}.", item.path, target, reason);
+        Ok(None)
+    };
+    // Neither created nor followed: a link out of the repository would let later
+    // writes through it land outside the output directory
+    let Some(resolved) = resolve_link_target(&item.path, &target) else {
+        return skip("target is outside the repository");
+    };
+
+    if options.symlinks == SymlinkPolicy::Create {
+        // A created link must also stay below the requested path, which is the
+        // output root; following one only copies the target's content
+        if resolve_created_link(&item.path, &target, base_path).is_none() {
+            return skip("target is outside the output directory");
+        }
+        return Ok(Some(DownloadTask {
+            item,
+            target_path,
+            size: Some(0),
+            link_target: Some(PathBuf::from(target)),
+            placeholder: None,
+        }));
+    }
+    let contents = fetch_github_contents(
+        &ctx.client,
+        request,
+        &resolved,
+        options.token,
+        Arc::clone(&ctx.rate_limit),
+        options.cache_policy(),
+    )
+    .await;
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(err) if matches!(err.cause(), GdlError::NotFound(_)) => {
+            return skip("target does not exist");
+        }
+        Err(err) => return Err(err.context(format!("unable to follow symlink {}", item.path))),
+    };
+    let followed = match contents.as_slice() {
+        [single] if single.content_type == ContentType::File => single.clone(),
+        // Directories are only followed by the git and zip strategies
+        _ => return skip("target is not a file"),
+    };
+
+    // Download the target's content under the link's own name
+    let size = followed.size;
+    Ok(Some(DownloadTask {
+        item: GitHubContent {
+            name: item.name,
+            path: item.path,
+            ..followed
+        },
+        target_path,
+        size,
+        link_target: None,
+        placeholder: None,
+    }))
+}
+
+/// A task writing `SUBMODULE_MARKER` where a submodule's checkout would be, naming the
+/// commit the repository pins so the gap in the tree is visible
+fn submodule_placeholder(
+    output_dir: &Path,
+    base_path: &Path,
+    item: &GitHubContent,
+    sanitize: bool,
+) -> Result<DownloadTask> {
+    debug!("Writing a placeholder for submodule {}", item.path);
+    let text = format!(
+        "{} is a git submodule; ghdl does not download submodules.\ncommit: {}\n",
+        item.path,
+        item.sha.as_deref().unwrap_or("unknown")
+    );
+    let marker = GitHubContent {
+        name: SUBMODULE_MARKER.to_string(),
+        path: format!("{}/{}", item.path, SUBMODULE_MARKER),
+        url: String::new(),
+        size: Some(text.len() as u64),
+        download_url: None,
+        content_type: ContentType::File,
+        sha: Some(calculate_git_blob_sha1(text.as_bytes())),
+    };
+    Ok(DownloadTask {
+        target_path: output_dir.join(relative_path(base_path, &marker, sanitize)?),
+        size: marker.size,
+        item: marker,
+        link_target: None,
+        placeholder: Some(text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::github::Endpoints;
+    use crate::github::types::ContentType;
+
+    /// A mock API answering the request paths in `routes` (query strings ignored)
+    /// with their body, and anything else with a 404
+    fn serve(routes: &'static [(&'static str, &'static str)]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                let mut header = String::new();
+                while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+                    header.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let path = path.split('?').next().unwrap_or_default();
+                let (status, body) = match routes.iter().find(|(route, _)| *route == path) {
+                    Some((_, body)) => ("200 OK", *body),
+                    None => ("404 Not Found", r#"{"message":"Not Found"}"#),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        base
+    }
+
+    fn link(path: &str, sha: &str) -> GitHubContent {
+        GitHubContent {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path: path.to_string(),
+            url: String::new(),
+            size: Some(8),
+            download_url: None,
+            content_type: ContentType::Symlink,
+            sha: Some(sha.to_string()),
+        }
+    }
+
+    async fn task_for(
+        base: &str,
+        item: GitHubContent,
+        symlinks: SymlinkPolicy,
+    ) -> Result<Option<DownloadTask>> {
+        let request = crate::github::parse_github_url("https://github.com/o/r/tree/main/docs")?;
+        let ctx = DownloadContext::new(
+            reqwest::Client::new(),
+            Arc::default(),
+            Default::default(),
+            1,
+            1,
+            1,
+        );
+        let options = DownloadOptions {
+            symlinks,
+            no_cache: true,
+            ..Default::default()
+        };
+        Endpoints::default()
+            .with_api(base)
+            .scope(symlink_task(
+                &ctx,
+                &request,
+                Path::new("out"),
+                Path::new("docs"),
+                item,
+                &options,
+            ))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_symlink_tasks() {
+        let base = serve(&[
+            ("/repos/o/r/git/blobs/inside", "guide.md"),
+            ("/repos/o/r/git/blobs/absolute", "/home/u/.bashrc"),
+            ("/repos/o/r/git/blobs/escaping", "../../outside"),
+            ("/repos/o/r/git/blobs/dangling", "missing.md"),
+            (
+                "/repos/o/r/contents/docs/guide.md",
+                r#"{"name":"guide.md","path":"docs/guide.md","url":"","size":5,"download_url":null,"type":"file","sha":"guidesha"}"#,
+            ),
+        ]);
+
+        // create keeps a link that stays in the repository as it is
+        let task = task_for(&base, link("docs/latest", "inside"), SymlinkPolicy::Create)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.link_target, Some(PathBuf::from("guide.md")));
+        assert_eq!(task.target_path, Path::new("out/latest"));
+
+        // Absolute and escaping targets are neither created nor followed
+        for sha in ["absolute", "escaping"] {
+            for policy in [SymlinkPolicy::Create, SymlinkPolicy::Follow] {
+                let task = task_for(&base, link("docs/latest", sha), policy).await;
+                assert!(task.unwrap().is_none(), "{} with {:?}", sha, policy);
+            }
+        }
+
+        // follow downloads the target's content under the link's name
+        let task = task_for(&base, link("docs/latest", "inside"), SymlinkPolicy::Follow)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.link_target, None);
+        assert_eq!(task.item.path, "docs/latest");
+        assert_eq!(task.item.sha.as_deref(), Some("guidesha"));
+        assert_eq!(task.size, Some(5));
+
+        // A target the API cannot find is skipped, not an error
+        let task = task_for(
+            &base,
+            link("docs/latest", "dangling"),
+            SymlinkPolicy::Follow,
+        )
+        .await;
+        assert!(task.unwrap().is_none());
+    }
+}
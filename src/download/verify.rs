@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use log::{debug, warn};
+use reqwest::Client;
+
+use crate::download::calculate_git_blob_sha1;
+use crate::error::{Context, Result};
+use crate::github::fetch_blob_shas;
+use crate::http::CachePolicy;
+use crate::rate_limit::RateLimitTracker;
+use crate::types::RequestInfo;
+
+/// Mismatched files named in the error before the rest are only counted
+const MAX_LISTED: usize = 5;
+
+/// Check files extracted from an archive against the blob SHAs of the ref's git tree.
+/// When the listing cannot be fetched the files are kept unverified with a warning.
+pub async fn verify_against_tree(
+    client: &Client,
+    request: &RequestInfo,
+    token: Option<&str>,
+    rate_limit: Arc<RateLimitTracker>,
+    cache: CachePolicy,
+    files: &[(String, PathBuf)],
+) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let expected = match fetch_blob_shas(client, request, token, rate_limit, cache).await {
+        Ok(expected) => expected,
+        Err(err) => {
+            warn!(
+                "Unable to list blob SHAs of {}/{}:{} ({:#}); files were not verified",
+                request.owner, request.repo, request.branch, err
+            );
+            return Ok(());
+        }
+    };
+    verify_blob_shas(files, &expected)
+}
+
+/// Compare written files with the git blob SHA-1 listed for their repository path,
+/// as the REST strategy does for every file. Paths without an entry (followed
+/// symlinks, truncated listings) are skipped.
+pub fn verify_blob_shas(
+    files: &[(String, PathBuf)],
+    expected: &HashMap<String, String>,
+) -> Result<()> {
+    compare_blob_shas(files, expected, |checked| {
+        checked
+            .iter()
+            .map(|(_, target)| {
+                let content = std::fs::read(target).with_context(|| {
+                    format!(
+                        "failed to read file {} for hash verification",
+                        target.display()
+                    )
+                })?;
+                Ok(calculate_git_blob_sha1(&content))
+            })
+            .collect()
+    })
+}
+
+/// [`verify_blob_shas`] with the blob SHAs computed by `hash`, which receives the files
+/// that have an expected SHA and returns one SHA per file, in order
+pub fn compare_blob_shas(
+    files: &[(String, PathBuf)],
+    expected: &HashMap<String, String>,
+    hash: impl FnOnce(&[&(String, PathBuf)]) -> Result<Vec<String>>,
+) -> Result<()> {
+    let checked: Vec<&(String, PathBuf)> = files
+        .iter()
+        .filter(|(path, _)| expected.contains_key(path))
+        .collect();
+    let actual = hash(&checked)?;
+    let mismatched: Vec<&str> = checked
+        .iter()
+        .zip(&actual)
+        .filter(|((path, _), sha)| expected.get(path) != Some(*sha))
+        .map(|((path, _), _)| path.as_str())
+        .collect();
+    let checked = checked.len();
+    debug!(
+        "Verified {} of {} file(s) against git blob SHAs",
+        checked,
+        files.len()
+    );
+
+    if mismatched.is_empty() {
+        return Ok(());
+    }
+    let mut listed = mismatched[..mismatched.len().min(MAX_LISTED)].join(", ");
+    if mismatched.len() > MAX_LISTED {
+        listed.push_str(&format!(" and {} more", mismatched.len() - MAX_LISTED));
+    }
+    Err(anyhow!(
+        "Hash verification failed for {} file(s): {}; they differ from the repository (rewritten by .gitattributes or corrupted). Use --no-verify to keep them",
+        mismatched.len(),
+        listed
+    ).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_blob_shas() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.txt");
+        let bad = dir.path().join("bad.txt");
+        std::fs::write(&good, "hello\n").unwrap();
+        std::fs::write(&bad, "hello\r\n").unwrap();
+        let expected: HashMap<String, String> = [
+            ("good.txt", "ce013625030ba8dba906f756967f9e9ca394464a"),
+            ("bad.txt", "ce013625030ba8dba906f756967f9e9ca394464a"),
+        ]
+        .into_iter()
+        .map(|(path, sha)| (path.to_string(), sha.to_string()))
+        .collect();
+
+        let files = vec![
+            ("good.txt".to_string(), good.clone()),
+            ("link.txt".to_string(), dir.path().join("missing")),
+        ];
+        verify_blob_shas(&files, &expected).unwrap();
+
+        let files = vec![("good.txt".to_string(), good), ("bad.txt".to_string(), bad)];
+        let err = verify_blob_shas(&files, &expected).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Hash verification failed for 1 file(s): bad.txt;")
+        );
+    }
+}
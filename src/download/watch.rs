@@ -0,0 +1,154 @@
+use std::path::Path;
+use std::time::Duration;
+
+use console::style;
+use log::{debug, warn};
+
+use crate::download::Downloader;
+use crate::error::{Context, Result};
+use crate::github::{fetch_latest_commit, parse_github_url};
+use crate::http::CachePolicy;
+use crate::progress::status;
+
+/// Parse a `--watch` interval: plain seconds, or a number followed by `s`, `m`, or `h`
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{}' (expected e.g. 30s, 5m, 1h)", value))?;
+    let multiplier = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        other => {
+            return Err(format!(
+                "unknown interval unit '{}' (use s, m, or h)",
+                other
+            ));
+        }
+    };
+    let secs = number
+        .checked_mul(multiplier)
+        .ok_or_else(|| "interval is too large".to_string())?;
+    if secs == 0 {
+        return Err("the interval must be at least one second".to_string());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Download `urls`, then check every `interval` whether the last commit touching
+/// each URL's path has changed, and download again when one has. Runs until the
+/// process is stopped; a failed check or download is retried at the next interval.
+///
+/// Unchanged paths cost one conditional request per check, which GitHub answers
+/// with a 304 that does not count against the rate limit.
+pub async fn watch(
+    downloader: &Downloader<'_>,
+    urls: &[String],
+    output: Option<&Path>,
+    interval: Duration,
+) -> Result<()> {
+    let mut seen = downloader
+        .endpoints()
+        .scope(latest_commits(downloader, urls))
+        .await?;
+    let quiet = downloader.options().quiet;
+    downloader.download_all(urls, output).await?;
+    report_watching(interval, quiet);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let current = match downloader
+            .endpoints()
+            .scope(latest_commits(downloader, urls))
+            .await
+        {
+            Ok(current) => current,
+            Err(err) => {
+                warn!("Unable to check for changes: {:#}", err);
+                continue;
+            }
+        };
+        if current == seen {
+            debug!("No new commits");
+            continue;
+        }
+
+        status(
+            quiet,
+            format!("{} New commits found, syncing...", style("↻").cyan().bold()),
+        );
+        match downloader.download_all(urls, output).await {
+            Ok(()) => {
+                seen = current;
+                report_watching(interval, quiet);
+            }
+            Err(err) => warn!("Sync failed, retrying at the next check: {:#}", err),
+        }
+    }
+}
+
+fn report_watching(interval: Duration, quiet: bool) {
+    status(
+        quiet,
+        format!(
+            "{} Watching for new commits every {}s (Ctrl-C to stop)...",
+            style("…").dim(),
+            interval.as_secs()
+        ),
+    );
+}
+
+/// SHA of the last commit touching each URL's path
+async fn latest_commits(
+    downloader: &Downloader<'_>,
+    urls: &[String],
+) -> Result<Vec<Option<String>>> {
+    let options = downloader.options();
+    // Always revalidate, so an unchanged path answers 304 without using quota
+    let cache = CachePolicy {
+        ttl: Duration::ZERO,
+        ..options.cache_policy()
+    };
+    let mut commits = Vec::with_capacity(urls.len());
+    for url in urls {
+        let request = parse_github_url(url)?;
+        let commit = fetch_latest_commit(
+            downloader.client(),
+            &request,
+            &request.path,
+            options.token,
+            downloader.rate_limit(),
+            cache,
+        )
+        .await
+        .with_context(|| format!("failed to check {} for new commits", url))?;
+        commits.push(commit.map(|commit| commit.sha));
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_interval("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_interval("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("5d").is_err());
+        assert!(parse_interval("m").is_err());
+        assert_eq!(
+            parse_interval("99999999999999999h"),
+            Err("interval is too large".to_string())
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! Error kinds library users and the exit status can tell apart.
+//!
+//! The engine returns [`Result`], failing with a [`GdlError`]. Where the cause is
+//! known that is one of its variants; anything else is [`GdlError::Other`], which
+//! keeps the context chain built with `anyhow`. [`GdlError::kind`] looks through that
+//! chain for a recognised error, or falls back to the network and I/O errors underneath.
+
+use std::fmt;
+use std::io;
+
+use reqwest::StatusCode;
+
+use crate::http::{SlowStream, StalledStream, TransientStatus};
+use crate::types::EmptyDownload;
+
+/// Result of the engine's operations
+pub type Result<T, E = GdlError> = std::result::Result<T, E>;
+
+/// Why an engine operation failed
+#[derive(Debug)]
+pub enum GdlError {
+    /// The URL is not a GitHub repository, tree, or blob URL
+    InvalidUrl(String),
+    /// The repository, ref, or path does not exist, or is hidden from this token
+    NotFound(String),
+    /// GitHub refused the request: the token is missing, invalid, or lacks access
+    AuthRequired(String),
+    /// The API rate limit is used up; `reset` is when it refills, in Unix seconds
+    RateLimited { reset: Option<u64>, message: String },
+    /// The request never got an answer: connection, TLS, or timeout failures
+    Network(String),
+    /// Some URLs of a `--keep-going` run failed while the others were downloaded
+    PartialFailure(String),
+    /// The user declined to go on, e.g. at the overwrite prompt
+    Cancelled(String),
+    /// The repository has no commits yet
+    EmptyRepository(String),
+    /// `--offline` is set and nothing needed is cached
+    Offline(String),
+    /// The git strategy was chosen but no `git` executable was found
+    GitMissing(String),
+    /// The requested path resolved but holds no files to download
+    EmptyDownload(EmptyDownload),
+    /// Any other failure, with the context chain that led to it
+    Other(anyhow::Error),
+}
+
+impl GdlError {
+    /// The kind of error a failed GitHub answer means, if it is one callers care about
+    pub fn from_status(status: StatusCode, message: String) -> Option<Self> {
+        match status {
+            StatusCode::NOT_FOUND => Some(GdlError::NotFound(message)),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Some(GdlError::AuthRequired(message))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            GdlError::InvalidUrl(_) => ErrorKind::InvalidUrl,
+            GdlError::NotFound(_) => ErrorKind::NotFound,
+            GdlError::AuthRequired(_) => ErrorKind::AuthRequired,
+            GdlError::RateLimited { .. } => ErrorKind::RateLimited,
+            GdlError::Network(_) => ErrorKind::Network,
+            GdlError::PartialFailure(_) => ErrorKind::PartialFailure,
+            GdlError::Cancelled(_) => ErrorKind::Cancelled,
+            GdlError::EmptyRepository(_) => ErrorKind::NotFound,
+            GdlError::EmptyDownload(_) | GdlError::Offline(_) | GdlError::GitMissing(_) => {
+                ErrorKind::Other
+            }
+            GdlError::Other(err) => error_kind(err),
+        }
+    }
+
+    /// The recognised error this one stands for: itself, or for [`GdlError::Other`]
+    /// the first variant other than `Other` in its chain
+    pub fn cause(&self) -> &GdlError {
+        match self {
+            GdlError::Other(err) => find_error(err).unwrap_or(self),
+            _ => self,
+        }
+    }
+
+    /// The error of type `E` in this error's chain, if there is one
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        match self {
+            GdlError::Other(err) => find_cause(err),
+            _ => (self as &(dyn std::error::Error + 'static)).downcast_ref::<E>(),
+        }
+    }
+
+    /// This error followed by its causes, looking inside [`GdlError::Other`]
+    pub fn chain(&self) -> impl Iterator<Item = &(dyn std::error::Error + 'static)> {
+        match self {
+            GdlError::Other(err) => causes(err),
+            err => vec![err as &(dyn std::error::Error + 'static)],
+        }
+        .into_iter()
+    }
+
+    /// The innermost error of the chain
+    pub fn root_cause(&self) -> &(dyn std::error::Error + 'static) {
+        self.chain().last().unwrap_or(self)
+    }
+
+    /// Add context to this error, like [`anyhow::Context`] does for results
+    pub fn context<C>(self, context: C) -> Self
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        match self {
+            GdlError::Other(err) => GdlError::Other(err.context(context)),
+            err => GdlError::Other(anyhow::Error::new(err).context(context)),
+        }
+    }
+}
+
+/// [`anyhow::Context`] for the engine's [`Result`]: the context joins the chain kept
+/// by [`GdlError::Other`]
+pub trait Context<T> {
+    fn context<C>(self, context: C) -> Result<T>
+    where
+        C: fmt::Display + Send + Sync + 'static;
+
+    fn with_context<C, F>(self, f: F) -> Result<T>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+        F: FnOnce() -> C;
+}
+
+impl<T, E: Into<GdlError>> Context<T> for Result<T, E> {
+    fn context<C>(self, context: C) -> Result<T>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        self.map_err(|err| err.into().context(context))
+    }
+
+    fn with_context<C, F>(self, f: F) -> Result<T>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|err| err.into().context(f()))
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context<C>(self, context: C) -> Result<T>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        self.ok_or_else(|| GdlError::Other(anyhow::Error::msg(context.to_string())))
+    }
+
+    fn with_context<C, F>(self, f: F) -> Result<T>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.ok_or_else(|| GdlError::Other(anyhow::Error::msg(f().to_string())))
+    }
+}
+
+impl From<EmptyDownload> for GdlError {
+    fn from(empty: EmptyDownload) -> Self {
+        GdlError::EmptyDownload(empty)
+    }
+}
+
+impl From<anyhow::Error> for GdlError {
+    fn from(err: anyhow::Error) -> Self {
+        // Unwrap a bare `GdlError`; one under context stays in the chain so the
+        // context isn't dropped
+        if err.source().is_none() {
+            return match err.downcast::<GdlError>() {
+                Ok(gdl) => gdl,
+                Err(err) => GdlError::Other(err),
+            };
+        }
+        GdlError::Other(err)
+    }
+}
+
+/// Errors commonly raised with `?` inside the engine, kept as [`GdlError::Other`]
+macro_rules! impl_from_other {
+    ($($source:ty),* $(,)?) => {
+        $(impl From<$source> for GdlError {
+            fn from(err: $source) -> Self {
+                GdlError::Other(err.into())
+            }
+        })*
+    };
+}
+
+impl_from_other!(
+    io::Error,
+    reqwest::Error,
+    serde_json::Error,
+    url::ParseError,
+    toml::de::Error,
+    zip::result::ZipError,
+    std::path::StripPrefixError,
+    reqwest::header::InvalidHeaderName,
+    reqwest::header::InvalidHeaderValue,
+    StalledStream,
+    SlowStream,
+);
+
+impl fmt::Display for GdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GdlError::InvalidUrl(message)
+            | GdlError::NotFound(message)
+            | GdlError::AuthRequired(message)
+            | GdlError::RateLimited { message, .. }
+            | GdlError::Network(message)
+            | GdlError::PartialFailure(message)
+            | GdlError::Cancelled(message)
+            | GdlError::EmptyRepository(message)
+            | GdlError::Offline(message)
+            | GdlError::GitMissing(message) => f.write_str(message),
+            GdlError::EmptyDownload(empty) => empty.fmt(f),
+            // Forwarded so `{:#}` still prints the whole chain
+            GdlError::Other(err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for GdlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            // The chain continues below the error this one displays
+            GdlError::Other(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+/// What went wrong, coarsely enough to match on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    InvalidUrl,
+    NotFound,
+    AuthRequired,
+    RateLimited,
+    Network,
+    Io,
+    PartialFailure,
+    Cancelled,
+    Other,
+}
+
+impl ErrorKind {
+    /// Process exit status for `ghdl`; 2 is shared with clap's usage errors
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::InvalidUrl => 2,
+            ErrorKind::NotFound => 3,
+            ErrorKind::AuthRequired => 4,
+            ErrorKind::RateLimited => 5,
+            ErrorKind::Network => 6,
+            ErrorKind::Io => 7,
+            ErrorKind::PartialFailure => 8,
+            ErrorKind::Cancelled => 9,
+        }
+    }
+}
+
+/// The recognised [`GdlError`] in an error's chain, if the engine raised one. One
+/// attached as context, like the `--keep-going` summary, wins over the error it wraps.
+pub fn find_error(err: &anyhow::Error) -> Option<&GdlError> {
+    err.downcast_ref::<GdlError>()
+        .into_iter()
+        .chain(
+            err.chain()
+                .filter_map(|cause| cause.downcast_ref::<GdlError>()),
+        )
+        .find_map(|gdl| match gdl {
+            GdlError::Other(inner) => find_error(inner),
+            gdl => Some(gdl),
+        })
+}
+
+/// The error of type `E` in `err`'s chain, looking inside [`GdlError::Other`]
+fn find_cause<E>(err: &anyhow::Error) -> Option<&E>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    if let Some(found) = err.downcast_ref::<E>() {
+        return Some(found);
+    }
+    for cause in err.chain() {
+        if let Some(GdlError::Other(inner)) = cause.downcast_ref::<GdlError>() {
+            return find_cause(inner);
+        }
+        if let Some(found) = cause.downcast_ref::<E>() {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// `err`'s chain, followed into the errors [`GdlError::Other`] wraps
+fn causes(err: &anyhow::Error) -> Vec<&(dyn std::error::Error + 'static)> {
+    let mut causes = Vec::new();
+    for cause in err.chain() {
+        if let Some(GdlError::Other(inner)) = cause.downcast_ref::<GdlError>() {
+            // The rest of this chain is the rest of `inner`'s
+            causes.extend(self::causes(inner));
+            break;
+        }
+        causes.push(cause);
+    }
+    causes
+}
+
+/// Classify an error: a [`GdlError`] wins, then stalled streams, 5xx answers, and
+/// `reqwest` failures count as network errors, then any I/O error
+pub fn error_kind(err: &anyhow::Error) -> ErrorKind {
+    if let Some(gdl) = find_error(err) {
+        return gdl.kind();
+    }
+    let causes = causes(err);
+    let network = causes.iter().any(|cause| {
+        cause.is::<StalledStream>()
+            || cause.is::<SlowStream>()
+            || cause.is::<TransientStatus>()
+            || cause.is::<reqwest::Error>()
+    });
+    if network {
+        ErrorKind::Network
+    } else if causes.iter().any(|cause| cause.is::<io::Error>()) {
+        ErrorKind::Io
+    } else {
+        ErrorKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::time::Duration;
+
+    #[test]
+    fn test_error_kind() {
+        let err = anyhow::Error::new(GdlError::RateLimited {
+            reset: Some(1_700_000_000),
+            message: "rate limit exceeded".into(),
+        })
+        .context("failed to list docs");
+        assert_eq!(error_kind(&err), ErrorKind::RateLimited);
+        assert!(matches!(
+            find_error(&err),
+            Some(GdlError::RateLimited {
+                reset: Some(1_700_000_000),
+                ..
+            })
+        ));
+        assert_eq!(
+            format!("{:#}", err),
+            "failed to list docs: rate limit exceeded"
+        );
+
+        let err = anyhow::Error::new(StalledStream {
+            idle: Duration::from_secs(30),
+        })
+        .context("download failed");
+        assert_eq!(error_kind(&err), ErrorKind::Network);
+
+        let err: anyhow::Error = Err::<(), _>(io::Error::other("disk full"))
+            .context("failed to write file")
+            .unwrap_err();
+        assert_eq!(error_kind(&err), ErrorKind::Io);
+        assert_eq!(error_kind(&anyhow::anyhow!("boom")), ErrorKind::Other);
+        assert_eq!(ErrorKind::NotFound.exit_code(), 3);
+
+        // An outer kind wins over the one of the error it wraps
+        let err = anyhow::Error::new(GdlError::NotFound("no such path".into()))
+            .context(GdlError::PartialFailure("1 of 2 URL(s) failed".into()));
+        assert_eq!(error_kind(&err), ErrorKind::PartialFailure);
+        assert_eq!(error_kind(&err).exit_code(), 8);
+    }
+
+    #[test]
+    fn test_gdl_error_chain() {
+        // A bare recognised error converts back to its variant
+        let err = GdlError::from(anyhow::Error::new(GdlError::NotFound("no docs".into())));
+        assert!(matches!(err, GdlError::NotFound(_)));
+
+        // Context keeps the variant findable and the chain printable
+        let err = err
+            .context("unable to list docs")
+            .context("download failed");
+        assert!(matches!(err, GdlError::Other(_)));
+        assert!(matches!(err.cause(), GdlError::NotFound(_)));
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(
+            format!("{:#}", err),
+            "download failed: unable to list docs: no docs"
+        );
+        assert_eq!(err.to_string(), "download failed");
+
+        // Errors below the engine's own are still classified
+        let err = GdlError::from(io::Error::other("disk full")).context("failed to write file");
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.root_cause().to_string(), "disk full");
+        assert!(err.downcast_ref::<io::Error>().is_some());
+
+        // ...also once main turns them into `anyhow::Error`
+        let err = anyhow::Error::new(err).context("run failed");
+        assert_eq!(error_kind(&err), ErrorKind::Io);
+        assert_eq!(
+            format!("{:#}", err),
+            "run failed: failed to write file: disk full"
+        );
+    }
+}
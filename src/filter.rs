@@ -0,0 +1,139 @@
+use log::{info, warn};
+
+/// Include and exclude globs limiting which files of a tree are downloaded.
+///
+/// Patterns are matched against paths relative to the requested directory. `*` and
+/// `?` stay within one path component, `**` spans any number of them, and a pattern
+/// without a `/` matches a file name at any depth, as in `.gitignore`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathFilter {
+    /// Repository path the patterns are relative to
+    pub root: String,
+    /// Keep only matching files; empty keeps everything
+    pub include: Vec<String>,
+    /// Drop matching files, even when an include pattern matches
+    pub exclude: Vec<String>,
+}
+
+impl PathFilter {
+    /// Whether the file at repository path `path` passes the filter
+    pub fn matches(&self, path: &str) -> bool {
+        let root = self.root.trim_matches('/');
+        let relative = if root.is_empty() {
+            path
+        } else {
+            path.strip_prefix(root)
+                .and_then(|rest| rest.strip_prefix('/'))
+                // A single-file request: the file itself is what is matched
+                .unwrap_or_else(|| path.rsplit('/').next().unwrap_or(path))
+        };
+
+        let matching = |pattern: &String| pattern_matches(pattern, relative);
+        (self.include.is_empty() || self.include.iter().any(matching))
+            && !self.exclude.iter().any(matching)
+    }
+}
+
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches('/');
+    if pattern.contains('/') {
+        return glob_components(
+            &pattern.split('/').collect::<Vec<_>>(),
+            &path.split('/').collect::<Vec<_>>(),
+        );
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    glob_component(pattern.as_bytes(), name.as_bytes())
+}
+
+/// Match path components, where a `**` component matches zero or more of them
+fn glob_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| glob_components(rest, &path[skip..])),
+        Some((first, rest)) => {
+            !path.is_empty()
+                && glob_component(first.as_bytes(), path[0].as_bytes())
+                && glob_components(rest, &path[1..])
+        }
+    }
+}
+
+/// Match one component against `*` and `?` wildcards
+fn glob_component(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| glob_component(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_component(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_component(rest, &name[1..]),
+    }
+}
+
+/// Keep only the tasks whose repository path passes `filter` (all of them without one)
+pub fn select_matching<T>(
+    tasks: Vec<T>,
+    filter: Option<&PathFilter>,
+    item_path: impl Fn(&T) -> &str,
+) -> Vec<T> {
+    let Some(filter) = filter else {
+        return tasks;
+    };
+
+    let total = tasks.len();
+    let selected: Vec<T> = tasks
+        .into_iter()
+        .filter(|task| filter.matches(item_path(task)))
+        .collect();
+    if selected.is_empty() {
+        warn!(
+            "None of the {} file(s) below /{} match the include and exclude filters",
+            total, filter.root
+        );
+    } else {
+        info!("Filters selected {} of {} file(s)", selected.len(), total);
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter {
+            root: "docs".into(),
+            include: vec!["*.md".into(), "images/**".into()],
+            exclude: vec!["drafts/**".into(), "README.md".into()],
+        };
+        assert!(filter.matches("docs/intro.md"));
+        assert!(filter.matches("docs/guide/setup.md"));
+        assert!(filter.matches("docs/images/a/logo.png"));
+        assert!(!filter.matches("docs/guide/setup.txt"));
+        assert!(!filter.matches("docs/drafts/next.md"));
+        assert!(!filter.matches("docs/README.md"));
+        assert!(!filter.matches("docs/guide/README.md"));
+
+        let filter = PathFilter {
+            root: String::new(),
+            include: vec!["src/**/*.rs".into(), "Cargo.?oml".into()],
+            exclude: Vec::new(),
+        };
+        assert!(filter.matches("src/main.rs"));
+        assert!(filter.matches("src/a/b/lib.rs"));
+        assert!(filter.matches("Cargo.toml"));
+        assert!(!filter.matches("tests/smoke.rs"));
+
+        let tasks = vec!["docs/a.md", "docs/b.txt"];
+        assert_eq!(select_matching(tasks.clone(), None, |task| task).len(), 2);
+        let filter = PathFilter {
+            root: "docs".into(),
+            include: vec!["*.md".into()],
+            exclude: Vec::new(),
+        };
+        assert_eq!(
+            select_matching(tasks, Some(&filter), |task| task),
+            vec!["docs/a.md"]
+        );
+    }
+}
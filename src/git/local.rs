@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use console::style;
+use log::{debug, warn};
+use tempfile::TempDir;
+use tokio::task::spawn_blocking;
+
+use crate::error::{Context, Result};
+use crate::git::sparse::{CopySettings, copy_from_checkout};
+use crate::git::utils::{ensure_git_available, git_output, run_git_command};
+use crate::progress::status;
+use crate::types::{DownloadContext, DownloadOptions, RequestInfo};
+
+/// Parameters for local repository export (owned values for blocking execution)
+struct LocalRepoParams {
+    request: RequestInfo,
+    url: String,
+    output: Option<PathBuf>,
+    local_repo: PathBuf,
+    settings: CopySettings,
+}
+
+/// Export the requested path from an existing clone without touching the network.
+///
+/// The ref is checked out into a temporary detached worktree, so the clone's own
+/// working tree, index, and current branch are left untouched. Returns the ref
+/// that was exported and the commit it resolved to.
+pub async fn download_via_local_repo(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    local_repo: &Path,
+    options: &DownloadOptions<'_>,
+) -> Result<(String, String)> {
+    let params = LocalRepoParams {
+        request: request.clone(),
+        url: url.to_string(),
+        output: output.cloned(),
+        local_repo: local_repo.to_path_buf(),
+        settings: CopySettings {
+            force: options.force,
+            on_conflict: options.on_conflict,
+            preserve_mtime: options.preserve_mtime,
+            symlinks: options.symlinks,
+            shard: options.shard,
+            filter: options.filter.clone(),
+            verify: options.verify,
+            sync: options.sync,
+            update_only: options.update_only,
+            delete: options.delete,
+            progress: options.progress.clone(),
+            quiet: options.quiet,
+            sanitize_names: options.sanitize_names,
+            prompt: ctx.prompt.clone(),
+        },
+    };
+
+    spawn_blocking(move || download_via_local_repo_blocking(params))
+        .await
+        .map_err(|err| anyhow!("local repository task failed: {}", err))?
+}
+
+fn download_via_local_repo_blocking(params: LocalRepoParams) -> Result<(String, String)> {
+    let LocalRepoParams {
+        mut request,
+        url,
+        output,
+        local_repo,
+        settings,
+    } = params;
+
+    ensure_git_available()?;
+
+    let repo_root = git_output(&["rev-parse", "--show-toplevel"], Some(&local_repo), &[])
+        .with_context(|| format!("{} is not a git repository", local_repo.display()))?;
+    let repo_root = PathBuf::from(repo_root);
+
+    warn_on_remote_mismatch(&repo_root, &request);
+
+    let commit = resolve_local_commit(&repo_root, &request.branch)?;
+    if request.branch.is_empty() {
+        // Mirror the remote default-branch lookup with the clone's current branch
+        request.branch = git_output(&["symbolic-ref", "--short", "HEAD"], Some(&repo_root), &[])
+            .unwrap_or_else(|_| "HEAD".to_string());
+    }
+    debug!(
+        "Resolved {} to commit {} in {}",
+        request.branch,
+        commit,
+        repo_root.display()
+    );
+
+    status(
+        settings.quiet,
+        format!(
+            "{} {} Preparing worktree...",
+            style("[1/2]").bold().dim(),
+            style("⟳").cyan()
+        ),
+    );
+    let worktree = TempWorktree::add(&repo_root, &commit)?;
+
+    copy_from_checkout(
+        &request,
+        worktree.path(),
+        output,
+        &url,
+        settings,
+        "local repository",
+    )?;
+    Ok((request.branch, commit))
+}
+
+/// Resolve a branch, tag, or commit to a commit id, trying remote-tracking branches too
+fn resolve_local_commit(repo_root: &Path, reference: &str) -> Result<String> {
+    for candidate in local_ref_candidates(reference) {
+        let spec = format!("{}^{{commit}}", candidate);
+        if let Ok(commit) = git_output(
+            &["rev-parse", "--verify", "--quiet", spec.as_str()],
+            Some(repo_root),
+            &[],
+        ) {
+            return Ok(commit);
+        }
+    }
+
+    Err(anyhow!(
+        "ref '{}' not found in local repository {} (fetch it first)",
+        reference,
+        repo_root.display()
+    )
+    .into())
+}
+
+/// Names to try, in order, when resolving `reference` in a local clone
+fn local_ref_candidates(reference: &str) -> Vec<String> {
+    if reference.is_empty() {
+        return vec!["HEAD".to_string()];
+    }
+
+    vec![
+        reference.to_string(),
+        format!("refs/remotes/origin/{}", reference),
+        format!("refs/tags/{}", reference),
+    ]
+}
+
+/// The URL names owner/repo; point out when the clone seems to be something else
+fn warn_on_remote_mismatch(repo_root: &Path, request: &RequestInfo) {
+    let Ok(remote) = git_output(&["remote", "get-url", "origin"], Some(repo_root), &[]) else {
+        return;
+    };
+
+    let expected = format!("{}/{}", request.owner, request.repo).to_lowercase();
+    let remote_path = remote.trim_end_matches('/').trim_end_matches(".git");
+    if !remote_path.to_lowercase().ends_with(&expected) {
+        warn!(
+            "Local repository origin {} does not look like {}/{}",
+            remote, request.owner, request.repo
+        );
+    }
+}
+
+/// A detached worktree in a temporary directory, removed on drop
+struct TempWorktree {
+    repo_root: PathBuf,
+    dir: TempDir,
+}
+
+impl TempWorktree {
+    fn add(repo_root: &Path, commit: &str) -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("ghdl-worktree-")
+            .tempdir()
+            .context("failed to create temporary worktree directory")?;
+        let dir_str = dir
+            .path()
+            .to_str()
+            .ok_or_else(|| anyhow!("temporary directory path contains invalid UTF-8"))?;
+
+        run_git_command(
+            &["worktree", "add", "--detach", "--force", dir_str, commit],
+            Some(repo_root),
+            &[],
+        )
+        .with_context(|| format!("failed to check out {} into a temporary worktree", commit))?;
+
+        Ok(Self {
+            repo_root: repo_root.to_path_buf(),
+            dir,
+        })
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Drop for TempWorktree {
+    fn drop(&mut self) {
+        let Some(dir) = self.dir.path().to_str() else {
+            return;
+        };
+        if let Err(err) = run_git_command(
+            &["worktree", "remove", "--force", dir],
+            Some(&self.repo_root),
+            &[],
+        ) {
+            warn!("Failed to remove temporary worktree {}: {}", dir, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_ref_candidates() {
+        assert_eq!(local_ref_candidates(""), vec!["HEAD"]);
+        assert_eq!(
+            local_ref_candidates("feature/x"),
+            vec![
+                "feature/x",
+                "refs/remotes/origin/feature/x",
+                "refs/tags/feature/x"
+            ]
+        );
+    }
+}
@@ -1,5 +1,7 @@
+pub mod local;
 pub mod sparse;
 pub mod utils;
 
+pub use local::*;
 pub use sparse::*;
 pub use utils::*;
@@ -1,72 +1,269 @@
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::anyhow;
 use console::style;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use log::{debug, info, warn};
 use sha2::{Digest, Sha256};
 use tokio::task::spawn_blocking;
 
-use crate::cache::repos_cache_dir;
-use crate::git::utils::{ensure_git_available, run_git_command, run_git_with_progress};
+use crate::cache::{record_repo_access, repos_cache_dir};
+use crate::cli::{GitTransport, OnConflict, SymlinkPolicy};
+use crate::download::sync::{local_blob_sha, mirror_output, skip_unchanged};
+use crate::download::verify::compare_blob_shas;
+use crate::error::{Context, GdlError, Result};
+use crate::filter::{PathFilter, select_matching};
+use crate::git::utils::{
+    checkout_blob_shas, collect_commit_times, ensure_git_available, git_lfs_available,
+    hash_worktree_files, is_full_commit_sha, is_shallow_repository, repo_url_for_transport,
+    run_git_command, run_git_with_progress,
+};
 use crate::github::types::{ContentType, GitHubContent};
-use crate::paths::{compute_base_and_default_output, ensure_directory, format_path_for_log};
-use crate::progress::{DownloadProgress, format_bytes};
-use crate::types::{DownloadOptions, FileCopyTask, RequestInfo, RequestKind};
+use crate::lfs::fetch_lfs_objects;
+use crate::overwrite::OverwritePrompt;
+use crate::paths::{
+    compute_base_and_default_output, disambiguate_targets, ensure_directory, format_path_for_log,
+    output_root,
+};
+use crate::progress::{DownloadProgress, ProgressSink, format_bytes, status};
+use crate::shard::{Shard, report_empty_shard, select_shard};
+use crate::types::{
+    DownloadContext, DownloadOptions, EmptyDownload, FileCopyTask, RequestInfo, RequestKind,
+};
+use crate::utils::{create_symlink, remove_symlinks_below, resolve_created_link, set_file_mtime};
+
+/// One requested path served from a shared checkout
+#[derive(Debug, Clone)]
+pub struct GitTarget {
+    pub request: RequestInfo,
+    pub url: String,
+}
+
+/// Parameters for git download implementation (owned values for blocking execution)
+struct GitDownloadParams {
+    targets: Vec<GitTarget>,
+    output: Option<PathBuf>,
+    token: Option<String>,
+    /// Host the repository is cloned from; see [`crate::github::Endpoints::archive`]
+    archive_base: String,
+    force: bool,
+    on_conflict: Option<OnConflict>,
+    preserve_mtime: bool,
+    symlinks: SymlinkPolicy,
+    shard: Option<Shard>,
+    filter: Option<PathFilter>,
+    verify: bool,
+    sync: bool,
+    update_only: bool,
+    delete: bool,
+    transport: GitTransport,
+    depth: u32,
+    filter_spec: Option<String>,
+    keep_git: bool,
+    lfs: bool,
+    offline: bool,
+    progress: Option<Arc<dyn ProgressSink>>,
+    quiet: bool,
+    sanitize_names: bool,
+    prompt: Arc<OverwritePrompt>,
+    multi: MultiProgress,
+}
 
 pub async fn download_via_git(
+    ctx: &DownloadContext,
     request: &RequestInfo,
     url: &str,
     output: Option<&PathBuf>,
     options: &DownloadOptions<'_>,
-    multi: &MultiProgress,
 ) -> Result<()> {
-    let request = request.clone();
-    let url = url.to_string();
-    let output = output.cloned();
-    let token = options.token.map(|t| t.to_string());
-    let force = options.force;
-    let multi = multi.clone();
-
-    spawn_blocking(move || download_via_git_blocking(request, url, output, token, force, multi))
+    let target = GitTarget {
+        request: request.clone(),
+        url: url.to_string(),
+    };
+    download_many_via_git(ctx, vec![target], output, options)
+        .await?
+        .pop()
+        .unwrap_or(Ok(()))
+}
+
+/// Download several paths of the same repository and ref with a single clone and checkout.
+///
+/// Fails as a whole only when the checkout itself fails; otherwise returns one result per
+/// target, in order.
+pub async fn download_many_via_git(
+    ctx: &DownloadContext,
+    targets: Vec<GitTarget>,
+    output: Option<&PathBuf>,
+    options: &DownloadOptions<'_>,
+) -> Result<Vec<Result<()>>> {
+    let requests: Vec<RequestInfo> = targets.iter().map(|t| t.request.clone()).collect();
+    let params = GitDownloadParams {
+        targets,
+        output: output.cloned(),
+        token: options.token.map(|t| t.to_string()),
+        // Task-local endpoints don't reach the blocking thread
+        archive_base: ctx.endpoints.archive().to_string(),
+        force: options.force,
+        on_conflict: options.on_conflict,
+        preserve_mtime: options.preserve_mtime,
+        symlinks: options.symlinks,
+        shard: options.shard,
+        filter: options.filter.clone(),
+        // Files smudged by git-lfs differ from their pointer blobs by design
+        verify: options.verify && !options.lfs,
+        sync: options.sync,
+        update_only: options.update_only,
+        delete: options.delete,
+        transport: options.git_transport,
+        depth: options.git_depth,
+        filter_spec: options.git_filter.clone(),
+        keep_git: options.keep_git,
+        lfs: options.lfs,
+        offline: options.offline,
+        progress: options.progress.clone(),
+        quiet: options.quiet,
+        sanitize_names: options.sanitize_names,
+        prompt: ctx.prompt.clone(),
+        multi: ctx.multi.clone(),
+    };
+
+    let copied = spawn_blocking(move || download_via_git_blocking(params))
         .await
         .map_err(|err| anyhow!("git download task failed: {}", err))??;
-    Ok(())
+
+    let mut results = Vec::with_capacity(copied.len());
+    for (request, copied) in requests.iter().zip(copied) {
+        let result = match copied {
+            // Covers checkouts where git-lfs is missing or `git lfs pull` failed;
+            // files that were already smudged are not pointers and are skipped.
+            Ok(files) if options.lfs => {
+                fetch_lfs_objects(&ctx.client, request, options.token, &files, &ctx.multi).await
+            }
+            Ok(_) => Ok(()),
+            Err(err) => Err(err),
+        };
+        results.push(result);
+    }
+    Ok(results)
 }
 
+/// Check out every target's path at once, then copy each target's files.
+///
+/// Returns each target's copied files (repository and local path) or its error.
+#[allow(clippy::type_complexity)]
 fn download_via_git_blocking(
-    request: RequestInfo,
-    url: String,
-    output: Option<PathBuf>,
-    token: Option<String>,
-    force: bool,
-    multi: MultiProgress,
-) -> Result<()> {
+    params: GitDownloadParams,
+) -> Result<Vec<Result<Vec<(String, PathBuf)>>>> {
+    let GitDownloadParams {
+        targets,
+        output,
+        token,
+        archive_base,
+        force,
+        on_conflict,
+        preserve_mtime,
+        symlinks,
+        shard,
+        filter,
+        verify,
+        sync,
+        update_only,
+        delete,
+        transport,
+        depth,
+        filter_spec,
+        keep_git,
+        lfs,
+        offline,
+        progress,
+        quiet,
+        sanitize_names,
+        prompt,
+        multi,
+    } = params;
+
     ensure_git_available()?;
 
-    let mut repo_url = url::Url::parse(&format!(
-        "https://github.com/{}/{}.git",
-        request.owner, request.repo
-    ))
-    .with_context(|| {
-        format!(
-            "failed to construct repository URL for {}/{}",
-            request.owner, request.repo
-        )
-    })?;
+    // Commit dates come from `git log`, which needs the history of every file, not
+    // just the tip commit; with the default blob filter that is commits and trees only
+    let depth = if preserve_mtime { 0 } else { depth };
 
-    if let Some(token) = token.as_deref() {
-        repo_url
-            .set_username(token.trim())
-            .map_err(|_| anyhow!("failed to encode token for git authentication"))?;
-        repo_url
-            .set_password(Some(""))
-            .map_err(|_| anyhow!("failed to set git authentication password"))?;
-    }
+    let request = targets
+        .first()
+        .map(|target| target.request.clone())
+        .ok_or_else(|| anyhow!("no paths requested for git download"))?;
+    let requests: Vec<&RequestInfo> = targets.iter().map(|target| &target.request).collect();
 
-    let repo_url_string = repo_url.to_string();
-    let repo_url_display = format!("https://github.com/{}/{}.git", request.owner, request.repo);
+    let repo_url_string = repo_url_for_transport(
+        &archive_base,
+        &request.owner,
+        &request.repo,
+        token.as_deref(),
+        transport,
+    )?;
+    let repo_url_display = match transport {
+        GitTransport::Https => repo_url_for_transport(
+            &archive_base,
+            &request.owner,
+            &request.repo,
+            None,
+            transport,
+        )?,
+        GitTransport::Ssh => repo_url_string.clone(),
+    };
+
+    // `clone --branch` cannot name a commit, so a pinned one is fetched on its own
+    let pinned = is_full_commit_sha(&request.branch);
+
+    if keep_git {
+        let repo_dir = output_root(output.unwrap_or_else(|| PathBuf::from(&request.repo)));
+        if repo_dir.exists()
+            && fs::read_dir(&repo_dir)
+                .with_context(|| format!("failed to read {}", repo_dir.display()))?
+                .next()
+                .is_some()
+        {
+            return Err(anyhow!(
+                "--keep-git clones into {}, which is not empty; choose another --output",
+                format_path_for_log(&repo_dir)
+            )
+            .into());
+        }
+        status(
+            quiet,
+            format!(
+                "{} {} Cloning {}/{} into {}...",
+                style("[1/1]").bold().dim(),
+                style("⟳").cyan(),
+                request.owner,
+                request.repo,
+                format_path_for_log(&repo_dir)
+            ),
+        );
+        let clone_args = clone_args(
+            (!pinned).then_some(request.branch.as_str()),
+            depth,
+            filter_spec.as_deref(),
+            &repo_url_string,
+            repo_dir
+                .to_str()
+                .ok_or_else(|| anyhow!("output directory path contains invalid UTF-8"))?,
+        );
+        clone_into_output(
+            &repo_dir,
+            &clone_args,
+            &repo_url_display,
+            &requests,
+            depth,
+            lfs,
+            &multi,
+        )?;
+        // The files stay where the checkout put them, so there is nothing to copy
+        return Ok(targets.iter().map(|_| Ok(Vec::new())).collect());
+    }
 
     // Use cache directory instead of temp directory
     let cache_dir = repos_cache_dir()?;
@@ -83,10 +280,13 @@ fn download_via_git_blocking(
         .ok_or_else(|| anyhow!("cache directory path contains invalid UTF-8"))?;
 
     // Show stage indicator for git operations
-    eprintln!(
-        "{} {} Preparing repository...",
-        style("[1/2]").bold().dim(),
-        style("⟳").cyan()
+    status(
+        quiet,
+        format!(
+            "{} {} Preparing repository...",
+            style("[1/2]").bold().dim(),
+            style("⟳").cyan()
+        ),
     );
 
     // Check if repo already exists and is valid
@@ -94,36 +294,39 @@ fn download_via_git_blocking(
         debug!("Found cached repository at {}", repo_dir.display());
         // Verify it's a valid git repository
         let is_valid = run_git_command(&["rev-parse", "--git-dir"], Some(&repo_dir), &[]).is_ok();
-        if is_valid {
+        if is_valid && offline {
+            info!("Offline: using cached repository without fetching updates");
+            false
+        } else if is_valid && pinned && has_commit(&repo_dir, &request.branch) {
+            debug!("Cached repository already has commit {}", request.branch);
+            false
+        } else if is_valid {
             debug!("Cached repository is valid, updating...");
 
-            // Show progress bar during fetch
-            let pb = multi.add(ProgressBar::new(100));
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {msg}")
-                    .expect("invalid progress bar template")
-                    .progress_chars("#>-"),
-            );
-            pb.set_message(format!(
-                "Fetching updates for {}/{}",
-                request.owner, request.repo
-            ));
-
-            // Update the repository
-            run_git_with_progress(
-                &[
-                    "fetch",
-                    "--progress",
-                    "--depth=1",
-                    "origin",
-                    request.branch.as_str(),
-                ],
+            // The cache may have been cloned over another transport or with another token
+            run_git_command(
+                &["remote", "set-url", "origin", repo_url_string.as_str()],
                 Some(&repo_dir),
-                &[7],
-                &pb,
+                &[3],
             )
-            .with_context(|| format!("failed to update cached repository {}", repo_url_display))?;
+            .context("failed to update the cached repository's remote URL")?;
+
+            // Update the repository; a full-history request deepens a shallow cache
+            let pb = git_progress_bar(
+                &multi,
+                format!("Fetching updates for {}/{}", request.owner, request.repo),
+            );
+            let depth_arg = format!("--depth={}", depth);
+            let mut fetch_args = vec!["fetch", "--progress"];
+            if depth > 0 {
+                fetch_args.push(&depth_arg);
+            } else if is_shallow_repository(&repo_dir) {
+                fetch_args.push("--unshallow");
+            }
+            fetch_args.extend(["origin", request.branch.as_str()]);
+            run_git_with_progress(&fetch_args, Some(&repo_dir), &[], &pb).with_context(|| {
+                format!("failed to update cached repository {}", repo_url_display)
+            })?;
 
             pb.finish_and_clear();
             false
@@ -141,63 +344,209 @@ fn download_via_git_blocking(
         true
     };
 
+    if needs_clone && offline {
+        return Err(GdlError::Offline(format!(
+            "offline: no cached clone of {}/{}; run once without --offline to populate the cache",
+            request.owner, request.repo
+        )));
+    }
+
     if needs_clone {
         debug!("Cloning repository into cache...");
 
-        // Show progress bar during clone
-        let pb = multi.add(ProgressBar::new(100));
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {msg}")
-                .expect("invalid progress bar template")
-                .progress_chars("#>-"),
+        let pb = git_progress_bar(
+            &multi,
+            format!("Cloning {}/{}", request.owner, request.repo),
         );
-        pb.set_message(format!("Cloning {}/{}", request.owner, request.repo));
-
-        let clone_args = vec![
-            "clone",
-            "--progress",
-            "--filter=blob:none",
-            "--depth=1",
-            "--branch",
-            request.branch.as_str(),
-            "--single-branch",
-            "--no-checkout",
-            repo_url_string.as_str(),
+        let clone_args = clone_args(
+            (!pinned).then_some(request.branch.as_str()),
+            depth,
+            filter_spec.as_deref(),
+            &repo_url_string,
             repo_dir_str,
-        ];
+        );
+        let clone_args: Vec<&str> = clone_args.iter().map(String::as_str).collect();
+        // The URL may carry a token
+        let url_index = clone_args.len() - 2;
 
-        run_git_with_progress(&clone_args, None, &[8], &pb)
+        run_git_with_progress(&clone_args, None, &[url_index], &pb)
             .with_context(|| format!("failed to clone {}", repo_url_display))?;
 
         pb.finish_and_clear();
+        if pinned {
+            fetch_commit(&repo_dir, &request.branch, depth, &multi)?;
+        }
+    }
+    if let Err(err) = record_repo_access(&repo_name) {
+        debug!("Unable to record cache access for {}: {}", repo_name, err);
     }
 
-    let sparse_checkout_needed = !request.path.is_empty() || request.kind == RequestKind::Blob;
-    if sparse_checkout_needed {
-        if request.kind == RequestKind::Blob {
-            run_git_command(
-                &["sparse-checkout", "init", "--no-cone"],
-                Some(&repo_dir),
-                &[],
+    match sparse_checkout_spec(&requests) {
+        Some(spec) => configure_sparse_checkout(&repo_dir, &spec)?,
+        None if !needs_clone => {
+            // A cached clone may still be sparse from an earlier run for a subpath
+            run_git_command(&["sparse-checkout", "disable"], Some(&repo_dir), &[])
+                .context("failed to disable sparse checkout")?;
+        }
+        None => {}
+    }
+
+    checkout_ref(&repo_dir, &request.branch, &multi)?;
+    if lfs {
+        pull_lfs_objects(&repo_dir, &requests);
+    }
+
+    let settings = CopySettings {
+        force,
+        on_conflict,
+        preserve_mtime,
+        symlinks,
+        shard,
+        filter,
+        verify,
+        sync,
+        update_only,
+        delete,
+        progress,
+        quiet,
+        sanitize_names,
+        prompt,
+    };
+    Ok(targets
+        .iter()
+        .map(|target| {
+            copy_from_checkout(
+                &target.request,
+                &repo_dir,
+                output.clone(),
+                &target.url,
+                settings.clone(),
+                "git sparse checkout",
             )
-            .context("failed to initialize sparse checkout (no-cone)")?;
-        } else {
-            run_git_command(&["sparse-checkout", "init", "--cone"], Some(&repo_dir), &[])
-                .context("failed to initialize sparse checkout (cone)")?;
+        })
+        .collect())
+}
+
+/// Clone into `repo_dir` for `--keep-git`, checking out only the requested paths (the
+/// first of which names the ref) and their LFS objects, and leave the clone pointing
+/// at `repo_url` (without the token a clone URL may carry). `repo_dir` must be empty
+/// or missing.
+fn clone_into_output(
+    repo_dir: &Path,
+    clone_args: &[String],
+    repo_url: &str,
+    requests: &[&RequestInfo],
+    depth: u32,
+    lfs: bool,
+    multi: &MultiProgress,
+) -> Result<()> {
+    let request = requests
+        .first()
+        .ok_or_else(|| anyhow!("no paths requested for git download"))?;
+    let pb = git_progress_bar(multi, format!("Cloning {}/{}", request.owner, request.repo));
+    let args: Vec<&str> = clone_args.iter().map(String::as_str).collect();
+    run_git_with_progress(&args, None, &[args.len() - 2], &pb)
+        .with_context(|| format!("failed to clone {}", repo_url))?;
+    pb.finish_and_clear();
+
+    // Fetching a pinned commit, checking out blobs a filtered clone left out and
+    // pulling LFS objects all go through origin, so it keeps the clone URL (and its
+    // token) until they are done. It is reset even when one of them fails, so the
+    // token never stays in the output's config.
+    let checked_out = (|| {
+        if is_full_commit_sha(&request.branch) {
+            fetch_commit(repo_dir, &request.branch, depth, multi)?;
+        }
+        if let Some(spec) = sparse_checkout_spec(requests) {
+            configure_sparse_checkout(repo_dir, &spec)?;
         }
+        checkout_ref(repo_dir, &request.branch, multi)?;
+        if lfs {
+            pull_lfs_objects(repo_dir, requests);
+        }
+        Ok::<_, GdlError>(())
+    })();
+    run_git_command(
+        &["remote", "set-url", "origin", repo_url],
+        Some(repo_dir),
+        &[],
+    )
+    .context("failed to set the clone's remote URL")?;
+    checked_out?;
 
-        let sparse_target = request.path.as_str();
+    info!(
+        "Cloned {}/{}:{} into {}",
+        request.owner,
+        request.repo,
+        request.branch,
+        format_path_for_log(repo_dir)
+    );
+    Ok(())
+}
 
+/// Apply `spec` to the clone at `repo_dir`
+fn configure_sparse_checkout(repo_dir: &Path, spec: &SparseSpec) -> Result<()> {
+    if spec.cone {
+        run_git_command(&["sparse-checkout", "init", "--cone"], Some(repo_dir), &[])
+            .context("failed to initialize sparse checkout (cone)")?;
+    } else {
         run_git_command(
-            &["sparse-checkout", "set", sparse_target],
-            Some(&repo_dir),
+            &["sparse-checkout", "init", "--no-cone"],
+            Some(repo_dir),
             &[],
         )
-        .with_context(|| format!("failed to configure sparse checkout for {}", sparse_target))?;
+        .context("failed to initialize sparse checkout (no-cone)")?;
+    }
+
+    let mut set_args = vec!["sparse-checkout", "set"];
+    set_args.extend(spec.patterns.iter().map(String::as_str));
+    run_git_command(&set_args, Some(repo_dir), &[]).with_context(|| {
+        format!(
+            "failed to configure sparse checkout for {}",
+            spec.patterns.join(", ")
+        )
+    })
+}
+
+/// Check out a branch, or a commit as a detached HEAD
+fn checkout_ref(repo_dir: &Path, reference: &str, multi: &MultiProgress) -> Result<()> {
+    let pb = git_progress_bar(multi, format!("Checking out {}", reference));
+    run_git_with_progress(
+        &["checkout", "--progress", reference],
+        Some(repo_dir),
+        &[],
+        &pb,
+    )
+    .with_context(|| format!("failed to checkout {}", reference))?;
+
+    pb.finish_and_clear();
+    Ok(())
+}
+
+/// Fetch one commit by its SHA into a clone, `depth` commits deep (all for 0)
+fn fetch_commit(repo_dir: &Path, commit: &str, depth: u32, multi: &MultiProgress) -> Result<()> {
+    let pb = git_progress_bar(multi, format!("Fetching commit {}", commit));
+    let depth_arg = format!("--depth={}", depth);
+    let mut args = vec!["fetch", "--progress"];
+    if depth > 0 {
+        args.push(&depth_arg);
     }
+    args.extend(["origin", commit]);
+    run_git_with_progress(&args, Some(repo_dir), &[], &pb)
+        .with_context(|| format!("failed to fetch commit {}", commit))?;
 
-    // Show progress bar during checkout
+    pb.finish_and_clear();
+    Ok(())
+}
+
+/// Whether the clone at `repo_dir` already has `commit`, so a pinned download needs no fetch
+fn has_commit(repo_dir: &Path, commit: &str) -> bool {
+    let object = format!("{}^{{commit}}", commit);
+    run_git_command(&["cat-file", "-e", &object], Some(repo_dir), &[]).is_ok()
+}
+
+/// Progress bar for a git command's progress output
+fn git_progress_bar(multi: &MultiProgress, message: String) -> ProgressBar {
     let pb = multi.add(ProgressBar::new(100));
     pb.set_style(
         ProgressStyle::default_bar()
@@ -205,45 +554,203 @@ fn download_via_git_blocking(
             .expect("invalid progress bar template")
             .progress_chars("#>-"),
     );
-    pb.set_message(format!("Checking out branch {}", request.branch));
+    pb.set_message(message);
+    pb
+}
 
-    run_git_with_progress(
-        &["checkout", "--progress", request.branch.as_str()],
-        Some(&repo_dir),
-        &[],
-        &pb,
-    )
-    .with_context(|| format!("failed to checkout branch {}", request.branch))?;
+/// Smudge the requested paths' LFS pointers with `git lfs pull` when git-lfs is
+/// installed; whatever is left is fetched through the batch API afterwards
+fn pull_lfs_objects(repo_dir: &Path, requests: &[&RequestInfo]) {
+    if !git_lfs_available() {
+        debug!("git-lfs is not installed; LFS objects will be fetched via the batch API");
+        return;
+    }
+    let includes = requests
+        .iter()
+        .map(|request| request.path.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut lfs_args = vec!["lfs", "pull"];
+    if requests.iter().all(|request| !request.path.is_empty()) {
+        lfs_args.extend(["--include", includes.as_str()]);
+    }
+    if let Err(err) = run_git_command(&lfs_args, Some(repo_dir), &[]) {
+        warn!(
+            "git lfs pull failed, falling back to the LFS batch API: {}",
+            err
+        );
+    }
+}
 
-    pb.finish_and_clear();
+/// Sparse-checkout configuration covering every request
+#[derive(Debug, PartialEq, Eq)]
+struct SparseSpec {
+    cone: bool,
+    patterns: Vec<String>,
+}
+
+/// Arguments of the initial clone: `depth` commits (all of them for 0) of one branch,
+/// the default one for `None`, without checking anything out yet
+fn clone_args(
+    branch: Option<&str>,
+    depth: u32,
+    filter_spec: Option<&str>,
+    repo_url: &str,
+    repo_dir: &str,
+) -> Vec<String> {
+    let mut args = vec!["clone".to_string(), "--progress".to_string()];
+    if let Some(filter_spec) = filter_spec {
+        args.push(format!("--filter={}", filter_spec));
+    }
+    if depth > 0 {
+        args.push(format!("--depth={}", depth));
+    }
+    if let Some(branch) = branch {
+        args.extend(["--branch".to_string(), branch.to_string()]);
+    }
+    args.extend(["--single-branch", "--no-checkout", repo_url, repo_dir].map(String::from));
+    args
+}
+
+/// Build one sparse-checkout spec for all requests; `None` means check out everything
+fn sparse_checkout_spec(requests: &[&RequestInfo]) -> Option<SparseSpec> {
+    if requests
+        .iter()
+        .any(|request| request.path.is_empty() && request.kind == RequestKind::Tree)
+    {
+        return None;
+    }
+
+    // Cone mode only understands directories; single files need full patterns
+    let cone = requests
+        .iter()
+        .all(|request| request.kind == RequestKind::Tree);
+    let mut patterns: Vec<String> = requests
+        .iter()
+        .map(|request| match (cone, request.kind) {
+            (true, _) => request.path.clone(),
+            (false, RequestKind::Blob) => format!("/{}", request.path),
+            (false, RequestKind::Tree) => format!("/{}/", request.path),
+        })
+        .collect();
+    patterns.sort();
+    patterns.dedup();
+
+    Some(SparseSpec { cone, patterns })
+}
+
+/// Per-run settings that affect how checked-out files are copied
+#[derive(Clone)]
+pub struct CopySettings {
+    pub force: bool,
+    pub on_conflict: Option<OnConflict>,
+    pub preserve_mtime: bool,
+    pub symlinks: SymlinkPolicy,
+    pub shard: Option<Shard>,
+    /// Manifest include/exclude globs
+    pub filter: Option<PathFilter>,
+    /// Compare copied files with the checkout's blob SHAs
+    pub verify: bool,
+    /// Skip files whose target already matches the checkout
+    pub sync: bool,
+    /// Skip them too, but ask before replacing the others
+    pub update_only: bool,
+    /// Remove files in the output directory that the checkout does not have
+    pub delete: bool,
+    pub progress: Option<Arc<dyn ProgressSink>>,
+    /// Leave out stage banners and notices
+    pub quiet: bool,
+    /// Rename files whose names Windows cannot store
+    pub sanitize_names: bool,
+    pub prompt: Arc<OverwritePrompt>,
+}
+
+/// Copy the requested files out of a checkout, returning each copied file's repository and local path
+pub fn copy_from_checkout(
+    request: &RequestInfo,
+    repo_dir: &Path,
+    output: Option<PathBuf>,
+    url: &str,
+    settings: CopySettings,
+    source: &str,
+) -> Result<Vec<(String, PathBuf)>> {
+    let CopySettings {
+        force,
+        on_conflict,
+        preserve_mtime,
+        symlinks,
+        shard,
+        filter,
+        verify,
+        sync,
+        update_only,
+        delete,
+        progress,
+        quiet,
+        sanitize_names,
+        prompt,
+    } = settings;
 
     let treat_as_single_file = request.kind == RequestKind::Blob;
     let (base_path, default_output_dir) =
-        compute_base_and_default_output(&request, treat_as_single_file, None);
-    let output_dir = output.unwrap_or(default_output_dir);
+        compute_base_and_default_output(request, treat_as_single_file, None);
+    let output_dir = output_root(output.unwrap_or(default_output_dir));
+
+    let tasks = build_git_copy_tasks(
+        request,
+        repo_dir,
+        &output_dir,
+        &base_path,
+        symlinks,
+        sanitize_names,
+    )?;
+    if tasks.is_empty() {
+        return Err(EmptyDownload::for_request(request).into());
+    }
+
+    let tasks = disambiguate_targets(tasks, |task| &task.item_path);
+    let tasks = select_matching(tasks, filter.as_ref(), |task| &task.item_path);
+    let tasks = select_shard(tasks, shard, |task| &task.item_path);
+    if tasks.is_empty() {
+        report_empty_shard(shard, quiet);
+        return Ok(Vec::new());
+    }
     ensure_directory(&output_dir)?;
 
-    let tasks = build_git_copy_tasks(&request, &repo_dir, &output_dir, &base_path)?;
+    let keep: Option<HashSet<PathBuf>> = (delete && !treat_as_single_file)
+        .then(|| tasks.iter().map(|task| task.target_path.clone()).collect());
+    let tasks = if sync || update_only {
+        skip_unchanged(tasks, |task| {
+            task.link_target
+                .is_none()
+                .then(|| local_blob_sha(&task.source_path))
+                .flatten()
+        })
+    } else {
+        tasks
+    };
     if tasks.is_empty() {
-        return Err(anyhow!(
-            "No files matched the requested path {} using git",
-            if request.path.is_empty() {
-                "/".to_string()
-            } else {
-                request.path.clone()
-            }
-        ));
+        mirror_output(&output_dir, keep.as_ref())?;
+        return Ok(Vec::new());
     }
 
-    // Check for file overwrites before proceeding
-    let target_paths = crate::overwrite::collect_target_paths(&tasks);
-    crate::overwrite::check_overwrite_permission(&target_paths, force)?;
+    // Check for file overwrites before proceeding; --sync replaces changed files
+    let tasks =
+        crate::overwrite::resolve_conflicts(tasks, on_conflict, force || sync, &prompt, |task| {
+            task.link_target
+                .is_none()
+                .then(|| std::fs::read(&task.source_path).ok())
+                .flatten()
+        })?;
+    if tasks.is_empty() {
+        mirror_output(&output_dir, keep.as_ref())?;
+        return Ok(Vec::new());
+    }
 
     let total_files = tasks.len();
     let total_bytes: u64 = tasks.iter().filter_map(|task| task.size).sum();
 
-    let mut progress =
-        DownloadProgress::with_multi_progress(total_files, total_bytes, Some(&multi));
+    let mut progress = DownloadProgress::new(total_files, total_bytes, progress);
 
     let target_display = if total_files == 1 && treat_as_single_file {
         format_path_for_log(&tasks[0].target_path)
@@ -251,14 +758,17 @@ fn download_via_git_blocking(
         format_path_for_log(&output_dir)
     };
 
-    eprintln!(
-        "{} {} Copying files...",
-        style("[2/2]").bold().dim(),
-        style("»").cyan()
+    status(
+        quiet,
+        format!(
+            "{} {} Copying files...",
+            style("[2/2]").bold().dim(),
+            style("»").cyan()
+        ),
     );
 
     info!(
-        "Downloading from {}/{}:{}:{} into {} (git sparse checkout)",
+        "Downloading from {}/{}:{}:{} into {} ({})",
         request.owner,
         request.repo,
         request.branch,
@@ -267,16 +777,40 @@ fn download_via_git_blocking(
         } else {
             &request.path
         },
-        target_display
+        target_display,
+        source
     );
 
+    let commit_times = if preserve_mtime {
+        if is_shallow_repository(repo_dir) {
+            warn!(
+                "The cached clone of {}/{} is shallow; files last changed before its oldest commit get that commit's date",
+                request.owner, request.repo
+            );
+        }
+        collect_commit_times(repo_dir, &request.path).unwrap_or_else(|err| {
+            warn!("Unable to read commit dates from git history: {}", err);
+            Default::default()
+        })
+    } else {
+        Default::default()
+    };
+
+    let mut copied = Vec::with_capacity(tasks.len());
     for task in tasks {
+        // A link left by an earlier --symlinks create run must not redirect the copy
+        remove_symlinks_below(&output_dir, &task.target_path)?;
         if let Some(parent) = task.target_path.parent() {
             fs::create_dir_all(parent).with_context(|| {
                 format!("failed to create output directory {}", parent.display())
             })?;
         }
         progress.log_start(&task.item_path, &task.target_path, task.size);
+        if let Some(link_target) = &task.link_target {
+            create_symlink(link_target, &task.target_path)?;
+            progress.record_download(&task.item_path, &task.target_path, task.size);
+            continue;
+        }
         fs::copy(&task.source_path, &task.target_path).with_context(|| {
             format!(
                 "failed to copy {} to {}",
@@ -284,19 +818,40 @@ fn download_via_git_blocking(
                 task.target_path.display()
             )
         })?;
+        if let Some(&secs) = commit_times.get(&task.item_path)
+            && let Err(err) = set_file_mtime(&task.target_path, secs)
+        {
+            warn!("Unable to preserve mtime for {}: {}", task.item_path, err);
+        }
         progress.record_download(&task.item_path, &task.target_path, task.size);
+        copied.push((task.item_path, task.target_path));
     }
 
     progress.finish();
 
+    if verify {
+        // The copies are byte-for-byte the checkout's files, which git hashes through
+        // the same filters it applies on commit
+        compare_blob_shas(
+            &copied,
+            &checkout_blob_shas(repo_dir, &request.path)?,
+            |files| {
+                let paths: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+                hash_worktree_files(repo_dir, &paths)
+            },
+        )?;
+    }
+    mirror_output(&output_dir, keep.as_ref())?;
+
     info!(
-        "Finished downloading {} file(s) ({} total) from {} using git.",
+        "Finished downloading {} file(s) ({} total) from {} using {}.",
         progress.downloaded_files,
         format_bytes(progress.downloaded_bytes),
-        url
+        url,
+        source
     );
 
-    Ok(())
+    Ok(copied)
 }
 
 fn build_git_copy_tasks(
@@ -304,9 +859,11 @@ fn build_git_copy_tasks(
     repo_dir: &Path,
     output_dir: &Path,
     base_path: &Path,
+    symlinks: SymlinkPolicy,
+    sanitize: bool,
 ) -> Result<Vec<FileCopyTask>> {
     if request.kind == RequestKind::Blob {
-        return build_git_file_task(request, repo_dir, output_dir, base_path)
+        return build_git_file_task(request, repo_dir, output_dir, base_path, sanitize)
             .map(|task| vec![task]);
     }
 
@@ -324,7 +881,15 @@ fn build_git_copy_tasks(
             } else {
                 request.path.clone()
             }
-        ));
+        )
+        .into());
+    }
+
+    let canonical_root = fs::canonicalize(repo_dir)
+        .with_context(|| format!("failed to resolve {}", repo_dir.display()))?;
+    let mut visited_dirs = HashSet::new();
+    if let Ok(canonical_source) = fs::canonicalize(&source_root) {
+        visited_dirs.insert(canonical_source);
     }
 
     let mut stack = vec![source_root];
@@ -354,15 +919,62 @@ fn build_git_copy_tasks(
                 continue;
             }
 
-            if metadata.file_type().is_symlink() {
-                warn!(
-                    "Skipping symlink {} encountered during git sparse checkout.",
-                    path.display()
-                );
-                continue;
-            }
+            let mut link_target = None;
+            let mut size = metadata.len();
 
-            if !metadata.is_file() {
+            if metadata.file_type().is_symlink() {
+                match symlinks {
+                    SymlinkPolicy::Skip => {
+                        warn!(
+                            "Skipping symlink {} encountered during git sparse checkout.",
+                            path.display()
+                        );
+                        continue;
+                    }
+                    SymlinkPolicy::Create => {
+                        let target = fs::read_link(&path).with_context(|| {
+                            format!("failed to read symlink {}", path.display())
+                        })?;
+                        let repo_relative = relative.to_string_lossy().replace('\\', "/");
+                        if resolve_created_link(
+                            &repo_relative,
+                            &target.to_string_lossy(),
+                            base_path,
+                        )
+                        .is_none()
+                        {
+                            warn!(
+                                "Skipping symlink {} -> {}: target is outside the output directory.",
+                                repo_relative,
+                                target.display()
+                            );
+                            continue;
+                        }
+                        size = 0;
+                        link_target = Some(target);
+                    }
+                    SymlinkPolicy::Follow => {
+                        let Some(resolved) = resolve_within_repo(&canonical_root, &path) else {
+                            warn!(
+                                "Skipping symlink {} whose target is missing or outside the repository.",
+                                path.display()
+                            );
+                            continue;
+                        };
+                        if resolved.is_dir() {
+                            if visited_dirs.insert(resolved) {
+                                stack.push(path);
+                            }
+                            continue;
+                        }
+                        size = fs::metadata(&resolved)
+                            .with_context(|| {
+                                format!("failed to inspect symlink target {}", resolved.display())
+                            })?
+                            .len();
+                    }
+                }
+            } else if !metadata.is_file() {
                 continue;
             }
 
@@ -376,18 +988,19 @@ fn build_git_copy_tasks(
                 name,
                 path: repo_relative.clone(),
                 url: String::new(),
-                size: Some(metadata.len()),
+                size: Some(size),
                 download_url: None,
                 content_type: ContentType::File,
                 sha: None,
             };
-            let relative_target = crate::paths::relative_path(base_path, &content)?;
+            let relative_target = crate::paths::relative_path(base_path, &content, sanitize)?;
             let target_path = output_dir.join(&relative_target);
             tasks.push(FileCopyTask {
                 item_path: content.path,
                 source_path: path,
                 target_path,
-                size: Some(metadata.len()),
+                size: Some(size),
+                link_target,
             });
         }
     }
@@ -395,14 +1008,25 @@ fn build_git_copy_tasks(
     Ok(tasks)
 }
 
+/// Canonicalize a symlink's destination, rejecting targets outside the repository
+fn resolve_within_repo(canonical_root: &Path, link: &Path) -> Option<PathBuf> {
+    let resolved = fs::canonicalize(link).ok()?;
+    if resolved.starts_with(canonical_root) && !resolved.starts_with(canonical_root.join(".git")) {
+        Some(resolved)
+    } else {
+        None
+    }
+}
+
 fn build_git_file_task(
     request: &RequestInfo,
     repo_dir: &Path,
     output_dir: &Path,
     base_path: &Path,
+    sanitize: bool,
 ) -> Result<FileCopyTask> {
     if request.path.is_empty() {
-        return Err(anyhow!("File download requested but no path provided"));
+        return Err(anyhow!("File download requested but no path provided").into());
     }
 
     let source_path = repo_dir.join(&request.path);
@@ -414,7 +1038,7 @@ fn build_git_file_task(
     })?;
 
     if !metadata.is_file() {
-        return Err(anyhow!("requested path {} is not a file", request.path));
+        return Err(anyhow!("requested path {} is not a file", request.path).into());
     }
 
     let repo_relative = Path::new(&request.path)
@@ -434,7 +1058,7 @@ fn build_git_file_task(
         content_type: ContentType::File,
         sha: None,
     };
-    let relative_target = crate::paths::relative_path(base_path, &content)?;
+    let relative_target = crate::paths::relative_path(base_path, &content, sanitize)?;
     let target_path = output_dir.join(&relative_target);
 
     Ok(FileCopyTask {
@@ -442,5 +1066,123 @@ fn build_git_file_task(
         source_path,
         target_path,
         size: Some(metadata.len()),
+        link_target: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str, kind: RequestKind) -> RequestInfo {
+        RequestInfo {
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            branch: "main".to_string(),
+            path: path.to_string(),
+            has_trailing_slash: false,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_sparse_checkout_spec() {
+        let docs = request("docs", RequestKind::Tree);
+        let src = request("src/lib", RequestKind::Tree);
+        let readme = request("README.md", RequestKind::Blob);
+        let root = request("", RequestKind::Tree);
+
+        // Directories only: cone mode with every path
+        assert_eq!(
+            sparse_checkout_spec(&[&src, &docs, &docs]),
+            Some(SparseSpec {
+                cone: true,
+                patterns: vec!["docs".to_string(), "src/lib".to_string()],
+            })
+        );
+
+        // Any single file switches to anchored no-cone patterns
+        assert_eq!(
+            sparse_checkout_spec(&[&docs, &readme]),
+            Some(SparseSpec {
+                cone: false,
+                patterns: vec!["/README.md".to_string(), "/docs/".to_string()],
+            })
+        );
+
+        // The repository root needs a full checkout
+        assert_eq!(sparse_checkout_spec(&[&docs, &root]), None);
+    }
+
+    #[test]
+    fn test_clone_args() {
+        let args = clone_args(
+            Some("main"),
+            1,
+            Some("blob:none"),
+            "https://host/o/r.git",
+            "dir",
+        );
+        assert_eq!(
+            args,
+            [
+                "clone",
+                "--progress",
+                "--filter=blob:none",
+                "--depth=1",
+                "--branch",
+                "main",
+                "--single-branch",
+                "--no-checkout",
+                "https://host/o/r.git",
+                "dir",
+            ]
+        );
+
+        // Full history of the default branch, without a filter
+        let args = clone_args(None, 0, None, "https://host/o/r.git", "dir");
+        assert!(!args.iter().any(|arg| {
+            arg.starts_with("--depth") || arg.starts_with("--filter") || arg == "--branch"
+        }));
+        assert!(is_full_commit_sha(&"a1".repeat(20)));
+        assert!(!is_full_commit_sha("a1b2c3d"));
+        assert_eq!(args[args.len() - 2], "https://host/o/r.git");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_created_symlinks_stay_in_the_output() {
+        let repo = tempfile::tempdir().unwrap();
+        let docs = repo.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(docs.join("guide.md"), "# Guide\n").unwrap();
+        std::os::unix::fs::symlink("guide.md", docs.join("latest")).unwrap();
+        std::os::unix::fs::symlink("/home/u/.bashrc", docs.join("absolute")).unwrap();
+        std::os::unix::fs::symlink("../../outside", docs.join("escaping")).unwrap();
+        // Inside the repository but outside the requested `docs/`
+        fs::create_dir(repo.path().join("src")).unwrap();
+        std::os::unix::fs::symlink("../src", docs.join("sibling")).unwrap();
+
+        let mut tasks = build_git_copy_tasks(
+            &request("docs", RequestKind::Tree),
+            repo.path(),
+            Path::new("out"),
+            Path::new("docs"),
+            SymlinkPolicy::Create,
+            false,
+        )
+        .unwrap();
+        tasks.sort_by(|a, b| a.item_path.cmp(&b.item_path));
+        let links: Vec<_> = tasks
+            .iter()
+            .map(|task| (task.item_path.as_str(), task.link_target.as_deref()))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                ("docs/guide.md", None),
+                ("docs/latest", Some(Path::new("guide.md")))
+            ]
+        );
+    }
+}
@@ -1,11 +1,15 @@
-use std::io::{BufRead, BufReader};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use std::process::{Command as StdCommand, Stdio};
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::anyhow;
 use indicatif::ProgressBar;
 use regex::Regex;
 
+use crate::cli::GitTransport;
+use crate::error::{Context, GdlError, Result};
+
 pub fn git_available() -> bool {
     StdCommand::new("git")
         .arg("--version")
@@ -16,12 +20,23 @@ pub fn git_available() -> bool {
         .unwrap_or(false)
 }
 
+/// Check whether the git-lfs extension is installed
+pub fn git_lfs_available() -> bool {
+    StdCommand::new("git")
+        .args(["lfs", "version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
 pub fn ensure_git_available() -> Result<()> {
     if git_available() {
         Ok(())
     } else {
-        Err(anyhow!(
-            "git executable not found in PATH; install git or choose `--strategy api`"
+        Err(GdlError::GitMissing(
+            "git executable not found in PATH; install git or choose `--strategy api`".into(),
         ))
     }
 }
@@ -63,12 +78,266 @@ pub fn run_git_command(
             command_display,
             output.status,
             detail
-        ));
+        )
+        .into());
     }
 
     Ok(())
 }
 
+/// Build the clone URL for a repository on the host at `base` (the archive base, e.g.
+/// `https://github.com`) over the chosen transport
+pub fn repo_url_for_transport(
+    base: &str,
+    owner: &str,
+    repo: &str,
+    token: Option<&str>,
+    transport: GitTransport,
+) -> Result<String> {
+    match transport {
+        GitTransport::Https => authenticated_repo_url(base, owner, repo, token),
+        // SSH authenticates with the user's keys; a token has no place in the URL
+        GitTransport::Ssh => {
+            let host = url::Url::parse(base)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .ok_or_else(|| anyhow!("no host in repository base URL {}", base))?;
+            Ok(format!("git@{}:{}/{}.git", host, owner, repo))
+        }
+    }
+}
+
+/// Build the HTTPS clone URL for a repository, embedding the token when provided
+fn authenticated_repo_url(
+    base: &str,
+    owner: &str,
+    repo: &str,
+    token: Option<&str>,
+) -> Result<String> {
+    let mut repo_url = url::Url::parse(&format!("{}/{}/{}.git", base, owner, repo))
+        .with_context(|| format!("failed to construct repository URL for {}/{}", owner, repo))?;
+
+    if let Some(token) = token {
+        repo_url
+            .set_username(token.trim())
+            .map_err(|_| anyhow!("failed to encode token for git authentication"))?;
+        repo_url
+            .set_password(Some(""))
+            .map_err(|_| anyhow!("failed to set git authentication password"))?;
+    }
+
+    Ok(repo_url.to_string())
+}
+
+/// List branch and tag names advertised by a remote repository
+pub fn list_remote_refs(repo_url: &str) -> Result<Vec<String>> {
+    let output = git_output(&["ls-remote", "--heads", "--tags", repo_url], None, &[3])?;
+    Ok(parse_ls_remote(&output))
+}
+
+/// Parse `git ls-remote` output into short branch/tag names
+fn parse_ls_remote(output: &str) -> Vec<String> {
+    let mut names: Vec<String> = output
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter_map(|reference| {
+            reference
+                .strip_prefix("refs/heads/")
+                .or_else(|| reference.strip_prefix("refs/tags/"))
+        })
+        .map(|name| name.trim_end_matches("^{}").to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Run a git command and return its trimmed stdout
+pub fn git_output(
+    args: &[&str],
+    workdir: Option<&Path>,
+    redacted_indices: &[usize],
+) -> Result<String> {
+    let mut cmd = StdCommand::new("git");
+    cmd.args(args);
+    if let Some(dir) = workdir {
+        cmd.current_dir(dir);
+    }
+    cmd.env("GIT_TERMINAL_PROMPT", "0");
+
+    let command_display = format_git_command(args, redacted_indices);
+    let output = cmd
+        .output()
+        .with_context(|| format!("failed to execute git {}", command_display))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!(
+            "git {} exited with status {}: {}",
+            command_display,
+            output.status,
+            stderr.trim()
+        )
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether `reference` is a full 40-character commit SHA rather than a branch or tag
+pub fn is_full_commit_sha(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Whether the clone at `repo_dir` is missing history, so a full fetch must
+/// `--unshallow` it
+pub fn is_shallow_repository(repo_dir: &Path) -> bool {
+    git_output(
+        &["rev-parse", "--is-shallow-repository"],
+        Some(repo_dir),
+        &[],
+    )
+    .is_ok_and(|output| output == "true")
+}
+
+/// Map each file under `pathspec` to the committer timestamp of the last commit touching it.
+///
+/// Only the history available locally is inspected, so in a shallow clone files last
+/// changed before the fetched commits report the date of the oldest one.
+pub fn collect_commit_times(repo_dir: &Path, pathspec: &str) -> Result<HashMap<String, u64>> {
+    let pathspec = if pathspec.is_empty() { "." } else { pathspec };
+    let output = git_output(
+        &[
+            "log",
+            "-z",
+            "--format=%x01%ct",
+            "--name-only",
+            "--no-renames",
+            "HEAD",
+            "--",
+            pathspec,
+        ],
+        Some(repo_dir),
+        &[],
+    )?;
+    Ok(parse_commit_times(&output))
+}
+
+/// Parse `git log -z --format=%x01%ct --name-only` output, keeping the newest timestamp
+/// per path. Records are NUL-separated: a `\x01`-prefixed timestamp, then the commit's
+/// paths verbatim (unquoted), the first one preceded by a newline.
+fn parse_commit_times(output: &str) -> HashMap<String, u64> {
+    let mut times = HashMap::new();
+    let mut current: Option<u64> = None;
+    let mut first_path = false;
+
+    for record in output.split('\0') {
+        if let Some(stamp) = record.strip_prefix('\x01') {
+            current = stamp.trim().parse::<u64>().ok();
+            first_path = true;
+            continue;
+        }
+
+        let path = if first_path {
+            record.strip_prefix('\n').unwrap_or(record)
+        } else {
+            record
+        };
+        first_path = false;
+        if path.is_empty() {
+            continue;
+        }
+
+        if let Some(secs) = current {
+            times.entry(path.to_string()).or_insert(secs);
+        }
+    }
+
+    times
+}
+
+/// Map each regular file under `pathspec` in the checkout's `HEAD` to its blob SHA-1.
+/// Symlinks and submodules are left out.
+pub fn checkout_blob_shas(repo_dir: &Path, pathspec: &str) -> Result<HashMap<String, String>> {
+    let pathspec = if pathspec.is_empty() { "." } else { pathspec };
+    let output = git_output(
+        &["ls-tree", "-r", "-z", "--full-tree", "HEAD", "--", pathspec],
+        Some(repo_dir),
+        &[],
+    )?;
+    Ok(parse_ls_tree(&output))
+}
+
+/// Blob SHA-1 of each checkout file in `paths` (relative to `repo_dir`), hashed by
+/// `git hash-object` so clean filters and line-ending conversion apply as on commit.
+/// A checkout written with `core.autocrlf` or `eol=crlf` then still matches its tree.
+pub fn hash_worktree_files(repo_dir: &Path, paths: &[&str]) -> Result<Vec<String>> {
+    if let Some(path) = paths.iter().find(|path| path.contains('\n')) {
+        return Err(anyhow!("cannot hash {:?}: its name contains a newline", path).into());
+    }
+
+    let mut child = StdCommand::new("git")
+        .args(["hash-object", "--stdin-paths"])
+        .current_dir(repo_dir)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to execute git hash-object")?;
+
+    // Written from another thread so a full stdout pipe cannot stall the input
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("failed to open git hash-object input"))?;
+    let input: String = paths.iter().map(|path| format!("{}\n", path)).collect();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .context("failed to wait for git hash-object")?;
+    writer
+        .join()
+        .map_err(|_| anyhow!("git hash-object input thread panicked"))?
+        .context("failed to write paths to git hash-object")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git hash-object exited with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let shas: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect();
+    if shas.len() != paths.len() {
+        return Err(anyhow!(
+            "git hash-object returned {} hashes for {} files",
+            shas.len(),
+            paths.len()
+        )
+        .into());
+    }
+    Ok(shas)
+}
+
+/// Parse `git ls-tree -r -z` output: `<mode> <type> <sha>\t<path>` records ended by NUL
+fn parse_ls_tree(output: &str) -> HashMap<String, String> {
+    output
+        .split('\0')
+        .filter_map(|record| {
+            let (meta, path) = record.split_once('\t')?;
+            let mut fields = meta.split_whitespace();
+            let (mode, kind, sha) = (fields.next()?, fields.next()?, fields.next()?);
+            (kind == "blob" && mode != "120000").then(|| (path.to_string(), sha.to_string()))
+        })
+        .collect()
+}
+
 fn format_git_command(args: &[&str], redacted_indices: &[usize]) -> String {
     args.iter()
         .enumerate()
@@ -129,7 +398,8 @@ pub fn run_git_with_progress(
             command_display,
             status,
             detail
-        ));
+        )
+        .into());
     }
 
     Ok(())
@@ -218,3 +488,115 @@ fn parse_git_progress<R: BufRead>(reader: R, progress_bar: &ProgressBar) -> Vec<
 
     error_messages
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_url_for_transport() {
+        const GH: &str = "https://github.com";
+        assert_eq!(
+            repo_url_for_transport(GH, "o", "r", None, GitTransport::Https).unwrap(),
+            "https://github.com/o/r.git"
+        );
+        assert_eq!(
+            repo_url_for_transport(GH, "o", "r", Some("tok"), GitTransport::Https).unwrap(),
+            "https://tok@github.com/o/r.git"
+        );
+        // The token is never embedded in SSH URLs
+        assert_eq!(
+            repo_url_for_transport(GH, "o", "r", Some("tok"), GitTransport::Ssh).unwrap(),
+            "git@github.com:o/r.git"
+        );
+        // A mirror's host and path prefix replace github.com's
+        let mirror = "https://git.example.com/gh";
+        assert_eq!(
+            repo_url_for_transport(mirror, "o", "r", Some("tok"), GitTransport::Https).unwrap(),
+            "https://tok@git.example.com/gh/o/r.git"
+        );
+        assert_eq!(
+            repo_url_for_transport(mirror, "o", "r", None, GitTransport::Ssh).unwrap(),
+            "git@git.example.com:o/r.git"
+        );
+    }
+
+    #[test]
+    fn test_parse_ls_tree() {
+        let output = [
+            "100644 blob aaa\tdocs/a.md",
+            "100755 blob bbb\tdocs/run sh",
+            "120000 blob ccc\tdocs/link",
+            "160000 commit ddd\tvendor/sub",
+        ]
+        .join("\0");
+        let shas = parse_ls_tree(&output);
+        assert_eq!(shas.len(), 2);
+        assert_eq!(shas["docs/a.md"], "aaa");
+        assert_eq!(shas["docs/run sh"], "bbb");
+    }
+
+    #[test]
+    fn test_parse_ls_remote() {
+        let output = "a1\trefs/heads/main\nb2\trefs/heads/feature/x\nc3\trefs/tags/v1.0\nd4\trefs/tags/v1.0^{}\ne5\tHEAD\n";
+        assert_eq!(
+            parse_ls_remote(output),
+            vec![
+                "feature/x".to_string(),
+                "main".to_string(),
+                "v1.0".to_string()
+            ]
+        );
+        assert!(parse_ls_remote("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_commit_times() {
+        let output = "\x011700000300\0\nsrc/lib.rs\0 café .md\0\x011700000200\0\nsrc/lib.rs\0src/main.rs\0\x01garbage\0\nignored.txt\0";
+        let times = parse_commit_times(output);
+
+        // Newest commit wins for files touched more than once
+        assert_eq!(times.get("src/lib.rs"), Some(&1700000300));
+        assert_eq!(times.get("src/main.rs"), Some(&1700000200));
+        // Non-ASCII names and surrounding spaces are kept as they are
+        assert_eq!(times.get(" café .md"), Some(&1700000300));
+        // Entries after an unparsable timestamp are skipped
+        assert!(!times.contains_key("ignored.txt"));
+        assert!(parse_commit_times("").is_empty());
+    }
+
+    #[test]
+    fn test_hash_worktree_files_applies_eol_conversion() {
+        if !git_available() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        let git = |args: &[&str]| git_output(args, Some(repo), &[]).unwrap();
+        git(&["init", "-q"]);
+        std::fs::write(repo.join(".gitattributes"), "*.txt text eol=crlf\n").unwrap();
+        std::fs::write(repo.join("notes.txt"), "a\nb\n").unwrap();
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=t",
+            "-c",
+            "user.email=t@example.com",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+        std::fs::remove_file(repo.join("notes.txt")).unwrap();
+        git(&["checkout", "--", "notes.txt"]);
+        assert_eq!(
+            std::fs::read(repo.join("notes.txt")).unwrap(),
+            b"a\r\nb\r\n"
+        );
+
+        let expected = checkout_blob_shas(repo, "").unwrap();
+        assert_eq!(
+            hash_worktree_files(repo, &["notes.txt"]).unwrap(),
+            vec![expected["notes.txt"].clone()]
+        );
+    }
+}
@@ -1,40 +1,133 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::anyhow;
 use log::{debug, warn};
-use reqwest::Client;
-use reqwest::header::AUTHORIZATION;
-use serde::Deserialize;
-
-use crate::github::types::GitTreeEntryType;
-use crate::github::types::{GitHubContent, GitTreeResponse, RepositoryInfo};
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::RateFormat;
+use crate::error::{Context, GdlError, Result};
+use crate::git::utils::is_full_commit_sha;
+use crate::github::types::{
+    AuthenticatedUser, CommitResponse, ContentType, GitHubContent, GitRefResponse, GitTreeResponse,
+    NamedRef, RepositoryInfo, SignatureVerification, SignedObject,
+};
+use crate::github::types::{GitTreeEntry, GitTreeEntryType};
+use crate::http::CachePolicy;
 use crate::rate_limit::RateLimitTracker;
 use crate::types::{FileMetadata, RequestInfo};
 
-pub async fn fetch_github_contents(
-    client: &Client,
-    request: &RequestInfo,
-    folder_path: &str,
-    token: Option<&str>,
-    rate_limit: Arc<RateLimitTracker>,
-    no_cache: bool,
-) -> Result<Vec<GitHubContent>> {
+/// REST API endpoint used unless `GHDL_API_BASE` or the config file's `api_url` is set
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Host of the zip and tar.gz archives and of Git LFS unless `GHDL_ARCHIVE_BASE` is set
+pub const DEFAULT_ARCHIVE_URL: &str = "https://github.com";
+
+tokio::task_local! {
+    static ENDPOINTS: Endpoints;
+}
+
+/// Where REST API requests and repository archives go, e.g. a mirror or a mock
+/// server. Applies only to futures run through [`Endpoints::scope`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoints {
+    api: String,
+    archive: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            api: DEFAULT_API_URL.to_string(),
+            archive: DEFAULT_ARCHIVE_URL.to_string(),
+        }
+    }
+}
+
+impl Endpoints {
+    /// Send REST API requests to `url`, e.g. a GitHub Enterprise Server's
+    /// `https://host/api/v3`
+    pub fn with_api(mut self, url: &str) -> Self {
+        self.api = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Download archives and LFS objects from `url`; the `/{owner}/{repo}/...` paths
+    /// are kept
+    pub fn with_archive(mut self, url: &str) -> Self {
+        self.archive = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn api(&self) -> &str {
+        &self.api
+    }
+
+    pub fn archive(&self) -> &str {
+        &self.archive
+    }
+
+    /// Run `future` with its requests sent to these endpoints
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        ENDPOINTS.scope(self.clone(), future).await
+    }
+}
+
+/// Base URL of the REST API in the current [`Endpoints::scope`], without a trailing slash
+pub fn api_base() -> String {
+    ENDPOINTS
+        .try_with(|endpoints| endpoints.api.clone())
+        .unwrap_or_else(|_| DEFAULT_API_URL.to_string())
+}
+
+/// Base URL of repository archives in the current [`Endpoints::scope`], without a
+/// trailing slash
+pub fn archive_base() -> String {
+    ENDPOINTS
+        .try_with(|endpoints| endpoints.archive.clone())
+        .unwrap_or_else(|_| DEFAULT_ARCHIVE_URL.to_string())
+}
+
+/// URL of the request's repository archive with the given extension (`zip`, `tar.gz`).
+/// A full commit SHA names that commit; anything else is looked up as a branch.
+pub fn archive_url(request: &RequestInfo, extension: &str) -> String {
+    let reference = if is_full_commit_sha(&request.branch) {
+        request.branch.clone()
+    } else {
+        format!("refs/heads/{}", request.branch)
+    };
+    format!(
+        "{}/{}/{}/archive/{}.{}",
+        archive_base(),
+        request.owner,
+        request.repo,
+        reference,
+        extension
+    )
+}
+
+/// The contents API caps directory listings at this many entries
+pub const CONTENTS_LISTING_CAP: usize = 1000;
+
+/// Contents API URL of `path` at the request's ref
+fn contents_url(request: &RequestInfo, path: &str) -> Result<url::Url> {
     let mut api_url = url::Url::parse(&format!(
-        "https://api.github.com/repos/{}/{}/contents",
-        request.owner, request.repo
+        "{}/repos/{}/{}/contents",
+        api_base(),
+        request.owner,
+        request.repo
     ))?;
 
     {
         let mut segments = api_url
             .path_segments_mut()
             .map_err(|_| anyhow!("failed to manipulate GitHub API URL"))?;
-        if !folder_path.is_empty() {
-            for segment in folder_path.split('/') {
-                if !segment.is_empty() {
-                    segments.push(segment);
-                }
+        for segment in path.split('/') {
+            if !segment.is_empty() {
+                segments.push(segment);
             }
         }
     }
@@ -42,6 +135,18 @@ pub async fn fetch_github_contents(
     api_url
         .query_pairs_mut()
         .append_pair("ref", &request.branch);
+    Ok(api_url)
+}
+
+pub async fn fetch_github_contents(
+    client: &Client,
+    request: &RequestInfo,
+    folder_path: &str,
+    token: Option<&str>,
+    rate_limit: Arc<RateLimitTracker>,
+    cache: CachePolicy,
+) -> Result<Vec<GitHubContent>> {
+    let api_url = contents_url(request, folder_path)?;
 
     let mut request_builder = client.get(api_url);
 
@@ -62,12 +167,36 @@ pub async fn fetch_github_contents(
     );
 
     let body =
-        crate::http::send_github_request_cached(&request_builder, &rate_limit, &context, no_cache)
+        crate::http::send_github_request_cached(&request_builder, &rate_limit, &context, cache)
             .await
             .context("GitHub API request failed")?;
 
     let items: Result<Vec<GitHubContent>, _> = serde_json::from_slice(&body);
     match items {
+        Ok(list) if list.len() >= CONTENTS_LISTING_CAP => {
+            // The listing was cut off; the directory's tree lists every entry
+            debug!(
+                "GitHub capped the listing at {} entries while {}; completing it from the git tree",
+                CONTENTS_LISTING_CAP, context
+            );
+            let source = TreeSource {
+                client,
+                request,
+                token,
+                rate_limit: &rate_limit,
+                cache,
+            };
+            let tree_spec = format!("{}:{}", request.branch, folder_path);
+            let tree = source.fetch(&tree_spec, false).await?;
+            if tree.truncated {
+                warn!(
+                    "Directory listing is incomplete while {}: the directory has too many entries",
+                    context
+                );
+            }
+            let missing = missing_from_listing(&list, tree.tree, request, folder_path)?;
+            Ok(list.into_iter().chain(missing).collect())
+        }
         Ok(list) => Ok(list),
         Err(_) => {
             let single: GitHubContent =
@@ -82,7 +211,7 @@ pub async fn fetch_git_tree(
     request: &RequestInfo,
     token: Option<&str>,
     rate_limit: Arc<RateLimitTracker>,
-    no_cache: bool,
+    cache: CachePolicy,
 ) -> Result<GitTreeResponse> {
     let tree_spec = if request.path.is_empty() {
         request.branch.clone()
@@ -90,37 +219,205 @@ pub async fn fetch_git_tree(
         format!("{}:{}", request.branch, request.path)
     };
 
-    let mut api_url = url::Url::parse("https://api.github.com/repos")
-        .context("failed to construct GitHub tree URL")?;
-    {
-        let mut segments = api_url
-            .path_segments_mut()
-            .map_err(|_| anyhow!("failed to manipulate GitHub tree URL"))?;
-        segments.push(&request.owner);
-        segments.push(&request.repo);
-        segments.push("git");
-        segments.push("trees");
-        segments.push(&tree_spec);
-    }
-    api_url.query_pairs_mut().append_pair("recursive", "1");
-
-    let mut request_builder = client.get(api_url);
-    if let Some(token) = token {
-        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    let source = TreeSource {
+        client,
+        request,
+        token,
+        rate_limit: &rate_limit,
+        cache,
+    };
+    let tree = source.fetch(&tree_spec, true).await?;
+    if !tree.truncated {
+        return Ok(tree);
     }
 
-    let context = format!(
-        "enumerating git tree for {}/{} ({})",
+    debug!(
+        "GitHub truncated the tree listing for {}/{} ({}); listing it directory by directory",
         request.owner, request.repo, tree_spec
     );
-    let body =
-        crate::http::send_github_request_cached(&request_builder, &rate_limit, &context, no_cache)
-            .await
-            .context("GitHub git tree request failed")?;
+    source.fetch_by_level(&tree_spec).await
+}
+
+/// Where `fetch_git_tree` sends its tree requests
+struct TreeSource<'a> {
+    client: &'a Client,
+    request: &'a RequestInfo,
+    token: Option<&'a str>,
+    rate_limit: &'a Arc<RateLimitTracker>,
+    cache: CachePolicy,
+}
+
+impl TreeSource<'_> {
+    /// One `git/trees` listing of `tree_spec` (a `ref:path` expression or a tree SHA)
+    async fn fetch(&self, tree_spec: &str, recursive: bool) -> Result<GitTreeResponse> {
+        let request = self.request;
+        let mut api_url = url::Url::parse(&format!("{}/repos", api_base()))
+            .context("failed to construct GitHub tree URL")?;
+        {
+            let mut segments = api_url
+                .path_segments_mut()
+                .map_err(|_| anyhow!("failed to manipulate GitHub tree URL"))?;
+            segments.push(&request.owner);
+            segments.push(&request.repo);
+            segments.push("git");
+            segments.push("trees");
+            segments.push(tree_spec);
+        }
+        if recursive {
+            api_url.query_pairs_mut().append_pair("recursive", "1");
+        }
 
-    let tree: GitTreeResponse =
-        serde_json::from_slice(&body).context("failed to decode GitHub tree response")?;
-    Ok(tree)
+        let mut request_builder = self.client.get(api_url);
+        if let Some(token) = self.token {
+            request_builder =
+                request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+        }
+
+        let context = format!(
+            "enumerating git tree for {}/{} ({})",
+            request.owner, request.repo, tree_spec
+        );
+        let body = crate::http::send_github_request_cached(
+            &request_builder,
+            self.rate_limit,
+            &context,
+            self.cache,
+        )
+        .await
+        .context("GitHub git tree request failed")?;
+
+        serde_json::from_slice(&body).context("failed to decode GitHub tree response")
+    }
+
+    /// Enumerate a tree too large for one recursive listing: list its top level, then
+    /// each subtree recursively, descending a level further wherever a listing is still
+    /// truncated. Paths are relative to `tree_spec`, as in a recursive listing.
+    async fn fetch_by_level(&self, tree_spec: &str) -> Result<GitTreeResponse> {
+        let mut tree = Vec::new();
+        let mut truncated = false;
+        // (path prefix, tree to list, whether its recursive listing is known to be truncated)
+        let mut pending = vec![(String::new(), tree_spec.to_string(), true)];
+        while let Some((prefix, spec, too_large)) = pending.pop() {
+            let mut listing = if too_large {
+                None
+            } else {
+                Some(self.fetch(&spec, true).await?)
+            };
+            if listing.as_ref().is_none_or(|listing| listing.truncated) {
+                let level = self.fetch(&spec, false).await?;
+                // A single directory with more entries than GitHub lists at once
+                truncated |= level.truncated;
+                pending.extend(subtrees(&level.tree, &prefix));
+                listing = Some(level);
+            }
+            tree.extend(
+                listing
+                    .into_iter()
+                    .flat_map(|listing| listing.tree)
+                    .map(|mut entry| {
+                        entry.path = format!("{}{}", prefix, entry.path);
+                        entry
+                    }),
+            );
+        }
+
+        if truncated {
+            warn!(
+                "GitHub tree listing for {}/{} is incomplete: a directory has too many entries",
+                self.request.owner, self.request.repo
+            );
+        }
+        Ok(GitTreeResponse { tree, truncated })
+    }
+}
+
+/// Directory entries a capped contents listing left out, from the directory's
+/// non-recursive tree listing. Files are fetched through their contents API URL.
+fn missing_from_listing(
+    listing: &[GitHubContent],
+    entries: Vec<GitTreeEntry>,
+    request: &RequestInfo,
+    folder_path: &str,
+) -> Result<Vec<GitHubContent>> {
+    let listed: HashSet<&str> = listing.iter().map(|item| item.name.as_str()).collect();
+    let mut missing = Vec::new();
+    for entry in entries {
+        if listed.contains(entry.path.as_str()) {
+            continue;
+        }
+        let content_type = match entry.entry_type {
+            GitTreeEntryType::Tree => ContentType::Dir,
+            GitTreeEntryType::Commit => ContentType::Submodule,
+            GitTreeEntryType::Blob if entry.mode.as_deref() == Some("120000") => {
+                ContentType::Symlink
+            }
+            GitTreeEntryType::Blob => ContentType::File,
+        };
+        let path = if folder_path.is_empty() {
+            entry.path.clone()
+        } else {
+            format!("{}/{}", folder_path.trim_end_matches('/'), entry.path)
+        };
+        missing.push(GitHubContent {
+            url: contents_url(request, &path)?.to_string(),
+            name: entry.path,
+            path,
+            size: entry.size,
+            download_url: None,
+            content_type,
+            sha: entry.sha,
+        });
+    }
+    Ok(missing)
+}
+
+/// The subtrees of a non-recursive listing, as `fetch_by_level` work items to list next
+fn subtrees(entries: &[GitTreeEntry], prefix: &str) -> Vec<(String, String, bool)> {
+    entries
+        .iter()
+        .filter(|entry| entry.entry_type == GitTreeEntryType::Tree)
+        .filter_map(|entry| {
+            let sha = entry.sha.clone()?;
+            Some((format!("{}{}/", prefix, entry.path), sha, false))
+        })
+        .collect()
+}
+
+/// Map each regular file the request covers to its blob SHA-1, from one recursive git
+/// tree listing
+pub async fn fetch_blob_shas(
+    client: &Client,
+    request: &RequestInfo,
+    token: Option<&str>,
+    rate_limit: Arc<RateLimitTracker>,
+    cache: CachePolicy,
+) -> Result<HashMap<String, String>> {
+    use crate::types::RequestKind;
+
+    // A file has no tree of its own; list its directory
+    let mut tree_request = request.clone();
+    if request.kind == RequestKind::Blob {
+        tree_request.path = request
+            .path
+            .rsplit_once('/')
+            .map(|(parent, _)| parent.to_string())
+            .unwrap_or_default();
+    }
+    let tree = fetch_git_tree(client, &tree_request, token, rate_limit, cache).await?;
+    let prefix = if tree_request.path.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", tree_request.path)
+    };
+
+    Ok(tree
+        .tree
+        .into_iter()
+        .filter(|entry| {
+            entry.entry_type == GitTreeEntryType::Blob && entry.mode.as_deref() != Some("120000")
+        })
+        .filter_map(|entry| Some((format!("{}{}", prefix, entry.path), entry.sha?)))
+        .collect())
 }
 
 pub async fn build_file_inventory(
@@ -129,10 +426,8 @@ pub async fn build_file_inventory(
     token: Option<&str>,
     contents: &[GitHubContent],
     rate_limit: Arc<RateLimitTracker>,
-    no_cache: bool,
+    cache: CachePolicy,
 ) -> Result<HashMap<String, FileMetadata>> {
-    use crate::github::types::ContentType;
-
     if contents.len() == 1 && contents[0].content_type == ContentType::File {
         let mut map = HashMap::new();
         map.insert(
@@ -144,13 +439,7 @@ pub async fn build_file_inventory(
         return Ok(map);
     }
 
-    let tree = fetch_git_tree(client, request, token, Arc::clone(&rate_limit), no_cache).await?;
-    if tree.truncated {
-        warn!(
-            "GitHub tree listing for {}/{} may be incomplete (truncated).",
-            request.owner, request.repo
-        );
-    }
+    let tree = fetch_git_tree(client, request, token, Arc::clone(&rate_limit), cache).await?;
 
     let mut files = HashMap::new();
     let base_prefix = if request.path.is_empty() {
@@ -178,6 +467,390 @@ pub async fn build_file_inventory(
     Ok(files)
 }
 
+/// Fetch the committer timestamp (seconds since epoch) of the last commit touching `path`
+pub async fn fetch_last_commit_time(
+    client: &Client,
+    request: &RequestInfo,
+    path: &str,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+    cache: CachePolicy,
+) -> Result<Option<u64>> {
+    let commit = fetch_latest_commit(client, request, path, token, rate_limit, cache).await?;
+    Ok(commit
+        .as_ref()
+        .and_then(|commit| commit.commit.committer.as_ref())
+        .and_then(|committer| crate::utils::parse_github_timestamp(&committer.date)))
+}
+
+/// Most recent commit on the request's ref touching `path` (the whole tree when empty)
+pub async fn fetch_latest_commit(
+    client: &Client,
+    request: &RequestInfo,
+    path: &str,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+    cache: CachePolicy,
+) -> Result<Option<CommitResponse>> {
+    let mut api_url = url::Url::parse(&format!(
+        "{}/repos/{}/{}/commits",
+        api_base(),
+        request.owner,
+        request.repo
+    ))?;
+    {
+        let mut query = api_url.query_pairs_mut();
+        query.append_pair("sha", &request.branch);
+        if !path.is_empty() {
+            query.append_pair("path", path);
+        }
+        query.append_pair("per_page", "1");
+    }
+
+    let mut request_builder = client.get(api_url);
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    let context = format!(
+        "fetching last commit for {}/{}:{}:{}",
+        request.owner, request.repo, request.branch, path
+    );
+    let body =
+        crate::http::send_github_request_cached(&request_builder, rate_limit, &context, cache)
+            .await
+            .context("GitHub commits request failed")?;
+
+    let commits: Vec<CommitResponse> =
+        serde_json::from_slice(&body).context("failed to decode GitHub commits response")?;
+    Ok(commits.into_iter().next())
+}
+
+/// Check whether `request.path` exists at the request's ref
+pub async fn path_exists(
+    client: &Client,
+    request: &RequestInfo,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+) -> Result<bool> {
+    let mut api_url = url::Url::parse(&format!("{}/repos", api_base()))?;
+    api_url
+        .path_segments_mut()
+        .map_err(|_| anyhow!("failed to manipulate GitHub API URL"))?
+        .extend([request.owner.as_str(), request.repo.as_str(), "contents"])
+        .extend(
+            request
+                .path
+                .split('/')
+                .filter(|segment| !segment.is_empty()),
+        );
+    api_url
+        .query_pairs_mut()
+        .append_pair("ref", &request.branch);
+
+    let mut request_builder = client.get(api_url);
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+    let response = request_builder
+        .send()
+        .await
+        .context("failed to look up the path")?;
+    rate_limit.record_headers(response.headers()).await;
+
+    match response.status() {
+        status if status.is_success() => Ok(true),
+        StatusCode::NOT_FOUND => Ok(false),
+        status => Err(anyhow!("path lookup failed with status: {}", status).into()),
+    }
+}
+
+/// Check whether a branch, tag or commit SHA resolves in the repository.
+///
+/// Returns `None` when the answer is inconclusive (e.g. network or auth failure).
+pub async fn ref_exists(
+    client: &Client,
+    owner: &str,
+    repo: &str,
+    reference: &str,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+) -> Option<bool> {
+    let mut api_url = url::Url::parse(&format!("{}/repos", api_base())).ok()?;
+    api_url
+        .path_segments_mut()
+        .ok()?
+        .extend([owner, repo, "commits", reference]);
+
+    // The sha media type returns only the commit id, keeping the response tiny
+    let mut request_builder = client
+        .get(api_url)
+        .header(ACCEPT, "application/vnd.github.sha");
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    let response = match request_builder.send().await {
+        Ok(response) => response,
+        Err(err) => {
+            debug!("Ref lookup for {} failed: {}", reference, err);
+            return None;
+        }
+    };
+    rate_limit.record_headers(response.headers()).await;
+
+    match response.status() {
+        status if status.is_success() => Some(true),
+        StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY => Some(false),
+        status => {
+            debug!("Ref lookup for {} returned status {}", reference, status);
+            None
+        }
+    }
+}
+
+/// SHA of the commit `request`'s ref points at (the default branch when it has none)
+pub async fn fetch_commit_sha(
+    client: &Client,
+    request: &RequestInfo,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+    cache: CachePolicy,
+) -> Result<String> {
+    let reference = if request.branch.is_empty() {
+        "HEAD"
+    } else {
+        request.branch.as_str()
+    };
+    let mut api_url = url::Url::parse(&format!("{}/repos", api_base()))?;
+    api_url
+        .path_segments_mut()
+        .map_err(|_| anyhow!("failed to construct GitHub commit URL"))?
+        .extend([&request.owner, &request.repo, "commits", reference]);
+
+    let mut request_builder = client
+        .get(api_url)
+        .header(ACCEPT, "application/vnd.github.sha");
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    let context = format!("resolving {}/{}:{}", request.owner, request.repo, reference);
+    let body =
+        crate::http::send_github_request_cached(&request_builder, rate_limit, &context, cache)
+            .await
+            .context("GitHub commit request failed")?;
+    let sha = String::from_utf8_lossy(&body).trim().to_string();
+    if sha.len() != 40 || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("unexpected commit SHA '{}' for {}", sha, context).into());
+    }
+    Ok(sha)
+}
+
+/// Signature status GitHub recorded for a tag
+#[derive(Debug, Clone)]
+pub struct TagSignature {
+    /// Annotated tags carry their own signature; lightweight tags report their commit's
+    pub annotated: bool,
+    pub verification: SignatureVerification,
+    /// The commit the tag points to, which is what a verified download must fetch
+    pub commit: String,
+}
+
+/// Look up the signature verification of `tag`, or `None` when no such tag exists
+pub async fn fetch_tag_signature(
+    client: &Client,
+    owner: &str,
+    repo: &str,
+    tag: &str,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+) -> Result<Option<TagSignature>> {
+    let mut api_url = url::Url::parse(&format!("{}/repos", api_base()))
+        .context("failed to construct GitHub ref URL")?;
+    api_url
+        .path_segments_mut()
+        .map_err(|_| anyhow!("failed to manipulate GitHub ref URL"))?
+        .extend([owner, repo, "git", "ref", "tags"])
+        .extend(tag.split('/'));
+
+    let mut request_builder = client.get(api_url);
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    let response = request_builder
+        .send()
+        .await
+        .with_context(|| format!("GitHub request failed for tag {}", tag))?;
+    rate_limit.record_headers(response.headers()).await;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "looking up tag {} failed with status {}",
+            tag,
+            response.status()
+        )
+        .into());
+    }
+
+    let reference: GitRefResponse = response
+        .json()
+        .await
+        .context("failed to decode GitHub ref response")?;
+    let annotated = reference.object.object_type == "tag";
+    let kind = if annotated { "tags" } else { "commits" };
+
+    let object_url = format!(
+        "{}/repos/{}/{}/git/{}/{}",
+        api_base(),
+        owner,
+        repo,
+        kind,
+        reference.object.sha
+    );
+    let mut request_builder = client.get(object_url);
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    // Not served from the response cache: a signature check must see the current state
+    let context = format!("reading signature of tag {}", tag);
+    let object: SignedObject =
+        crate::http::send_github_request(&request_builder, rate_limit, &context)
+            .await?
+            .json()
+            .await
+            .context("failed to decode GitHub tag response")?;
+
+    let commit = if annotated {
+        match object.object {
+            Some(target) if target.object_type == "commit" => target.sha,
+            _ => return Err(anyhow!("tag {} does not point to a commit", tag).into()),
+        }
+    } else {
+        reference.object.sha
+    };
+
+    Ok(Some(TagSignature {
+        annotated,
+        commit,
+        verification: object.verification.unwrap_or(SignatureVerification {
+            verified: false,
+            reason: "unsigned".to_string(),
+        }),
+    }))
+}
+
+/// List branch and tag names of a repository (first page of each)
+pub async fn fetch_ref_names(
+    client: &Client,
+    owner: &str,
+    repo: &str,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+
+    for kind in ["branches", "tags"] {
+        let api_url = format!(
+            "{}/repos/{}/{}/{}?per_page=100",
+            api_base(),
+            owner,
+            repo,
+            kind
+        );
+        let mut request_builder = client.get(&api_url);
+        if let Some(token) = token {
+            request_builder =
+                request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+        }
+
+        let context = format!("listing {} for {}/{}", kind, owner, repo);
+        let response = crate::http::send_github_request(&request_builder, rate_limit, &context)
+            .await
+            .with_context(|| format!("failed to list {} for {}/{}", kind, owner, repo))?;
+        let refs: Vec<NamedRef> = response
+            .json()
+            .await
+            .with_context(|| format!("failed to decode {} listing", kind))?;
+        names.extend(refs.into_iter().map(|named| named.name));
+    }
+
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// What `GET /user` reveals about a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenDetails {
+    /// Account the token belongs to
+    pub login: String,
+    /// OAuth scopes of a classic token; `None` for fine-grained and app tokens
+    pub scopes: Option<Vec<String>>,
+    /// Expiration date, absent for tokens that never expire
+    pub expires: Option<String>,
+    /// `X-GitHub-SSO` header: organizations the token still needs SSO authorization for
+    pub sso: Option<String>,
+}
+
+impl TokenDetails {
+    fn from_headers(login: String, headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let scopes = headers
+            .get("x-oauth-scopes")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|scope| !scope.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            });
+        Self {
+            login,
+            scopes,
+            expires: header("github-authentication-token-expiration"),
+            sso: header("x-github-sso"),
+        }
+    }
+}
+
+/// Account, scopes, and expiry of `token`; fails when GitHub rejects the token
+pub async fn fetch_authenticated_user(client: &Client, token: &str) -> Result<TokenDetails> {
+    let response = client
+        .get(format!("{}/user", api_base()))
+        .header(AUTHORIZATION, format!("token {}", token.trim()))
+        .send()
+        .await
+        .context("failed to check the token")?;
+
+    match response.status() {
+        status if status.is_success() => {}
+        StatusCode::UNAUTHORIZED => return Err(anyhow!("GitHub rejected the token (401)").into()),
+        status => return Err(anyhow!("token check failed with status: {}", status).into()),
+    }
+
+    let headers = response.headers().clone();
+    let user: AuthenticatedUser = response
+        .json()
+        .await
+        .context("failed to parse the authenticated user")?;
+    Ok(TokenDetails::from_headers(user.login, &headers))
+}
+
 /// Fetch repository metadata including the default branch
 pub async fn fetch_repository_info(
     client: &Client,
@@ -185,7 +858,7 @@ pub async fn fetch_repository_info(
     repo: &str,
     token: Option<&str>,
 ) -> Result<RepositoryInfo> {
-    let api_url = format!("https://api.github.com/repos/{}/{}", owner, repo);
+    let api_url = format!("{}/repos/{}/{}", api_base(), owner, repo);
     let mut request_builder = client.get(&api_url);
 
     if let Some(token) = token {
@@ -198,11 +871,9 @@ pub async fn fetch_repository_info(
         .context("failed to fetch repository information")?;
 
     if !response.status().is_success() {
-        return Err(anyhow!(
-            "repository not found or inaccessible: {}/{}",
-            owner,
-            repo
-        ));
+        let message = format!("repository not found or inaccessible: {}/{}", owner, repo);
+        return Err(GdlError::from_status(response.status(), message.clone())
+            .unwrap_or_else(|| anyhow!(message).into()));
     }
 
     let repo_info: RepositoryInfo = response
@@ -213,15 +884,58 @@ pub async fn fetch_repository_info(
     Ok(repo_info)
 }
 
+/// Longest a cached default branch is trusted before GitHub is asked again
+pub const DEFAULT_BRANCH_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The response cache policy for default-branch lookups: the usual one, with the
+/// TTL capped so a renamed default branch is picked up within minutes
+pub fn default_branch_policy(cache: CachePolicy) -> CachePolicy {
+    CachePolicy {
+        ttl: cache.ttl.min(DEFAULT_BRANCH_TTL),
+        ..cache
+    }
+}
+
+/// Resolve a repository's default branch, answered from the response cache when a
+/// recent lookup exists (and from any cached lookup when offline)
+pub async fn fetch_default_branch(
+    client: &Client,
+    owner: &str,
+    repo: &str,
+    token: Option<&str>,
+    rate_limit: &Arc<RateLimitTracker>,
+    cache: CachePolicy,
+) -> Result<String> {
+    let api_url = format!("{}/repos/{}/{}", api_base(), owner, repo);
+    let mut request_builder = client.get(&api_url);
+    if let Some(token) = token {
+        request_builder = request_builder.header(AUTHORIZATION, format!("token {}", token.trim()));
+    }
+
+    let context = format!("looking up the default branch of {}/{}", owner, repo);
+    let body = crate::http::send_github_request_cached(
+        &request_builder,
+        rate_limit,
+        &context,
+        default_branch_policy(cache),
+    )
+    .await?;
+    let repo_info: RepositoryInfo =
+        serde_json::from_slice(&body).context("failed to parse repository information")?;
+    Ok(repo_info.default_branch)
+}
+
 pub fn parse_github_url(raw_url: &str) -> Result<RequestInfo> {
     use crate::types::RequestKind;
 
-    let parsed = url::Url::parse(raw_url).context("invalid GitHub URL")?;
+    let invalid = |message: &str| GdlError::InvalidUrl(message.to_string());
+    let parsed = url::Url::parse(raw_url)
+        .map_err(|err| GdlError::from(err).context(invalid("invalid GitHub URL")))?;
     let has_trailing_slash = raw_url.ends_with('/');
 
     let segments: Vec<_> = parsed
         .path_segments()
-        .ok_or_else(|| anyhow!("GitHub URL is missing path segments"))?
+        .ok_or_else(|| invalid("GitHub URL is missing path segments"))?
         .filter(|s| !s.is_empty())
         .collect();
 
@@ -239,8 +953,8 @@ pub fn parse_github_url(raw_url: &str) -> Result<RequestInfo> {
 
     // Handle full URLs with /tree/ or /blob/
     if segments.len() < 4 || (segments[2] != "tree" && segments[2] != "blob") {
-        return Err(anyhow!(
-            "URL must be either 'https://github.com/owner/repo' or include /tree/ or /blob/ with a branch and path component"
+        return Err(invalid(
+            "URL must be either 'https://github.com/owner/repo' or include /tree/ or /blob/ with a branch and path component",
         ));
     }
 
@@ -274,28 +988,34 @@ pub fn parse_github_url(raw_url: &str) -> Result<RequestInfo> {
     })
 }
 
-#[derive(Debug, Deserialize)]
-struct RateLimitResource {
-    limit: u64,
-    remaining: u64,
-    used: u64,
-    reset: u64,
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitResource {
+    pub limit: u64,
+    pub remaining: u64,
+    pub used: u64,
+    pub reset: u64,
 }
 
 #[derive(Debug, Deserialize)]
 struct RateLimitResponse {
-    resources: RateLimitResources,
+    resources: BTreeMap<String, RateLimitResource>,
 }
 
-#[derive(Debug, Deserialize)]
-struct RateLimitResources {
-    core: RateLimitResource,
-}
+/// Version of the `rate --format json` output, described by `docs/rate.schema.json`.
+/// Adding a field keeps the version; renaming or removing one bumps it.
+pub const RATE_SCHEMA_VERSION: u32 = 1;
 
-/// Fetch rate limit information from the GitHub API
+/// Quotas to list first, in this order; any other resource follows by name
+const RATE_LIMIT_ORDER: [&str; 4] = ["core", "search", "graphql", "code_scanning_upload"];
+
+/// Every quota of `token` (or of the client's IP without one), keyed by resource
+/// name (`core`, `search`, `graphql`, ...)
 /// Note: This endpoint does not count against your primary rate limit
-pub async fn fetch_rate_limit_info(client: &Client, token: Option<&str>) -> Result<()> {
-    let mut request = client.get("https://api.github.com/rate_limit");
+pub async fn fetch_rate_limits(
+    client: &Client,
+    token: Option<&str>,
+) -> Result<BTreeMap<String, RateLimitResource>> {
+    let mut request = client.get(format!("{}/rate_limit", api_base()));
 
     if let Some(token) = token {
         request = request.header(AUTHORIZATION, format!("token {}", token.trim()));
@@ -307,18 +1027,32 @@ pub async fn fetch_rate_limit_info(client: &Client, token: Option<&str>) -> Resu
         .context("failed to fetch rate limit information")?;
 
     if !response.status().is_success() {
-        return Err(anyhow!(
-            "rate limit check failed with status: {}",
-            response.status()
-        ));
+        return Err(anyhow!("rate limit check failed with status: {}", response.status()).into());
     }
 
     let rate_limit: RateLimitResponse = response
         .json()
         .await
         .context("failed to parse rate limit response")?;
+    Ok(rate_limit.resources)
+}
+
+/// Core API quota of `token` (or of the client's IP without one)
+/// Note: This endpoint does not count against your primary rate limit
+pub async fn fetch_core_rate_limit(
+    client: &Client,
+    token: Option<&str>,
+) -> Result<RateLimitResource> {
+    fetch_rate_limits(client, token)
+        .await?
+        .remove("core")
+        .context("rate limit response has no core quota")
+}
 
-    let core = &rate_limit.resources.core;
+/// Fetch rate limit information from the GitHub API
+/// Note: This endpoint does not count against your primary rate limit
+pub async fn fetch_rate_limit_info(client: &Client, token: Option<&str>) -> Result<()> {
+    let core = fetch_core_rate_limit(client, token).await?;
     let reset_time = UNIX_EPOCH + Duration::from_secs(core.reset);
     let eta = reset_time
         .duration_since(SystemTime::now())
@@ -334,37 +1068,10 @@ pub async fn fetch_rate_limit_info(client: &Client, token: Option<&str>) -> Resu
     Ok(())
 }
 
-/// Display rate limit information to the user
-/// Note: This endpoint does not count against your primary rate limit
-pub async fn display_rate_limit_info(client: &Client, token: Option<&str>) -> Result<()> {
-    let mut request = client.get("https://api.github.com/rate_limit");
-
-    if let Some(token) = token {
-        request = request.header(AUTHORIZATION, format!("token {}", token.trim()));
-    }
-
-    let response = request
-        .send()
-        .await
-        .context("failed to fetch rate limit information")?;
-
-    if !response.status().is_success() {
-        return Err(anyhow!(
-            "rate limit check failed with status: {}",
-            response.status()
-        ));
-    }
-
-    let rate_limit: RateLimitResponse = response
-        .json()
-        .await
-        .context("failed to parse rate limit response")?;
-
-    let core = &rate_limit.resources.core;
-    let reset_time = UNIX_EPOCH + Duration::from_secs(core.reset);
-    let now = SystemTime::now();
-
-    let reset_str = if let Ok(duration) = reset_time.duration_since(now) {
+/// When a quota resetting at `reset` (Unix seconds) refills, e.g. `in 12 minute(s)`
+pub fn describe_reset(reset: u64) -> String {
+    let reset_time = UNIX_EPOCH + Duration::from_secs(reset);
+    if let Ok(duration) = reset_time.duration_since(SystemTime::now()) {
         let secs = duration.as_secs();
         if secs >= 3600 {
             format!(
@@ -379,21 +1086,86 @@ pub async fn display_rate_limit_info(client: &Client, token: Option<&str>) -> Re
         }
     } else {
         "now".to_string()
-    };
+    }
+}
 
-    let auth_status = if token.is_some() {
+/// Display rate limit information to the user
+/// Note: This endpoint does not count against your primary rate limit
+pub async fn display_rate_limit_info(
+    client: &Client,
+    token: Option<&str>,
+    format: RateFormat,
+) -> Result<()> {
+    let resources = fetch_rate_limits(client, token).await?;
+
+    match format {
+        RateFormat::Table => print!("{}", format_rate_limits(&resources, token.is_some())),
+        RateFormat::Json => {
+            let report = rate_limit_report(&resources, token.is_some());
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report)
+                    .context("failed to encode the rate limit report")?
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// What `rate --format json` prints
+fn rate_limit_report(
+    resources: &BTreeMap<String, RateLimitResource>,
+    authenticated: bool,
+) -> serde_json::Value {
+    serde_json::json!({
+        "schema": RATE_SCHEMA_VERSION,
+        "authenticated": authenticated,
+        "resources": resources,
+    })
+}
+
+/// One row per quota, the commonly used ones first
+fn format_rate_limits(
+    resources: &BTreeMap<String, RateLimitResource>,
+    authenticated: bool,
+) -> String {
+    let mut names: Vec<&String> = resources.keys().collect();
+    names.sort_by_key(|name| {
+        RATE_LIMIT_ORDER
+            .iter()
+            .position(|known| known == name)
+            .unwrap_or(RATE_LIMIT_ORDER.len())
+    });
+
+    let auth_status = if authenticated {
         "authenticated"
     } else {
         "unauthenticated"
     };
-
-    println!("GitHub API Rate Limit ({}):", auth_status);
-    println!("  Limit:     {}", core.limit);
-    println!("  Used:      {}", core.used);
-    println!("  Remaining: {}", core.remaining);
-    println!("  Resets:    {}", reset_str);
-
-    Ok(())
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(8);
+    let mut out = format!("GitHub API Rate Limit ({}):\n", auth_status);
+    out.push_str(&format!(
+        "  {:<width$}  {:>7}  {:>7}  {:>9}  Resets\n",
+        "Resource", "Limit", "Used", "Remaining"
+    ));
+    for name in names {
+        let quota = &resources[name];
+        out.push_str(&format!(
+            "  {:<width$}  {:>7}  {:>7}  {:>9}  {}\n",
+            name,
+            quota.limit,
+            quota.used,
+            quota.remaining,
+            describe_reset(quota.reset)
+        ));
+    }
+    out
 }
 
 #[cfg(test)]
@@ -401,6 +1173,153 @@ mod tests {
     use super::*;
     use crate::types::RequestKind;
 
+    #[test]
+    fn test_archive_url_pins_commits() {
+        let mut request = parse_github_url("https://github.com/foo/bar/tree/main/docs").unwrap();
+        assert_eq!(
+            archive_url(&request, "zip"),
+            format!("{}/foo/bar/archive/refs/heads/main.zip", archive_base())
+        );
+        request.branch = "a1".repeat(20);
+        assert_eq!(
+            archive_url(&request, "tar.gz"),
+            format!(
+                "{}/foo/bar/archive/{}.tar.gz",
+                archive_base(),
+                "a1".repeat(20)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_scope() {
+        assert_eq!(api_base(), DEFAULT_API_URL);
+        let mirror = Endpoints::default()
+            .with_api("https://ghe.example.com/api/v3")
+            .with_archive("http://127.0.0.1:8080/mirror/");
+        let (api, archive) = mirror.scope(async { (api_base(), archive_base()) }).await;
+        assert_eq!(api, "https://ghe.example.com/api/v3");
+        assert_eq!(archive, "http://127.0.0.1:8080/mirror");
+        // Nothing leaks outside the scope
+        assert_eq!(archive_base(), DEFAULT_ARCHIVE_URL);
+    }
+
+    #[test]
+    fn test_missing_from_listing() {
+        let request = parse_github_url("https://github.com/foo/bar/tree/main/big").unwrap();
+        let listing: Vec<GitHubContent> = serde_json::from_str(
+            r#"[{"name": "a.txt", "path": "big/a.txt", "url": "u", "size": 1, "download_url": "d", "type": "file", "sha": "1"}]"#,
+        )
+        .unwrap();
+        let entries: Vec<GitTreeEntry> = serde_json::from_str(
+            r#"[
+                {"path": "a.txt", "type": "blob", "sha": "1", "size": 1},
+                {"path": "b.txt", "type": "blob", "sha": "2", "size": 2},
+                {"path": "link", "mode": "120000", "type": "blob", "sha": "3", "size": 5},
+                {"path": "sub", "type": "tree", "sha": "4"}
+            ]"#,
+        )
+        .unwrap();
+
+        let missing = missing_from_listing(&listing, entries, &request, "big").unwrap();
+        let summary: Vec<_> = missing
+            .iter()
+            .map(|item| (item.path.as_str(), item.content_type.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("big/b.txt", ContentType::File),
+                ("big/link", ContentType::Symlink),
+                ("big/sub", ContentType::Dir),
+            ]
+        );
+        assert!(missing[0].download_url.is_none());
+        assert!(
+            missing[0]
+                .url
+                .ends_with("/repos/foo/bar/contents/big/b.txt?ref=main")
+        );
+    }
+
+    #[test]
+    fn test_subtrees_to_list_next() {
+        let entries: Vec<GitTreeEntry> = serde_json::from_str(
+            r#"[
+                {"path": "README.md", "type": "blob", "sha": "a1", "size": 10},
+                {"path": "src", "type": "tree", "sha": "b2"},
+                {"path": "vendor", "type": "commit", "sha": "c3"},
+                {"path": "docs", "type": "tree"}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            subtrees(&entries, "pkg/"),
+            vec![("pkg/src/".to_string(), "b2".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn test_default_branch_policy_caps_ttl() {
+        let policy = default_branch_policy(CachePolicy::default());
+        assert_eq!(policy.ttl, DEFAULT_BRANCH_TTL);
+        assert!(policy.enabled);
+
+        let short = CachePolicy {
+            ttl: Duration::from_secs(30),
+            offline: true,
+            ..CachePolicy::default()
+        };
+        assert_eq!(default_branch_policy(short), short);
+    }
+
+    #[test]
+    fn formats_rate_limits_core_first() {
+        let quota = |limit, used| RateLimitResource {
+            limit,
+            remaining: limit - used,
+            used,
+            reset: 0,
+        };
+        let resources = BTreeMap::from([
+            ("actions_runner_registration".to_string(), quota(10000, 0)),
+            ("graphql".to_string(), quota(5000, 12)),
+            ("search".to_string(), quota(30, 1)),
+            ("core".to_string(), quota(5000, 4321)),
+        ]);
+
+        assert_eq!(
+            format_rate_limits(&resources, true),
+            "GitHub API Rate Limit (authenticated):\n\
+             \x20 Resource                       Limit     Used  Remaining  Resets\n\
+             \x20 core                            5000     4321        679  now\n\
+             \x20 search                            30        1         29  now\n\
+             \x20 graphql                         5000       12       4988  now\n\
+             \x20 actions_runner_registration    10000        0      10000  now\n"
+        );
+
+        // Every emitted field is documented, and every required one is emitted
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../../docs/rate.schema.json")).unwrap();
+        assert_eq!(schema["properties"]["schema"]["const"], RATE_SCHEMA_VERSION);
+        let report = rate_limit_report(&resources, false);
+        let check = |value: &serde_json::Value, schema: &serde_json::Value| {
+            let object = value.as_object().unwrap();
+            for key in object.keys() {
+                assert!(schema["properties"].get(key).is_some(), "{} missing", key);
+            }
+            for name in schema["required"].as_array().unwrap() {
+                assert!(object.contains_key(name.as_str().unwrap()));
+            }
+        };
+        check(&report, &schema);
+        let quota_schema = &schema["properties"]["resources"]["additionalProperties"];
+        for quota in report["resources"].as_object().unwrap().values() {
+            check(quota, quota_schema);
+        }
+    }
+
     #[test]
     fn parses_tree_url_with_trailing_slash() {
         let info = parse_github_url("https://github.com/foo/bar/tree/main/path/to/dir/").unwrap();
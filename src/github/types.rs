@@ -23,8 +23,13 @@ pub struct GitTreeResponse {
 #[derive(Debug, Deserialize)]
 pub struct GitTreeEntry {
     pub path: String,
+    /// File mode, e.g. `100644` for files or `120000` for symlinks
+    #[serde(default)]
+    pub mode: Option<String>,
     #[serde(rename = "type")]
     pub entry_type: GitTreeEntryType,
+    #[serde(default)]
+    pub sha: Option<String>,
     pub size: Option<u64>,
 }
 
@@ -47,9 +52,85 @@ pub enum ContentType {
     Other,
 }
 
+/// The account a token belongs to (`GET /user`)
+#[derive(Debug, Deserialize)]
+pub struct AuthenticatedUser {
+    pub login: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RepositoryInfo {
     pub default_branch: String,
+    /// GitHub's estimate of the repository size, in KiB
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub private: bool,
+    /// `public`, `private`, or `internal`; missing on older GitHub Enterprise servers
+    #[serde(default)]
+    pub visibility: Option<String>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Entry returned by the branches and tags listing endpoints
+#[derive(Debug, Deserialize)]
+pub struct NamedRef {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommitResponse {
+    #[serde(default)]
+    pub sha: String,
+    pub commit: CommitDetails,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommitDetails {
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub author: Option<CommitSignature>,
+    pub committer: Option<CommitSignature>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommitSignature {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub date: String,
+}
+
+/// Response of the single-ref endpoint (`git/ref/tags/{tag}`)
+#[derive(Debug, Deserialize)]
+pub struct GitRefResponse {
+    pub object: GitObject,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitObject {
+    pub sha: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+}
+
+/// Tag or commit object from the git database endpoints, reduced to its signature status
+#[derive(Debug, Deserialize)]
+pub struct SignedObject {
+    pub verification: Option<SignatureVerification>,
+    /// The object a tag points to; absent for commits
+    #[serde(default)]
+    pub object: Option<GitObject>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SignatureVerification {
+    pub verified: bool,
+    /// GitHub's verdict, e.g. `valid`, `unsigned`, `invalid`, `unknown_key`
+    pub reason: String,
 }
 
 #[cfg(test)]
@@ -168,6 +249,55 @@ mod tests {
         assert_eq!(info.default_branch, "main");
     }
 
+    #[test]
+    fn test_deserialize_commit_response() {
+        let json = r#"[{
+            "sha": "6dcb09b5b57875f334f61aebed695e2e4193db5e",
+            "commit": {
+                "message": "Fix all the bugs",
+                "committer": {"name": "Monalisa", "date": "2011-04-14T16:00:49Z"}
+            }
+        }]"#;
+        let commits: Vec<CommitResponse> = serde_json::from_str(json).unwrap();
+        assert_eq!(commits.len(), 1);
+        assert_eq!(
+            commits[0].commit.committer.as_ref().unwrap().date,
+            "2011-04-14T16:00:49Z"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_tag_verification() {
+        let reference: GitRefResponse = serde_json::from_str(
+            r#"{"ref": "refs/tags/v1.0", "object": {"sha": "abc123", "type": "tag", "url": ""}}"#,
+        )
+        .unwrap();
+        assert_eq!(reference.object.sha, "abc123");
+        assert_eq!(reference.object.object_type, "tag");
+
+        let tag: SignedObject = serde_json::from_str(
+            r#"{
+                "tag": "v1.0",
+                "object": {"sha": "def456", "type": "commit", "url": ""},
+                "verification": {
+                    "verified": false,
+                    "reason": "unsigned",
+                    "signature": null,
+                    "payload": null
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            tag.verification,
+            Some(SignatureVerification {
+                verified: false,
+                reason: "unsigned".to_string()
+            })
+        );
+        assert_eq!(tag.object.unwrap().sha, "def456");
+    }
+
     #[test]
     fn test_content_type_clone() {
         let content_type = ContentType::File;
@@ -0,0 +1,86 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by every download stream of a run (`--limit-rate`)
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// Bytes per second, which is also the burst size
+    rate: u64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BucketState {
+    /// Available bytes; negative while streams are waiting off a debt
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl BucketState {
+    /// Take `bytes` from the bucket and return how long the caller must wait before
+    /// using them. Streams going into debt wait in turn, so the total stays at `rate`.
+    fn reserve(&mut self, rate: u64, bytes: u64, now: Instant) -> Duration {
+        let rate = rate as f64;
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.refilled_at = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+impl TokenBucket {
+    /// Cap the combined speed of the streams sharing this bucket
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1);
+        Self {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: rate as f64,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Account for `bytes` just received, sleeping as long as the limit requires
+    pub async fn consume(&self, bytes: usize) {
+        let wait = match self.state.lock() {
+            Ok(mut state) => state.reserve(self.rate, bytes as u64, Instant::now()),
+            Err(_) => return,
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_reserve() {
+        let start = Instant::now();
+        let mut state = BucketState {
+            tokens: 1000.0,
+            refilled_at: start,
+        };
+
+        // The initial burst is free, then each stream waits off its share of the debt
+        assert_eq!(state.reserve(1000, 1000, start), Duration::ZERO);
+        assert_eq!(state.reserve(1000, 500, start), Duration::from_millis(500));
+        assert_eq!(state.reserve(1000, 500, start), Duration::from_secs(1));
+
+        // Two seconds later the debt is repaid; the bucket never holds more than a second
+        let later = start + Duration::from_secs(10);
+        assert_eq!(state.reserve(1000, 1000, later), Duration::ZERO);
+        assert_eq!(state.reserve(1000, 100, later), Duration::from_millis(100));
+    }
+}
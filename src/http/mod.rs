@@ -1,22 +1,309 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::anyhow;
+use futures::{Stream, StreamExt};
 use log::{debug, warn};
-use reqwest::StatusCode;
-use reqwest::header::{ETAG, LAST_MODIFIED};
+use reqwest::header::{
+    ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{Certificate, Client, NoProxy, Proxy, StatusCode};
+use serde::Deserialize;
 use tokio::time::sleep;
 
-use crate::cache::{CachedResponse, load_cached_response, save_cached_response};
-use crate::rate_limit::RateLimitTracker;
+mod bandwidth;
+mod retry;
+
+pub use bandwidth::TokenBucket;
+pub use retry::{
+    RetriesExhausted, RetryPolicy, TransientStatus, describe_delay, is_transient, parse_jitter,
+};
+
+use crate::cache::{CachedResponse, load_cached_entry, load_cached_response, save_cached_response};
+use crate::config::ProviderConfig;
+use crate::error::{Context, GdlError, Result};
+use crate::github::api_base;
+use crate::rate_limit::{RateLimitSnapshot, RateLimitTracker};
 use crate::utils::system_time_to_secs;
 
 pub const DEFAULT_CACHE_TTL_SECS: u64 = 60 * 60; // 1 hour
 
+/// User agent sent unless the config file overrides it
+pub const DEFAULT_USER_AGENT: &str = "ghdl-rs (https://github.com/CaddyGlow/ghdl)";
+
+/// Network settings applied to every request
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// Time allowed to establish a connection (`--connect-timeout`)
+    pub connect_timeout: Option<Duration>,
+    /// Time allowed for a whole request, including reading the body of a file (`--timeout`)
+    pub timeout: Option<Duration>,
+    /// Proxy for every request (`--proxy`), overriding the `*_proxy` environment variables
+    pub proxy: Option<String>,
+    /// PEM bundle of extra trusted root certificates (`--cacert`)
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip TLS certificate verification (`--insecure`)
+    pub insecure: bool,
+}
+
+/// Build the HTTP client with the configured user agent, extra headers and network
+/// settings. Without `--proxy`, `https_proxy`, `http_proxy`, `all_proxy` and
+/// `no_proxy` are honored.
+pub fn build_client(provider: &ProviderConfig, options: &ClientOptions) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &provider.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("invalid header name '{}' in config", name))?;
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("invalid value for header '{}' in config", name))?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = Client::builder()
+        .user_agent(provider.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+        .default_headers(headers);
+    if let Some(connect) = options.connect_timeout {
+        builder = builder.connect_timeout(connect);
+    }
+    if let Some(total) = options.timeout {
+        builder = builder.timeout(total);
+    }
+    if let Some(proxy) = &options.proxy {
+        // The URL may embed credentials, so it is left out of the error
+        let proxy = Proxy::all(proxy)
+            .map_err(|_| {
+                anyhow!("invalid --proxy URL; expected e.g. http://host:3128 or socks5://host:1080")
+            })?
+            .no_proxy(NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &options.ca_bundle {
+        for certificate in load_ca_bundle(path)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    if options.insecure {
+        warn!("TLS certificate verification is disabled (--insecure)");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder.build().context("failed to construct HTTP client")
+}
+
+/// Read every certificate of a PEM bundle
+fn load_ca_bundle(path: &Path) -> Result<Vec<Certificate>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("failed to read CA bundle {}", path.display()))?;
+    let certificates = Certificate::from_pem_bundle(&pem)
+        .with_context(|| format!("invalid CA bundle {}", path.display()))?;
+    if certificates.is_empty() {
+        return Err(anyhow!("no certificates found in {}", path.display()).into());
+    }
+    debug!(
+        "Trusting {} extra root certificate(s) from {}",
+        certificates.len(),
+        path.display()
+    );
+    Ok(certificates)
+}
+
+/// How cached API responses are used for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Read and write the response cache (off with `--no-cache`)
+    pub enabled: bool,
+    /// Age after which a cached response is no longer reused
+    pub ttl: Duration,
+    /// Serve every response from the cache, whatever its age, and never send requests
+    pub offline: bool,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl: Duration::from_secs(DEFAULT_CACHE_TTL_SECS),
+            offline: false,
+        }
+    }
+}
+
+/// A response body stopped delivering data for longer than the stall timeout
+#[derive(Debug, Clone, Copy)]
+pub struct StalledStream {
+    pub idle: Duration,
+}
+
+impl fmt::Display for StalledStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no data received for {}s", self.idle.as_secs())
+    }
+}
+
+impl std::error::Error for StalledStream {}
+
+/// Check whether an error (or any context wrapping it) is a [`StalledStream`]
+pub fn is_stalled(err: &GdlError) -> bool {
+    err.downcast_ref::<StalledStream>().is_some()
+}
+
+/// Minimum average speed of a stream over a period (`--speed-limit`, `--speed-time`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LowSpeedLimit {
+    pub bytes_per_sec: u64,
+    pub time: Duration,
+}
+
+/// Checks and pacing applied to every response body of a run (`--stall-timeout`,
+/// `--speed-limit`, `--speed-time`, `--limit-rate`)
+#[derive(Debug, Clone, Default)]
+pub struct StreamLimits {
+    /// Abort a stream that delivers no data for this long
+    pub stall_timeout: Option<Duration>,
+    /// Abort a stream slower than this on average
+    pub low_speed: Option<LowSpeedLimit>,
+    /// Combined speed cap shared by the run's streams
+    pub bandwidth: Option<Arc<TokenBucket>>,
+}
+
+/// A response body stayed below the low-speed limit for the whole period
+#[derive(Debug, Clone, Copy)]
+pub struct SlowStream {
+    pub received: u64,
+    pub limit: LowSpeedLimit,
+}
+
+impl fmt::Display for SlowStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "received {} bytes in {}s, below the limit of {} bytes/s",
+            self.received,
+            self.limit.time.as_secs(),
+            self.limit.bytes_per_sec
+        )
+    }
+}
+
+impl std::error::Error for SlowStream {}
+
+/// Check whether an error (or any context wrapping it) is a [`SlowStream`]
+pub fn is_too_slow(err: &GdlError) -> bool {
+    err.downcast_ref::<SlowStream>().is_some()
+}
+
+/// Per-stream health checks applied by [`next_chunk`]: the stall timeout and the
+/// low-speed limit, plus the run's bandwidth cap
+#[derive(Debug)]
+pub struct StreamWatch {
+    stall_timeout: Option<Duration>,
+    low_speed: Option<LowSpeedLimit>,
+    bandwidth: Option<Arc<TokenBucket>>,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl StreamWatch {
+    /// Start watching a stream with the run's limits
+    pub fn new(limits: &StreamLimits) -> Self {
+        Self {
+            bandwidth: limits.bandwidth.clone(),
+            ..Self::with_limit(limits.stall_timeout, limits.low_speed, Instant::now())
+        }
+    }
+
+    fn with_limit(
+        stall_timeout: Option<Duration>,
+        low_speed: Option<LowSpeedLimit>,
+        now: Instant,
+    ) -> Self {
+        Self {
+            stall_timeout,
+            low_speed,
+            bandwidth: None,
+            window_start: now,
+            window_bytes: 0,
+        }
+    }
+
+    /// When the current low-speed period ends
+    fn window_end(&self) -> Option<Instant> {
+        self.low_speed.map(|limit| self.window_start + limit.time)
+    }
+
+    /// Count `bytes` received at `now`. At the end of each period the average speed
+    /// is compared with the limit, then a new period starts.
+    fn record(&mut self, bytes: u64, now: Instant) -> std::result::Result<(), SlowStream> {
+        self.window_bytes += bytes;
+        let Some(limit) = self.low_speed else {
+            return Ok(());
+        };
+        if now.duration_since(self.window_start) < limit.time {
+            return Ok(());
+        }
+        let required = (limit.bytes_per_sec as f64 * limit.time.as_secs_f64()) as u64;
+        if self.window_bytes < required {
+            return Err(SlowStream {
+                received: self.window_bytes,
+                limit,
+            });
+        }
+        self.window_start = now;
+        self.window_bytes = 0;
+        Ok(())
+    }
+}
+
+/// Await the next chunk of a response body, failing with [`StalledStream`] if nothing
+/// arrives within the stall timeout and with [`SlowStream`] if the stream falls below
+/// the low-speed limit. Chunks are paced by the run's `--limit-rate` bandwidth
+/// limit, if any.
+pub async fn next_chunk<S, T, E>(stream: &mut S, watch: &mut StreamWatch) -> Result<Option<T>>
+where
+    S: Stream<Item = std::result::Result<T, E>> + Unpin,
+    T: AsRef<[u8]>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let stall_deadline = watch
+        .stall_timeout
+        .map(|idle| (Instant::now() + idle, idle));
+    let next = loop {
+        let deadline = match (stall_deadline.map(|(at, _)| at), watch.window_end()) {
+            (Some(stall), Some(window)) => Some(stall.min(window)),
+            (stall, window) => stall.or(window),
+        };
+        let Some(deadline) = deadline else {
+            break stream.next().await;
+        };
+        match tokio::time::timeout_at(deadline.into(), stream.next()).await {
+            Ok(next) => break next,
+            Err(_) => {
+                let now = Instant::now();
+                if let Some((at, idle)) = stall_deadline
+                    && now >= at
+                {
+                    return Err(StalledStream { idle }.into());
+                }
+                watch.record(0, now)?;
+            }
+        }
+    };
+    let chunk = next.transpose().map_err(anyhow::Error::new)?;
+    if let Some(chunk) = &chunk {
+        watch.record(chunk.as_ref().len() as u64, Instant::now())?;
+        if let Some(bucket) = &watch.bandwidth {
+            bucket.consume(chunk.as_ref().len()).await;
+        }
+    }
+    Ok(chunk)
+}
+
 pub async fn send_github_request_cached(
     builder: &reqwest::RequestBuilder,
     rate_limit: &Arc<RateLimitTracker>,
     context: &str,
-    no_cache: bool,
+    cache: CachePolicy,
 ) -> Result<Vec<u8>> {
     // Get the URL from the request builder for cache key
     let url = builder
@@ -27,9 +314,19 @@ pub async fn send_github_request_cached(
         .url()
         .to_string();
 
+    if cache.offline {
+        return load_cached_entry(&url)
+            .ok()
+            .flatten()
+            .map(|cached| cached.body)
+            .ok_or_else(|| {
+                GdlError::Offline(format!("offline: no cached response for {}", context))
+            });
+    }
+
     // Try to load from cache if caching is enabled
-    let cached = if !no_cache {
-        load_cached_response(&url, DEFAULT_CACHE_TTL_SECS)
+    let fresh = if cache.enabled {
+        load_cached_response(&url, cache.ttl.as_secs())
             .ok()
             .flatten()
     } else {
@@ -38,22 +335,47 @@ pub async fn send_github_request_cached(
 
     // If we have a valid cached response, use it directly without making any request
     // This avoids consuming GitHub API rate limit
-    if let Some(cached_resp) = cached {
+    let now = system_time_to_secs(std::time::SystemTime::now());
+    if let Some(cached_resp) = fresh {
         debug!(
             "Using cached response for {} (age: {}s, no request made)",
             url,
-            system_time_to_secs(std::time::SystemTime::now()) - cached_resp.timestamp
+            now.saturating_sub(cached_resp.timestamp)
         );
         return Ok(cached_resp.body);
     }
 
-    // No valid cache, make a fresh request
-    let request_builder = builder
+    // An expired entry can still be revalidated
+    let cached = if cache.enabled {
+        load_cached_entry(&url).ok().flatten()
+    } else {
+        None
+    };
+
+    // Stale or missing cache: make a request, conditional on the cached validators so
+    // an unchanged resource answers 304 (which does not count against the rate limit)
+    let mut request_builder = builder
         .try_clone()
         .ok_or_else(|| anyhow!("failed to clone GitHub request for {}", context))?;
+    if let Some(stale) = &cached {
+        for (name, value) in conditional_headers(stale) {
+            request_builder = request_builder.header(name, value);
+        }
+    }
 
     let response = send_github_request(&request_builder, rate_limit, context).await?;
 
+    if response.status() == StatusCode::NOT_MODIFIED
+        && let Some(mut revalidated) = cached
+    {
+        debug!("Cached response for {} revalidated (304 Not Modified)", url);
+        revalidated.timestamp = now;
+        if let Err(e) = save_cached_response(&revalidated) {
+            warn!("Failed to refresh cached response for {}: {}", url, e);
+        }
+        return Ok(revalidated.body);
+    }
+
     // Extract caching headers from response
     let headers = response.headers();
     let etag = headers
@@ -73,7 +395,7 @@ pub async fn send_github_request_cached(
         .to_vec();
 
     // Cache the response if caching is enabled
-    if !no_cache && (etag.is_some() || last_modified.is_some()) {
+    if cache.enabled && (etag.is_some() || last_modified.is_some()) {
         let cached_response = CachedResponse {
             url: url.clone(),
             body: body.clone(),
@@ -90,104 +412,273 @@ pub async fn send_github_request_cached(
     Ok(body)
 }
 
+/// `If-None-Match`/`If-Modified-Since` headers revalidating a cached response
+fn conditional_headers(cached: &CachedResponse) -> Vec<(HeaderName, &str)> {
+    let mut headers = Vec::new();
+    if let Some(etag) = &cached.etag {
+        headers.push((IF_NONE_MATCH, etag.as_str()));
+    }
+    if let Some(last_modified) = &cached.last_modified {
+        headers.push((IF_MODIFIED_SINCE, last_modified.as_str()));
+    }
+    headers
+}
+
+/// GitHub answers contents requests for repositories without commits with a 404
+/// whose message says so
+fn is_empty_repository(status: StatusCode, body: &str) -> bool {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        message: String,
+    }
+    status == StatusCode::NOT_FOUND
+        && serde_json::from_str::<ErrorBody>(body)
+            .is_ok_and(|body| body.message.starts_with("This repository is empty"))
+}
+
 pub async fn send_github_request(
     builder: &reqwest::RequestBuilder,
     rate_limit: &Arc<RateLimitTracker>,
     context: &str,
 ) -> Result<reqwest::Response> {
-    const MAX_ATTEMPTS: usize = 5;
+    const MAX_RATE_LIMIT_ATTEMPTS: usize = 5;
 
-    for attempt in 1..=MAX_ATTEMPTS {
+    let policy = rate_limit.retry;
+    let mut rate_limited = 0;
+    let mut failures = 0;
+    // Only REST API calls count against the quota; file downloads are not paced
+    let paced = builder
+        .try_clone()
+        .and_then(|request| request.build().ok())
+        .is_some_and(|request| request.url().as_str().starts_with(&api_base()));
+    loop {
         let request = builder
             .try_clone()
             .ok_or_else(|| anyhow!("failed to clone GitHub request for {}", context))?;
+        if paced {
+            rate_limit
+                .spend_api_call()
+                .with_context(|| format!("not requesting {}", context))?;
+            rate_limit.pace().await;
+        }
 
-        let response = request
-            .send()
-            .await
-            .with_context(|| format!("GitHub request failed for {}", context))?;
-
-        if let Some((snapshot, log_change, warn_low)) =
-            rate_limit.record_headers(response.headers()).await
-        {
-            if log_change {
-                debug!(
-                    "GitHub rate limit: {} remaining of {} (used: {}) - resets {}",
-                    snapshot
-                        .remaining
-                        .map(|value| value.to_string())
-                        .unwrap_or_else(|| "unknown".to_string()),
-                    snapshot
-                        .limit
-                        .map(|value| value.to_string())
-                        .unwrap_or_else(|| "unknown".to_string()),
-                    snapshot
-                        .used
-                        .map(|value| value.to_string())
-                        .unwrap_or_else(|| "unknown".to_string()),
-                    snapshot.reset_eta_display()
-                );
-            }
+        let err: GdlError = match request.send().await {
+            Ok(response) => {
+                if let Some((snapshot, log_change, warn_low)) =
+                    rate_limit.record_headers(response.headers()).await
+                {
+                    if log_change {
+                        debug!(
+                            "GitHub rate limit: {} remaining of {} (used: {}) - resets {}",
+                            snapshot
+                                .remaining
+                                .map(|value| value.to_string())
+                                .unwrap_or_else(|| "unknown".to_string()),
+                            snapshot
+                                .limit
+                                .map(|value| value.to_string())
+                                .unwrap_or_else(|| "unknown".to_string()),
+                            snapshot
+                                .used
+                                .map(|value| value.to_string())
+                                .unwrap_or_else(|| "unknown".to_string()),
+                            snapshot.reset_eta_display()
+                        );
+                    }
 
-            if warn_low {
-                warn!(
-                    "GitHub rate limit low: {} remaining of {} (resets {}).",
-                    snapshot
-                        .remaining
-                        .map(|value| value.to_string())
-                        .unwrap_or_else(|| "unknown".to_string()),
-                    snapshot
-                        .limit
-                        .map(|value| value.to_string())
-                        .unwrap_or_else(|| "unknown".to_string()),
-                    snapshot.reset_eta_display()
-                );
-            }
-        }
+                    if warn_low {
+                        warn!(
+                            "GitHub rate limit low: {} remaining of {} (resets {}).",
+                            snapshot
+                                .remaining
+                                .map(|value| value.to_string())
+                                .unwrap_or_else(|| "unknown".to_string()),
+                            snapshot
+                                .limit
+                                .map(|value| value.to_string())
+                                .unwrap_or_else(|| "unknown".to_string()),
+                            snapshot.reset_eta_display()
+                        );
+                    }
+                }
 
-        let status = response.status();
-        if status.is_success() || status == StatusCode::NOT_MODIFIED {
-            return Ok(response);
-        }
+                let status = response.status();
+                if status.is_success() || status == StatusCode::NOT_MODIFIED {
+                    return Ok(response);
+                }
+
+                if let Some(wait) = RateLimitTracker::backoff_duration(status, response.headers()) {
+                    rate_limited += 1;
+                    if rate_limited == MAX_RATE_LIMIT_ATTEMPTS {
+                        let headers = response.headers().clone();
+                        let body = response
+                            .text()
+                            .await
+                            .unwrap_or_else(|_| "<unable to read response body>".into());
+                        return Err(GdlError::RateLimited {
+                            reset: RateLimitSnapshot::from_headers(&headers)
+                                .and_then(|snapshot| snapshot.reset_epoch),
+                            message: format!(
+                                "GitHub request {} exceeded rate limit after {} attempts (status {}): {}",
+                                context, rate_limited, status, body
+                            ),
+                        });
+                    }
+
+                    let wait_secs = wait.as_secs().max(1);
+                    warn!(
+                        "GitHub request {} hit a rate limit (status {}), retrying after {}s...",
+                        context, status, wait_secs
+                    );
+                    sleep(wait).await;
+                    continue;
+                }
 
-        if let Some(wait) = RateLimitTracker::backoff_duration(status, response.headers()) {
-            if attempt == MAX_ATTEMPTS {
                 let body = response
                     .text()
                     .await
                     .unwrap_or_else(|_| "<unable to read response body>".into());
-                return Err(anyhow!(
-                    "GitHub request {} exceeded rate limit after {} attempts (status {}): {}",
-                    context,
-                    attempt,
-                    status,
-                    body
-                ));
+                let message = format!(
+                    "GitHub request {} failed with status {}: {}",
+                    context, status, body
+                );
+                match TransientStatus::check(status) {
+                    Some(transient) => anyhow::Error::new(transient).context(message).into(),
+                    None if is_empty_repository(status, &body) => {
+                        return Err(GdlError::EmptyRepository(message));
+                    }
+                    None => {
+                        return Err(GdlError::from_status(status, message.clone())
+                            .unwrap_or_else(|| anyhow!(message).into()));
+                    }
+                }
             }
+            Err(err) => anyhow::Error::new(err)
+                .context(GdlError::Network(format!(
+                    "GitHub request failed for {}",
+                    context
+                )))
+                .into(),
+        };
 
-            let wait_secs = wait.as_secs().max(1);
-            warn!(
-                "GitHub request {} hit a rate limit (status {}), retrying after {}s...",
-                context, status, wait_secs
-            );
-            sleep(wait).await;
-            continue;
+        // Dropped connections, timeouts and 5xx answers are retried with exponential
+        // backoff, on a budget separate from rate limit waits
+        if !is_transient(&err) {
+            return Err(err);
         }
-
-        let body = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "<unable to read response body>".into());
-        return Err(anyhow!(
-            "GitHub request {} failed with status {}: {}",
+        if failures == policy.retries {
+            return Err(err.context(RetriesExhausted {
+                attempts: failures + 1,
+            }));
+        }
+        failures += 1;
+        let delay = policy.delay(failures);
+        warn!(
+            "GitHub request {} failed ({}), retrying in {} ({}/{})...",
             context,
-            status,
-            body
+            err.root_cause(),
+            describe_delay(delay),
+            failures,
+            policy.retries
+        );
+        sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    #[test]
+    fn test_conditional_headers() {
+        let mut cached = CachedResponse {
+            url: "https://api.github.com/repos/o/r/contents".to_string(),
+            body: Vec::new(),
+            etag: Some("W/\"abc\"".to_string()),
+            last_modified: Some("Mon, 01 Jan 2024 00:00:00 GMT".to_string()),
+            timestamp: 0,
+        };
+        assert_eq!(
+            conditional_headers(&cached),
+            vec![
+                (IF_NONE_MATCH, "W/\"abc\""),
+                (IF_MODIFIED_SINCE, "Mon, 01 Jan 2024 00:00:00 GMT"),
+            ]
+        );
+
+        cached.etag = None;
+        cached.last_modified = None;
+        assert!(conditional_headers(&cached).is_empty());
+    }
+
+    #[test]
+    fn test_is_empty_repository() {
+        let body = r#"{"message":"This repository is empty.","status":"404"}"#;
+        assert!(is_empty_repository(StatusCode::NOT_FOUND, body));
+        assert!(!is_empty_repository(StatusCode::CONFLICT, body));
+        assert!(!is_empty_repository(
+            StatusCode::NOT_FOUND,
+            r#"{"message":"Not Found"}"#
         ));
+        assert!(!is_empty_repository(StatusCode::NOT_FOUND, "<html>"));
     }
 
-    Err(anyhow!(
-        "GitHub request {} failed after exhausting retries",
-        context
-    ))
+    #[tokio::test]
+    async fn test_next_chunk() {
+        let mut chunks = stream::iter(vec![Ok::<_, std::io::Error>(vec![1u8]), Ok(vec![2])]);
+        let mut watch = StreamWatch::with_limit(Some(Duration::from_secs(5)), None, Instant::now());
+        assert_eq!(
+            next_chunk(&mut chunks, &mut watch).await.unwrap(),
+            Some(vec![1])
+        );
+        let mut unwatched = StreamWatch::new(&StreamLimits::default());
+        assert_eq!(
+            next_chunk(&mut chunks, &mut unwatched).await.unwrap(),
+            Some(vec![2])
+        );
+        assert_eq!(next_chunk(&mut chunks, &mut watch).await.unwrap(), None);
+
+        // A stream that never yields trips the stall timeout
+        let mut silent = stream::pending::<std::result::Result<Vec<u8>, std::io::Error>>();
+        let mut watch =
+            StreamWatch::with_limit(Some(Duration::from_millis(10)), None, Instant::now());
+        let err = next_chunk(&mut silent, &mut watch).await.unwrap_err();
+        assert!(is_stalled(&err));
+        assert!(!is_stalled(&anyhow!("other failure").into()));
+
+        // ...and the low-speed limit, before the longer stall timeout
+        let limit = LowSpeedLimit {
+            bytes_per_sec: 1000,
+            time: Duration::from_millis(10),
+        };
+        let mut watch =
+            StreamWatch::with_limit(Some(Duration::from_secs(5)), Some(limit), Instant::now());
+        let err = next_chunk(&mut silent, &mut watch).await.unwrap_err();
+        assert!(is_too_slow(&err));
+        assert!(!is_stalled(&err));
+    }
+
+    #[test]
+    fn test_stream_watch_low_speed() {
+        let start = Instant::now();
+        let limit = LowSpeedLimit {
+            bytes_per_sec: 100,
+            time: Duration::from_secs(10),
+        };
+        let mut watch = StreamWatch::with_limit(None, Some(limit), start);
+
+        // Slow bursts are fine until the period ends, then the average is checked
+        watch.record(600, start + Duration::from_secs(2)).unwrap();
+        watch.record(500, start + Duration::from_secs(10)).unwrap();
+        assert_eq!(watch.window_end(), Some(start + Duration::from_secs(20)));
+        let err = watch
+            .record(999, start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(err.received, 999);
+
+        let mut unlimited = StreamWatch::with_limit(None, None, start);
+        assert!(unlimited.record(0, start + Duration::from_secs(60)).is_ok());
+        assert_eq!(unlimited.window_end(), None);
+    }
 }
@@ -0,0 +1,186 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use super::{SlowStream, StalledStream};
+use crate::error::GdlError;
+
+/// Longest wait between two attempts, whatever the attempt number
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How transient network failures are retried (`--retries`, `--retry-delay`,
+/// `--retry-jitter`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub retries: usize,
+    /// Wait before the first retry, doubled for each following one
+    pub base_delay: Duration,
+    /// Fraction of each wait that is randomized, from 0 (none) to 1
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            base_delay: Duration::from_secs(1),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `attempt` (starting at 1)
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        self.delay_with(attempt, random)
+    }
+
+    /// Exponential backoff with `random` (0 to 1) taking up to `jitter` of the wait off
+    fn delay_with(&self, attempt: usize, random: f64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16) as i32;
+        let backoff = self
+            .base_delay
+            .mul_f64(2f64.powi(exponent))
+            .min(MAX_RETRY_DELAY);
+        backoff.mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+/// Parse `--retry-jitter`, a fraction between 0 and 1
+pub fn parse_jitter(value: &str) -> Result<f64, String> {
+    let jitter: f64 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid fraction '{}'", value))?;
+    if (0.0..=1.0).contains(&jitter) {
+        Ok(jitter)
+    } else {
+        Err(format!("jitter must be between 0 and 1, got {}", jitter))
+    }
+}
+
+/// The server answered with a status worth retrying (408 or a 5xx gateway error)
+#[derive(Debug, Clone, Copy)]
+pub struct TransientStatus {
+    pub status: StatusCode,
+}
+
+impl fmt::Display for TransientStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "server answered {}", self.status)
+    }
+}
+
+impl std::error::Error for TransientStatus {}
+
+impl TransientStatus {
+    pub fn check(status: StatusCode) -> Option<Self> {
+        matches!(
+            status,
+            StatusCode::REQUEST_TIMEOUT
+                | StatusCode::INTERNAL_SERVER_ERROR
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        )
+        .then_some(Self { status })
+    }
+}
+
+/// Context added once a request has used up its retries, so callers that retry
+/// themselves do not start over
+#[derive(Debug, Clone, Copy)]
+pub struct RetriesExhausted {
+    pub attempts: usize,
+}
+
+impl fmt::Display for RetriesExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gave up after {} attempts", self.attempts)
+    }
+}
+
+impl std::error::Error for RetriesExhausted {}
+
+/// Check whether an error is a failure that may go away on its own: a stalled or too
+/// slow stream, a dropped or timed-out connection, or a 5xx answer
+pub fn is_transient(err: &GdlError) -> bool {
+    if err.downcast_ref::<RetriesExhausted>().is_some() {
+        return false;
+    }
+    err.chain().any(|cause| {
+        if cause.is::<StalledStream>() || cause.is::<SlowStream>() || cause.is::<TransientStatus>()
+        {
+            return true;
+        }
+        if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            return err.is_timeout() || err.is_connect() || err.is_body();
+        }
+        cause.downcast_ref::<io::Error>().is_some_and(|err| {
+            matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::UnexpectedEof
+            )
+        })
+    })
+}
+
+/// Human-readable wait, e.g. `1.5s`
+pub fn describe_delay(delay: Duration) -> String {
+    format!("{:.1}s", delay.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy {
+            retries: 5,
+            base_delay: Duration::from_millis(500),
+            jitter: 0.5,
+        };
+        assert_eq!(policy.delay_with(1, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.delay_with(3, 0.0), Duration::from_secs(2));
+        assert_eq!(policy.delay_with(3, 1.0), Duration::from_secs(1));
+        assert_eq!(policy.delay_with(40, 0.0), MAX_RETRY_DELAY);
+        assert!(policy.delay(2) <= Duration::from_secs(1));
+
+        assert_eq!(parse_jitter("0.25"), Ok(0.25));
+        assert!(parse_jitter("1.5").is_err());
+        assert!(parse_jitter("some").is_err());
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let stalled = anyhow::Error::new(StalledStream {
+            idle: Duration::from_secs(1),
+        });
+        assert!(is_transient(
+            &GdlError::from(stalled).context("failed to download a.txt")
+        ));
+
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer");
+        assert!(is_transient(&reset.into()));
+
+        let bad_gateway = TransientStatus::check(StatusCode::BAD_GATEWAY).unwrap();
+        assert!(is_transient(&anyhow::Error::new(bad_gateway).into()));
+        assert!(TransientStatus::check(StatusCode::NOT_FOUND).is_none());
+
+        let exhausted = anyhow::Error::new(bad_gateway).context(RetriesExhausted { attempts: 4 });
+        assert!(!is_transient(&exhausted.into()));
+        assert!(!is_transient(&anyhow!("404 Not Found").into()));
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use console::style;
+use reqwest::Client;
+
+use crate::error::GdlError;
+use crate::github::types::{CommitResponse, RepositoryInfo};
+use crate::github::{
+    fetch_latest_commit, fetch_repository_info, parse_github_url, path_exists, ref_exists,
+};
+use crate::http::CachePolicy;
+use crate::progress::format_bytes;
+use crate::rate_limit::RateLimitTracker;
+
+/// Print what a URL refers to before downloading it (`ghdl info`). Fails when the
+/// ref or the path does not exist, so scripts can check a URL by the exit status.
+pub async fn run_info(client: &Client, url: &str, token: Option<&str>) -> Result<()> {
+    let mut request = parse_github_url(url)?;
+    let repo = fetch_repository_info(client, &request.owner, &request.repo, token)
+        .await
+        .context("failed to fetch repository information")?;
+    let rate_limit = Arc::new(RateLimitTracker::default());
+
+    println!("Repository:     {}/{}", request.owner, request.repo);
+    if let Some(description) = repo.description.as_deref().filter(|d| !d.is_empty()) {
+        println!("Description:    {}", description);
+    }
+    println!("Visibility:     {}", describe_visibility(&repo));
+    println!("Default branch: {}", repo.default_branch);
+    println!(
+        "Size:           {} (GitHub's estimate)",
+        format_bytes(repo.size.saturating_mul(1024))
+    );
+
+    if request.branch.is_empty() {
+        request.branch = repo.default_branch.clone();
+    } else if ref_exists(
+        client,
+        &request.owner,
+        &request.repo,
+        &request.branch,
+        token,
+        &rate_limit,
+    )
+    .await
+        == Some(false)
+    {
+        println!("Ref:            {} (not found)", request.branch);
+        return Err(GdlError::NotFound(format!(
+            "ref '{}' does not exist in {}/{}",
+            request.branch, request.owner, request.repo
+        ))
+        .into());
+    }
+
+    let commit = fetch_latest_commit(
+        client,
+        &request,
+        "",
+        token,
+        &rate_limit,
+        CachePolicy::default(),
+    )
+    .await?;
+    match &commit {
+        Some(commit) => println!(
+            "Ref:            {} at {}",
+            request.branch,
+            describe_commit(commit)
+        ),
+        None => println!("Ref:            {}", request.branch),
+    }
+
+    if request.path.is_empty() {
+        return Ok(());
+    }
+    if !path_exists(client, &request, token, &rate_limit).await? {
+        println!("Path:           {} (not found)", request.path);
+        return Err(GdlError::NotFound(format!(
+            "'{}' does not exist at {}",
+            request.path, request.branch
+        ))
+        .into());
+    }
+    println!(
+        "Path:           {} {}",
+        request.path,
+        style("(exists)").green()
+    );
+    Ok(())
+}
+
+/// `public`, `private`, or `internal`, plus `archived` when it no longer changes
+fn describe_visibility(repo: &RepositoryInfo) -> String {
+    let visibility = repo
+        .visibility
+        .clone()
+        .unwrap_or_else(|| if repo.private { "private" } else { "public" }.to_string());
+    if repo.archived {
+        format!("{}, archived", visibility)
+    } else {
+        visibility
+    }
+}
+
+/// Abbreviated SHA, subject line, author, and date of a commit
+fn describe_commit(commit: &CommitResponse) -> String {
+    let subject = commit.commit.message.lines().next().unwrap_or_default();
+    let sha = &commit.sha[..commit.sha.len().min(7)];
+    let mut description = format!("{} \"{}\"", sha, subject);
+    let signature = commit
+        .commit
+        .author
+        .as_ref()
+        .or(commit.commit.committer.as_ref());
+    if let Some(signature) = signature {
+        if let Some(name) = &signature.name {
+            description.push_str(&format!(" by {}", name));
+        }
+        // Keep the date only: `2024-05-01T12:00:00Z` -> `2024-05-01`
+        let date = signature.date.split('T').next().unwrap_or(&signature.date);
+        description.push_str(&format!(", {}", date));
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_repository_and_commit() {
+        let repo: RepositoryInfo = serde_json::from_str(
+            r#"{"default_branch": "main", "size": 2048, "private": true, "archived": true}"#,
+        )
+        .unwrap();
+        assert_eq!(describe_visibility(&repo), "private, archived");
+        let repo: RepositoryInfo =
+            serde_json::from_str(r#"{"default_branch": "main", "visibility": "internal"}"#)
+                .unwrap();
+        assert_eq!(describe_visibility(&repo), "internal");
+
+        let commit: CommitResponse = serde_json::from_str(
+            r#"{
+                "sha": "0123456789abcdef",
+                "commit": {
+                    "message": "Fix the build\n\nLonger explanation",
+                    "author": {"name": "Octo Cat", "date": "2024-05-01T12:00:00Z"},
+                    "committer": {"name": "GitHub", "date": "2024-05-02T08:00:00Z"}
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            describe_commit(&commit),
+            "0123456 \"Fix the build\" by Octo Cat, 2024-05-01"
+        );
+    }
+}
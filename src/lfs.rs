@@ -0,0 +1,461 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use futures::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
+use reqwest::Client;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::error::{Context, Result};
+use crate::github::archive_base;
+use crate::progress::format_bytes;
+use crate::types::RequestInfo;
+
+/// Pointer files are tiny; anything larger is real content
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// Maximum number of objects per batch API request
+const BATCH_SIZE: usize = 100;
+
+const LFS_MEDIA_TYPE: &str = "application/vnd.git-lfs+json";
+
+const POINTER_VERSIONS: [&str; 2] = [
+    "version https://git-lfs.github.com/spec/v1",
+    "version https://hawser.github.com/spec/v1",
+];
+
+/// Object reference stored in a Git LFS pointer file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfsPointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    objects: Vec<BatchObject>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchObject {
+    oid: String,
+    actions: Option<BatchActions>,
+    error: Option<BatchError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchActions {
+    download: Option<BatchAction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BatchAction {
+    href: String,
+    #[serde(default)]
+    header: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchError {
+    code: u16,
+    message: String,
+}
+
+/// Parse the contents of a Git LFS pointer file
+pub fn parse_lfs_pointer(content: &[u8]) -> Option<LfsPointer> {
+    if content.len() as u64 > MAX_POINTER_SIZE {
+        return None;
+    }
+
+    let text = std::str::from_utf8(content).ok()?;
+    let mut lines = text.lines();
+    let version = lines.next()?;
+    if !POINTER_VERSIONS.contains(&version) {
+        return None;
+    }
+
+    let mut oid = None;
+    let mut size = None;
+    for line in lines.filter(|line| !line.is_empty()) {
+        let (key, value) = line.split_once(' ')?;
+        match key {
+            "oid" => {
+                let hash = value.strip_prefix("sha256:")?;
+                if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return None;
+                }
+                oid = Some(hash.to_ascii_lowercase());
+            }
+            "size" => size = Some(value.parse().ok()?),
+            // Extension lines (`ext-0-name ...`) don't affect the object itself
+            _ => {}
+        }
+    }
+
+    Some(LfsPointer {
+        oid: oid?,
+        size: size?,
+    })
+}
+
+/// Replace any Git LFS pointer files among `files` with the objects they reference.
+///
+/// `files` pairs each repository path with the local file it was written to. Files that
+/// are not pointers are left untouched, so this is safe to run over a whole download.
+pub async fn fetch_lfs_objects(
+    client: &Client,
+    request: &RequestInfo,
+    token: Option<&str>,
+    files: &[(String, PathBuf)],
+    multi: &MultiProgress,
+) -> Result<()> {
+    let mut pointers = Vec::new();
+    for (item_path, target_path) in files {
+        if let Some(pointer) = read_pointer(target_path).await {
+            pointers.push((item_path.as_str(), target_path.as_path(), pointer));
+        }
+    }
+
+    if pointers.is_empty() {
+        debug!("No Git LFS pointer files found");
+        return Ok(());
+    }
+
+    let total_bytes: u64 = pointers.iter().map(|(_, _, pointer)| pointer.size).sum();
+    info!(
+        "Fetching {} Git LFS object(s) ({})",
+        pointers.len(),
+        format_bytes(total_bytes)
+    );
+
+    let mut actions: HashMap<String, BatchAction> = HashMap::new();
+    let mut errors: HashMap<String, String> = HashMap::new();
+    let mut seen = HashSet::new();
+    let unique: Vec<&LfsPointer> = pointers
+        .iter()
+        .map(|(_, _, pointer)| pointer)
+        .filter(|pointer| seen.insert(pointer.oid.clone()))
+        .collect();
+
+    for chunk in unique.chunks(BATCH_SIZE) {
+        for object in request_batch(client, request, token, chunk).await? {
+            match (object.actions.and_then(|a| a.download), object.error) {
+                (_, Some(error)) => {
+                    errors.insert(object.oid, format!("{} ({})", error.message, error.code));
+                }
+                (Some(action), None) => {
+                    actions.insert(object.oid, action);
+                }
+                (None, None) => {
+                    errors.insert(object.oid, "no download action returned".to_string());
+                }
+            }
+        }
+    }
+
+    let pb = multi.add(ProgressBar::new(total_bytes));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%)")
+            .expect("invalid progress bar template")
+            .progress_chars("#>-"),
+    );
+    pb.set_message("Fetching Git LFS objects");
+
+    let mut failed = 0;
+    for (item_path, target_path, pointer) in &pointers {
+        let result = match actions.get(&pointer.oid) {
+            Some(action) => download_object(client, action, pointer, target_path, &pb).await,
+            None => Err(anyhow!(
+                "{}",
+                errors
+                    .get(&pointer.oid)
+                    .map(String::as_str)
+                    .unwrap_or("object missing from batch response")
+            )
+            .into()),
+        };
+
+        match result {
+            Ok(()) => debug!("Fetched Git LFS object for {}", item_path),
+            Err(err) => {
+                warn!(
+                    "Unable to fetch Git LFS object for {}: {:#}",
+                    item_path, err
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    pb.finish_and_clear();
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} Git LFS object(s) could not be fetched; pointer files were left in place",
+            failed,
+            pointers.len()
+        )
+        .into());
+    }
+
+    info!("Fetched {} Git LFS object(s)", pointers.len());
+    Ok(())
+}
+
+async fn read_pointer(path: &Path) -> Option<LfsPointer> {
+    let metadata = tokio::fs::symlink_metadata(path).await.ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_POINTER_SIZE {
+        return None;
+    }
+    let content = tokio::fs::read(path).await.ok()?;
+    parse_lfs_pointer(&content)
+}
+
+fn batch_url(request: &RequestInfo) -> String {
+    format!(
+        "{}/{}/{}.git/info/lfs/objects/batch",
+        archive_base(),
+        request.owner,
+        request.repo
+    )
+}
+
+async fn request_batch(
+    client: &Client,
+    request: &RequestInfo,
+    token: Option<&str>,
+    pointers: &[&LfsPointer],
+) -> Result<Vec<BatchObject>> {
+    let body = serde_json::json!({
+        "operation": "download",
+        "transfers": ["basic"],
+        "objects": pointers
+            .iter()
+            .map(|pointer| serde_json::json!({ "oid": pointer.oid, "size": pointer.size }))
+            .collect::<Vec<_>>(),
+    });
+
+    let mut builder = client
+        .post(batch_url(request))
+        .header(ACCEPT, LFS_MEDIA_TYPE)
+        .header(CONTENT_TYPE, LFS_MEDIA_TYPE)
+        .body(body.to_string());
+    if let Some(token) = token {
+        builder = builder.basic_auth("x-access-token", Some(token.trim()));
+    }
+
+    let response = builder.send().await.with_context(|| {
+        format!(
+            "failed to contact the Git LFS server for {}/{}",
+            request.owner, request.repo
+        )
+    })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let hint = if token.is_none() && (status.as_u16() == 401 || status.as_u16() == 404) {
+            " (private repositories require a token)"
+        } else {
+            ""
+        };
+        return Err(anyhow!(
+            "Git LFS batch request for {}/{} failed: HTTP {}{}",
+            request.owner,
+            request.repo,
+            status,
+            hint
+        )
+        .into());
+    }
+
+    let text = response
+        .text()
+        .await
+        .context("failed to read Git LFS batch response")?;
+    let batch: BatchResponse =
+        serde_json::from_str(&text).context("failed to parse Git LFS batch response")?;
+    Ok(batch.objects)
+}
+
+async fn download_object(
+    client: &Client,
+    action: &BatchAction,
+    pointer: &LfsPointer,
+    target_path: &Path,
+    pb: &ProgressBar,
+) -> Result<()> {
+    let mut builder = client.get(&action.href);
+    for (name, value) in &action.header {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+
+    let response = builder
+        .send()
+        .await
+        .context("failed to request Git LFS object")?;
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP {}", response.status()).into());
+    }
+
+    // Keep whatever timestamp the strategy assigned (e.g. --preserve-mtime)
+    let modified = std::fs::metadata(target_path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+
+    let file_name = target_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = target_path.with_file_name(format!(".{}.lfs-tmp", file_name));
+    let mut file = tokio::fs::File::create(&temp_path)
+        .await
+        .with_context(|| format!("failed to create temporary file {}", temp_path.display()))?;
+
+    let mut hasher = Sha256::new();
+    let mut written: u64 = 0;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.context("failed to read Git LFS object data")?;
+        hasher.update(&chunk);
+        file.write_all(&chunk)
+            .await
+            .with_context(|| format!("failed to write to {}", temp_path.display()))?;
+        written += chunk.len() as u64;
+        pb.inc(chunk.len() as u64);
+    }
+    file.flush()
+        .await
+        .with_context(|| format!("failed to flush {}", temp_path.display()))?;
+    drop(file);
+
+    let digest = format!("{:x}", hasher.finalize());
+    if digest != pointer.oid || written != pointer.size {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(anyhow!(
+            "downloaded object does not match pointer (sha256 {}, {} bytes)",
+            digest,
+            written
+        )
+        .into());
+    }
+
+    tokio::fs::rename(&temp_path, target_path)
+        .await
+        .with_context(|| {
+            format!(
+                "failed to move {} to {}",
+                temp_path.display(),
+                target_path.display()
+            )
+        })?;
+
+    if let Some(modified) = modified
+        && let Err(err) = std::fs::File::options()
+            .write(true)
+            .open(target_path)
+            .and_then(|file| file.set_modified(modified))
+    {
+        debug!(
+            "Unable to restore mtime on {}: {}",
+            target_path.display(),
+            err
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OID: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+
+    #[test]
+    fn test_parse_lfs_pointer() {
+        let pointer = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12345\n",
+            OID
+        );
+        assert_eq!(
+            parse_lfs_pointer(pointer.as_bytes()),
+            Some(LfsPointer {
+                oid: OID.to_string(),
+                size: 12345
+            })
+        );
+
+        // Extension lines and the legacy version URL are accepted
+        let with_ext = format!(
+            "version https://hawser.github.com/spec/v1\next-0-foo sha256:{}\noid sha256:{}\nsize 1\n",
+            OID, OID
+        );
+        assert!(parse_lfs_pointer(with_ext.as_bytes()).is_some());
+
+        // Not pointers
+        assert_eq!(parse_lfs_pointer(b"fn main() {}\n"), None);
+        assert_eq!(parse_lfs_pointer(b""), None);
+        let missing_size = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\n",
+            OID
+        );
+        assert_eq!(parse_lfs_pointer(missing_size.as_bytes()), None);
+        assert_eq!(
+            parse_lfs_pointer(
+                b"version https://git-lfs.github.com/spec/v1\noid sha256:abc\nsize 3\n"
+            ),
+            None
+        );
+        let oversized = format!("{}{}", pointer, " ".repeat(MAX_POINTER_SIZE as usize));
+        assert_eq!(parse_lfs_pointer(oversized.as_bytes()), None);
+    }
+
+    #[test]
+    fn test_batch_response_deserialization() {
+        let json = format!(
+            r#"{{
+                "transfer": "basic",
+                "objects": [
+                    {{
+                        "oid": "{}",
+                        "size": 12345,
+                        "actions": {{
+                            "download": {{
+                                "href": "https://example.com/object",
+                                "header": {{ "Authorization": "RemoteAuth abc" }},
+                                "expires_in": 3600
+                            }}
+                        }}
+                    }},
+                    {{
+                        "oid": "missing",
+                        "size": 1,
+                        "error": {{ "code": 404, "message": "Object does not exist" }}
+                    }}
+                ]
+            }}"#,
+            OID
+        );
+
+        let batch: BatchResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(batch.objects.len(), 2);
+
+        let download = batch.objects[0]
+            .actions
+            .as_ref()
+            .and_then(|actions| actions.download.as_ref())
+            .unwrap();
+        assert_eq!(download.href, "https://example.com/object");
+        assert_eq!(download.header["Authorization"], "RemoteAuth abc");
+
+        let error = batch.objects[1].error.as_ref().unwrap();
+        assert_eq!(error.code, 404);
+        assert_eq!(error.message, "Object does not exist");
+    }
+}
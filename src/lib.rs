@@ -0,0 +1,89 @@
+//! Download files and directories from GitHub repositories.
+//!
+//! This is the engine behind the `ghdl` command. A URL is resolved to a repository,
+//! ref, and path, then fetched with the REST API, a sparse git checkout, or the
+//! repository archive, whichever [`DownloadStrategy`] selects.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), ghdl::GdlError> {
+//! // Defaults match the command line: automatic strategy, 4 parallel files
+//! ghdl::download(
+//!     "https://github.com/owner/repo/tree/main/docs",
+//!     Some(std::path::Path::new("vendor/docs")),
+//!     std::env::var("GITHUB_TOKEN").ok().as_deref(),
+//! )
+//! .await
+//! # }
+//! ```
+//!
+//! For more control, configure a [`Downloader`] with [`Downloader::builder`]; set a
+//! [`ProgressSink`] on it to follow each file.
+
+use std::path::Path;
+
+use crate::error::Result;
+
+pub mod cli;
+pub mod config;
+pub mod download;
+pub mod error;
+pub mod github;
+pub mod http;
+pub mod types;
+
+// The modules below serve the `ghdl` binary and are not a stable API
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod auth;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod filter;
+#[doc(hidden)]
+pub mod git;
+#[doc(hidden)]
+pub mod info;
+#[doc(hidden)]
+pub mod lfs;
+#[doc(hidden)]
+pub mod listing;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod overwrite;
+#[doc(hidden)]
+pub mod paths;
+#[doc(hidden)]
+pub mod progress;
+#[doc(hidden)]
+pub mod rate_limit;
+#[doc(hidden)]
+pub mod shard;
+#[doc(hidden)]
+pub mod telemetry;
+#[doc(hidden)]
+pub mod tree;
+#[doc(hidden)]
+pub mod update;
+#[doc(hidden)]
+pub mod utils;
+#[doc(hidden)]
+pub mod zip;
+
+pub use cli::{DownloadStrategy, GitTransport, SymlinkPolicy};
+pub use download::{Downloader, DownloaderBuilder, download_github_path, download_github_paths};
+pub use error::{ErrorKind, GdlError, error_kind};
+pub use github::parse_github_url;
+pub use progress::ProgressSink;
+pub use types::{DownloadContext, DownloadOptions, EmptyDownload, RequestInfo, RequestKind};
+
+/// Download `url` into `output` (by default a directory named after the requested
+/// path, as the command line does) with the automatic strategy and default settings
+pub async fn download(url: &str, output: Option<&Path>, token: Option<&str>) -> Result<()> {
+    let mut builder = Downloader::builder();
+    if let Some(token) = token {
+        builder = builder.token(token);
+    }
+    builder.build()?.download(url, output).await
+}
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::cli::ListFormat;
+use crate::github::types::{ContentType, GitHubContent, GitTreeEntry, GitTreeEntryType};
+use crate::github::{
+    fetch_git_tree, fetch_github_contents, fetch_repository_info, parse_github_url,
+};
+use crate::http::CachePolicy;
+use crate::progress::format_bytes;
+use crate::rate_limit::RateLimitTracker;
+use crate::types::RequestKind;
+
+/// Version of the `ls --format json` output, described by `docs/ls.schema.json`.
+/// Adding a field keeps the version; renaming or removing one bumps it.
+pub const LISTING_SCHEMA_VERSION: u32 = 1;
+
+/// What `ls --format json` prints
+#[derive(Debug, Serialize)]
+struct Listing<'a> {
+    schema: u32,
+    entries: &'a [RemoteEntry],
+}
+
+/// One file or directory of a remote listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RemoteEntry {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+}
+
+impl RemoteEntry {
+    fn from_content(content: GitHubContent) -> Self {
+        let kind = match content.content_type {
+            ContentType::File => "file",
+            ContentType::Dir => "dir",
+            ContentType::Symlink => "symlink",
+            ContentType::Submodule => "submodule",
+            ContentType::Other => "other",
+        };
+        Self {
+            path: content.path,
+            kind,
+            size: content.size.filter(|_| kind == "file" || kind == "symlink"),
+            sha: content.sha,
+        }
+    }
+
+    /// `prefix` is the directory the tree was listed from; tree paths are relative to it
+    fn from_tree_entry(entry: GitTreeEntry, prefix: &str) -> Self {
+        let kind = match entry.entry_type {
+            GitTreeEntryType::Blob if entry.mode.as_deref() == Some("120000") => "symlink",
+            GitTreeEntryType::Blob => "file",
+            GitTreeEntryType::Tree => "dir",
+            GitTreeEntryType::Commit => "submodule",
+        };
+        let path = if prefix.is_empty() {
+            entry.path
+        } else {
+            format!("{}/{}", prefix, entry.path)
+        };
+        Self {
+            path,
+            kind,
+            size: entry.size,
+            sha: entry.sha,
+        }
+    }
+}
+
+/// List what a GitHub URL points at without downloading it (`ghdl ls`)
+pub async fn run_ls(
+    client: &Client,
+    url: &str,
+    recursive: bool,
+    format: ListFormat,
+    token: Option<&str>,
+) -> Result<()> {
+    let mut request = parse_github_url(url)?;
+    if request.branch.is_empty() {
+        request.branch = fetch_repository_info(client, &request.owner, &request.repo, token)
+            .await
+            .context("failed to fetch repository information")?
+            .default_branch;
+    }
+
+    let rate_limit = Arc::new(RateLimitTracker::default());
+    let entries = if recursive && request.kind == RequestKind::Tree {
+        let tree =
+            fetch_git_tree(client, &request, token, rate_limit, CachePolicy::default()).await?;
+        tree.tree
+            .into_iter()
+            .map(|entry| RemoteEntry::from_tree_entry(entry, &request.path))
+            .collect()
+    } else {
+        fetch_github_contents(
+            client,
+            &request,
+            &request.path,
+            token,
+            rate_limit,
+            CachePolicy::default(),
+        )
+        .await?
+        .into_iter()
+        .map(RemoteEntry::from_content)
+        .collect::<Vec<_>>()
+    };
+
+    match format {
+        ListFormat::Table => print!("{}", render_table(&entries)),
+        ListFormat::Json => {
+            let listing = Listing {
+                schema: LISTING_SCHEMA_VERSION,
+                entries: &entries,
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&listing).context("failed to encode the listing")?
+            )
+        }
+    }
+    Ok(())
+}
+
+/// Aligned `TYPE SIZE SHA PATH` rows followed by a summary line
+fn render_table(entries: &[RemoteEntry]) -> String {
+    let sizes: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            entry
+                .size
+                .map(format_bytes)
+                .unwrap_or_else(|| "-".to_string())
+        })
+        .collect();
+    let size_width = sizes.iter().map(String::len).max().unwrap_or(0).max(4);
+
+    let mut out = format!(
+        "{:<9}  {:>size_width$}  {:<7}  PATH\n",
+        "TYPE", "SIZE", "SHA"
+    );
+    for (entry, size) in entries.iter().zip(&sizes) {
+        let sha = entry
+            .sha
+            .as_deref()
+            .map_or("-", |sha| &sha[..sha.len().min(7)]);
+        out.push_str(&format!(
+            "{:<9}  {:>size_width$}  {:<7}  {}\n",
+            entry.kind, size, sha, entry.path
+        ));
+    }
+
+    let files = entries.iter().filter(|entry| entry.kind != "dir").count();
+    let total: u64 = entries.iter().filter_map(|entry| entry.size).sum();
+    out.push_str(&format!(
+        "{} file(s), {} director{}, {}\n",
+        files,
+        entries.len() - files,
+        if entries.len() - files == 1 {
+            "y"
+        } else {
+            "ies"
+        },
+        format_bytes(total)
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_entries() {
+        let tree: Vec<GitTreeEntry> = serde_json::from_str(
+            r#"[
+                {"path": "src", "mode": "040000", "type": "tree", "sha": "aaaaaaaaaaaa"},
+                {"path": "src/lib.rs", "mode": "100644", "type": "blob", "sha": "bbbbbbbbbbbb", "size": 2048},
+                {"path": "link", "mode": "120000", "type": "blob", "sha": "cccccccccccc", "size": 6},
+                {"path": "vendor", "mode": "160000", "type": "commit", "sha": "dddddddddddd"}
+            ]"#,
+        )
+        .unwrap();
+        let entries: Vec<RemoteEntry> = tree
+            .into_iter()
+            .map(|entry| RemoteEntry::from_tree_entry(entry, "crates/core"))
+            .collect();
+        let kinds: Vec<_> = entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, ["dir", "file", "symlink", "submodule"]);
+        assert_eq!(entries[1].path, "crates/core/src/lib.rs");
+
+        let json = serde_json::to_value(&entries[0]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"path": "crates/core/src", "type": "dir", "sha": "aaaaaaaaaaaa"})
+        );
+
+        // Every emitted field is documented, and every required one is emitted
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../docs/ls.schema.json")).unwrap();
+        assert_eq!(
+            schema["properties"]["schema"]["const"],
+            LISTING_SCHEMA_VERSION
+        );
+        let listing = serde_json::to_value(Listing {
+            schema: LISTING_SCHEMA_VERSION,
+            entries: &entries,
+        })
+        .unwrap();
+        for key in listing.as_object().unwrap().keys() {
+            assert!(schema["properties"].get(key).is_some(), "{} missing", key);
+        }
+        for name in schema["required"].as_array().unwrap() {
+            assert!(listing.get(name.as_str().unwrap()).is_some());
+        }
+        let items = &schema["properties"]["entries"]["items"];
+        let properties = items["properties"].as_object().unwrap();
+        for entry in listing["entries"].as_array().unwrap() {
+            let object = entry.as_object().unwrap();
+            for key in object.keys() {
+                assert!(properties.contains_key(key), "{} missing", key);
+            }
+            for name in items["required"].as_array().unwrap() {
+                assert!(object.contains_key(name.as_str().unwrap()));
+            }
+            let kinds = properties["type"]["enum"].as_array().unwrap();
+            assert!(kinds.contains(&entry["type"]), "{}", entry["type"]);
+        }
+
+        let table = render_table(&entries);
+        let lines: Vec<_> = table.lines().collect();
+        assert!(lines[0].starts_with("TYPE"));
+        assert!(lines[2].starts_with("file"));
+        assert!(lines[2].contains("bbbbbbb "));
+        assert!(lines[2].ends_with("crates/core/src/lib.rs"));
+        assert_eq!(
+            lines[5],
+            format!("3 file(s), 1 directory, {}", format_bytes(2054))
+        );
+    }
+}
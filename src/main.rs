@@ -1,58 +1,351 @@
-use std::env;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{Context, Result, anyhow};
+use clap::{CommandFactory, Parser};
 use console::style;
-use reqwest::Client;
-
-mod cache;
-mod cli;
-mod download;
-mod git;
-mod github;
-mod http;
-mod overwrite;
-mod paths;
-mod progress;
-mod rate_limit;
-mod types;
-mod update;
-mod utils;
-mod zip;
-
-use cache::clear_all_caches;
-use cli::Cli;
-use download::download_github_path;
-use github::{display_rate_limit_info, fetch_rate_limit_info};
-use rate_limit::RateLimitTracker;
-use types::{DownloadContext, DownloadOptions};
-use update::{auto_check_for_updates, check_for_update, run_self_update};
-use utils::init_logging;
-
-fn main() -> Result<()> {
+
+use ghdl::audit::run_audit;
+use ghdl::auth::{run_auth_command, stored_token};
+use ghdl::cache::{
+    cache_available, clear_all_caches, enforce_repo_cache_limit, print_cache_summary,
+    rate_limits_cache_dir, run_cache_command,
+};
+use ghdl::cli::{Cli, Command, DownloadArgs, DownloadStrategy, ProgressOutput, RateFormat};
+use ghdl::config::Config;
+use ghdl::download::Downloader;
+use ghdl::download::checksums::ChecksumSink;
+use ghdl::download::explain::classify_failure;
+use ghdl::download::lock::{Lockfile, ensure_unmoved, resolve_sources};
+use ghdl::error::{GdlError, error_kind};
+use ghdl::github::{Endpoints, display_rate_limit_info, fetch_rate_limit_info};
+use ghdl::http::DEFAULT_CACHE_TTL_SECS;
+use ghdl::http::{ClientOptions, LowSpeedLimit, RetryPolicy, build_client};
+use ghdl::info::run_info;
+use ghdl::listing::run_ls;
+use ghdl::notify::send_notification;
+use ghdl::progress::{JsonProgress, ProgressBars, ProgressSink, status};
+use ghdl::rate_limit::{RateLimitTracker, load_snapshot, save_snapshot, snapshot_file};
+use ghdl::telemetry::{Telemetry, run_telemetry_stats};
+use ghdl::types::DownloadOptions;
+use ghdl::update::{auto_check_for_updates, check_for_update, run_self_update};
+use ghdl::utils::{init_logging, read_url_list, system_time_to_secs};
+
+/// Exit with a status matching the error's kind (see `ErrorKind::exit_code`) so
+/// scripts can tell a missing path from a network failure
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(error_kind(&err).exit_code())
+        }
+    }
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
     let verbose = cli.verbose;
-    let multi_progress = init_logging(verbose);
 
     let Cli {
+        command,
         urls,
+        download,
         self_update,
         check_update,
         api_rate,
-        output,
+        clear_cache,
         token,
+        gh_auth,
         verbose: _,
+        log_format,
+        log_file,
+        proxy,
+        cacert,
+        insecure,
+        connect_timeout,
+        timeout,
+    } = cli;
+
+    // `ghdl get` spells out the bare URL form; `update` and `rate` replace old flags
+    let (command, urls, download, install) = match command {
+        Some(Command::Get { urls, download }) => (None, urls, *download, None),
+        Some(Command::Install {
+            manifest,
+            update,
+            download,
+        }) => (None, Vec::new(), *download, Some((manifest, update))),
+        command => (command, urls, download, None),
+    };
+    let self_update = self_update || matches!(command, Some(Command::Update { check: false }));
+    let check_update = check_update || matches!(command, Some(Command::Update { check: true }));
+    let rate_format = match &command {
+        Some(Command::Rate { format }) => Some(*format),
+        _ if api_rate => Some(RateFormat::Table),
+        _ => None,
+    };
+
+    let download_features = download.features();
+    let DownloadArgs {
+        output,
+        input_file,
         parallel,
+        listing_parallel,
+        jobs,
+        keep_going,
         strategy,
         no_cache,
-        clear_cache,
+        cache_ttl,
+        cache_max_size,
         force,
-    } = cli;
+        on_conflict,
+        sync,
+        update_only,
+        delete,
+        provenance,
+        notify,
+        watch,
+        write_lock,
+        locked,
+        preserve_mtime,
+        fail_on_empty,
+        symlinks,
+        git_transport,
+        git_depth,
+        git_filter,
+        keep_git,
+        lfs,
+        shard,
+        verify_tag,
+        local_repo,
+        stall_timeout,
+        explain,
+        dry_run,
+        offline,
+        tree,
+        chunks,
+        chunk_threshold,
+        no_telemetry,
+        limit_rate,
+        max_api_calls,
+        ephemeral,
+        retries,
+        retry_delay,
+        retry_jitter,
+        speed_limit,
+        speed_time,
+        stream_archive,
+        quiet,
+        no_progress,
+        progress,
+        progress_file,
+        checksums,
+        verify: _,
+        no_verify,
+        no_sanitize_names,
+    } = download;
+
+    if install.is_some()
+        && (output.is_some()
+            || watch.is_some()
+            || write_lock.is_some()
+            || locked.is_some()
+            || local_repo.is_some()
+            || input_file.is_some())
+    {
+        return Err(anyhow!(
+            "ghdl install takes destinations from the manifest and commits from gdl.lock; --output, --watch, --write-lock, --locked, --local-repo, and --input-file do not apply"
+        ));
+    }
+
+    if keep_git && !matches!(strategy, DownloadStrategy::Auto | DownloadStrategy::Git) {
+        return Err(anyhow!(
+            "--keep-git clones the repository with git; it cannot be combined with --strategy {}",
+            format!("{:?}", strategy).to_lowercase()
+        ));
+    }
+    let strategy = if keep_git {
+        DownloadStrategy::Git
+    } else {
+        strategy
+    };
+
+    let mut urls = urls;
+    if let Some(path) = &input_file {
+        let listed = read_url_list(path)?;
+        if listed.is_empty() && urls.is_empty() {
+            return Err(anyhow!("no URLs listed in {}", path.display()));
+        }
+        urls.extend(listed);
+    }
+
+    let json_progress = progress == ProgressOutput::Json || progress_file.is_some();
+    // Both would write JSON lines to stdout, interleaved
+    if explain && json_progress && progress_file.is_none() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--explain and --progress json both print JSON lines on stdout; \
+                 send the progress events elsewhere with --progress-file",
+            )
+            .exit();
+    }
+    let multi_progress = init_logging(
+        verbose,
+        log_format,
+        !(quiet || no_progress || json_progress),
+        log_file.as_deref(),
+    )?;
+    let mut progress_sink: Option<Arc<dyn ProgressSink>> = if json_progress {
+        let writer: Box<dyn std::io::Write + Send> = match &progress_file {
+            Some(path) => Box::new(
+                std::fs::File::create(path)
+                    .with_context(|| format!("failed to open progress file {}", path.display()))?,
+            ),
+            None => Box::new(std::io::stdout()),
+        };
+        Some(Arc::new(JsonProgress::new(writer)))
+    } else if quiet || no_progress {
+        None
+    } else {
+        Some(Arc::new(ProgressBars::new(multi_progress.clone())))
+    };
+    let checksum_sink = checksums
+        .as_ref()
+        .map(|_| Arc::new(ChecksumSink::new(progress_sink.clone())));
+    if let Some(sink) = &checksum_sink {
+        progress_sink = Some(sink.clone());
+    }
+
+    let client_options = ClientOptions {
+        connect_timeout: (connect_timeout > 0).then(|| Duration::from_secs(connect_timeout)),
+        timeout: (timeout > 0).then(|| Duration::from_secs(timeout)),
+        proxy: proxy.clone(),
+        ca_bundle: cacert,
+        insecure,
+    };
+    if let Some(proxy) = &proxy {
+        // git reads the proxy from the environment (curl honors socks5:// URLs too)
+        // SAFETY: no other threads exist yet; the runtime is created below.
+        unsafe {
+            std::env::set_var("http_proxy", proxy);
+            std::env::set_var("https_proxy", proxy);
+        }
+    }
+    if insecure {
+        // SAFETY: no other threads exist yet; the runtime is created below.
+        unsafe {
+            std::env::set_var("GIT_SSL_NO_VERIFY", "1");
+        }
+    }
 
-    let token = token
-        .or_else(|| env::var("GITHUB_TOKEN").ok())
-        .or_else(|| env::var("GH_TOKEN").ok());
+    let config = Config::load()?;
+    let provider = config.github();
+    // GHDL_API_BASE and GHDL_ARCHIVE_BASE redirect traffic to a mirror or a mock server
+    let base_override = |name| std::env::var(name).ok().filter(|url| !url.is_empty());
+    let mut endpoints = Endpoints::default();
+    if let Some(api_url) = base_override("GHDL_API_BASE").or_else(|| provider.api_url.clone()) {
+        endpoints = endpoints.with_api(&api_url);
+    }
+    if let Some(archive_url) = base_override("GHDL_ARCHIVE_BASE") {
+        endpoints = endpoints.with_archive(&archive_url);
+    }
+
+    if let Some(Command::Cache { action }) = command {
+        return run_cache_command(action);
+    }
+
+    if let Some(Command::Stats {
+        telemetry,
+        upload,
+        reset,
+    }) = command
+    {
+        if !telemetry {
+            return print_cache_summary();
+        }
+        let client = build_client(&provider, &client_options)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build async runtime")?;
+        return runtime.block_on(run_telemetry_stats(&client, &config, upload, reset));
+    }
+
+    // Resolved once: token_cmd may prompt or be slow, so it never runs twice
+    let resolve_token = || -> Result<Option<String>> {
+        // A token mapped to the host is more specific than GITHUB_TOKEN and friends
+        Ok(
+            match token
+                .or_else(|| config.token_for_host(&provider.host()))
+                .or_else(|| provider.token_from_env())
+            {
+                Some(token) => Some(token),
+                None if offline || clear_cache => None,
+                None => match provider
+                    .token_from_cmd()?
+                    .or_else(|| stored_token(&provider.host()))
+                    .or_else(|| provider.token_from_netrc())
+                {
+                    Some(token) => Some(token),
+                    None if gh_auth || provider.gh_auth == Some(true) => provider.token_from_gh(),
+                    None => None,
+                },
+            },
+        )
+    };
+
+    if let Some(Command::Auth { action }) = command {
+        let client = build_client(&provider, &client_options)?;
+        return run_auth_command(action, &provider, &client, &endpoints, resolve_token);
+    }
+
+    let token = resolve_token()?;
+
+    if let Some(Command::Audit { dir, source }) = command {
+        let client = build_client(&provider, &client_options)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build async runtime")?;
+        runtime.block_on(endpoints.scope(run_audit(
+            &client,
+            &dir,
+            source.as_deref(),
+            token.as_deref(),
+        )))?;
+        return Ok(());
+    }
+
+    if let Some(Command::Info { url }) = command {
+        let client = build_client(&provider, &client_options)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build async runtime")?;
+        return runtime.block_on(endpoints.scope(run_info(&client, &url, token.as_deref())));
+    }
+
+    if let Some(Command::Ls {
+        url,
+        recursive,
+        format,
+    }) = command
+    {
+        let client = build_client(&provider, &client_options)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to build async runtime")?;
+        return runtime.block_on(endpoints.scope(run_ls(
+            &client,
+            &url,
+            recursive,
+            format,
+            token.as_deref(),
+        )));
+    }
 
     if clear_cache {
         clear_all_caches()?;
@@ -69,26 +362,104 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    if api_rate {
-        let client = Client::builder()
-            .user_agent("ghdl-rs (https://github.com/CaddyGlow/ghdl)")
-            .build()
-            .context("failed to construct HTTP client")?;
+    if let Some(format) = rate_format {
+        let client = build_client(&provider, &client_options)?;
         let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
             .context("failed to build async runtime")?;
-        runtime.block_on(display_rate_limit_info(&client, token.as_deref()))?;
+        runtime.block_on(endpoints.scope(display_rate_limit_info(
+            &client,
+            token.as_deref(),
+            format,
+        )))?;
         return Ok(());
     }
 
-    let client = Client::builder()
-        .user_agent("ghdl-rs (https://github.com/CaddyGlow/ghdl)")
-        .build()
-        .context("failed to construct HTTP client")?;
-    let rate_limit = Arc::new(RateLimitTracker::default());
+    let client = build_client(&provider, &client_options)?;
+
+    let cache_ttl = cache_ttl
+        .or(config.cache_ttl)
+        .unwrap_or(DEFAULT_CACHE_TTL_SECS);
+
+    // Every cache lives in a temporary directory, removed when main returns
+    let _ephemeral_cache = if ephemeral {
+        let dir = tempfile::Builder::new()
+            .prefix("ghdl-ephemeral-")
+            .tempdir()
+            .context("failed to create a temporary cache directory")?;
+        log::debug!("Using ephemeral cache directory {}", dir.path().display());
+        // SAFETY: no other threads exist yet; the runtime is created below.
+        unsafe {
+            std::env::set_var("XDG_CACHE_HOME", dir.path());
+        }
+        Some(dir)
+    } else {
+        None
+    };
+
+    // Without any writable cache directory, run as if --no-cache were given
+    let no_cache = no_cache || !cache_available();
 
+    // Start from the quota an earlier run left, so a nearly used-up limit is paced
+    // from the first request instead of after the first response
+    let rate_limit_file = rate_limits_cache_dir()
+        .ok()
+        .map(|dir| snapshot_file(&dir, token.as_deref()));
+    let saved_rate_limit = rate_limit_file.as_deref().and_then(load_snapshot);
+    if let Some(snapshot) = &saved_rate_limit
+        && snapshot.is_low(provider.rate_limit_warn)
+        && !offline
+    {
+        log::warn!(
+            "GitHub API rate limit is nearly used up ({} of {} remaining), resets {}",
+            snapshot.remaining.unwrap_or_default(),
+            snapshot.limit.unwrap_or_default(),
+            snapshot.reset_eta_display()
+        );
+    }
+    let rate_limit = Arc::new(
+        RateLimitTracker::with_warn_threshold(provider.rate_limit_warn)
+            .with_snapshot(saved_rate_limit)
+            .with_max_api_calls(max_api_calls)
+            .with_retry_policy(RetryPolicy {
+                retries,
+                base_delay: Duration::from_millis(retry_delay),
+                jitter: retry_jitter,
+            }),
+    );
+
+    let started = system_time_to_secs(SystemTime::now());
     let parallel = parallel.max(1);
+    let listing_parallel = listing_parallel.unwrap_or(parallel).max(1);
+
+    if offline {
+        // Keep git from lazily fetching blobs missing from cached partial clones.
+        // SAFETY: no other threads exist yet; the runtime is created below.
+        unsafe {
+            std::env::set_var("GIT_NO_LAZY_FETCH", "1");
+        }
+    }
+
+    let telemetry = Telemetry::new(&config, no_telemetry || ephemeral);
+    telemetry.feature(&format!("strategy:{:?}", strategy).to_lowercase());
+    for feature in download_features {
+        telemetry.feature(feature);
+    }
+    for (feature, used) in [
+        ("multiple_urls", urls.len() > 1),
+        ("install", install.is_some()),
+        ("timeout", timeout > 0),
+        ("proxy", proxy.is_some()),
+        ("cacert", client_options.ca_bundle.is_some()),
+        ("insecure", insecure),
+        ("gh_auth", gh_auth || provider.gh_auth == Some(true)),
+    ] {
+        if used {
+            telemetry.feature(feature);
+        }
+    }
+    let telemetry_ref = &telemetry;
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -96,42 +467,174 @@ fn main() -> Result<()> {
         .context("failed to build async runtime")?;
 
     // Spawn update check in background - don't block startup
-    let token_for_update = token.clone();
-    runtime.spawn(async move {
-        if let Err(e) = auto_check_for_updates(token_for_update.as_deref()).await {
-            log::debug!("Update check failed: {}", e);
-        }
-    });
-
-    let rate_limit_for_runtime = Arc::clone(&rate_limit);
+    if !offline && !ephemeral {
+        let token_for_update = token.clone();
+        runtime.spawn(async move {
+            if let Err(e) = auto_check_for_updates(token_for_update.as_deref()).await {
+                log::debug!("Update check failed: {}", e);
+            }
+        });
+    }
 
-    runtime.block_on(async move {
-        let output_ref = output.as_ref();
+    // Kept past the run to report the API requests sent and save the quota left
+    let rate_limit_seen = rate_limit.clone();
+    let download = async move {
         let token_ref = token.as_deref();
-        let rate_limit = rate_limit_for_runtime;
-        let options = DownloadOptions::new(token_ref, no_cache, force);
-        let ctx = DownloadContext::new(
-            client.clone(),
-            Arc::clone(&rate_limit),
-            multi_progress.clone(),
-            parallel,
-        );
-        for url in urls {
-            download_github_path(&ctx, &url, output_ref, strategy, &options).await?;
+        let options = DownloadOptions {
+            token: token_ref,
+            no_cache,
+            cache_ttl: Duration::from_secs(cache_ttl),
+            force,
+            on_conflict,
+            preserve_mtime,
+            fail_on_empty,
+            symlinks,
+            git_transport,
+            git_depth,
+            git_filter: Some(git_filter).filter(|filter| !filter.is_empty() && filter != "none"),
+            keep_git,
+            lfs,
+            shard,
+            verify_tag,
+            local_repo: local_repo.as_deref(),
+            stream_archive,
+            verify: !no_verify,
+            sync: sync || watch.is_some() || install.is_some(),
+            update_only,
+            delete,
+            provenance,
+            keep_going,
+            filter: None,
+            stall_timeout: (stall_timeout > 0).then(|| Duration::from_secs(stall_timeout)),
+            low_speed_limit: speed_limit.map(|bytes_per_sec| LowSpeedLimit {
+                bytes_per_sec,
+                time: Duration::from_secs(speed_time.max(1)),
+            }),
+            explain,
+            quiet,
+            sanitize_names: cfg!(windows) && !no_sanitize_names,
+            dry_run,
+            offline,
+            tree,
+            chunks,
+            chunk_threshold,
+            telemetry: Some(telemetry_ref),
+            progress: progress_sink,
+        };
+        let downloader = Downloader::builder()
+            .client(client)
+            .rate_limit(rate_limit)
+            .multi_progress(multi_progress.clone())
+            .strategy(strategy)
+            .parallel(parallel)
+            .listing_parallel(listing_parallel)
+            .jobs(jobs)
+            .limit_rate(limit_rate)
+            .endpoints(endpoints)
+            .options(options)
+            .build()?;
+        if let Some((manifest, update)) = &install {
+            ghdl::download::manifest::install(&downloader, manifest, *update).await?;
+        } else {
+            let lockfile = locked
+                .as_deref()
+                .map(|path| Lockfile::load(path).map(|lockfile| (path, lockfile)))
+                .transpose()?;
+            let sources = if lockfile.is_some() || write_lock.is_some() {
+                resolve_sources(&downloader, &urls).await?
+            } else {
+                Vec::new()
+            };
+            if let Some((path, lockfile)) = &lockfile {
+                lockfile.check(path, &sources)?;
+            }
+
+            match watch {
+                Some(interval) => {
+                    ghdl::download::watch::watch(&downloader, &urls, output.as_deref(), interval)
+                        .await?
+                }
+                None => downloader.download_all(&urls, output.as_deref()).await?,
+            }
+            if !sources.is_empty() && !dry_run {
+                ensure_unmoved(&downloader, &sources).await?;
+            }
+            if let Some(path) = &write_lock {
+                Lockfile::update(path, sources)?;
+            }
+        }
+        if let (Some(sink), Some(path)) = (&checksum_sink, &checksums) {
+            sink.write_manifest(path)?;
+        }
+
+        if let Some(max_bytes) = cache_max_size
+            && !dry_run
+        {
+            match enforce_repo_cache_limit(max_bytes, started) {
+                Ok(evicted) if !evicted.is_empty() => {
+                    log::info!("Evicted {} entries from the repos cache", evicted.len())
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!("Unable to enforce --cache-max-size: {}", err),
+            }
         }
 
         // Fetch and display rate limit info in verbose mode
         // Note: This endpoint does not count against your primary rate limit
-        if verbose >= 1 {
-            let _ = fetch_rate_limit_info(&ctx.client, token_ref).await;
+        if verbose >= 1 && !offline {
+            let _ = downloader
+                .endpoints()
+                .scope(fetch_rate_limit_info(downloader.client(), token_ref))
+                .await;
+        }
+
+        Ok::<(), GdlError>(())
+    };
+    let result = runtime.block_on(async move {
+        // Turn Ctrl-C into an error so it exits with the documented status, and so
+        // the temporary cache of `--ephemeral` is still removed
+        tokio::select! {
+            result = download => result,
+            _ = tokio::signal::ctrl_c() => Err(GdlError::Cancelled("interrupted".into())),
         }
+    });
 
-        Ok::<(), anyhow::Error>(())
-    })?;
+    log::info!("{} GitHub API request(s) sent", rate_limit_seen.api_calls());
+    if let Some(path) = &rate_limit_file
+        && let Some(snapshot) = runtime
+            .block_on(rate_limit_seen.state.lock())
+            .last_snapshot
+            .clone()
+        && let Err(err) = save_snapshot(path, &snapshot)
+    {
+        log::debug!("Unable to save rate-limit state: {:#}", err);
+    }
+    if let Err(err) = &result {
+        telemetry.error(&format!("run.{}", classify_failure(err)));
+    }
+    if let Err(err) = telemetry.flush() {
+        log::debug!("Unable to save telemetry: {:#}", err);
+    }
+    if notify && !dry_run {
+        match &result {
+            Ok(()) => send_notification(
+                "ghdl: download finished",
+                "All downloads completed successfully.",
+            ),
+            Err(err) => send_notification("ghdl: download failed", &format!("{:#}", err)),
+        }
+    }
+    result?;
+    if dry_run {
+        return Ok(());
+    }
 
-    eprintln!(
-        "\n{} All downloads completed successfully.",
-        style("✓").green().bold()
+    status(
+        quiet,
+        format!(
+            "\n{} All downloads completed successfully.",
+            style("✓").green().bold()
+        ),
     );
     Ok(())
 }
@@ -0,0 +1,38 @@
+use log::debug;
+use notify_rust::Notification;
+
+/// Longest notification body; longer error chains are cut here
+const MAX_BODY_CHARS: usize = 240;
+
+/// Show a desktop notification. The run is over by then, so failures are only logged.
+pub fn send_notification(title: &str, body: &str) {
+    let body = truncate(body, MAX_BODY_CHARS);
+    let result = Notification::new()
+        .appname("ghdl")
+        .summary(title)
+        .body(&body)
+        .show();
+    match result {
+        Ok(_) => debug!("Sent desktop notification: {}", title),
+        Err(err) => debug!("Unable to send desktop notification: {}", err),
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("abcdef", 3), "abc…");
+        assert_eq!(truncate("abc", 3), "abc");
+        assert_eq!(truncate("ééé", 2), "éé…");
+    }
+}
@@ -1,36 +1,38 @@
+use std::collections::HashSet;
+use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use anyhow::{Result, anyhow};
+use anyhow::anyhow;
 use atty::Stream;
-use log::{debug, warn};
+use console::style;
+use log::{debug, info, warn};
 
-/// Check if we should proceed with downloading files that might overwrite existing ones
-pub fn check_overwrite_permission(
-    files_to_download: &[(impl AsRef<Path>, u64)],
+use crate::cli::OnConflict;
+use crate::error::{Context, GdlError, Result};
+
+/// Check if we should proceed with downloading files that might overwrite existing
+/// ones, returning the tasks to go ahead with. `incoming` reads a task's new content
+/// for the prompt's diff, or gives `None` when it is unavailable.
+pub fn check_overwrite_permission<T: TargetPath>(
+    tasks: Vec<T>,
     force: bool,
-) -> Result<()> {
+    prompt: &OverwritePrompt,
+    incoming: impl FnMut(&T) -> Option<Vec<u8>>,
+) -> Result<Vec<T>> {
     // Find files that already exist
-    let existing_files: Vec<_> = files_to_download
-        .iter()
-        .filter_map(|(path, _size)| {
-            let path = path.as_ref();
-            if path.exists() { Some(path) } else { None }
-        })
-        .collect();
-
-    if existing_files.is_empty() {
+    let existing = tasks.iter().filter(|task| task.path().exists()).count();
+
+    if existing == 0 {
         debug!("No existing files will be overwritten");
-        return Ok(());
+        return Ok(tasks);
     }
 
     // If force flag is set, proceed without prompting
     if force {
-        debug!(
-            "Force flag set, overwriting {} existing file(s)",
-            existing_files.len()
-        );
-        return Ok(());
+        debug!("Force flag set, overwriting {} existing file(s)", existing);
+        return Ok(tasks);
     }
 
     // Check if we're in a TTY (interactive terminal)
@@ -41,45 +43,298 @@ pub fn check_overwrite_permission(
         return Err(anyhow!(
             "Refusing to overwrite {} existing file(s) in non-interactive mode. \
              Use --force to override.",
-            existing_files.len()
-        ));
+            existing
+        )
+        .into());
     }
 
     // In a TTY, prompt the user
-    prompt_user_for_overwrite(&existing_files)
+    prompt_user_for_overwrite(tasks, existing, prompt, incoming)
 }
 
-fn prompt_user_for_overwrite(existing_files: &[&Path]) -> Result<()> {
-    // Log warning for tracking
-    warn!(
-        "{} existing file(s) will be overwritten if user confirms",
-        existing_files.len()
-    );
+/// Apply `on_conflict` to the tasks whose target already exists.
+///
+/// Without a mode, existing files are overwritten once the user agrees (or right away
+/// with `force`). `Skip` drops those tasks, `Backup` moves the existing files to
+/// the first free `<name>.bak`, `<name>.bak.1`, ..., and `Rename` points the tasks at the first free `<stem>.N.<ext>`.
+pub fn resolve_conflicts<T: TargetPath>(
+    mut tasks: Vec<T>,
+    on_conflict: Option<OnConflict>,
+    force: bool,
+    prompt: &OverwritePrompt,
+    incoming: impl FnMut(&T) -> Option<Vec<u8>>,
+) -> Result<Vec<T>> {
+    let Some(mode) = on_conflict else {
+        return check_overwrite_permission(tasks, force, prompt, incoming);
+    };
 
-    // Display prompt to user (not through logger)
-    eprintln!(
-        "\n⚠  The following {} file(s) already exist:",
-        existing_files.len()
-    );
-    for path in existing_files.iter().take(10) {
-        eprintln!("  - {}", path.display());
+    match mode {
+        OnConflict::Overwrite => {}
+        OnConflict::Skip => {
+            let total = tasks.len();
+            tasks.retain(|task| !task.path().exists());
+            if tasks.len() < total {
+                info!("Kept {} existing file(s)", total - tasks.len());
+            }
+        }
+        OnConflict::Backup => {
+            let taken: HashSet<PathBuf> =
+                tasks.iter().map(|task| task.path().to_path_buf()).collect();
+            for task in tasks.iter().filter(|task| task.path().exists()) {
+                // An earlier run's backup is kept, not replaced
+                let backup = std::iter::once(with_name_suffix(task.path(), ".bak"))
+                    .chain((1..).map(|n| with_name_suffix(task.path(), &format!(".bak.{}", n))))
+                    .find(|path| fs::symlink_metadata(path).is_err() && !taken.contains(path))
+                    .expect("unbounded search finds a free name");
+                fs::rename(task.path(), &backup).with_context(|| {
+                    format!(
+                        "failed to move {} to {}",
+                        task.path().display(),
+                        backup.display()
+                    )
+                })?;
+                info!("Moved {} to {}", task.path().display(), backup.display());
+            }
+        }
+        OnConflict::Rename => {
+            let mut taken: HashSet<PathBuf> =
+                tasks.iter().map(|task| task.path().to_path_buf()).collect();
+            for task in tasks.iter_mut() {
+                if !task.path().exists() {
+                    continue;
+                }
+                let renamed = (1..)
+                    .map(|n| numbered(task.path(), n))
+                    .find(|path| !path.exists() && !taken.contains(path))
+                    .expect("unbounded search finds a free name");
+                info!(
+                    "{} exists; writing {}",
+                    task.path().display(),
+                    renamed.display()
+                );
+                taken.insert(renamed.clone());
+                task.set_path(renamed);
+            }
+        }
     }
-    if existing_files.len() > 10 {
-        eprintln!("  ... and {} more", existing_files.len() - 10);
+    Ok(tasks)
+}
+
+fn with_name_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// `dir/name.ext` as `dir/name.N.ext`
+pub fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// An answer to the overwrite prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+    None,
+    Quit,
+    Diff,
+}
+
+fn parse_answer(input: &str) -> Option<Answer> {
+    // `n` and `N` differ, so only the long forms ignore case
+    match input.trim() {
+        "y" | "Y" => Some(Answer::Yes),
+        "" | "n" => Some(Answer::No),
+        "a" | "A" => Some(Answer::All),
+        "N" => Some(Answer::None),
+        "q" | "Q" => Some(Answer::Quit),
+        "d" | "D" => Some(Answer::Diff),
+        word => match word.to_lowercase().as_str() {
+            "yes" => Some(Answer::Yes),
+            "no" => Some(Answer::No),
+            "all" => Some(Answer::All),
+            "none" => Some(Answer::None),
+            "quit" => Some(Answer::Quit),
+            "diff" => Some(Answer::Diff),
+            _ => None,
+        },
     }
+}
 
-    eprint!("\nOverwrite these file(s)? [y/N]: ");
-    io::stdout().flush()?;
+/// The overwrite prompt of one run. Held while a prompt waits for an answer, so
+/// concurrent `--jobs` ask one at a time; holds the `all` or `none` answer that
+/// stands for the rest of the run once given.
+#[derive(Debug, Default)]
+pub struct OverwritePrompt {
+    standing: Mutex<Option<bool>>,
+}
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+fn prompt_user_for_overwrite<T: TargetPath>(
+    tasks: Vec<T>,
+    existing: usize,
+    prompt: &OverwritePrompt,
+    mut incoming: impl FnMut(&T) -> Option<Vec<u8>>,
+) -> Result<Vec<T>> {
+    let mut standing = prompt
+        .standing
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
 
-    let answer = input.trim().to_lowercase();
-    if answer == "y" || answer == "yes" {
-        Ok(())
-    } else {
-        Err(anyhow!("Download cancelled by user"))
+    if standing.is_none() {
+        // Log warning for tracking
+        warn!(
+            "{} existing file(s) will be overwritten if user confirms",
+            existing
+        );
+        // Display prompt to user (not through logger)
+        eprintln!("\n⚠  {} file(s) already exist.", existing);
+    }
+
+    let mut selected = Vec::with_capacity(tasks.len());
+    let mut kept = 0;
+    for task in tasks {
+        if !task.path().exists() {
+            selected.push(task);
+            continue;
+        }
+        let overwrite = match *standing {
+            Some(all) => all,
+            None => loop {
+                eprint!(
+                    "Overwrite {}? [y]es/[n]o/[a]ll/[N]one/[q]uit/[d]iff: ",
+                    task.path().display()
+                );
+                io::stderr().flush()?;
+
+                let mut input = String::new();
+                if io::stdin().read_line(&mut input)? == 0 {
+                    return Err(cancelled());
+                }
+                match parse_answer(&input) {
+                    Some(Answer::Yes) => break true,
+                    Some(Answer::No) => break false,
+                    Some(Answer::All) => {
+                        *standing = Some(true);
+                        break true;
+                    }
+                    Some(Answer::None) => {
+                        *standing = Some(false);
+                        break false;
+                    }
+                    Some(Answer::Quit) => return Err(cancelled()),
+                    Some(Answer::Diff) => show_diff(task.path(), incoming(&task)),
+                    None => eprintln!("Please answer y, n, a, N, q, or d."),
+                }
+            },
+        };
+        if overwrite {
+            selected.push(task);
+        } else {
+            debug!("Keeping existing {}", task.path().display());
+            kept += 1;
+        }
     }
+    if kept > 0 {
+        info!("Kept {} existing file(s)", kept);
+    }
+    Ok(selected)
+}
+
+fn cancelled() -> GdlError {
+    GdlError::Cancelled("Download cancelled by user".into())
+}
+
+/// Diff lines shown before the rest is cut off
+const MAX_DIFF_LINES: usize = 200;
+
+/// Print a unified diff from the file at `path` to the `incoming` content
+fn show_diff(path: &Path, incoming: Option<Vec<u8>>) {
+    let Some(incoming) = incoming else {
+        eprintln!(
+            "The new content of {} is not available for a diff.",
+            path.display()
+        );
+        return;
+    };
+    let existing = match fs::read(path) {
+        Ok(existing) => existing,
+        Err(err) => {
+            eprintln!("Unable to read {}: {}", path.display(), err);
+            return;
+        }
+    };
+    if existing == incoming {
+        eprintln!("{} already has the new content.", path.display());
+        return;
+    }
+    let (Some(existing), Some(incoming)) = (as_text(&existing), as_text(&incoming)) else {
+        eprintln!("{} is a binary file; no diff to show.", path.display());
+        return;
+    };
+
+    let lines = diff_lines(&path.display().to_string(), existing, incoming);
+    if lines.is_empty() {
+        eprintln!("{} differs only in line endings.", path.display());
+        return;
+    }
+    for line in lines.iter().take(MAX_DIFF_LINES) {
+        let styled = if line.starts_with("---") || line.starts_with("+++") {
+            style(line).bold()
+        } else if line.starts_with("@@") {
+            style(line).cyan()
+        } else if line.starts_with('+') {
+            style(line).green()
+        } else if line.starts_with('-') {
+            style(line).red()
+        } else {
+            style(line)
+        };
+        eprintln!("{}", styled);
+    }
+    if lines.len() > MAX_DIFF_LINES {
+        eprintln!("... {} more line(s)", lines.len() - MAX_DIFF_LINES);
+    }
+}
+
+fn as_text(content: &[u8]) -> Option<&str> {
+    if content.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
+
+/// Unified diff lines, without line terminators, from `existing` to `incoming`
+fn diff_lines(label: &str, existing: &str, incoming: &str) -> Vec<String> {
+    let existing: Vec<&str> = existing.lines().collect();
+    let incoming: Vec<&str> = incoming.lines().collect();
+    difflib::unified_diff(
+        &existing,
+        &incoming,
+        &format!("{} (local)", label),
+        &format!("{} (remote)", label),
+        "",
+        "",
+        3,
+    )
+    .into_iter()
+    .enumerate()
+    .map(|(index, line)| {
+        let line = line.trim_end_matches('\n');
+        // The file headers end in a tab before their (empty) dates
+        if index < 2 {
+            line.trim_end_matches('\t').to_string()
+        } else {
+            line.to_string()
+        }
+    })
+    .collect()
 }
 
 /// Check a single file for overwrite permission
@@ -100,7 +355,8 @@ pub fn check_single_file_overwrite(path: &Path, force: bool) -> Result<()> {
         return Err(anyhow!(
             "File {} already exists. Use --force to override.",
             path.display()
-        ));
+        )
+        .into());
     }
 
     eprint!("File {} already exists. Overwrite? [y/N]: ", path.display());
@@ -113,19 +369,103 @@ pub fn check_single_file_overwrite(path: &Path, force: bool) -> Result<()> {
     if answer == "y" || answer == "yes" {
         Ok(())
     } else {
-        Err(anyhow!("Download cancelled by user"))
+        Err(cancelled())
     }
 }
 
-/// Collect paths that will be created by the download
-pub fn collect_target_paths<T: TargetPath>(tasks: &[T]) -> Vec<(&Path, u64)> {
-    tasks
-        .iter()
-        .map(|task| (task.path(), task.size()))
-        .collect()
-}
-
 pub trait TargetPath {
     fn path(&self) -> &Path;
     fn size(&self) -> u64;
+    fn set_path(&mut self, path: PathBuf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FileCopyTask;
+
+    fn task(target: PathBuf) -> FileCopyTask {
+        FileCopyTask {
+            item_path: String::new(),
+            source_path: PathBuf::new(),
+            target_path: target,
+            size: None,
+            link_target: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(parse_answer("y\n"), Some(Answer::Yes));
+        assert_eq!(parse_answer("\n"), Some(Answer::No));
+        assert_eq!(parse_answer("n"), Some(Answer::No));
+        assert_eq!(parse_answer("N"), Some(Answer::None));
+        assert_eq!(parse_answer("None"), Some(Answer::None));
+        assert_eq!(parse_answer("ALL"), Some(Answer::All));
+        assert_eq!(parse_answer("q"), Some(Answer::Quit));
+        assert_eq!(parse_answer("d"), Some(Answer::Diff));
+        assert_eq!(parse_answer("maybe"), None);
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = diff_lines("a.txt", "one\ntwo\nthree\n", "one\n2\nthree\n");
+        assert_eq!(
+            lines,
+            vec![
+                "--- a.txt (local)",
+                "+++ a.txt (remote)",
+                "@@ -1,3 +1,3 @@",
+                " one",
+                "-two",
+                "+2",
+                " three",
+            ]
+        );
+        assert!(diff_lines("a.txt", "same\n", "same").is_empty());
+        assert_eq!(as_text(b"\x00\x01"), None);
+    }
+
+    #[test]
+    fn test_resolve_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("notes.txt");
+        let fresh = dir.path().join("new.txt");
+        fs::write(&existing, "old").unwrap();
+        fs::write(dir.path().join("notes.1.txt"), "older").unwrap();
+        let tasks = || vec![task(existing.clone()), task(fresh.clone())];
+        let prompt = OverwritePrompt::default();
+
+        let kept =
+            resolve_conflicts(tasks(), Some(OnConflict::Skip), false, &prompt, |_| None).unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].target_path, fresh);
+
+        let renamed =
+            resolve_conflicts(tasks(), Some(OnConflict::Rename), false, &prompt, |_| None).unwrap();
+        assert_eq!(renamed[0].target_path, dir.path().join("notes.2.txt"));
+        assert_eq!(renamed[1].target_path, fresh);
+        assert_eq!(numbered(Path::new(".env"), 1), Path::new(".env.1"));
+
+        let backed_up =
+            resolve_conflicts(tasks(), Some(OnConflict::Backup), false, &prompt, |_| None).unwrap();
+        assert_eq!(backed_up[0].target_path, existing);
+        assert!(!existing.exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes.txt.bak")).unwrap(),
+            "old"
+        );
+
+        // A second run keeps the first backup and numbers its own
+        fs::write(&existing, "newer").unwrap();
+        resolve_conflicts(tasks(), Some(OnConflict::Backup), false, &prompt, |_| None).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes.txt.bak")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes.txt.bak.1")).unwrap(),
+            "newer"
+        );
+    }
 }
@@ -1,14 +1,23 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::anyhow;
+use log::warn;
 
+use crate::error::{Context, Result};
 use crate::github::types::{ContentType, GitHubContent};
+use crate::overwrite::{TargetPath, numbered};
 use crate::types::RequestInfo;
 
 pub fn format_path_for_log(path: &Path) -> String {
     if path.is_absolute() {
-        return path.display().to_string();
+        let display = path.display().to_string();
+        return match display.strip_prefix(r"\\?\") {
+            Some(unc) if unc.starts_with(r"UNC\") => format!(r"\\{}", &unc[4..]),
+            Some(local) => local.to_string(),
+            None => display,
+        };
     }
 
     match path.components().next() {
@@ -68,13 +77,39 @@ fn normalize_base(base: PathBuf) -> PathBuf {
     }
 }
 
+/// The directory a strategy writes below. On Windows this is the extended-length
+/// (`\\?\`) form of `dir`, which lifts the 260-character `MAX_PATH` limit for every
+/// file and directory created under it; elsewhere `dir` is returned unchanged.
+pub fn output_root(dir: PathBuf) -> PathBuf {
+    #[cfg(windows)]
+    if let Ok(absolute) = std::path::absolute(&dir)
+        && let Some(absolute) = absolute.to_str()
+    {
+        return PathBuf::from(extended_length(absolute));
+    }
+    dir
+}
+
+/// Prefix a normalized absolute Windows path with `\\?\` (`\\?\UNC\` for shares)
+#[cfg_attr(not(windows), allow(dead_code))]
+fn extended_length(absolute: &str) -> String {
+    if absolute.starts_with(r"\\?\") {
+        absolute.to_string()
+    } else if let Some(share) = absolute.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", absolute)
+    }
+}
+
 pub fn ensure_directory(dir: &Path) -> Result<()> {
     if dir.exists() {
         if !dir.is_dir() {
             return Err(anyhow!(
                 "output path {} exists but is not a directory",
                 dir.display()
-            ));
+            )
+            .into());
         }
     } else {
         fs::create_dir_all(dir)
@@ -83,7 +118,48 @@ pub fn ensure_directory(dir: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn relative_path(base_path: &Path, item: &GitHubContent) -> Result<PathBuf> {
+/// Names Windows reserves for devices, with or without an extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A Windows-safe spelling of one path component, or `None` when it is already valid.
+///
+/// Characters Windows forbids (`<>:"/\|?*` and control characters) become `_`, as do
+/// trailing dots and spaces, and reserved device names (`aux`, `con.txt`) get a `_`
+/// after their stem.
+pub fn windows_safe_name(name: &str) -> Option<String> {
+    let mut safe: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    let kept = safe.trim_end_matches(['.', ' ']).len();
+    if kept < safe.len() {
+        let trailing = safe.len() - kept;
+        safe.truncate(kept);
+        safe.push_str(&"_".repeat(trailing));
+    }
+
+    let stem_len = safe.find('.').unwrap_or(safe.len());
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| safe[..stem_len].eq_ignore_ascii_case(reserved))
+    {
+        safe.insert(stem_len, '_');
+    }
+
+    (safe != name).then_some(safe)
+}
+
+/// `item`'s path below `base_path`; with `sanitize`, names Windows cannot store are
+/// renamed (see [`windows_safe_name`])
+pub fn relative_path(base_path: &Path, item: &GitHubContent, sanitize: bool) -> Result<PathBuf> {
     let full_path = Path::new(&item.path);
     let mut relative = if base_path.as_os_str().is_empty() {
         full_path.to_path_buf()
@@ -101,13 +177,26 @@ pub fn relative_path(base_path: &Path, item: &GitHubContent) -> Result<PathBuf>
     let mut sanitized = PathBuf::new();
     for component in relative.components() {
         match component {
+            Component::Normal(part) if sanitize => {
+                match part.to_str().and_then(windows_safe_name) {
+                    Some(safe) => {
+                        warn!(
+                            "Renaming {:?} in {} to {:?}, which Windows can store",
+                            part, item.path, safe
+                        );
+                        sanitized.push(safe);
+                    }
+                    None => sanitized.push(part),
+                }
+            }
             Component::Normal(part) => sanitized.push(part),
             Component::CurDir => continue,
             _ => {
                 return Err(anyhow!(
                     "refusing to write outside the output directory ({})",
                     item.path
-                ));
+                )
+                .into());
             }
         }
     }
@@ -119,13 +208,65 @@ pub fn relative_path(base_path: &Path, item: &GitHubContent) -> Result<PathBuf>
     Ok(sanitized)
 }
 
+/// Give every task its own target. Renaming names for Windows can map distinct
+/// repository files (`a:b`, `a?b`) to the same path; the one whose repository path
+/// sorts first keeps it and the others get the first free `<stem>.N.<ext>`, with a
+/// warning, instead of silently overwriting each other.
+pub fn disambiguate_targets<T: TargetPath>(
+    mut tasks: Vec<T>,
+    item_path: impl Fn(&T) -> &str,
+) -> Vec<T> {
+    let mut by_target: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (index, task) in tasks.iter().enumerate() {
+        by_target
+            .entry(task.path().to_path_buf())
+            .or_default()
+            .push(index);
+    }
+    let mut collisions: Vec<Vec<usize>> = by_target
+        .into_values()
+        .filter(|indices| indices.len() > 1)
+        .collect();
+    if collisions.is_empty() {
+        return tasks;
+    }
+
+    let mut taken: HashSet<PathBuf> = tasks.iter().map(|task| task.path().to_path_buf()).collect();
+    for indices in &mut collisions {
+        indices.sort_by(|&a, &b| item_path(&tasks[a]).cmp(item_path(&tasks[b])));
+    }
+    collisions.sort_by(|a, b| item_path(&tasks[a[0]]).cmp(item_path(&tasks[b[0]])));
+    for indices in collisions {
+        let (kept, renamed) = indices.split_first().expect("collisions have two tasks");
+        for &index in renamed {
+            let target = tasks[index].path().to_path_buf();
+            let free = (1..)
+                .map(|n| numbered(&target, n))
+                .find(|path| !taken.contains(path))
+                .expect("unbounded search finds a free name");
+            warn!(
+                "{} and {} both map to {}; writing {} to {}",
+                item_path(&tasks[*kept]),
+                item_path(&tasks[index]),
+                format_path_for_log(&target),
+                item_path(&tasks[index]),
+                format_path_for_log(&free)
+            );
+            taken.insert(free.clone());
+            tasks[index].set_path(free);
+        }
+    }
+    tasks
+}
+
 pub fn describe_download_target(
     output_dir: &Path,
     base_path: &Path,
     contents: &[GitHubContent],
+    sanitize: bool,
 ) -> Result<String> {
     if contents.len() == 1 && contents[0].content_type == ContentType::File {
-        let relative = relative_path(base_path, &contents[0])?;
+        let relative = relative_path(base_path, &contents[0], sanitize)?;
         let target = output_dir.join(relative);
         Ok(format_path_for_log(&target))
     } else {
@@ -207,7 +348,7 @@ mod tests {
     fn relative_path_removes_base_prefix() {
         let base = Path::new("dir/subdir");
         let item = make_file("dir/subdir/file.txt");
-        let relative = relative_path(base, &item).unwrap();
+        let relative = relative_path(base, &item, false).unwrap();
         assert_eq!(relative, PathBuf::from("file.txt"));
     }
 
@@ -215,13 +356,73 @@ mod tests {
     fn relative_path_rejects_traversal() {
         let base = Path::new("dir");
         let item = make_file("dir/../evil.txt");
-        let err = relative_path(base, &item).unwrap_err();
+        let err = relative_path(base, &item, false).unwrap_err();
         assert!(
             err.to_string()
                 .contains("refusing to write outside the output directory")
         );
     }
 
+    #[test]
+    fn test_windows_safe_name() {
+        assert_eq!(windows_safe_name("README.md"), None);
+        assert_eq!(windows_safe_name("a:b?.txt").as_deref(), Some("a_b_.txt"));
+        assert_eq!(windows_safe_name("notes...").as_deref(), Some("notes___"));
+        assert_eq!(windows_safe_name("aux").as_deref(), Some("aux_"));
+        assert_eq!(windows_safe_name("Con.h").as_deref(), Some("Con_.h"));
+        assert_eq!(windows_safe_name("console.h"), None);
+        assert_eq!(
+            windows_safe_name("lpt1.tar.gz").as_deref(),
+            Some("lpt1_.tar.gz")
+        );
+    }
+
+    #[test]
+    fn test_disambiguate_targets() {
+        let task = |item: &str, target: &str| crate::types::FileCopyTask {
+            item_path: item.to_string(),
+            source_path: PathBuf::new(),
+            target_path: PathBuf::from(target),
+            size: None,
+            link_target: None,
+        };
+        // Names that all become `a_b.txt` once made safe for Windows
+        for name in ["a:b.txt", "a?b.txt", "a*b.txt"] {
+            assert_eq!(windows_safe_name(name).as_deref(), Some("a_b.txt"));
+        }
+        let tasks = vec![
+            task("docs/a?b.txt", "out/a_b.txt"),
+            task("docs/a_b.1.txt", "out/a_b.1.txt"),
+            task("docs/a:b.txt", "out/a_b.txt"),
+            task("docs/c.txt", "out/c.txt"),
+            task("docs/a*b.txt", "out/a_b.txt"),
+        ];
+        let targets: Vec<_> = disambiguate_targets(tasks, |task| &task.item_path)
+            .into_iter()
+            .map(|task| (task.item_path, task.target_path))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                ("docs/a?b.txt".to_string(), PathBuf::from("out/a_b.3.txt")),
+                ("docs/a_b.1.txt".to_string(), PathBuf::from("out/a_b.1.txt")),
+                ("docs/a:b.txt".to_string(), PathBuf::from("out/a_b.2.txt")),
+                ("docs/c.txt".to_string(), PathBuf::from("out/c.txt")),
+                ("docs/a*b.txt".to_string(), PathBuf::from("out/a_b.txt")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extended_length() {
+        assert_eq!(extended_length(r"C:\src\repo"), r"\\?\C:\src\repo");
+        assert_eq!(
+            extended_length(r"\\server\share\repo"),
+            r"\\?\UNC\server\share\repo"
+        );
+        assert_eq!(extended_length(r"\\?\C:\repo"), r"\\?\C:\repo");
+    }
+
     #[test]
     fn test_format_path_for_log_absolute() {
         let path = Path::new("/home/user/file.txt");
@@ -311,7 +512,7 @@ mod tests {
     fn test_relative_path_empty_base() {
         let base = Path::new("");
         let item = make_file("file.txt");
-        let relative = relative_path(base, &item).unwrap();
+        let relative = relative_path(base, &item, false).unwrap();
         assert_eq!(relative, PathBuf::from("file.txt"));
     }
 
@@ -320,7 +521,7 @@ mod tests {
         let base = Path::new("dir");
         let mut item = make_file("dir");
         item.name = "filename.txt".to_string();
-        let relative = relative_path(base, &item).unwrap();
+        let relative = relative_path(base, &item, false).unwrap();
         assert_eq!(relative, PathBuf::from("filename.txt"));
     }
 
@@ -328,8 +529,14 @@ mod tests {
     fn test_relative_path_sanitizes_current_dir() {
         let base = Path::new("dir");
         let item = make_file("dir/./file.txt");
-        let relative = relative_path(base, &item).unwrap();
+        let relative = relative_path(base, &item, false).unwrap();
         assert_eq!(relative, PathBuf::from("file.txt"));
+
+        let item = make_file("dir/a:b/aux.txt");
+        let relative = relative_path(base, &item, true).unwrap();
+        assert_eq!(relative, PathBuf::from("a_b/aux_.txt"));
+        let relative = relative_path(base, &item, false).unwrap();
+        assert_eq!(relative, PathBuf::from("a:b/aux.txt"));
     }
 
     #[test]
@@ -347,7 +554,7 @@ mod tests {
         let output_dir = Path::new("output");
         let base_path = Path::new("dir");
         let contents = vec![make_file("dir/file.txt")];
-        let result = describe_download_target(output_dir, base_path, &contents).unwrap();
+        let result = describe_download_target(output_dir, base_path, &contents, false).unwrap();
         assert_eq!(result, "./output/file.txt");
     }
 
@@ -360,7 +567,7 @@ mod tests {
             make_file("dir/file1.txt"),
             make_file("dir/file2.txt"),
         ];
-        let result = describe_download_target(output_dir, base_path, &contents).unwrap();
+        let result = describe_download_target(output_dir, base_path, &contents, false).unwrap();
         assert_eq!(result, "./output");
     }
 }
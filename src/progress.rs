@@ -1,144 +1,427 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io::Write;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use log::info;
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use log::{debug, info};
+use serde::Serialize;
 
 use crate::paths::format_path_for_log;
 
+/// Streamed bytes between two progress updates
+pub const PROGRESS_STEP: u64 = 256 * 1024;
+
+/// Print a status message (stage banners, notices) on stderr unless `quiet`
+/// (`--quiet`) is set. Warnings and errors go through `log` and are not affected.
+pub fn status(quiet: bool, message: impl Display) {
+    if !quiet {
+        eprintln!("{}", message);
+    }
+}
+
+/// Receives file progress from every download strategy. The CLI draws progress bars
+/// or prints JSON events with it; library users can implement it to show their own.
+/// Every method does nothing by default.
+pub trait ProgressSink: Send + Sync {
+    /// A strategy is about to write `files` files totaling `bytes` (0 when unknown)
+    fn start(&self, _files: usize, _bytes: u64) {}
+
+    fn file_start(&self, _path: &str, _target: &Path, _size: Option<u64>) {}
+
+    /// One range of a file downloaded in parallel chunks; `index` counts from 0
+    fn chunk_done(&self, _path: &str, _index: usize, _count: usize, _bytes: u64) {}
+
+    /// Bytes written so far, reported after every file and chunk, and as a streamed
+    /// file's data arrives
+    fn bytes(&self, _downloaded: u64) {}
+
+    fn file_done(&self, _path: &str, _target: &Path, _size: Option<u64>) {}
+
+    /// All files of the strategy are written
+    fn finish(&self, _files: usize, _bytes: u64) {}
+}
+
+impl fmt::Debug for dyn ProgressSink + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressSink")
+    }
+}
+
+/// File and byte progress bars, drawn through the shared [`MultiProgress`]
+pub struct ProgressBars {
+    multi: MultiProgress,
+    bars: Mutex<Option<(ProgressBar, ProgressBar)>>,
+}
+
+impl ProgressBars {
+    pub fn new(multi: MultiProgress) -> Self {
+        Self {
+            multi,
+            bars: Mutex::new(None),
+        }
+    }
+
+    fn bar(&self, len: u64, template: &str) -> ProgressBar {
+        let bar = self.multi.add(ProgressBar::new(len));
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template(template)
+                .expect("invalid progress bar template")
+                .with_key("speed", |state: &ProgressState, w: &mut dyn fmt::Write| {
+                    let speed = calculate_download_speed(state.pos(), state.elapsed());
+                    let _ = write!(w, "{}/s", format_bytes(speed as u64));
+                })
+                .with_key(
+                    "remaining",
+                    |state: &ProgressState, w: &mut dyn fmt::Write| {
+                        let speed = calculate_download_speed(state.pos(), state.elapsed());
+                        let remaining = state.len().unwrap_or(0).saturating_sub(state.pos());
+                        let _ = match calculate_time_remaining_secs(remaining, speed) {
+                            Some(secs) => write!(w, "ETA {}", format_eta(secs)),
+                            None => write!(w, "ETA --"),
+                        };
+                    },
+                )
+                .progress_chars("#>-"),
+        );
+        bar
+    }
+}
+
+impl ProgressSink for ProgressBars {
+    fn start(&self, files: usize, bytes: u64) {
+        let file_bar = self.bar(
+            files as u64,
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({percent}%)",
+        );
+        let byte_bar = self.bar(
+            bytes,
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {speed}, {remaining}",
+        );
+        if let Ok(mut bars) = self.bars.lock() {
+            *bars = Some((file_bar, byte_bar));
+        }
+    }
+
+    fn bytes(&self, downloaded: u64) {
+        if let Ok(bars) = self.bars.lock()
+            && let Some((_, byte_bar)) = bars.as_ref()
+        {
+            byte_bar.set_position(downloaded);
+        }
+    }
+
+    fn file_done(&self, _path: &str, _target: &Path, _size: Option<u64>) {
+        if let Ok(bars) = self.bars.lock()
+            && let Some((file_bar, _)) = bars.as_ref()
+        {
+            file_bar.inc(1);
+        }
+    }
+
+    fn finish(&self, _files: usize, _bytes: u64) {
+        if let Ok(mut bars) = self.bars.lock()
+            && let Some((file_bar, byte_bar)) = bars.take()
+        {
+            file_bar.finish_and_clear();
+            byte_bar.finish_and_clear();
+        }
+    }
+}
+
+/// Version of the `--progress json` events, described by `docs/progress.schema.json`.
+/// Adding a field keeps the version; renaming or removing one bumps it.
+pub const PROGRESS_SCHEMA_VERSION: u32 = 1;
+
+/// One line of `--progress json` output
+#[derive(Debug, Serialize)]
+struct ProgressLine<'a> {
+    schema: u32,
+    #[serde(flatten)]
+    event: ProgressEvent<'a>,
+}
+
+/// Event reported by a `--progress json` line
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    Start {
+        files: usize,
+        bytes: u64,
+    },
+    FileStart {
+        path: &'a str,
+        target: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes: Option<u64>,
+    },
+    ChunkDone {
+        path: &'a str,
+        index: usize,
+        count: usize,
+        bytes: u64,
+    },
+    FileDone {
+        path: &'a str,
+        target: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes: Option<u64>,
+    },
+    Finish {
+        files: usize,
+        bytes: u64,
+    },
+}
+
+/// Writes every event as a JSON line (`--progress json`)
+pub struct JsonProgress {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonProgress {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// A failed write (e.g. a closed pipe) never interrupts the download
+    fn emit(&self, event: ProgressEvent<'_>) {
+        let line = ProgressLine {
+            schema: PROGRESS_SCHEMA_VERSION,
+            event,
+        };
+        let Ok(line) = serde_json::to_string(&line) else {
+            return;
+        };
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{}", line).and_then(|_| writer.flush());
+        }
+    }
+}
+
+impl ProgressSink for JsonProgress {
+    fn start(&self, files: usize, bytes: u64) {
+        self.emit(ProgressEvent::Start { files, bytes });
+    }
+
+    fn file_start(&self, path: &str, target: &Path, size: Option<u64>) {
+        self.emit(ProgressEvent::FileStart {
+            path,
+            target,
+            bytes: size,
+        });
+    }
+
+    fn chunk_done(&self, path: &str, index: usize, count: usize, bytes: u64) {
+        self.emit(ProgressEvent::ChunkDone {
+            path,
+            index,
+            count,
+            bytes,
+        });
+    }
+
+    fn file_done(&self, path: &str, target: &Path, size: Option<u64>) {
+        self.emit(ProgressEvent::FileDone {
+            path,
+            target,
+            bytes: size,
+        });
+    }
+
+    fn finish(&self, files: usize, bytes: u64) {
+        self.emit(ProgressEvent::Finish { files, bytes });
+    }
+}
+
+/// Counts the files and bytes of one strategy run and reports them to the sink.
+/// Without a sink, each file is logged at info level instead.
 #[derive(Debug)]
 pub struct DownloadProgress {
     pub total_files: usize,
     pub downloaded_files: usize,
     pub total_bytes: u64,
     pub downloaded_bytes: u64,
-    file_bar: Option<ProgressBar>,
-    byte_bar: Option<ProgressBar>,
+    sink: Option<Arc<dyn ProgressSink>>,
+    /// Bytes already counted for files still downloading, in chunks or as a stream,
+    /// until they complete
+    chunk_bytes: HashMap<String, u64>,
+    started: Instant,
 }
 
 impl DownloadProgress {
-    /// Create a new progress tracker with visual progress bars
-    #[allow(dead_code)]
-    pub fn new(total_files: usize, total_bytes: u64) -> Self {
-        Self::with_multi_progress(total_files, total_bytes, None)
-    }
-
-    /// Create a new progress tracker with optional MultiProgress for coordinated display
-    pub fn with_multi_progress(
-        total_files: usize,
-        total_bytes: u64,
-        multi: Option<&MultiProgress>,
-    ) -> Self {
-        let (file_bar, byte_bar) = if let Some(mp) = multi {
-            // Create file progress bar
-            let file_pb = mp.add(ProgressBar::new(total_files as u64));
-            file_pb.set_style(
-                ProgressStyle::default_bar()
-                    .template(
-                        "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({percent}%)",
-                    )
-                    .expect("invalid progress bar template")
-                    .progress_chars("#>-"),
-            );
-
-            // Create byte progress bar
-            let byte_pb = mp.add(ProgressBar::new(total_bytes));
-            byte_pb.set_style(
-                ProgressStyle::default_bar()
-                    .template(
-                        "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%)",
-                    )
-                    .expect("invalid progress bar template")
-                    .progress_chars("#>-"),
-            );
-
-            (Some(file_pb), Some(byte_pb))
-        } else {
-            (None, None)
-        };
-
+    pub fn new(total_files: usize, total_bytes: u64, sink: Option<Arc<dyn ProgressSink>>) -> Self {
+        if let Some(sink) = &sink {
+            sink.start(total_files, total_bytes);
+        }
         Self {
             total_files,
             downloaded_files: 0,
             total_bytes,
             downloaded_bytes: 0,
-            file_bar,
-            byte_bar,
+            sink,
+            chunk_bytes: HashMap::new(),
+            started: Instant::now(),
         }
     }
 
     pub fn log_start(&self, item_path: &str, target_path: &Path, size: Option<u64>) {
+        if let Some(sink) = &self.sink {
+            sink.file_start(item_path, target_path, size);
+            return;
+        }
+
         let current = self.downloaded_files + 1;
         let total = self.total_files.max(current);
         let size_info = size
             .map(format_bytes)
             .unwrap_or_else(|| "size unknown".to_string());
+        info!(
+            "Starting ({}/{}) {} -> {} [{}]",
+            current,
+            total,
+            item_path,
+            format_path_for_log(target_path),
+            size_info
+        );
+    }
+
+    /// Count one finished chunk of a file downloaded in parallel ranges
+    pub fn record_chunk(&mut self, item_path: &str, index: usize, count: usize, bytes: u64) {
+        *self.chunk_bytes.entry(item_path.to_string()).or_default() += bytes;
+        self.downloaded_bytes = self.downloaded_bytes.saturating_add(bytes);
 
-        if self.file_bar.is_none() {
-            info!(
-                "Starting ({}/{}) {} -> {} [{}]",
-                current,
-                total,
+        if let Some(sink) = &self.sink {
+            sink.chunk_done(item_path, index, count, bytes);
+            sink.bytes(self.downloaded_bytes);
+        } else {
+            debug!(
+                "Chunk {}/{} of {} done [{}]",
+                index + 1,
+                count,
                 item_path,
-                format_path_for_log(target_path),
-                size_info
+                format_bytes(bytes)
             );
         }
     }
 
+    /// Count bytes of a file as its stream delivers them, so a large file moves the
+    /// byte progress before it completes
+    pub fn record_bytes(&mut self, item_path: &str, bytes: u64) {
+        *self.chunk_bytes.entry(item_path.to_string()).or_default() += bytes;
+        self.downloaded_bytes = self.downloaded_bytes.saturating_add(bytes);
+        if let Some(sink) = &self.sink {
+            sink.bytes(self.downloaded_bytes);
+        }
+    }
+
+    /// Take back the bytes counted for a file whose download starts over
+    pub fn forget_bytes(&mut self, item_path: &str) {
+        if let Some(bytes) = self.chunk_bytes.remove(item_path) {
+            self.downloaded_bytes = self.downloaded_bytes.saturating_sub(bytes);
+            if let Some(sink) = &self.sink {
+                sink.bytes(self.downloaded_bytes);
+            }
+        }
+    }
+
     pub fn record_download(&mut self, item_path: &str, target_path: &Path, size: Option<u64>) {
         self.downloaded_files += 1;
+        // Bytes of chunked downloads were already counted chunk by chunk
+        let counted = self.chunk_bytes.remove(item_path).unwrap_or(0);
         if let Some(bytes) = size {
-            self.downloaded_bytes = self.downloaded_bytes.saturating_add(bytes);
-        }
-
-        // Update progress bars if they exist
-        if let Some(ref bar) = self.file_bar {
-            bar.set_position(self.downloaded_files as u64);
-        }
-        if let Some(ref bar) = self.byte_bar {
-            bar.set_position(self.downloaded_bytes);
-        }
-
-        // Only log to console if no progress bars are being used
-        if self.file_bar.is_none() {
-            let total = self.total_files.max(self.downloaded_files);
-            let size_info = match (size, self.total_bytes) {
-                (Some(bytes), total_bytes) if total_bytes > 0 => format!(
-                    "{} ({} / {})",
-                    format_bytes(bytes),
-                    format_bytes(self.downloaded_bytes),
-                    format_bytes(total_bytes)
-                ),
-                (Some(bytes), _) => format_bytes(bytes),
-                (None, total_bytes) if total_bytes > 0 => format!(
-                    "{} / {}",
-                    format_bytes(self.downloaded_bytes),
-                    format_bytes(total_bytes)
-                ),
-                _ => "size unknown".to_string(),
-            };
-            info!(
-                "({}/{}) {} -> {} [{}]",
-                self.downloaded_files,
-                total,
-                item_path,
-                format_path_for_log(target_path),
-                size_info
-            );
+            self.downloaded_bytes = self
+                .downloaded_bytes
+                .saturating_add(bytes.saturating_sub(counted));
+        }
+
+        if let Some(sink) = &self.sink {
+            sink.file_done(item_path, target_path, size);
+            sink.bytes(self.downloaded_bytes);
+            return;
+        }
+
+        let total = self.total_files.max(self.downloaded_files);
+        let size_info = match (size, self.total_bytes) {
+            (Some(bytes), total_bytes) if total_bytes > 0 => format!(
+                "{} ({} / {}{})",
+                format_bytes(bytes),
+                format_bytes(self.downloaded_bytes),
+                format_bytes(total_bytes),
+                self.describe_rate()
+            ),
+            (Some(bytes), _) => format_bytes(bytes),
+            (None, total_bytes) if total_bytes > 0 => format!(
+                "{} / {}{}",
+                format_bytes(self.downloaded_bytes),
+                format_bytes(total_bytes),
+                self.describe_rate()
+            ),
+            _ => "size unknown".to_string(),
+        };
+        info!(
+            "({}/{}) {} -> {} [{}]",
+            self.downloaded_files,
+            total,
+            item_path,
+            format_path_for_log(target_path),
+            size_info
+        );
+    }
+
+    /// `, <speed>/s, ETA <time>` while bytes are still to come
+    fn describe_rate(&self) -> String {
+        let speed = calculate_download_speed(self.downloaded_bytes, self.started.elapsed());
+        let remaining = self.total_bytes.saturating_sub(self.downloaded_bytes);
+        match calculate_time_remaining_secs(remaining, speed) {
+            Some(secs) if remaining > 0 => format!(
+                ", {}/s, ETA {}",
+                format_bytes(speed as u64),
+                format_eta(secs)
+            ),
+            _ => String::new(),
         }
     }
 
-    /// Finish and clean up progress bars
+    /// Report the end of the run to the sink
     pub fn finish(&self) {
-        if let Some(ref bar) = self.file_bar {
-            bar.finish_and_clear();
-        }
-        if let Some(ref bar) = self.byte_bar {
-            bar.finish_and_clear();
+        if let Some(sink) = &self.sink {
+            sink.finish(self.downloaded_files, self.downloaded_bytes);
         }
     }
 }
 
+/// Average bytes per second over `elapsed`; 0 before any time has passed
+pub fn calculate_download_speed(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs <= 0.0 {
+        return 0.0;
+    }
+    bytes as f64 / secs
+}
+
+/// Seconds until `remaining` bytes arrive at `speed` bytes per second; `None` until
+/// there is a speed to go by
+pub fn calculate_time_remaining_secs(remaining: u64, speed: f64) -> Option<u64> {
+    if speed <= 0.0 {
+        return None;
+    }
+    Some((remaining as f64 / speed).ceil() as u64)
+}
+
+/// `42s`, `3m 05s`, or `1h 02m`
+pub fn format_eta(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
 pub fn format_bytes(bytes: u64) -> String {
     const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
     if bytes == 0 {
@@ -154,3 +437,106 @@ pub fn format_bytes(bytes: u64) -> String {
         format!("{:.1} {}", value, UNITS[index])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects what a [`JsonProgress`] writes
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_speed_and_time_remaining() {
+        let speed = calculate_download_speed(3 * 1024 * 1024, Duration::from_secs(2));
+        assert_eq!(speed, 1.5 * 1024.0 * 1024.0);
+        assert_eq!(calculate_download_speed(100, Duration::ZERO), 0.0);
+        assert_eq!(calculate_time_remaining_secs(10, 4.0), Some(3));
+        assert_eq!(calculate_time_remaining_secs(10, 0.0), None);
+        assert_eq!(format_eta(42), "42s");
+        assert_eq!(format_eta(185), "3m 05s");
+        assert_eq!(format_eta(3720), "1h 02m");
+    }
+
+    #[test]
+    fn test_progress_events() {
+        let buffer = Buffer::default();
+        let sink: Arc<dyn ProgressSink> = Arc::new(JsonProgress::new(Box::new(buffer.clone())));
+        let mut progress = DownloadProgress::new(2, 30, Some(sink));
+        progress.log_start("big.bin", Path::new("out/big.bin"), Some(20));
+        progress.record_chunk("big.bin", 0, 2, 10);
+        progress.record_chunk("big.bin", 1, 2, 10);
+        progress.record_download("big.bin", Path::new("out/big.bin"), Some(20));
+        // A restarted stream does not count its first attempt's bytes
+        progress.record_bytes("a", 7);
+        progress.forget_bytes("a");
+        assert_eq!(progress.downloaded_bytes, 20);
+        progress.record_download("a", Path::new("out/a"), None);
+        progress.finish();
+        // Chunked bytes are not counted twice when the file completes
+        assert_eq!(progress.downloaded_bytes, 20);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 7);
+        assert_eq!(
+            events[0],
+            serde_json::json!({"schema": 1, "event": "start", "files": 2, "bytes": 30})
+        );
+        assert_eq!(
+            events[2],
+            serde_json::json!({"schema": 1, "event": "chunk_done", "path": "big.bin", "index": 0, "count": 2, "bytes": 10})
+        );
+        assert_eq!(
+            events[4],
+            serde_json::json!({
+                "schema": 1,
+                "event": "file_done",
+                "path": "big.bin",
+                "target": "out/big.bin",
+                "bytes": 20
+            })
+        );
+        assert_eq!(
+            events[5],
+            serde_json::json!({"schema": 1, "event": "file_done", "path": "a", "target": "out/a"})
+        );
+        assert_eq!(
+            events[6],
+            serde_json::json!({"schema": 1, "event": "finish", "files": 2, "bytes": 20})
+        );
+
+        // Every emitted field is documented, and every required one is emitted
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../docs/progress.schema.json")).unwrap();
+        assert_eq!(
+            schema["properties"]["schema"]["const"],
+            PROGRESS_SCHEMA_VERSION
+        );
+        let properties = schema["properties"].as_object().unwrap();
+        for event in &events {
+            let object = event.as_object().unwrap();
+            for key in object.keys() {
+                assert!(properties.contains_key(key), "{} missing", key);
+            }
+            for name in schema["required"].as_array().unwrap() {
+                assert!(object.contains_key(name.as_str().unwrap()));
+            }
+            let kinds = properties["event"]["enum"].as_array().unwrap();
+            assert!(kinds.contains(&event["event"]), "{}", event["event"]);
+        }
+    }
+}
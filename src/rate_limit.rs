@@ -1,10 +1,21 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use anyhow::{Context, Result};
+use log::info;
 use reqwest::StatusCode;
 use reqwest::header::{HeaderMap, RETRY_AFTER};
-use tokio::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::error::GdlError;
+use crate::http::RetryPolicy;
+use tokio::sync::{Mutex, Notify};
+use tokio::time::{Instant, sleep};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RateLimitSnapshot {
     pub limit: Option<u64>,
     pub remaining: Option<u64>,
@@ -40,6 +51,54 @@ impl RateLimitSnapshot {
             .map(|duration| format!("in {}s", duration.as_secs()))
             .unwrap_or_else(|| "at an unknown time".to_string())
     }
+
+    /// Whether the quota this snapshot describes has not refilled yet at `now`
+    pub fn is_current(&self, now: SystemTime) -> bool {
+        self.reset_epoch
+            .is_some_and(|epoch| UNIX_EPOCH + Duration::from_secs(epoch) > now)
+    }
+
+    /// Whether the remaining quota is at or below the warning threshold
+    pub fn is_low(&self, warn_threshold: Option<u64>) -> bool {
+        match (self.limit, self.remaining) {
+            (Some(limit), Some(remaining)) => {
+                remaining <= low_quota_threshold(limit, warn_threshold)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Remaining quota at which to warn: the configured threshold, or 10% of the limit
+/// (at least 50), never above the limit itself
+fn low_quota_threshold(limit: u64, warn_threshold: Option<u64>) -> u64 {
+    warn_threshold
+        .unwrap_or_else(|| (((limit as f64) * 0.1).ceil() as u64).max(50))
+        .min(limit)
+}
+
+/// File holding the last snapshot for a token. Tokens are stored only as a hash;
+/// unauthenticated requests share one per-IP quota.
+pub fn snapshot_file(dir: &Path, token: Option<&str>) -> PathBuf {
+    let name = match token {
+        Some(token) => format!("{:x}", Sha256::digest(token.as_bytes()))[..16].to_string(),
+        None => "anonymous".to_string(),
+    };
+    dir.join(format!("{}.json", name))
+}
+
+/// The snapshot a previous run saved, unless its window has reset since
+pub fn load_snapshot(path: &Path) -> Option<RateLimitSnapshot> {
+    let data = fs::read(path).ok()?;
+    let snapshot: RateLimitSnapshot = serde_json::from_slice(&data).ok()?;
+    snapshot.is_current(SystemTime::now()).then_some(snapshot)
+}
+
+/// Save the last snapshot of this run so the next one starts out knowing the quota
+pub fn save_snapshot(path: &Path, snapshot: &RateLimitSnapshot) -> Result<()> {
+    let data = serde_json::to_vec(snapshot).context("failed to serialize rate-limit snapshot")?;
+    fs::write(path, data)
+        .with_context(|| format!("failed to write rate-limit snapshot {}", path.display()))
 }
 
 #[derive(Debug, Default)]
@@ -47,14 +106,212 @@ pub struct RateLimitState {
     pub last_snapshot: Option<RateLimitSnapshot>,
     pub lowest_remaining: Option<u64>,
     pub last_warned_remaining: Option<u64>,
+    /// Concurrency last allowed by `throttle`, to log only changes
+    pub last_allowed: Option<usize>,
+    /// Earliest time the next paced API request may be sent
+    pub next_request: Option<Instant>,
+    /// Spacing last applied by `pace`, to log only when pacing starts
+    pub last_interval: Option<Duration>,
 }
 
 #[derive(Debug, Default)]
 pub struct RateLimitTracker {
     pub state: Mutex<RateLimitState>,
+    /// Remaining quota at which to warn (default: 10% of the limit, at least 50)
+    pub warn_threshold: Option<u64>,
+    /// API requests this run may send in total (`--max-api-calls`)
+    pub max_api_calls: Option<u64>,
+    /// How this run's requests and downloads retry transient failures
+    pub retry: RetryPolicy,
+    /// API requests sent so far, retries included
+    api_calls: AtomicU64,
+    /// API-bound tasks currently holding a `ThrottlePermit`
+    in_flight: AtomicUsize,
+    released: Notify,
+}
+
+/// Share of the quota below which concurrency is scaled down
+const FULL_SPEED_HEADROOM: f64 = 0.25;
+
+/// Number of concurrent API-bound tasks allowed out of `max` for the given quota:
+/// all of them while a quarter of the limit remains, then proportionally fewer,
+/// down to one when the quota is nearly gone.
+pub fn adaptive_concurrency(max: usize, snapshot: Option<&RateLimitSnapshot>) -> usize {
+    let max = max.max(1);
+    let Some((limit, remaining)) =
+        snapshot.and_then(|snapshot| Some((snapshot.limit?, snapshot.remaining?)))
+    else {
+        return max;
+    };
+    if limit == 0 {
+        return max;
+    }
+
+    let headroom = remaining as f64 / limit as f64;
+    if headroom >= FULL_SPEED_HEADROOM {
+        return max;
+    }
+    ((max as f64 * headroom / FULL_SPEED_HEADROOM).ceil() as usize).clamp(1, max)
+}
+
+/// Share of the quota below which API requests are spaced out until the reset
+const PACING_HEADROOM: f64 = 0.1;
+
+/// Spacing between API requests that makes the remaining quota last until it resets:
+/// none while a tenth of the limit remains, then the time left until the reset
+/// divided by the requests left. An exhausted quota is left to the 403 backoff.
+pub fn pacing_interval(snapshot: Option<&RateLimitSnapshot>, now: SystemTime) -> Option<Duration> {
+    let snapshot = snapshot?;
+    let (limit, remaining, reset_epoch) =
+        (snapshot.limit?, snapshot.remaining?, snapshot.reset_epoch?);
+    if limit == 0 || remaining == 0 || remaining as f64 / limit as f64 >= PACING_HEADROOM {
+        return None;
+    }
+    let until_reset = (UNIX_EPOCH + Duration::from_secs(reset_epoch))
+        .duration_since(now)
+        .ok()?;
+    Some(until_reset / remaining as u32)
+}
+
+/// Slot held by one API-bound task; releasing it lets a waiting task start
+pub struct ThrottlePermit<'a> {
+    tracker: &'a RateLimitTracker,
+}
+
+impl Drop for ThrottlePermit<'_> {
+    fn drop(&mut self) {
+        self.tracker.in_flight.fetch_sub(1, Ordering::AcqRel);
+        self.tracker.released.notify_waiters();
+    }
 }
 
 impl RateLimitTracker {
+    pub fn with_warn_threshold(warn_threshold: Option<u64>) -> Self {
+        Self {
+            warn_threshold,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_api_calls(mut self, max_api_calls: Option<u64>) -> Self {
+        self.max_api_calls = max_api_calls;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Count one API request against `max_api_calls`, or fail once the budget is
+    /// spent. The error is a rate-limit error, so `auto` falls back to another
+    /// strategy and the exit status is the rate limit's.
+    pub fn spend_api_call(&self) -> std::result::Result<(), GdlError> {
+        let Some(max) = self.max_api_calls else {
+            self.api_calls.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        };
+        self.api_calls
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |spent| {
+                (spent < max).then_some(spent + 1)
+            })
+            .map(|_| ())
+            .map_err(|_| GdlError::RateLimited {
+                reset: None,
+                message: format!(
+                    "API call budget of {} request(s) is spent (--max-api-calls)",
+                    max
+                ),
+            })
+    }
+
+    /// API requests sent so far
+    pub fn api_calls(&self) -> u64 {
+        self.api_calls.load(Ordering::Relaxed)
+    }
+
+    /// Start from a snapshot saved by an earlier run, so throttling and pacing apply
+    /// before the first response of this one arrives
+    pub fn with_snapshot(mut self, snapshot: Option<RateLimitSnapshot>) -> Self {
+        self.state.get_mut().last_snapshot = snapshot;
+        self
+    }
+
+    /// Wait until fewer API-bound tasks are running than the quota allows (see
+    /// [`adaptive_concurrency`]), so a draining quota slows requests down instead of
+    /// running into 403s. `max` is the configured concurrency.
+    pub async fn throttle(&self, max: usize) -> ThrottlePermit<'_> {
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            let allowed = self.allowed_concurrency(max).await;
+            let acquired = self
+                .in_flight
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+                    (running < allowed).then_some(running + 1)
+                })
+                .is_ok();
+            if acquired {
+                return ThrottlePermit { tracker: self };
+            }
+            released.await;
+        }
+    }
+
+    /// Wait for this request's turn while the quota is low (see [`pacing_interval`]).
+    /// Each caller reserves the next slot, so concurrent requests are spaced too.
+    pub async fn pace(&self) {
+        let wait = {
+            let mut state = self.state.lock().await;
+            let interval = pacing_interval(state.last_snapshot.as_ref(), SystemTime::now());
+            let previous = std::mem::replace(&mut state.last_interval, interval);
+            let Some(interval) = interval else {
+                state.next_request = None;
+                return;
+            };
+            if previous.is_none() {
+                let snapshot = state.last_snapshot.as_ref();
+                info!(
+                    "Pacing API requests {:.1}s apart so the remaining {} requests last until the rate limit resets {}",
+                    interval.as_secs_f64(),
+                    snapshot.and_then(|s| s.remaining).unwrap_or_default(),
+                    snapshot.map(|s| s.reset_eta_display()).unwrap_or_default()
+                );
+            }
+            let now = Instant::now();
+            let slot = state.next_request.map_or(now, |next| next.max(now));
+            state.next_request = Some(slot + interval);
+            slot - now
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    async fn allowed_concurrency(&self, max: usize) -> usize {
+        let mut state = self.state.lock().await;
+        let allowed = adaptive_concurrency(max, state.last_snapshot.as_ref());
+        if state
+            .last_allowed
+            .is_some_and(|previous| previous != allowed)
+            || (state.last_allowed.is_none() && allowed < max.max(1))
+        {
+            let remaining = state
+                .last_snapshot
+                .as_ref()
+                .and_then(|snapshot| snapshot.remaining)
+                .unwrap_or_default();
+            info!(
+                "Adjusting API concurrency to {} ({} requests left in the rate limit)",
+                allowed, remaining
+            );
+        }
+        state.last_allowed = Some(allowed);
+        allowed
+    }
+
     pub async fn record_headers(
         &self,
         headers: &HeaderMap,
@@ -79,9 +336,7 @@ impl RateLimitTracker {
 
         let warn_low = if let (Some(limit), Some(remaining)) = (snapshot.limit, snapshot.remaining)
         {
-            let threshold = ((limit as f64) * 0.1).ceil() as u64;
-            let threshold = threshold.max(50).min(limit);
-            if remaining <= threshold {
+            if remaining <= low_quota_threshold(limit, self.warn_threshold) {
                 let should_warn = state
                     .last_warned_remaining
                     .is_none_or(|previous| remaining < previous);
@@ -104,10 +359,10 @@ impl RateLimitTracker {
     }
 
     pub fn backoff_duration(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
-        if status == StatusCode::TOO_MANY_REQUESTS {
-            if let Some(duration) = parse_retry_after(headers) {
-                return Some(duration);
-            }
+        if status == StatusCode::TOO_MANY_REQUESTS
+            && let Some(duration) = parse_retry_after(headers)
+        {
+            return Some(duration);
         }
 
         if status == StatusCode::FORBIDDEN {
@@ -129,10 +384,10 @@ impl RateLimitTracker {
 
         if let Some(reset_epoch) = header_value_to_u64(headers, "x-ratelimit-reset") {
             let reset_time = UNIX_EPOCH + Duration::from_secs(reset_epoch);
-            if let Ok(duration) = reset_time.duration_since(SystemTime::now()) {
-                if duration > Duration::from_secs(0) {
-                    return Some(duration + Duration::from_secs(1));
-                }
+            if let Ok(duration) = reset_time.duration_since(SystemTime::now())
+                && duration > Duration::from_secs(0)
+            {
+                return Some(duration + Duration::from_secs(1));
             }
         }
 
@@ -252,6 +507,18 @@ mod tests {
         assert!(warn_low); // Should warn when at or below threshold
     }
 
+    #[tokio::test]
+    async fn test_rate_limit_tracker_configured_threshold() {
+        let tracker = RateLimitTracker::with_warn_threshold(Some(1000));
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit", "5000".parse().unwrap());
+        headers.insert("x-ratelimit-remaining", "1200".parse().unwrap());
+        assert!(!tracker.record_headers(&headers).await.unwrap().2);
+
+        headers.insert("x-ratelimit-remaining", "900".parse().unwrap());
+        assert!(tracker.record_headers(&headers).await.unwrap().2);
+    }
+
     #[tokio::test]
     async fn test_rate_limit_tracker_no_duplicate_warnings() {
         let tracker = RateLimitTracker::default();
@@ -270,6 +537,124 @@ mod tests {
         assert!(!result2.2);
     }
 
+    #[test]
+    fn test_pacing_interval() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let snapshot = |remaining: u64, reset_in: u64| RateLimitSnapshot {
+            limit: Some(5000),
+            remaining: Some(remaining),
+            used: None,
+            reset_epoch: Some(1_000_000 + reset_in),
+        };
+        assert_eq!(pacing_interval(None, now), None);
+        assert_eq!(pacing_interval(Some(&snapshot(500, 600)), now), None);
+        assert_eq!(
+            pacing_interval(Some(&snapshot(300, 600)), now),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(pacing_interval(Some(&snapshot(0, 600)), now), None);
+        // A reset time in the past means the quota is about to refill
+        let mut stale = snapshot(10, 0);
+        stale.reset_epoch = Some(999_000);
+        assert_eq!(pacing_interval(Some(&stale), now), None);
+    }
+
+    #[test]
+    fn test_spend_api_call() {
+        let tracker = RateLimitTracker::default().with_max_api_calls(Some(2));
+        assert!(tracker.spend_api_call().is_ok());
+        assert!(tracker.spend_api_call().is_ok());
+        let err = tracker.spend_api_call().unwrap_err();
+        assert!(matches!(err, GdlError::RateLimited { reset: None, .. }));
+        assert_eq!(tracker.api_calls(), 2);
+
+        let unlimited = RateLimitTracker::default();
+        for _ in 0..5 {
+            unlimited.spend_api_call().unwrap();
+        }
+        assert_eq!(unlimited.api_calls(), 5);
+    }
+
+    #[test]
+    fn test_snapshot_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let reset = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 600;
+        let snapshot = RateLimitSnapshot {
+            limit: Some(5000),
+            remaining: Some(40),
+            used: Some(4960),
+            reset_epoch: Some(reset),
+        };
+        let path = snapshot_file(dir.path(), Some("ghp_secret"));
+        save_snapshot(&path, &snapshot).unwrap();
+
+        assert_eq!(load_snapshot(&path), Some(snapshot.clone()));
+        assert!(!path.to_string_lossy().contains("ghp_secret"));
+        assert_ne!(path, snapshot_file(dir.path(), Some("ghp_other")));
+        assert_eq!(load_snapshot(&snapshot_file(dir.path(), None)), None);
+        assert!(snapshot.is_low(None));
+        assert!(!snapshot.is_low(Some(10)));
+
+        // A window that has already reset is ignored
+        let expired = RateLimitSnapshot {
+            reset_epoch: Some(reset - 1200),
+            ..snapshot
+        };
+        save_snapshot(&path, &expired).unwrap();
+        assert_eq!(load_snapshot(&path), None);
+    }
+
+    #[test]
+    fn test_adaptive_concurrency() {
+        let snapshot = |remaining| RateLimitSnapshot {
+            limit: Some(5000),
+            remaining: Some(remaining),
+            used: None,
+            reset_epoch: None,
+        };
+
+        let cases = [
+            (None, 8),
+            (Some(snapshot(5000)), 8),
+            (Some(snapshot(1250)), 8),
+            (Some(snapshot(625)), 4),
+            (Some(snapshot(100)), 1),
+            (Some(snapshot(0)), 1),
+        ];
+        for (snapshot, expected) in cases {
+            assert_eq!(
+                adaptive_concurrency(8, snapshot.as_ref()),
+                expected,
+                "{:?}",
+                snapshot
+            );
+        }
+        assert_eq!(adaptive_concurrency(0, None), 1);
+    }
+
+    #[tokio::test]
+    async fn test_throttle_limits_in_flight_tasks() {
+        let tracker = RateLimitTracker::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit", "5000".parse().unwrap());
+        headers.insert("x-ratelimit-remaining", "100".parse().unwrap());
+        tracker.record_headers(&headers).await;
+
+        let permit = tracker.throttle(8).await;
+        // Only one task may run with 2% of the quota left
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), tracker.throttle(8))
+                .await
+                .is_err()
+        );
+        drop(permit);
+        let _permit = tracker.throttle(8).await;
+    }
+
     #[test]
     fn test_backoff_duration_too_many_requests() {
         let mut headers = HeaderMap::new();
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::str::FromStr;
+
+use console::style;
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::progress::status;
+
+/// One partition of a download split across several runs (`--shard i/n`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// 1-based index of this partition
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Whether `path` belongs to this shard.
+    ///
+    /// Files are assigned by hashing their repository path, so the partition is stable
+    /// across machines and does not depend on enumeration order.
+    pub fn contains(&self, path: &str) -> bool {
+        let digest = Sha256::digest(path.as_bytes());
+        let mut prefix = [0u8; 8];
+        prefix.copy_from_slice(&digest[..8]);
+        let bucket = u64::from_be_bytes(prefix) % self.count as u64;
+        bucket as usize == self.index - 1
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (index, count) = value
+            .split_once('/')
+            .ok_or_else(|| format!("expected i/n (e.g. 1/4), got '{}'", value))?;
+        let index: usize = index
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid shard index '{}'", index))?;
+        let count: usize = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid shard count '{}'", count))?;
+
+        if count == 0 || index == 0 || index > count {
+            return Err(format!(
+                "shard index must be between 1 and {} (got {})",
+                count.max(1),
+                index
+            ));
+        }
+
+        Ok(Self { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Keep only the tasks that belong to `shard` (all of them when no shard is set)
+pub fn select_shard<T>(
+    tasks: Vec<T>,
+    shard: Option<Shard>,
+    item_path: impl Fn(&T) -> &str,
+) -> Vec<T> {
+    let Some(shard) = shard else {
+        return tasks;
+    };
+
+    let total = tasks.len();
+    let selected: Vec<T> = tasks
+        .into_iter()
+        .filter(|task| shard.contains(item_path(task)))
+        .collect();
+    info!(
+        "Shard {} selected {} of {} file(s)",
+        shard,
+        selected.len(),
+        total
+    );
+    selected
+}
+
+/// Tell the user this shard has nothing to do (not an error, other shards do the work)
+pub fn report_empty_shard(shard: Option<Shard>, quiet: bool) {
+    if let Some(shard) = shard {
+        status(
+            quiet,
+            format!(
+                "{} Nothing to download: shard {} received none of the files",
+                style("ℹ").cyan(),
+                shard
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shard() {
+        assert_eq!("2/5".parse::<Shard>(), Ok(Shard { index: 2, count: 5 }));
+        assert_eq!("1/1".parse::<Shard>(), Ok(Shard { index: 1, count: 1 }));
+
+        assert!("0/3".parse::<Shard>().is_err());
+        assert!("4/3".parse::<Shard>().is_err());
+        assert!("1/0".parse::<Shard>().is_err());
+        assert!("3".parse::<Shard>().is_err());
+        assert!("a/b".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn test_select_shard_partitions_every_file_once() {
+        let paths: Vec<String> = (0..200).map(|i| format!("src/file{}.rs", i)).collect();
+
+        let mut seen = 0;
+        for index in 1..=3 {
+            let shard = Shard { index, count: 3 };
+            let selected = select_shard(paths.clone(), Some(shard), |p| p.as_str());
+            assert!(!selected.is_empty());
+            assert!(selected.iter().all(|p| shard.contains(p)));
+            seen += selected.len();
+        }
+        assert_eq!(seen, paths.len());
+
+        // No shard keeps everything
+        assert_eq!(select_shard(paths.clone(), None, |p| p.as_str()).len(), 200);
+    }
+}
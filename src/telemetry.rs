@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result, anyhow};
+use console::style;
+use log::debug;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::PKG_VERSION;
+use crate::config::Config;
+use crate::download::explain::{Action, Decision};
+use crate::utils::system_time_to_secs;
+
+/// Aggregate usage counters. Only counts are kept: no URLs, paths, repository
+/// names, or tokens are ever recorded.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageCounts {
+    /// When counting started (seconds since the Unix epoch)
+    pub since: u64,
+    pub runs: u64,
+    /// Flags and modes used, e.g. `strategy:auto`, `lfs`
+    pub features: BTreeMap<String, u64>,
+    /// Strategy outcomes, e.g. `git.succeeded`, `api.failed`
+    pub strategies: BTreeMap<String, u64>,
+    /// Failure classes, e.g. `rate_limited`, `not_found`
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl UsageCounts {
+    fn merge(&mut self, other: &UsageCounts) {
+        if self.since == 0 {
+            self.since = other.since;
+        }
+        self.runs += other.runs;
+        for (target, source) in [
+            (&mut self.features, &other.features),
+            (&mut self.strategies, &other.strategies),
+            (&mut self.errors, &other.errors),
+        ] {
+            for (key, count) in source {
+                *target.entry(key.clone()).or_default() += count;
+            }
+        }
+    }
+}
+
+/// Counters for the current run, merged into the local telemetry file on flush.
+/// Does nothing unless telemetry was enabled in the config file.
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    enabled: bool,
+    counts: Mutex<UsageCounts>,
+}
+
+impl Telemetry {
+    /// Enabled only when the config opts in and `--no-telemetry` is absent
+    pub fn new(config: &Config, no_telemetry: bool) -> Self {
+        Self {
+            enabled: config.telemetry.unwrap_or(false) && !no_telemetry,
+            counts: Mutex::new(UsageCounts::default()),
+        }
+    }
+
+    pub fn feature(&self, name: &str) {
+        self.update(|counts| *counts.features.entry(name.to_string()).or_default() += 1);
+    }
+
+    pub fn error(&self, reason: &str) {
+        self.update(|counts| *counts.errors.entry(reason.to_string()).or_default() += 1);
+    }
+
+    /// Count a strategy decision and, for failures, its error class
+    pub fn decision(&self, decision: &Decision<'_>) {
+        let action = match decision.action {
+            Action::Skipped => "skipped",
+            Action::Attempted => "attempted",
+            Action::Failed => "failed",
+            Action::Succeeded => "succeeded",
+        };
+        let key = format!("{}.{}", decision.strategy, action);
+        self.update(|counts| *counts.strategies.entry(key).or_default() += 1);
+        if decision.action == Action::Failed {
+            self.error(decision.reason);
+        }
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut UsageCounts)) {
+        if !self.enabled {
+            return;
+        }
+        if let Ok(mut counts) = self.counts.lock() {
+            apply(&mut counts);
+        }
+    }
+
+    /// Add this run's counters to the local telemetry file
+    pub fn flush(&self) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let mut run = self
+            .counts
+            .lock()
+            .map_err(|_| anyhow!("telemetry counters are poisoned"))?
+            .clone();
+        run.runs = 1;
+        run.since = system_time_to_secs(SystemTime::now());
+
+        let mut stored = load_counts()?;
+        stored.merge(&run);
+        save_counts(&stored)
+    }
+}
+
+/// `$XDG_STATE_HOME/ghdl/telemetry.json`, or `~/.local/state/ghdl/telemetry.json`
+fn telemetry_path() -> Result<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| !p.as_os_str().is_empty())
+        .or_else(|| {
+            env::var_os("HOME")
+                .map(PathBuf::from)
+                .map(|home| home.join(".local").join("state"))
+        })
+        .map(|base| base.join("ghdl").join("telemetry.json"))
+        .ok_or_else(|| anyhow!("Unable to determine state directory (set XDG_STATE_HOME or HOME)"))
+}
+
+fn load_counts() -> Result<UsageCounts> {
+    let path = telemetry_path()?;
+    match fs::read(&path) {
+        Ok(contents) => Ok(serde_json::from_slice(&contents).unwrap_or_else(|err| {
+            debug!(
+                "Discarding unreadable telemetry file {}: {}",
+                path.display(),
+                err
+            );
+            UsageCounts::default()
+        })),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(UsageCounts::default()),
+        Err(err) => Err(anyhow!("failed to read {}: {}", path.display(), err)),
+    }
+}
+
+fn save_counts(counts: &UsageCounts) -> Result<()> {
+    let path = telemetry_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let contents = serde_json::to_vec_pretty(counts).context("failed to serialize telemetry")?;
+    fs::write(&path, contents).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// `ghdl stats --telemetry`: print the locally recorded counters, optionally upload
+/// them to the configured endpoint, or reset them
+pub async fn run_telemetry_stats(
+    client: &Client,
+    config: &Config,
+    upload: bool,
+    reset: bool,
+) -> Result<()> {
+    let path = telemetry_path()?;
+    if reset {
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(anyhow!("failed to remove {}: {}", path.display(), err));
+            }
+            _ => {}
+        }
+        eprintln!("{} Telemetry counters reset", style("✓").green().bold());
+        return Ok(());
+    }
+
+    let counts = load_counts()?;
+    print_counts(&counts, config.telemetry.unwrap_or(false));
+
+    if upload {
+        let endpoint = config.telemetry_endpoint.as_deref().ok_or_else(|| {
+            anyhow!("no telemetry_endpoint is configured; set one in the config file to upload")
+        })?;
+        upload_counts(client, endpoint, &counts).await?;
+        eprintln!(
+            "{} Uploaded aggregate counts to {}",
+            style("✓").green().bold(),
+            endpoint
+        );
+    }
+    Ok(())
+}
+
+async fn upload_counts(client: &Client, endpoint: &str, counts: &UsageCounts) -> Result<()> {
+    #[derive(Serialize)]
+    struct Payload<'a> {
+        version: &'a str,
+        #[serde(flatten)]
+        counts: &'a UsageCounts,
+    }
+
+    client
+        .post(endpoint)
+        .timeout(Duration::from_secs(10))
+        .json(&Payload {
+            version: PKG_VERSION,
+            counts,
+        })
+        .send()
+        .await
+        .with_context(|| format!("failed to upload telemetry to {}", endpoint))?
+        .error_for_status()
+        .with_context(|| format!("telemetry endpoint {} rejected the upload", endpoint))?;
+    Ok(())
+}
+
+fn print_counts(counts: &UsageCounts, enabled: bool) {
+    println!(
+        "{} ({})",
+        style("Telemetry").bold(),
+        if enabled {
+            "enabled"
+        } else {
+            "disabled; set `telemetry = true` in the config file to record"
+        }
+    );
+    if counts.runs == 0 {
+        println!("  nothing recorded yet");
+        return;
+    }
+
+    println!("  runs: {}", counts.runs);
+    for (title, map) in [
+        ("features", &counts.features),
+        ("strategies", &counts.strategies),
+        ("errors", &counts.errors),
+    ] {
+        if map.is_empty() {
+            continue;
+        }
+        println!("  {}:", title);
+        let mut rows: Vec<_> = map.iter().collect();
+        rows.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        for (key, count) in rows {
+            println!("    {:<28} {}", key, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::DownloadStrategy;
+
+    #[test]
+    fn test_counters_respect_opt_in() {
+        let url = "https://github.com/o/r/tree/main/docs";
+        let failure = Decision::new(url, DownloadStrategy::Git, Action::Failed, "not_found");
+
+        let disabled = Telemetry::new(&Config::default(), false);
+        disabled.feature("lfs");
+        disabled.decision(&failure);
+        assert_eq!(*disabled.counts.lock().unwrap(), UsageCounts::default());
+
+        let config = Config {
+            telemetry: Some(true),
+            ..Config::default()
+        };
+        assert!(!Telemetry::new(&config, true).enabled);
+
+        let telemetry = Telemetry::new(&config, false);
+        telemetry.feature("lfs");
+        telemetry.feature("lfs");
+        telemetry.decision(&failure);
+        telemetry.decision(&Decision::new(
+            url,
+            DownloadStrategy::Zip,
+            Action::Succeeded,
+            "fallback",
+        ));
+
+        let counts = telemetry.counts.lock().unwrap().clone();
+        assert_eq!(counts.features.get("lfs"), Some(&2));
+        assert_eq!(counts.strategies.get("git.failed"), Some(&1));
+        assert_eq!(counts.strategies.get("zip.succeeded"), Some(&1));
+        assert_eq!(counts.errors.get("not_found"), Some(&1));
+
+        let mut total = UsageCounts {
+            since: 5,
+            runs: 1,
+            ..UsageCounts::default()
+        };
+        total.merge(&UsageCounts {
+            runs: 1,
+            since: 9,
+            ..counts
+        });
+        assert_eq!(total.since, 5);
+        assert_eq!(total.runs, 2);
+        assert_eq!(total.features.get("lfs"), Some(&2));
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use console::style;
+
+use crate::progress::format_bytes;
+
+/// File count and total size of a directory, including everything below it
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DirSummary {
+    pub name: String,
+    pub files: u64,
+    pub bytes: u64,
+    pub children: Vec<DirSummary>,
+}
+
+/// Walk `dir` and total its files per directory. `.git` directories are ignored and
+/// symlinks are counted as files without being followed.
+pub fn summarize_dir(dir: &Path) -> Result<DirSummary> {
+    let mut summary = DirSummary {
+        name: dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| dir.display().to_string()),
+        ..DirSummary::default()
+    };
+
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read directory {}", dir.display()))?
+    {
+        let entry = entry
+            .with_context(|| format!("failed to read directory entry in {}", dir.display()))?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let metadata = fs::symlink_metadata(entry.path())
+            .with_context(|| format!("failed to inspect {}", entry.path().display()))?;
+
+        if metadata.is_dir() {
+            let child = summarize_dir(&entry.path())?;
+            summary.files += child.files;
+            summary.bytes += child.bytes;
+            summary.children.push(child);
+        } else {
+            summary.files += 1;
+            summary.bytes += metadata.len();
+        }
+    }
+
+    summary.children.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(summary)
+}
+
+/// Render directories down to `max_depth` levels below the root; deeper directories
+/// only contribute to their ancestors' totals.
+pub fn render_tree(summary: &DirSummary, max_depth: usize) -> Vec<String> {
+    let mut lines = vec![format!("{}/  ({})", summary.name, describe_totals(summary))];
+    render_children(summary, max_depth, "", &mut lines);
+    lines
+}
+
+fn render_children(summary: &DirSummary, depth_left: usize, prefix: &str, lines: &mut Vec<String>) {
+    if depth_left == 0 {
+        return;
+    }
+
+    let count = summary.children.len();
+    for (index, child) in summary.children.iter().enumerate() {
+        let last = index + 1 == count;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        lines.push(format!(
+            "{}{}{}/  ({})",
+            prefix,
+            branch,
+            child.name,
+            describe_totals(child)
+        ));
+        render_children(
+            child,
+            depth_left - 1,
+            &format!("{}{}", prefix, indent),
+            lines,
+        );
+    }
+}
+
+fn describe_totals(summary: &DirSummary) -> String {
+    format!(
+        "{} file{}, {}",
+        summary.files,
+        if summary.files == 1 { "" } else { "s" },
+        format_bytes(summary.bytes)
+    )
+}
+
+/// Print the directory tree of a finished download on stderr
+pub fn print_tree(dir: &Path, max_depth: usize) -> Result<()> {
+    let summary = summarize_dir(dir)?;
+    eprintln!("\n{} {}", style("Downloaded into").bold(), dir.display());
+    for line in render_tree(&summary, max_depth) {
+        eprintln!("  {}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_and_render_tree() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let root = dir.path().join("examples");
+        fs::create_dir_all(root.join("basic/nested")).unwrap();
+        fs::create_dir_all(root.join("advanced")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("README.md"), b"readme").unwrap();
+        fs::write(root.join("basic/main.rs"), b"fn main() {}").unwrap();
+        fs::write(root.join("basic/nested/lib.rs"), b"").unwrap();
+        fs::write(root.join(".git/HEAD"), b"ignored").unwrap();
+
+        let summary = summarize_dir(&root).unwrap();
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.bytes, 18);
+
+        assert_eq!(
+            render_tree(&summary, 2),
+            vec![
+                "examples/  (3 files, 18 B)",
+                "├── advanced/  (0 files, 0 B)",
+                "└── basic/  (2 files, 12 B)",
+                "    └── nested/  (1 file, 0 B)",
+            ]
+        );
+        // Deeper levels are folded into their parents
+        assert_eq!(render_tree(&summary, 1).len(), 3);
+        assert_eq!(render_tree(&summary, 0).len(), 1);
+    }
+}
@@ -1,4 +1,16 @@
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cli::{GitTransport, OnConflict, SymlinkPolicy, TagVerification};
+use crate::error::GdlError;
+use crate::filter::PathFilter;
+use crate::http::{CachePolicy, DEFAULT_CACHE_TTL_SECS, LowSpeedLimit, StreamLimits, TokenBucket};
+use crate::overwrite::OverwritePrompt;
+use crate::progress::ProgressSink;
+use crate::shard::Shard;
+use crate::telemetry::Telemetry;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestKind {
@@ -26,19 +38,177 @@ pub struct FileMetadata {
 pub struct DownloadOptions<'a> {
     pub token: Option<&'a str>,
     pub no_cache: bool,
+    /// How long cached API responses are reused
+    pub cache_ttl: Duration,
     pub force: bool,
+    /// How existing files are handled; `None` asks, unless `force` or `sync` is set
+    pub on_conflict: Option<OnConflict>,
+    /// Set file modification times to the date of their last commit
+    pub preserve_mtime: bool,
+    /// Treat an empty repository or directory as an error
+    pub fail_on_empty: bool,
+    /// How symlinks in the repository are materialized
+    pub symlinks: SymlinkPolicy,
+    /// Transport used when cloning with git
+    pub git_transport: GitTransport,
+    /// Commits of history fetched when cloning with git; 0 means all of them
+    pub git_depth: u32,
+    /// Partial-clone filter passed to `git clone --filter`; `None` clones every object
+    pub git_filter: Option<String>,
+    /// Clone into the output directory with its `.git` instead of copying files out
+    /// of a cached checkout
+    pub keep_git: bool,
+    /// Fetch Git LFS objects in place of pointer files
+    pub lfs: bool,
+    /// Only download the files assigned to this partition
+    pub shard: Option<Shard>,
+    /// Verify the requested tag's signature before downloading
+    pub verify_tag: Option<TagVerification>,
+    /// Export from this existing clone instead of contacting GitHub
+    pub local_repo: Option<&'a Path>,
+    /// Abort and retry a file when its stream delivers no data for this long
+    pub stall_timeout: Option<Duration>,
+    /// Abort and retry a file when its stream stays below this speed
+    pub low_speed_limit: Option<LowSpeedLimit>,
+    /// Extract the zip strategy's archive while it downloads instead of caching it
+    pub stream_archive: bool,
+    /// Check files written by the git and zip strategies against their git blob SHAs
+    pub verify: bool,
+    /// Leave existing files that match the remote blob alone and replace the rest
+    pub sync: bool,
+    /// Leave existing files that match the remote blob alone; the rest still go
+    /// through the overwrite check
+    pub update_only: bool,
+    /// With `sync`, remove local files the remote path no longer has
+    pub delete: bool,
+    /// Try every URL even when some fail, and fail at the end
+    pub keep_going: bool,
+    /// Include and exclude globs from a manifest source
+    pub filter: Option<PathFilter>,
+    /// Write `.gdl-source.json` into each downloaded directory
+    pub provenance: bool,
+    /// Print each strategy decision as a JSON line on stdout
+    pub explain: bool,
+    /// List the files and their targets instead of downloading them
+    pub dry_run: bool,
+    /// Serve everything from the caches and never contact GitHub
+    pub offline: bool,
+    /// Print the downloaded directory tree down to this depth
+    pub tree: Option<usize>,
+    /// Number of parallel range requests used for large files (1 disables)
+    pub chunks: usize,
+    /// Files at least this many bytes are downloaded in chunks
+    pub chunk_threshold: u64,
+    /// Opt-in usage counters, fed by strategy decisions
+    pub telemetry: Option<&'a Telemetry>,
+    /// Receives per-file progress; without one, files are logged at info level
+    pub progress: Option<Arc<dyn ProgressSink>>,
+    /// Leave out stage banners and notices on stderr
+    pub quiet: bool,
+    /// Rename files whose names Windows cannot store
+    pub sanitize_names: bool,
+}
+
+/// The command line's defaults
+impl Default for DownloadOptions<'_> {
+    fn default() -> Self {
+        Self {
+            token: None,
+            no_cache: false,
+            cache_ttl: Duration::from_secs(DEFAULT_CACHE_TTL_SECS),
+            force: false,
+            on_conflict: None,
+            preserve_mtime: false,
+            fail_on_empty: false,
+            symlinks: SymlinkPolicy::default(),
+            git_transport: GitTransport::default(),
+            git_depth: 1,
+            git_filter: Some("blob:none".to_string()),
+            keep_git: false,
+            lfs: false,
+            shard: None,
+            verify_tag: None,
+            local_repo: None,
+            stall_timeout: Some(Duration::from_secs(30)),
+            low_speed_limit: None,
+            stream_archive: false,
+            verify: true,
+            sync: false,
+            update_only: false,
+            delete: false,
+            keep_going: false,
+            filter: None,
+            provenance: false,
+            explain: false,
+            dry_run: false,
+            offline: false,
+            tree: None,
+            chunks: 4,
+            chunk_threshold: 64 * 1024 * 1024,
+            telemetry: None,
+            progress: None,
+            quiet: false,
+            sanitize_names: cfg!(windows),
+        }
+    }
+}
+
+impl DownloadOptions<'_> {
+    /// Response cache settings derived from `--no-cache` and `--cache-ttl`
+    pub fn cache_policy(&self) -> CachePolicy {
+        CachePolicy {
+            enabled: !self.no_cache,
+            ttl: self.cache_ttl,
+            offline: self.offline,
+        }
+    }
 }
 
-impl<'a> DownloadOptions<'a> {
-    pub fn new(token: Option<&'a str>, no_cache: bool, force: bool) -> Self {
+/// The requested path resolved successfully but contains no downloadable files
+#[derive(Debug, Clone)]
+pub struct EmptyDownload {
+    pub owner: String,
+    pub repo: String,
+    pub branch: String,
+    pub path: String,
+}
+
+impl EmptyDownload {
+    pub fn for_request(request: &RequestInfo) -> Self {
         Self {
-            token,
-            no_cache,
-            force,
+            owner: request.owner.clone(),
+            repo: request.repo.clone(),
+            branch: request.branch.clone(),
+            path: request.path.clone(),
+        }
+    }
+}
+
+impl fmt::Display for EmptyDownload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(
+                f,
+                "{}/{} has no files on {}",
+                self.owner, self.repo, self.branch
+            )
+        } else {
+            write!(
+                f,
+                "{} in {}/{}:{} contains no files",
+                self.path, self.owner, self.repo, self.branch
+            )
         }
     }
 }
 
+impl std::error::Error for EmptyDownload {}
+
+/// Check whether an error (or any context wrapping it) is an [`EmptyDownload`]
+pub fn is_empty_download(err: &GdlError) -> bool {
+    matches!(err.cause(), GdlError::EmptyDownload(_))
+}
+
 /// Infrastructure context for download operations
 /// Holds shared resources like HTTP client, rate limiter, and progress tracking
 #[derive(Clone)]
@@ -47,6 +217,16 @@ pub struct DownloadContext {
     pub rate_limit: std::sync::Arc<crate::rate_limit::RateLimitTracker>,
     pub multi: indicatif::MultiProgress,
     pub parallel: usize,
+    /// Concurrency for directory enumeration requests
+    pub listing_parallel: usize,
+    /// URLs downloaded at once
+    pub jobs: usize,
+    /// Where API, archive, and LFS requests go
+    pub endpoints: crate::github::Endpoints,
+    /// Caps the combined speed of the run's downloads (`--limit-rate`)
+    pub bandwidth: Option<Arc<TokenBucket>>,
+    /// Asks before overwriting files, one prompt at a time for the whole run
+    pub prompt: Arc<OverwritePrompt>,
 }
 
 impl DownloadContext {
@@ -55,12 +235,28 @@ impl DownloadContext {
         rate_limit: std::sync::Arc<crate::rate_limit::RateLimitTracker>,
         multi: indicatif::MultiProgress,
         parallel: usize,
+        listing_parallel: usize,
+        jobs: usize,
     ) -> Self {
         Self {
             client,
             rate_limit,
             multi,
             parallel,
+            listing_parallel,
+            jobs,
+            endpoints: crate::github::Endpoints::default(),
+            bandwidth: None,
+            prompt: Arc::default(),
+        }
+    }
+
+    /// Stream checks from `options` with this run's bandwidth cap
+    pub fn stream_limits(&self, options: &DownloadOptions<'_>) -> StreamLimits {
+        StreamLimits {
+            stall_timeout: options.stall_timeout,
+            low_speed: options.low_speed_limit,
+            bandwidth: self.bandwidth.clone(),
         }
     }
 }
@@ -70,6 +266,10 @@ pub struct DownloadTask {
     pub item: crate::github::types::GitHubContent,
     pub target_path: PathBuf,
     pub size: Option<u64>,
+    /// When set, create a symlink pointing here instead of downloading `item`
+    pub link_target: Option<PathBuf>,
+    /// When set, write this text instead of downloading `item` (submodule placeholders)
+    pub placeholder: Option<String>,
 }
 
 #[derive(Debug)]
@@ -78,6 +278,8 @@ pub struct FileCopyTask {
     pub source_path: PathBuf,
     pub target_path: PathBuf,
     pub size: Option<u64>,
+    /// When set, create a symlink pointing here instead of copying `source_path`
+    pub link_target: Option<PathBuf>,
 }
 
 impl crate::overwrite::TargetPath for DownloadTask {
@@ -88,6 +290,10 @@ impl crate::overwrite::TargetPath for DownloadTask {
     fn size(&self) -> u64 {
         self.size.unwrap_or(0)
     }
+
+    fn set_path(&mut self, path: PathBuf) {
+        self.target_path = path;
+    }
 }
 
 impl crate::overwrite::TargetPath for FileCopyTask {
@@ -98,6 +304,10 @@ impl crate::overwrite::TargetPath for FileCopyTask {
     fn size(&self) -> u64 {
         self.size.unwrap_or(0)
     }
+
+    fn set_path(&mut self, path: PathBuf) {
+        self.target_path = path;
+    }
 }
 
 #[cfg(test)]
@@ -129,6 +339,8 @@ mod tests {
             },
             target_path: PathBuf::from("output/test.txt"),
             size: Some(100),
+            link_target: None,
+            placeholder: None,
         };
 
         assert_eq!(task.path(), Path::new("output/test.txt"));
@@ -151,6 +363,8 @@ mod tests {
             },
             target_path: PathBuf::from("output/test.txt"),
             size: None,
+            link_target: None,
+            placeholder: None,
         };
 
         assert_eq!(task.size(), 0);
@@ -163,6 +377,7 @@ mod tests {
             source_path: PathBuf::from("temp/file.txt"),
             target_path: PathBuf::from("dest/file.txt"),
             size: Some(200),
+            link_target: None,
         };
 
         assert_eq!(task.path(), Path::new("dest/file.txt"));
@@ -176,6 +391,7 @@ mod tests {
             source_path: PathBuf::from("temp/file.txt"),
             target_path: PathBuf::from("dest/file.txt"),
             size: None,
+            link_target: None,
         };
 
         assert_eq!(task.size(), 0);
@@ -201,6 +417,27 @@ mod tests {
         assert_eq!(cloned.kind, RequestKind::Blob);
     }
 
+    #[test]
+    fn test_empty_download_display_and_detection() {
+        let mut request = RequestInfo {
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            branch: "main".to_string(),
+            path: String::new(),
+            has_trailing_slash: false,
+            kind: RequestKind::Tree,
+        };
+        let whole_repo = EmptyDownload::for_request(&request);
+        assert_eq!(whole_repo.to_string(), "owner/repo has no files on main");
+
+        request.path = "docs".to_string();
+        let err = GdlError::from(EmptyDownload::for_request(&request)).context("wrapped");
+        assert!(is_empty_download(&err));
+        assert!(!is_empty_download(
+            &anyhow::anyhow!("docs not found").into()
+        ));
+    }
+
     #[test]
     fn test_file_metadata_clone() {
         let metadata = FileMetadata { size: Some(1024) };
@@ -0,0 +1,90 @@
+use self_update::update::ReleaseAsset;
+
+/// Checksum files looked for among a release's assets, besides `<asset>.sha256`
+const CHECKSUM_FILES: &[&str] = &["SHA256SUMS", "sha256sums.txt", "checksums.txt"];
+
+/// The release asset holding the SHA-256 of `asset_name`: a per-asset `.sha256` file
+/// first, then a combined checksum file
+pub fn find_checksum_asset<'a>(
+    assets: &'a [ReleaseAsset],
+    asset_name: &str,
+) -> Option<&'a ReleaseAsset> {
+    let own = format!("{}.sha256", asset_name);
+    assets
+        .iter()
+        .find(|asset| asset.name.eq_ignore_ascii_case(&own))
+        .or_else(|| {
+            assets.iter().find(|asset| {
+                CHECKSUM_FILES
+                    .iter()
+                    .any(|name| asset.name.eq_ignore_ascii_case(name))
+            })
+        })
+}
+
+/// Look up `asset_name` in `sha256sum` output (`<hex>  <name>`, `*` marking binary
+/// mode). A file holding a single digest without a name applies to the asset itself.
+pub fn expected_sha256(contents: &str, asset_name: &str) -> Option<String> {
+    let mut lone_digest = None;
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(digest) = fields.next().filter(|digest| is_sha256(digest)) else {
+            continue;
+        };
+        match fields.next() {
+            Some(name) => {
+                let name = name.trim_start_matches('*');
+                let file_name = name.rsplit('/').next().unwrap_or(name);
+                if file_name == asset_name {
+                    return Some(digest.to_ascii_lowercase());
+                }
+            }
+            None => lone_digest = Some(digest.to_ascii_lowercase()),
+        }
+    }
+    lone_digest
+}
+
+fn is_sha256(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_lookup() {
+        let asset = |name: &str| ReleaseAsset {
+            download_url: format!("https://example.com/{}", name),
+            name: name.to_string(),
+        };
+        let archive = "ghdl-x86_64-unknown-linux-gnu.tar.gz";
+        let assets = vec![asset(archive), asset("checksums.txt")];
+        assert_eq!(
+            find_checksum_asset(&assets, archive).unwrap().name,
+            "checksums.txt"
+        );
+        let assets = vec![asset("SHA256SUMS"), asset(&format!("{}.sha256", archive))];
+        assert_eq!(
+            find_checksum_asset(&assets, archive).unwrap().name,
+            format!("{}.sha256", archive)
+        );
+        assert!(find_checksum_asset(&[asset(archive)], archive).is_none());
+
+        let digest = "a".repeat(64);
+        let other = "b".repeat(64);
+        let sums = format!(
+            "{}  ghdl-aarch64-apple-darwin.tar.gz\n{} *dist/{}\n",
+            other,
+            digest.to_uppercase(),
+            archive
+        );
+        assert_eq!(expected_sha256(&sums, archive), Some(digest.clone()));
+        assert_eq!(expected_sha256(&sums, "ghdl.zip"), None);
+        assert_eq!(
+            expected_sha256(&format!("{}\n", digest), archive),
+            Some(digest)
+        );
+    }
+}
@@ -1,15 +1,20 @@
 use std::env;
+use std::fs::File;
+use std::io::Write;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime};
 
 use anyhow::{Context, Result, anyhow};
-use log::{debug, info};
+use log::{debug, info, warn};
+use reqwest::header::{ACCEPT, HeaderValue};
 use self_update::backends::github;
-use self_update::update::ReleaseUpdate;
-use self_update::version;
+use self_update::update::{Release, ReleaseAsset, ReleaseUpdate};
+use self_update::{Download, Extract, self_replace, version};
 
-use super::prompt::prompt_for_update;
+use super::checksum::{expected_sha256, find_checksum_asset};
+use super::prompt::{prompt_for_update, render_release_notes};
 use super::state::{UpdateDecision, load_update_state, save_update_state, update_state_path};
+use crate::cache::file_sha256;
 use crate::utils::{system_time_from_secs, system_time_to_secs};
 
 const GITHUB_OWNER: &str = "CaddyGlow";
@@ -25,19 +30,101 @@ pub fn run_self_update(token: Option<&str>) -> Result<()> {
     }
 
     let updater = build_updater(token)?;
-    let status = updater
-        .update()
+    let latest = updater
+        .get_latest_release()
+        .context("failed to fetch latest ghdl release information")?;
+    let current_version = updater.current_version();
+    if !version::bump_is_greater(&current_version, &latest.version)
+        .context("failed to compare semantic versions")?
+    {
+        info!("ghdl is already up to date (current: {})", current_version);
+        return Ok(());
+    }
+
+    install_release(updater.as_ref(), &latest)
         .context("failed to download and install the latest ghdl release")?;
+    info!("Updated ghdl to version {}", latest.version);
+    Ok(())
+}
 
-    if status.updated() {
-        info!("Updated ghdl to version {}", status.version());
-    } else {
-        info!("ghdl is already up to date (current: {})", status.version());
+/// Download the release's archive for this platform, check it against the release's
+/// published SHA-256, then replace the running executable with the binary inside.
+/// A release without a checksum is refused unless GHDL_ALLOW_UNVERIFIED_UPDATE is set.
+fn install_release(updater: &dyn ReleaseUpdate, release: &Release) -> Result<()> {
+    let target = updater.target();
+    let asset = release
+        .asset_for(&target, updater.identifier().as_deref())
+        .ok_or_else(|| anyhow!("release {} has no asset for {}", release.version, target))?;
+
+    let tmp_dir = tempfile::tempdir().context("failed to create a temporary directory")?;
+    let archive_path = tmp_dir.path().join(&asset.name);
+    let mut archive = File::create(&archive_path)
+        .with_context(|| format!("failed to create {}", archive_path.display()))?;
+    download_asset(updater, &asset, true, &mut archive)?;
+    drop(archive);
+
+    match find_checksum_asset(&release.assets, &asset.name) {
+        Some(checksum_asset) => {
+            let mut contents = Vec::new();
+            download_asset(updater, checksum_asset, false, &mut contents)?;
+            let expected = expected_sha256(&String::from_utf8_lossy(&contents), &asset.name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "{} lists no checksum for {}",
+                        checksum_asset.name,
+                        asset.name
+                    )
+                })?;
+            let actual = file_sha256(&archive_path)?;
+            if actual != expected {
+                return Err(anyhow!(
+                    "checksum mismatch for {}: expected {}, got {}; refusing to install it",
+                    asset.name,
+                    expected,
+                    actual
+                ));
+            }
+            info!("Verified {} against {}", asset.name, checksum_asset.name);
+        }
+        None if allow_unverified_update() => warn!(
+            "Release {} publishes no checksums; installing {} unverified because \
+             GHDL_ALLOW_UNVERIFIED_UPDATE is set",
+            release.version, asset.name
+        ),
+        None => {
+            return Err(anyhow!(
+                "release {} publishes no checksum for {}; refusing to install it \
+                 (set GHDL_ALLOW_UNVERIFIED_UPDATE=1 to install it anyway)",
+                release.version,
+                asset.name
+            ));
+        }
     }
 
+    let bin_path = updater.bin_path_in_archive();
+    Extract::from_source(&archive_path)
+        .extract_file(tmp_dir.path(), &bin_path)
+        .with_context(|| format!("failed to extract {} from {}", bin_path, asset.name))?;
+    self_replace::self_replace(tmp_dir.path().join(&bin_path))
+        .context("failed to replace the ghdl executable")?;
     Ok(())
 }
 
+fn download_asset(
+    updater: &dyn ReleaseUpdate,
+    asset: &ReleaseAsset,
+    show_progress: bool,
+    dest: impl Write,
+) -> Result<()> {
+    let mut headers = updater.api_headers(&updater.auth_token())?;
+    headers.insert(ACCEPT, HeaderValue::from_static("application/octet-stream"));
+    Download::from_url(&asset.download_url)
+        .set_headers(headers)
+        .show_progress(show_progress)
+        .download_to(dest)
+        .with_context(|| format!("failed to download {}", asset.name))
+}
+
 pub fn check_for_update(token: Option<&str>) -> Result<()> {
     if skip_self_update() {
         info!("Skipping update check because GHDL_SKIP_SELF_UPDATE is set");
@@ -81,10 +168,10 @@ fn build_updater(token: Option<&str>) -> Result<Box<dyn ReleaseUpdate>> {
         .no_confirm(true)
         .current_version(crate::cli::PKG_VERSION);
 
-    if let Some(token) = token {
-        if !token.trim().is_empty() {
-            builder.auth_token(token.trim());
-        }
+    if let Some(token) = token
+        && !token.trim().is_empty()
+    {
+        builder.auth_token(token.trim());
     }
 
     builder
@@ -104,6 +191,10 @@ fn skip_self_update() -> bool {
     env::var("GHDL_SKIP_SELF_UPDATE").is_ok()
 }
 
+fn allow_unverified_update() -> bool {
+    env::var("GHDL_ALLOW_UNVERIFIED_UPDATE").is_ok()
+}
+
 pub async fn auto_check_for_updates(token: Option<&str>) -> Result<()> {
     // Run the blocking self_update operations in a background thread
     let token_owned = token.map(|s| s.to_string());
@@ -179,6 +270,10 @@ pub async fn auto_check_for_updates(token: Option<&str>) -> Result<()> {
             "A newer ghdl release is available: {} (current: {}).",
             latest.version, current_version
         );
+        let releases_url = format!("https://github.com/{}/{}/releases", GITHUB_OWNER, GITHUB_REPO);
+        if let Some(notes) = render_release_notes(&latest, &releases_url) {
+            println!("\n{}", notes);
+        }
 
         let decision = prompt_for_update()?;
 
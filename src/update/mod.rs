@@ -1,3 +1,4 @@
+pub mod checksum;
 pub mod manager;
 pub mod prompt;
 pub mod state;
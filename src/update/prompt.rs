@@ -2,9 +2,60 @@ use std::io::{self, Write};
 
 use anyhow::{Context, Result};
 use log::info;
+use self_update::update::Release;
 
 use super::state::UpdateDecision;
 
+/// Lines of release notes shown before the rest is left to the releases page
+const MAX_NOTES_LINES: usize = 20;
+
+/// The release's notes as plain text for the update prompt: Markdown headings and
+/// bullets are simplified, and long notes are cut with a pointer to `releases_url`
+pub fn render_release_notes(release: &Release, releases_url: &str) -> Option<String> {
+    let body = release.body.as_deref()?.trim();
+    if body.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<String> = body
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim_start().starts_with("<!--"))
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if let Some(heading) = trimmed.strip_prefix('#') {
+                heading.trim_start_matches('#').trim().to_string()
+            } else if let Some(item) = trimmed
+                .strip_prefix("- ")
+                .or_else(|| trimmed.strip_prefix("* "))
+            {
+                let indent = line.len() - trimmed.len();
+                format!("{}• {}", " ".repeat(indent), item)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+
+    let date = release.date.split('T').next().unwrap_or(&release.date);
+    let mut notes = format!("What's new in {} ({}):\n", release.name, date);
+    for line in lines.iter().take(MAX_NOTES_LINES) {
+        if !line.is_empty() {
+            notes.push_str("  ");
+        }
+        notes.push_str(line);
+        notes.push('\n');
+    }
+    if lines.len() > MAX_NOTES_LINES {
+        notes.push_str(&format!(
+            "  ... {} more line(s) at {}\n",
+            lines.len() - MAX_NOTES_LINES,
+            releases_url
+        ));
+    }
+    Some(notes)
+}
+
 pub fn prompt_for_update() -> Result<UpdateDecision> {
     loop {
         print!("Would you like to update now? [yes/postpone/discard]: ");
@@ -29,3 +80,35 @@ pub fn prompt_for_update() -> Result<UpdateDecision> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_release_notes() {
+        let mut release = Release {
+            name: "v1.2.0".to_string(),
+            version: "1.2.0".to_string(),
+            date: "2026-09-30T10:00:00Z".to_string(),
+            body: Some(
+                "<!-- generated -->\n## Features\n- Add `ghdl info`\n  * nested\n\nThanks!\n"
+                    .to_string(),
+            ),
+            assets: Vec::new(),
+        };
+        assert_eq!(
+            render_release_notes(&release, "https://example.com/releases").unwrap(),
+            "What's new in v1.2.0 (2026-09-30):\n  Features\n  • Add `ghdl info`\n    • nested\n\n  Thanks!\n"
+        );
+
+        release.body = Some((1..=25).map(|n| format!("line {}\n", n)).collect());
+        let notes = render_release_notes(&release, "https://example.com/releases").unwrap();
+        assert!(notes.contains("  line 20\n"));
+        assert!(!notes.contains("line 21"));
+        assert!(notes.ends_with("  ... 5 more line(s) at https://example.com/releases\n"));
+
+        release.body = Some("  \n".to_string());
+        assert_eq!(render_release_notes(&release, ""), None);
+    }
+}
@@ -1,6 +1,13 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use indicatif::MultiProgress;
+use anyhow::{Context, Result};
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use log::LevelFilter;
+
+use crate::cli::LogFormat;
 
 /// Initialize logging with the specified verbosity level.
 ///
@@ -9,8 +16,15 @@ use indicatif::MultiProgress;
 /// - 2: debug
 /// - 3+: trace
 ///
-/// Returns a MultiProgress instance for coordinating progress bars with logging
-pub fn init_logging(verbosity: u8) -> MultiProgress {
+/// Returns a MultiProgress instance for coordinating progress bars with logging;
+/// with `show_progress` off, bars added to it are never drawn. With `log_file`,
+/// every record down to trace level (debug for other crates) is also written there.
+pub fn init_logging(
+    verbosity: u8,
+    format: LogFormat,
+    show_progress: bool,
+    log_file: Option<&Path>,
+) -> Result<MultiProgress> {
     let default_level = match verbosity {
         0 => "warn",
         1 => "info",
@@ -19,18 +33,97 @@ pub fn init_logging(verbosity: u8) -> MultiProgress {
     };
 
     let multi = MultiProgress::new();
+    if !show_progress {
+        multi.set_draw_target(ProgressDrawTarget::hidden());
+    }
 
     let env = env_logger::Env::default().default_filter_or(default_level);
-    let logger = env_logger::Builder::from_env(env)
-        .format_timestamp_secs()
-        .build();
+    let mut builder = env_logger::Builder::from_env(env);
+    builder.format_timestamp_secs();
+    set_log_format(&mut builder, format);
+    let logger = builder.build();
+    let mut max_level = logger.filter();
+
+    let file = match log_file {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .with_context(|| format!("failed to create log file {}", path.display()))?;
+            let mut builder = env_logger::Builder::new();
+            builder
+                .parse_filters("debug,ghdl=trace")
+                .format_timestamp_millis()
+                .write_style(env_logger::WriteStyle::Never)
+                .target(env_logger::Target::Pipe(Box::new(file)));
+            set_log_format(&mut builder, format);
+            max_level = LevelFilter::Trace;
+            Some(builder.build())
+        }
+        None => None,
+    };
 
     // Set up the log bridge so logs don't interfere with progress bars
-    indicatif_log_bridge::LogWrapper::new(multi.clone(), logger)
-        .try_init()
-        .ok();
+    let terminal = indicatif_log_bridge::LogWrapper::new(multi.clone(), logger);
+    if log::set_boxed_logger(Box::new(TeeLogger { terminal, file })).is_ok() {
+        log::set_max_level(max_level);
+    }
+
+    Ok(multi)
+}
+
+fn set_log_format(builder: &mut env_logger::Builder, format: LogFormat) {
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json_log_line(
+                &buf.timestamp_seconds().to_string(),
+                record.level(),
+                record.target(),
+                &record.args().to_string(),
+            );
+            writeln!(buf, "{}", line)
+        });
+    }
+}
 
-    multi
+/// Passes records to the terminal logger and to the `--log-file` one, each of which
+/// applies its own filter
+struct TeeLogger {
+    terminal: indicatif_log_bridge::LogWrapper<env_logger::Logger>,
+    file: Option<env_logger::Logger>,
+}
+
+impl log::Log for TeeLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.terminal.enabled(metadata)
+            || self
+                .file
+                .as_ref()
+                .is_some_and(|file| file.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.terminal.log(record);
+        if let Some(file) = &self.file {
+            file.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+        if let Some(file) = &self.file {
+            file.flush();
+        }
+    }
+}
+
+/// A log record as one line of JSON, for `--log-format json`
+fn json_log_line(timestamp: &str, level: log::Level, target: &str, message: &str) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": level.as_str().to_lowercase(),
+        "target": target,
+        "message": message,
+    })
+    .to_string()
 }
 
 pub fn system_time_to_secs(time: SystemTime) -> u64 {
@@ -43,6 +136,225 @@ pub fn system_time_from_secs(secs: u64) -> SystemTime {
     UNIX_EPOCH + Duration::from_secs(secs)
 }
 
+/// Convert a UTC calendar date and time into seconds since the Unix epoch.
+///
+/// Returns `None` for dates before 1970 or out-of-range components.
+pub fn civil_to_unix_secs(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    if second > 60 {
+        return None;
+    }
+
+    // Days from civil algorithm (Howard Hinnant)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86_400 + hour as i64 * 3_600 + minute as i64 * 60 + second as i64;
+    u64::try_from(secs).ok()
+}
+
+/// Format seconds since the Unix epoch as a UTC timestamp (e.g. `2024-01-31T12:00:00Z`).
+pub fn format_utc_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil from days algorithm (Howard Hinnant)
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Parse a UTC timestamp as returned by the GitHub API (e.g. `2024-01-31T12:00:00Z`).
+pub fn parse_github_timestamp(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix('Z')
+        .or_else(|| value.strip_suffix("+00:00"))?;
+    let (date, time) = value.split_once('T')?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year = date_parts.next()?.parse::<i64>().ok()?;
+    let month = date_parts.next()?.parse::<u32>().ok()?;
+    let day = date_parts.next()?.parse::<u32>().ok()?;
+
+    // Ignore fractional seconds if present
+    let time = time.split('.').next()?;
+    let mut time_parts = time.splitn(3, ':');
+    let hour = time_parts.next()?.parse::<u32>().ok()?;
+    let minute = time_parts.next()?.parse::<u32>().ok()?;
+    let second = time_parts.next()?.parse::<u32>().ok()?;
+
+    civil_to_unix_secs(year, month, day, hour, minute, second)
+}
+
+/// Read newline-separated URLs from `path`, or from standard input when it is `-`.
+pub fn read_url_list(path: &Path) -> Result<Vec<String>> {
+    let contents = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).context("failed to read URLs from stdin")?
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("failed to read URL list {}", path.display()))?
+    };
+    Ok(parse_url_list(&contents))
+}
+
+/// The URLs of a list, skipping blank lines and `#` comments.
+fn parse_url_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Set the modification time of an existing file.
+pub fn set_file_mtime(path: &Path, secs: u64) -> Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("failed to open {} to set its mtime", path.display()))?;
+    file.set_modified(system_time_from_secs(secs))
+        .with_context(|| format!("failed to set mtime on {}", path.display()))
+}
+
+/// Remove the symlinks at `path` and at its ancestors below `root`, so writing `path`
+/// cannot follow a link an earlier run created out of `root`. Paths outside `root`
+/// are left alone.
+pub fn remove_symlinks_below(root: &Path, path: &Path) -> Result<()> {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return Ok(());
+    };
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                // Windows links to directories are removed as directories
+                fs::remove_file(&current)
+                    .or_else(|_| fs::remove_dir(&current))
+                    .with_context(|| format!("failed to remove symlink {}", current.display()))?;
+                break;
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Create a symlink at `link` pointing to `target`, replacing any existing file.
+pub fn create_symlink(target: &Path, link: &Path) -> Result<()> {
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_file(link)
+            .with_context(|| format!("failed to replace existing file {}", link.display()))?;
+    }
+
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(target, link);
+
+    #[cfg(windows)]
+    let result = {
+        let resolved = link
+            .parent()
+            .map(|parent| parent.join(target))
+            .unwrap_or_else(|| target.to_path_buf());
+        if resolved.is_dir() {
+            std::os::windows::fs::symlink_dir(target, link)
+        } else {
+            std::os::windows::fs::symlink_file(target, link)
+        }
+    };
+
+    #[cfg(not(any(unix, windows)))]
+    let result: std::io::Result<()> = Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symlinks are not supported on this platform",
+    ));
+
+    result.with_context(|| {
+        format!(
+            "failed to create symlink {} -> {}",
+            link.display(),
+            target.display()
+        )
+    })
+}
+
+/// Resolve a repository-relative symlink target against the link's own location.
+///
+/// Returns `None` when the target is absolute or escapes the repository root.
+pub fn resolve_link_target(link_path: &str, target: &str) -> Option<String> {
+    if target.starts_with('/') || target.starts_with('\\') {
+        return None;
+    }
+
+    let mut parts: Vec<&str> = link_path.split('/').filter(|s| !s.is_empty()).collect();
+    parts.pop();
+
+    for component in target.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            other => parts.push(other),
+        }
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("/"))
+    }
+}
+
+/// Resolve the target of a symlink that will be created in the output, requiring it
+/// to stay below `output_root`, the repository path the output directory mirrors.
+///
+/// A link that leaves `output_root` would point outside the output directory even
+/// when it stays in the repository, e.g. `docs/x -> ../src` when downloading `docs/`.
+pub fn resolve_created_link(link_path: &str, target: &str, output_root: &Path) -> Option<String> {
+    let resolved = resolve_link_target(link_path, target)?;
+    let root = output_root.to_string_lossy().replace('\\', "/");
+    let root = root.trim_matches('/');
+    let inside = root.is_empty()
+        || resolved
+            .strip_prefix(root)
+            .is_some_and(|rest| rest.starts_with('/'));
+    inside.then_some(resolved)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,4 +388,141 @@ mod tests {
         let recovered_secs = system_time_to_secs(time);
         assert_eq!(recovered_secs, original_secs);
     }
+
+    #[test]
+    fn test_parse_github_timestamp() {
+        assert_eq!(parse_github_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_github_timestamp("2021-01-01T00:00:00Z"),
+            Some(1609459200)
+        );
+        assert_eq!(
+            parse_github_timestamp("2024-02-29T12:34:56+00:00"),
+            Some(1709210096)
+        );
+        assert_eq!(
+            parse_github_timestamp("2024-02-29T12:34:56.789Z"),
+            Some(1709210096)
+        );
+
+        // Invalid or unsupported inputs
+        assert_eq!(parse_github_timestamp("2024-02-29 12:34:56"), None);
+        assert_eq!(parse_github_timestamp("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_github_timestamp("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_github_timestamp(""), None);
+    }
+
+    #[test]
+    fn test_parse_url_list() {
+        let contents = "# docs\nhttps://github.com/o/r/tree/main/docs\n\n  https://github.com/o/r/blob/main/README.md  \r\n";
+        assert_eq!(
+            parse_url_list(contents),
+            vec![
+                "https://github.com/o/r/tree/main/docs",
+                "https://github.com/o/r/blob/main/README.md"
+            ]
+        );
+        assert!(parse_url_list("\n# nothing\n").is_empty());
+    }
+
+    #[test]
+    fn test_format_utc_timestamp() {
+        assert_eq!(format_utc_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc_timestamp(1709210096), "2024-02-29T12:34:56Z");
+        for secs in [951_782_400, 1_609_459_199, 4_102_444_800] {
+            assert_eq!(
+                parse_github_timestamp(&format_utc_timestamp(secs)),
+                Some(secs)
+            );
+        }
+    }
+
+    #[test]
+    fn test_json_log_line() {
+        let line = json_log_line(
+            "2024-05-01T12:00:00Z",
+            log::Level::Warn,
+            "ghdl::rate_limit",
+            "Only 10 \"core\" requests left",
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "timestamp": "2024-05-01T12:00:00Z",
+                "level": "warn",
+                "target": "ghdl::rate_limit",
+                "message": "Only 10 \"core\" requests left",
+            })
+        );
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn test_resolve_link_target() {
+        assert_eq!(
+            resolve_link_target("docs/latest", "v2/index.md"),
+            Some("docs/v2/index.md".to_string())
+        );
+        assert_eq!(
+            resolve_link_target("docs/guide/link.md", "../README.md"),
+            Some("docs/README.md".to_string())
+        );
+        assert_eq!(
+            resolve_link_target("link", "./target.txt"),
+            Some("target.txt".to_string())
+        );
+
+        // Absolute targets and targets escaping the repository are rejected
+        assert_eq!(resolve_link_target("link", "/etc/passwd"), None);
+        assert_eq!(resolve_link_target("docs/link", "../../outside"), None);
+        assert_eq!(resolve_link_target("docs/link", ".."), None);
+    }
+
+    #[test]
+    fn test_resolve_created_link() {
+        let root = Path::new("docs");
+        assert_eq!(
+            resolve_created_link("docs/latest", "v2/index.md", root),
+            Some("docs/v2/index.md".to_string())
+        );
+        assert_eq!(
+            resolve_created_link("docs/guide/link.md", "../README.md", root),
+            Some("docs/README.md".to_string())
+        );
+        assert_eq!(
+            resolve_created_link("src/link", "../README.md", Path::new("")),
+            Some("README.md".to_string())
+        );
+
+        // Targets that stay in the repository but leave the output root are rejected
+        assert_eq!(resolve_created_link("docs/x", "../src", root), None);
+        assert_eq!(resolve_created_link("docs/x", "../docs-old/a", root), None);
+        assert_eq!(resolve_created_link("docs/x", ".", root), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_symlinks_below() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("out");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(root.join("real")).unwrap();
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("victim"), "keep").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("linked")).unwrap();
+        std::os::unix::fs::symlink(outside.join("victim"), root.join("real/file")).unwrap();
+
+        // A linked parent directory and a linked file are both unlinked
+        remove_symlinks_below(&root, &root.join("linked/victim")).unwrap();
+        assert!(fs::symlink_metadata(root.join("linked")).is_err());
+        remove_symlinks_below(&root, &root.join("real/file")).unwrap();
+        assert!(fs::symlink_metadata(root.join("real/file")).is_err());
+        assert!(root.join("real").is_dir());
+        assert_eq!(fs::read_to_string(outside.join("victim")).unwrap(), "keep");
+
+        // Nothing outside the root is touched
+        remove_symlinks_below(&root, &outside.join("victim")).unwrap();
+        assert!(outside.join("victim").exists());
+    }
 }
@@ -1,133 +1,458 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::anyhow;
 use console::style;
-use futures::StreamExt;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use log::{debug, info, warn};
 use reqwest::Client;
 use sha2::{Digest, Sha256};
 
-use crate::cache::repos_cache_dir;
+use super::ranged::fetch_sparse_zip;
+use super::tarball::{download_via_tarball, matches_request};
+use crate::cache::{record_repo_access, repos_cache_dir, verify_cached_file, write_checksum};
+use crate::cli::{OnConflict, SymlinkPolicy};
+use crate::download::calculate_git_blob_sha1;
+use crate::download::mtime::apply_commit_times;
+use crate::download::sync::{mirror_output, skip_unchanged};
+use crate::download::verify::verify_against_tree;
+use crate::error::{Context, GdlError, Result};
+use crate::filter::{PathFilter, select_matching};
+use crate::git::sparse::GitTarget;
+use crate::github::archive_url;
 use crate::github::types::{ContentType, GitHubContent};
-use crate::paths::{compute_base_and_default_output, ensure_directory, format_path_for_log};
-use crate::progress::{DownloadProgress, format_bytes};
+use crate::http::{
+    CachePolicy, StreamLimits, StreamWatch, TransientStatus, describe_delay, is_transient,
+    next_chunk,
+};
+use crate::lfs::fetch_lfs_objects;
+use crate::overwrite::OverwritePrompt;
+use crate::paths::{
+    compute_base_and_default_output, disambiguate_targets, ensure_directory, format_path_for_log,
+    output_root,
+};
+use crate::progress::{DownloadProgress, PROGRESS_STEP, ProgressSink, format_bytes, status};
 use crate::rate_limit::RateLimitTracker;
-use crate::types::{DownloadOptions, FileCopyTask, RequestInfo, RequestKind};
+use crate::shard::{Shard, report_empty_shard, select_shard};
+use crate::types::{
+    DownloadContext, DownloadOptions, EmptyDownload, FileCopyTask, RequestInfo, RequestKind,
+};
+use crate::utils::{
+    civil_to_unix_secs, create_symlink, remove_symlinks_below, resolve_created_link,
+    resolve_link_target, set_file_mtime,
+};
 
 /// Parameters for zip download implementation (owned values for async execution)
-struct ZipDownloadParams {
-    client: Client,
-    request: RequestInfo,
-    url: String,
-    output: Option<PathBuf>,
-    token: Option<String>,
-    rate_limit: std::sync::Arc<RateLimitTracker>,
-    no_cache: bool,
-    force: bool,
-    multi: MultiProgress,
+pub(super) struct ZipDownloadParams {
+    pub(super) client: Client,
+    pub(super) request: RequestInfo,
+    pub(super) url: String,
+    pub(super) output: Option<PathBuf>,
+    pub(super) token: Option<String>,
+    pub(super) rate_limit: std::sync::Arc<RateLimitTracker>,
+    pub(super) no_cache: bool,
+    pub(super) offline: bool,
+    pub(super) force: bool,
+    pub(super) on_conflict: Option<OnConflict>,
+    pub(super) preserve_mtime: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) shard: Option<Shard>,
+    pub(super) filter: Option<PathFilter>,
+    pub(super) lfs: bool,
+    pub(super) limits: StreamLimits,
+    pub(super) stream_archive: bool,
+    /// Compare extracted files with the blob SHAs of the git tree
+    pub(super) verify: bool,
+    /// Skip files whose target already matches the archive
+    pub(super) sync: bool,
+    /// Skip them too, but ask before replacing the others
+    pub(super) update_only: bool,
+    /// Remove files in the output directory that the archive does not have
+    pub(super) delete: bool,
+    pub(super) cache: CachePolicy,
+    pub(super) progress: Option<Arc<dyn ProgressSink>>,
+    /// Leave out stage banners and notices
+    pub(super) quiet: bool,
+    /// Rename files whose names Windows cannot store
+    pub(super) sanitize_names: bool,
+    pub(super) prompt: Arc<OverwritePrompt>,
+    pub(super) multi: MultiProgress,
 }
 
 pub async fn download_via_zip(
-    client: &Client,
+    ctx: &DownloadContext,
     request: &RequestInfo,
     url: &str,
     output: Option<&PathBuf>,
-    rate_limit: std::sync::Arc<RateLimitTracker>,
     options: &DownloadOptions<'_>,
-    multi: &MultiProgress,
 ) -> Result<()> {
-    let params = ZipDownloadParams {
-        client: client.clone(),
+    let params = zip_params(ctx, request, url, output, options);
+    download_via_zip_impl(params).await
+}
+
+/// Download several paths of the same repository and ref from one zip archive.
+///
+/// Fails as a whole only when the archive cannot be fetched; otherwise returns one
+/// result per target, in order.
+pub async fn download_many_via_zip(
+    ctx: &DownloadContext,
+    targets: Vec<GitTarget>,
+    output: Option<&PathBuf>,
+    options: &DownloadOptions<'_>,
+) -> Result<Vec<Result<()>>> {
+    let Some(first) = targets.first() else {
+        return Ok(Vec::new());
+    };
+    let params = zip_params(ctx, &first.request, &first.url, output, options);
+    // The whole archive serves every path, so it is neither fetched in parts nor
+    // streamed once per path
+    let archive = prepare_archive(&params, false).await?;
+
+    let mut results = Vec::with_capacity(targets.len());
+    for target in &targets {
+        results.push(extract_target(&params, &target.request, &target.url, archive.path()).await);
+    }
+    Ok(results)
+}
+
+fn zip_params(
+    ctx: &DownloadContext,
+    request: &RequestInfo,
+    url: &str,
+    output: Option<&PathBuf>,
+    options: &DownloadOptions<'_>,
+) -> ZipDownloadParams {
+    ZipDownloadParams {
+        client: ctx.client.clone(),
         request: request.clone(),
         url: url.to_string(),
         output: output.cloned(),
         token: options.token.map(|t| t.to_string()),
-        rate_limit,
+        rate_limit: ctx.rate_limit.clone(),
         no_cache: options.no_cache,
+        offline: options.offline,
         force: options.force,
-        multi: multi.clone(),
-    };
-
-    download_via_zip_impl(params).await
+        on_conflict: options.on_conflict,
+        preserve_mtime: options.preserve_mtime,
+        symlinks: options.symlinks,
+        shard: options.shard,
+        filter: options.filter.clone(),
+        lfs: options.lfs,
+        limits: ctx.stream_limits(options),
+        stream_archive: options.stream_archive,
+        // GitHub archives hold LFS pointers, which --lfs replaces with the objects
+        verify: options.verify && !options.lfs,
+        sync: options.sync,
+        update_only: options.update_only,
+        delete: options.delete,
+        cache: options.cache_policy(),
+        progress: options.progress.clone(),
+        quiet: options.quiet,
+        sanitize_names: options.sanitize_names,
+        prompt: ctx.prompt.clone(),
+        multi: ctx.multi.clone(),
+    }
 }
 
 async fn download_via_zip_impl(params: ZipDownloadParams) -> Result<()> {
+    // Streaming skips the archive on disk, but a cached one is still preferred
+    if params.stream_archive && !params.offline {
+        let zip_path = repos_cache_dir()?.join(cached_zip_name(&params.request));
+        let cached = zip_path.exists() && verify_cached_file(&zip_path, is_readable_zip)?;
+        if params.no_cache || !cached {
+            return download_via_tarball(params).await;
+        }
+    }
+
+    let archive = prepare_archive(&params, true).await?;
+    extract_target(&params, &params.request, &params.url, archive.path()).await
+}
+
+/// A zip archive ready for extraction
+enum PreparedArchive {
+    /// The archive in the repository cache
+    Cached(PathBuf),
+    /// Only the entries below the requested path, fetched with range requests
+    Sparse(tempfile::NamedTempFile),
+}
+
+impl PreparedArchive {
+    fn path(&self) -> &Path {
+        match self {
+            PreparedArchive::Cached(path) => path,
+            PreparedArchive::Sparse(temp) => temp.path(),
+        }
+    }
+}
+
+/// Download the archive of `params.request`'s ref, or reuse the cached one. With
+/// `allow_sparse`, a path inside the repository may fetch just its own entries.
+async fn prepare_archive(
+    params: &ZipDownloadParams,
+    allow_sparse: bool,
+) -> Result<PreparedArchive> {
     let ZipDownloadParams {
         client,
         request,
-        url,
-        output,
         token,
         rate_limit,
         no_cache,
-        force,
+        offline,
+        symlinks,
+        limits,
+        quiet,
         multi,
+        ..
     } = params;
+    let (no_cache, offline, quiet) = (*no_cache, *offline, *quiet);
 
     // Construct the zip download URL
-    let zip_url = format!(
-        "https://github.com/{}/{}/archive/refs/heads/{}.zip",
-        request.owner, request.repo, request.branch
-    );
+    let zip_url = archive_url(request, "zip");
 
     debug!("Downloading zip archive from {}", zip_url);
 
     // Use cache directory for zip files
-    let cache_dir = repos_cache_dir()?;
+    let zip_filename = cached_zip_name(request);
+    let zip_path = repos_cache_dir()?.join(&zip_filename);
 
-    // Create a unique filename based on owner/repo/branch
-    let mut hasher = Sha256::new();
-    hasher.update(format!("{}/{}/{}", request.owner, request.repo, request.branch).as_bytes());
-    let zip_hash = format!("{:x}", hasher.finalize());
-    let zip_filename = format!("{}-{}-{}.zip", request.owner, request.repo, &zip_hash[..8]);
-    let zip_path = cache_dir.join(&zip_filename);
+    // A cached archive that fails its checksum is deleted here and downloaded again
+    let cached = zip_path.exists() && verify_cached_file(&zip_path, is_readable_zip)?;
 
     // Download the zip file if not cached or if cache is disabled
-    if !zip_path.exists() || no_cache {
-        eprintln!(
-            "{} {} Downloading zip archive...",
-            style("[1/2]").bold().dim(),
-            style("▼").cyan()
+    if offline && !cached {
+        return Err(GdlError::Offline(format!(
+            "offline: no cached zip archive of {}/{}:{}; run once without --offline to populate the cache",
+            request.owner, request.repo, request.branch
+        )));
+    }
+
+    // A path inside the repository only needs some of the archive's entries; when the
+    // server supports range requests, fetch just those instead of the whole archive.
+    // Followed symlinks may point at entries outside the path, so they need it all.
+    if allow_sparse
+        && !offline
+        && (!cached || no_cache)
+        && !request.path.is_empty()
+        && *symlinks != SymlinkPolicy::Follow
+    {
+        status(
+            quiet,
+            format!(
+                "{} {} Fetching matching entries of the zip archive...",
+                style("[1/2]").bold().dim(),
+                style("▼").cyan()
+            ),
+        );
+        let temp = tempfile::Builder::new()
+            .prefix("ghdl-sparse-")
+            .suffix(".zip")
+            .tempfile()
+            .context("failed to create a temporary file for the zip archive")?;
+        match fetch_sparse_zip(
+            client,
+            &zip_url,
+            token.as_deref(),
+            rate_limit,
+            request,
+            temp.path(),
+            limits,
+        )
+        .await
+        {
+            Ok(true) => {
+                debug!("Using sparse zip archive at {}", temp.path().display());
+                return Ok(PreparedArchive::Sparse(temp));
+            }
+            Ok(false) => {}
+            Err(err) => warn!(
+                "Fetching parts of the zip archive failed ({:#}); downloading all of it",
+                err
+            ),
+        }
+    }
+
+    if !offline && (!cached || no_cache) {
+        status(
+            quiet,
+            format!(
+                "{} {} Downloading zip archive...",
+                style("[1/2]").bold().dim(),
+                style("▼").cyan()
+            ),
         );
         debug!("Downloading zip archive to {}", zip_path.display());
         download_zip_file(
-            &client,
+            client,
             &zip_url,
             &zip_path,
             token.as_deref(),
-            &rate_limit,
-            &multi,
+            rate_limit,
+            limits,
+            multi,
         )
         .await?;
+        if let Err(err) = write_checksum(&zip_path) {
+            debug!(
+                "Unable to record checksum of {}: {}",
+                zip_path.display(),
+                err
+            );
+        }
     } else {
-        eprintln!(
-            "{} {} Using cached zip archive",
-            style("[1/2]").bold().dim(),
-            style("✓").green()
+        status(
+            quiet,
+            format!(
+                "{} {} Using cached zip archive",
+                style("[1/2]").bold().dim(),
+                style("✓").green()
+            ),
         );
         info!("Using cached zip archive at {}", zip_path.display());
     }
+    if let Err(err) = record_repo_access(&zip_filename) {
+        debug!(
+            "Unable to record cache access for {}: {}",
+            zip_filename, err
+        );
+    }
+    Ok(PreparedArchive::Cached(zip_path))
+}
 
+/// Extract `request`'s path from the prepared archive, then verify it and fetch its
+/// LFS objects as `params` asks
+async fn extract_target(
+    params: &ZipDownloadParams,
+    request: &RequestInfo,
+    url: &str,
+    archive_path: &Path,
+) -> Result<()> {
     // Extract the specific files from the zip
-    eprintln!(
-        "{} {} Extracting files...",
-        style("[2/2]").bold().dim(),
-        style("»").cyan()
+    status(
+        params.quiet,
+        format!(
+            "{} {} Extracting files...",
+            style("[2/2]").bold().dim(),
+            style("»").cyan()
+        ),
     );
-    extract_from_zip(&request, &zip_path, output, &url, force, &multi)?;
+    let settings = ExtractSettings {
+        force: params.force,
+        on_conflict: params.on_conflict,
+        preserve_mtime: params.preserve_mtime,
+        symlinks: params.symlinks,
+        shard: params.shard,
+        filter: params.filter.clone(),
+        sync: params.sync,
+        update_only: params.update_only,
+        delete: params.delete,
+        progress: params.progress.clone(),
+        quiet: params.quiet,
+        sanitize_names: params.sanitize_names,
+        prompt: params.prompt.clone(),
+        multi: params.multi.clone(),
+    };
+    let extracted = extract_from_zip(request, archive_path, params.output.clone(), url, settings)?;
+
+    if params.verify {
+        verify_against_tree(
+            &params.client,
+            request,
+            params.token.as_deref(),
+            params.rate_limit.clone(),
+            params.cache,
+            &extracted,
+        )
+        .await?;
+    }
+
+    if params.lfs {
+        fetch_lfs_objects(
+            &params.client,
+            request,
+            params.token.as_deref(),
+            &extracted,
+            &params.multi,
+        )
+        .await?;
+    }
+
+    if params.preserve_mtime {
+        apply_commit_times(
+            &params.client,
+            request,
+            params.token.as_deref(),
+            &params.rate_limit,
+            params.cache,
+            &extracted,
+        )
+        .await;
+    }
 
     Ok(())
 }
 
+/// Per-run settings that affect how archive entries are written
+struct ExtractSettings {
+    force: bool,
+    on_conflict: Option<OnConflict>,
+    preserve_mtime: bool,
+    symlinks: SymlinkPolicy,
+    shard: Option<Shard>,
+    filter: Option<PathFilter>,
+    sync: bool,
+    update_only: bool,
+    delete: bool,
+    progress: Option<Arc<dyn ProgressSink>>,
+    quiet: bool,
+    sanitize_names: bool,
+    prompt: Arc<OverwritePrompt>,
+    multi: MultiProgress,
+}
+
 async fn download_zip_file(
     client: &Client,
     url: &str,
     dest_path: &Path,
     token: Option<&str>,
     rate_limit: &RateLimitTracker,
+    limits: &StreamLimits,
+    multi: &MultiProgress,
+) -> Result<()> {
+    let policy = rate_limit.retry;
+    let mut attempt = 1;
+    loop {
+        let result =
+            download_zip_file_attempt(client, url, dest_path, token, rate_limit, limits, multi)
+                .await;
+
+        match result {
+            Err(err) if is_transient(&err) && attempt <= policy.retries => {
+                let delay = policy.delay(attempt);
+                warn!(
+                    "Zip archive download failed ({}), retrying in {} ({}/{})...",
+                    err.root_cause(),
+                    describe_delay(delay),
+                    attempt,
+                    policy.retries
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn download_zip_file_attempt(
+    client: &Client,
+    url: &str,
+    dest_path: &Path,
+    token: Option<&str>,
+    rate_limit: &RateLimitTracker,
+    limits: &StreamLimits,
     multi: &MultiProgress,
 ) -> Result<()> {
     let mut req = client.get(url);
@@ -145,38 +470,15 @@ async fn download_zip_file(
     rate_limit.record_headers(response.headers()).await;
 
     if !response.status().is_success() {
-        return Err(anyhow!(
-            "failed to download zip: HTTP {}",
-            response.status()
-        ));
+        let message = format!("failed to download zip: HTTP {}", response.status());
+        return Err(match TransientStatus::check(response.status()) {
+            Some(transient) => anyhow::Error::new(transient).context(message).into(),
+            None => GdlError::from_status(response.status(), message.clone())
+                .unwrap_or_else(|| anyhow!(message).into()),
+        });
     }
 
-    let total_size = response.content_length();
-
-    // Create progress bar or spinner for zip download based on whether we know the size
-    let pb = if let Some(size) = total_size {
-        info!("Downloading zip archive: {}", format_bytes(size));
-        let bar = multi.add(ProgressBar::new(size));
-        bar.set_style(
-            ProgressStyle::default_bar()
-                .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%)")
-                .expect("invalid progress bar template")
-                .progress_chars("#>-"),
-        );
-        bar.set_message("Downloading zip archive");
-        bar
-    } else {
-        info!("Downloading zip archive (size unknown)");
-        let spinner = multi.add(ProgressBar::new_spinner());
-        spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} [{elapsed_precise}] {msg} [{bytes}]")
-                .expect("invalid spinner template"),
-        );
-        spinner.set_message("Downloading zip archive");
-        spinner.enable_steady_tick(std::time::Duration::from_millis(100));
-        spinner
-    };
+    let pb = archive_progress_bar(multi, response.content_length(), "zip archive");
 
     // Create temp file and download
     let temp_path = dest_path.with_extension("zip.tmp");
@@ -187,15 +489,24 @@ async fn download_zip_file(
     let mut stream = response.bytes_stream();
     let mut downloaded: u64 = 0;
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.with_context(|| format!("failed to download chunk from {}", url))?;
-        file.write_all(&chunk)
-            .with_context(|| format!("failed to write to {}", temp_path.display()))?;
-        downloaded += chunk.len() as u64;
-        pb.set_position(downloaded);
+    let mut watch = StreamWatch::new(limits);
+
+    let streamed: Result<()> = async {
+        while let Some(chunk) = next_chunk(&mut stream, &mut watch)
+            .await
+            .with_context(|| format!("failed to download chunk from {}", url))?
+        {
+            file.write_all(&chunk)
+                .with_context(|| format!("failed to write to {}", temp_path.display()))?;
+            downloaded += chunk.len() as u64;
+            pb.set_position(downloaded);
+        }
+        Ok(())
     }
+    .await;
 
     pb.finish_and_clear();
+    streamed?;
 
     // Rename temp file to final path
     fs::rename(&temp_path, dest_path).with_context(|| {
@@ -210,14 +521,129 @@ async fn download_zip_file(
     Ok(())
 }
 
+/// Progress bar for an archive download, or a spinner when its size is unknown
+pub(super) fn archive_progress_bar(
+    multi: &MultiProgress,
+    total_size: Option<u64>,
+    what: &str,
+) -> ProgressBar {
+    if let Some(size) = total_size {
+        info!("Downloading {}: {}", what, format_bytes(size));
+        let bar = multi.add(ProgressBar::new(size));
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%)")
+                .expect("invalid progress bar template")
+                .progress_chars("#>-"),
+        );
+        bar.set_message(format!("Downloading {}", what));
+        bar
+    } else {
+        info!("Downloading {} (size unknown)", what);
+        let spinner = multi.add(ProgressBar::new_spinner());
+        spinner.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} [{elapsed_precise}] {msg} [{bytes}]")
+                .expect("invalid spinner template"),
+        );
+        spinner.set_message(format!("Downloading {}", what));
+        spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+        spinner
+    }
+}
+
+/// Progress bar for the bytes written while extracting an archive, with throughput
+fn extraction_progress_bar(multi: &MultiProgress, total_bytes: u64) -> ProgressBar {
+    let bar = multi.add(ProgressBar::new(total_bytes));
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {binary_bytes_per_sec}")
+            .expect("invalid progress bar template")
+            .progress_chars("#>-"),
+    );
+    bar.set_message("Extracting zip archive");
+    bar
+}
+
+/// Whether a file opens as a zip archive, to vouch for archives cached before checksums
+fn is_readable_zip(path: &Path) -> bool {
+    File::open(path)
+        .ok()
+        .is_some_and(|file| zip::ZipArchive::new(file).is_ok())
+}
+
+/// Cache file name of the archive for the request's owner/repo/branch
+fn cached_zip_name(request: &RequestInfo) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}/{}/{}", request.owner, request.repo, request.branch).as_bytes());
+    let zip_hash = format!("{:x}", hasher.finalize());
+    format!("{}-{}-{}.zip", request.owner, request.repo, &zip_hash[..8])
+}
+
+/// Write one file from a previously cached archive of the request's ref to `target`.
+///
+/// Returns `false` when no archive is cached or it lacks the file. The archive may
+/// predate the ref's current commit, so callers should verify the content.
+pub fn extract_file_from_cached_zip(
+    request: &RequestInfo,
+    item_path: &str,
+    target: &Path,
+) -> Result<bool> {
+    let zip_path = repos_cache_dir()?.join(cached_zip_name(request));
+    if !zip_path.exists() || !verify_cached_file(&zip_path, is_readable_zip)? {
+        return Ok(false);
+    }
+
+    let file = File::open(&zip_path)
+        .with_context(|| format!("failed to open zip file {}", zip_path.display()))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("failed to read zip archive {}", zip_path.display()))?;
+
+    let entry_name = format!("{}-{}/{}", request.repo, request.branch, item_path);
+    let mut entry = match archive.by_name(&entry_name) {
+        Ok(entry) if entry.is_file() => entry,
+        _ => return Ok(false),
+    };
+
+    let mut output = File::create(target)
+        .with_context(|| format!("failed to create file {}", target.display()))?;
+    io::copy(&mut entry, &mut output)
+        .with_context(|| format!("failed to extract {} from zip", entry_name))?;
+    Ok(true)
+}
+
+/// Whether the zip entry `name` is the requested file, or the requested directory or
+/// lies in it; `docs` does not take in `docs-old/`
+fn entry_in_request(request: &RequestInfo, zip_prefix: &str, name: &str) -> bool {
+    name.strip_prefix(zip_prefix)
+        .is_some_and(|item| matches_request(request, item.trim_end_matches('/')))
+}
+
+/// Extract the requested entries, returning each written file's repository and local path
 fn extract_from_zip(
     request: &RequestInfo,
     zip_path: &Path,
     output: Option<PathBuf>,
     url: &str,
-    force: bool,
-    multi: &MultiProgress,
-) -> Result<()> {
+    settings: ExtractSettings,
+) -> Result<Vec<(String, PathBuf)>> {
+    let ExtractSettings {
+        force,
+        on_conflict,
+        preserve_mtime,
+        symlinks,
+        shard,
+        filter,
+        sync,
+        update_only,
+        delete,
+        progress,
+        quiet,
+        sanitize_names,
+        prompt,
+        multi,
+    } = settings;
+
     let file = File::open(zip_path)
         .with_context(|| format!("failed to open zip file {}", zip_path.display()))?;
 
@@ -239,33 +665,33 @@ fn extract_from_zip(
     let treat_as_single_file = request.kind == RequestKind::Blob;
     let (base_path, default_output_dir) =
         compute_base_and_default_output(request, treat_as_single_file, None);
-    let output_dir = output.unwrap_or(default_output_dir);
-    ensure_directory(&output_dir)?;
+    let output_dir = output_root(output.unwrap_or(default_output_dir));
 
-    // Collect files to extract
+    // Collect files to extract, remembering their entries so they are found again
+    // without another lookup by name
     let mut tasks: Vec<FileCopyTask> = Vec::new();
-    let mut total_bytes: u64 = 0;
+    let mut entries: HashMap<PathBuf, usize> = HashMap::new();
+    let mut path_exists = false;
+    let mut pending_links = Vec::new();
 
     for i in 0..archive.len() {
-        let file = archive
-            .by_index(i)
-            .with_context(|| format!("failed to access file at index {} in zip", i))?;
-
-        let file_path = file.name().to_string();
-
-        // Check if this file matches our target path
-        let matches = if request.kind == RequestKind::Blob {
-            // For a single file, exact match
-            file_path == target_path_in_zip
-        } else {
-            // For a directory, file should be under the target path
-            file_path.starts_with(&target_path_in_zip) && !file.is_dir()
-        };
+        // Names come from the central directory alone; entries are only opened when
+        // they match, which a sparse archive (see `ranged`) relies on
+        let file_path = archive.name_for_index(i).unwrap_or_default().to_string();
+        let requested = entry_in_request(request, &zip_prefix, &file_path);
+        if requested {
+            path_exists = true;
+        }
 
-        if !matches {
+        // Directory entries exist only to prove the path
+        if !requested || file_path.ends_with('/') {
             continue;
         }
 
+        let mut file = archive
+            .by_index(i)
+            .with_context(|| format!("failed to access file at index {} in zip", i))?;
+
         // Strip the zip prefix from the path
         let relative_path = file_path.strip_prefix(&zip_prefix).unwrap_or(&file_path);
 
@@ -288,18 +714,86 @@ fn extract_from_zip(
             sha: None,
         };
 
-        let relative_target = crate::paths::relative_path(&base_path, &content)?;
+        let relative_target = crate::paths::relative_path(&base_path, &content, sanitize_names)?;
         let target_path = output_dir.join(&relative_target);
 
-        total_bytes += file.size();
+        if file.is_symlink() {
+            // Symlink entries store the link target as their contents
+            let mut link_target = String::new();
+            file.read_to_string(&mut link_target)
+                .with_context(|| format!("failed to read symlink {} in zip", file_path))?;
+
+            match symlinks {
+                SymlinkPolicy::Skip => {
+                    warn!("Skipping symlink {} in zip archive.", content.path);
+                }
+                SymlinkPolicy::Create
+                    if resolve_created_link(&content.path, &link_target, &base_path).is_none() =>
+                {
+                    warn!(
+                        "Skipping symlink {} -> {}: target is outside the output directory.",
+                        content.path, link_target
+                    );
+                }
+                SymlinkPolicy::Create => tasks.push(FileCopyTask {
+                    item_path: content.path,
+                    source_path: PathBuf::from(&file_path),
+                    target_path,
+                    size: Some(0),
+                    link_target: Some(PathBuf::from(link_target)),
+                }),
+                SymlinkPolicy::Follow => {
+                    pending_links.push((content.path, target_path, link_target));
+                }
+            }
+            continue;
+        }
+
+        entries.insert(PathBuf::from(&file_path), i);
         tasks.push(FileCopyTask {
             item_path: content.path,
-            source_path: PathBuf::new(), // Not used for zip extraction
+            source_path: PathBuf::from(&file_path),
             target_path,
             size: Some(file.size()),
+            link_target: None,
         });
     }
 
+    // Dereference followed symlinks to the archive entries they point at
+    for (item_path, target_path, link_target) in pending_links {
+        let resolved = resolve_link_target(&item_path, &link_target)
+            .map(|resolved| format!("{}{}", zip_prefix, resolved));
+        let entry = resolved
+            .as_deref()
+            .and_then(|name| archive.index_for_name(name))
+            .and_then(|index| {
+                let entry = archive.by_index(index).ok()?;
+                (entry.is_file() && !entry.is_symlink())
+                    .then(|| (index, entry.name().to_string(), entry.size()))
+            });
+
+        match entry {
+            Some((index, entry_name, size)) => {
+                entries.insert(PathBuf::from(&entry_name), index);
+                tasks.push(FileCopyTask {
+                    item_path,
+                    source_path: PathBuf::from(entry_name),
+                    target_path,
+                    size: Some(size),
+                    link_target: None,
+                });
+            }
+            None => warn!(
+                "Skipping symlink {} -> {}: target is not a file inside the repository.",
+                item_path, link_target
+            ),
+        }
+    }
+
+    if tasks.is_empty() && path_exists && request.kind == RequestKind::Tree {
+        return Err(EmptyDownload::for_request(request).into());
+    }
+
     if tasks.is_empty() {
         return Err(anyhow!(
             "No files matched the requested path {} in zip archive",
@@ -308,16 +802,49 @@ fn extract_from_zip(
             } else {
                 request.path.clone()
             }
-        ));
+        )
+        .into());
+    }
+
+    let tasks = disambiguate_targets(tasks, |task| &task.item_path);
+    let tasks = select_matching(tasks, filter.as_ref(), |task| &task.item_path);
+    let tasks = select_shard(tasks, shard, |task| &task.item_path);
+    if tasks.is_empty() {
+        report_empty_shard(shard, quiet);
+        return Ok(Vec::new());
+    }
+    ensure_directory(&output_dir)?;
+
+    let keep: Option<HashSet<PathBuf>> = (delete && !treat_as_single_file)
+        .then(|| tasks.iter().map(|task| task.target_path.clone()).collect());
+    let tasks = if sync || update_only {
+        skip_unchanged(tasks, |task| {
+            read_entry(&mut archive, &entries, task)
+                .map(|content| calculate_git_blob_sha1(&content))
+        })
+    } else {
+        tasks
+    };
+    if tasks.is_empty() {
+        mirror_output(&output_dir, keep.as_ref())?;
+        return Ok(Vec::new());
     }
 
-    // Check for file overwrites before proceeding
-    let target_paths = crate::overwrite::collect_target_paths(&tasks);
-    crate::overwrite::check_overwrite_permission(&target_paths, force)?;
+    // Check for file overwrites before proceeding; --sync replaces changed files
+    let tasks =
+        crate::overwrite::resolve_conflicts(tasks, on_conflict, force || sync, &prompt, |task| {
+            read_entry(&mut archive, &entries, task)
+        })?;
+    if tasks.is_empty() {
+        mirror_output(&output_dir, keep.as_ref())?;
+        return Ok(Vec::new());
+    }
 
     let total_files = tasks.len();
+    let total_bytes: u64 = tasks.iter().filter_map(|task| task.size).sum();
 
-    let mut progress = DownloadProgress::with_multi_progress(total_files, total_bytes, Some(multi));
+    let mut progress = DownloadProgress::new(total_files, total_bytes, progress);
+    let bar = extraction_progress_bar(&multi, total_bytes);
 
     let target_display = if total_files == 1 && treat_as_single_file {
         format_path_for_log(&tasks[0].target_path)
@@ -339,32 +866,41 @@ fn extract_from_zip(
     );
 
     // Extract files
+    let mut extracted = Vec::with_capacity(tasks.len());
     for task in &tasks {
-        // Find the file in the archive again
-        let zip_path_to_find = format!("{}{}", zip_prefix, task.item_path);
-
-        let mut found = false;
-        for i in 0..archive.len() {
-            {
-                let file = archive
-                    .by_index(i)
-                    .with_context(|| format!("failed to access file at index {} in zip", i))?;
-
-                if file.name() != zip_path_to_find {
-                    continue;
-                }
-                found = true;
+        // A link left by an earlier --symlinks create run must not redirect the write
+        remove_symlinks_below(&output_dir, &task.target_path)?;
+        if let Some(link_target) = &task.link_target {
+            if let Some(parent) = task.target_path.parent() {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!("failed to create output directory {}", parent.display())
+                })?;
             }
-            extract_file_from_zip(&mut archive, i, task, &mut progress)?;
-            break;
+            progress.log_start(&task.item_path, &task.target_path, task.size);
+            create_symlink(link_target, &task.target_path)?;
+            progress.record_download(&task.item_path, &task.target_path, task.size);
+            continue;
         }
 
-        if !found {
-            warn!("File {} not found in zip during extraction", task.item_path);
+        match entries.get(&task.source_path) {
+            Some(&index) => {
+                extract_file_from_zip(
+                    &mut archive,
+                    index,
+                    task,
+                    preserve_mtime,
+                    &mut progress,
+                    &bar,
+                )?;
+                extracted.push((task.item_path.clone(), task.target_path.clone()));
+            }
+            None => warn!("File {} not found in zip during extraction", task.item_path),
         }
     }
 
+    bar.finish_and_clear();
     progress.finish();
+    mirror_output(&output_dir, keep.as_ref())?;
 
     info!(
         "Finished downloading {} file(s) ({} total) from {} using zip archive.",
@@ -373,14 +909,16 @@ fn extract_from_zip(
         url
     );
 
-    Ok(())
+    Ok(extracted)
 }
 
 fn extract_file_from_zip(
     archive: &mut zip::ZipArchive<File>,
     index: usize,
     task: &FileCopyTask,
+    preserve_mtime: bool,
     progress: &mut DownloadProgress,
+    bar: &ProgressBar,
 ) -> Result<()> {
     let mut file = archive
         .by_index(index)
@@ -396,10 +934,175 @@ fn extract_file_from_zip(
     let mut output_file = File::create(&task.target_path)
         .with_context(|| format!("failed to create file {}", task.target_path.display()))?;
 
-    io::copy(&mut file, &mut output_file)
-        .with_context(|| format!("failed to extract file to {}", task.target_path.display()))?;
+    if let Err(err) = copy_entry(&mut file, &mut output_file, &task.item_path, progress, bar) {
+        progress.forget_bytes(&task.item_path);
+        return Err(err)
+            .with_context(|| format!("failed to extract file to {}", task.target_path.display()));
+    }
+    drop(output_file);
+
+    // GitHub stamps archive entries with the commit date of the requested ref, which
+    // each file keeps until `apply_commit_times` finds its own
+    if preserve_mtime
+        && let Some(secs) = file.last_modified().and_then(|modified| {
+            civil_to_unix_secs(
+                modified.year() as i64,
+                modified.month() as u32,
+                modified.day() as u32,
+                modified.hour() as u32,
+                modified.minute() as u32,
+                modified.second() as u32,
+            )
+        })
+        && let Err(err) = set_file_mtime(&task.target_path, secs)
+    {
+        warn!("Unable to preserve mtime for {}: {}", task.item_path, err);
+    }
 
     progress.record_download(&task.item_path, &task.target_path, task.size);
 
     Ok(())
 }
+
+/// Copy an archive entry to its target, counting the bytes on the extraction bar as
+/// they are written and in `progress` every [`PROGRESS_STEP`]
+fn copy_entry(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    item_path: &str,
+    progress: &mut DownloadProgress,
+    bar: &ProgressBar,
+) -> io::Result<()> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut pending = 0;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buffer[..read])?;
+        bar.inc(read as u64);
+        pending += read as u64;
+        if pending >= PROGRESS_STEP {
+            progress.record_bytes(item_path, pending);
+            pending = 0;
+        }
+    }
+}
+
+/// The contents of the archive entry `task` extracts; `None` for symlinks
+fn read_entry(
+    archive: &mut zip::ZipArchive<File>,
+    entries: &HashMap<PathBuf, usize>,
+    task: &FileCopyTask,
+) -> Option<Vec<u8>> {
+    let index = *entries.get(&task.source_path)?;
+    let mut entry = archive.by_index(index).ok()?;
+    let mut content = Vec::new();
+    entry.read_to_end(&mut content).ok()?;
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::env;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    #[serial]
+    fn test_extract_file_from_cached_zip() {
+        let cache_home = tempfile::tempdir().unwrap();
+        unsafe {
+            env::set_var("XDG_CACHE_HOME", cache_home.path());
+        }
+
+        let request = RequestInfo {
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            branch: "main".to_string(),
+            path: "docs".to_string(),
+            has_trailing_slash: false,
+            kind: RequestKind::Tree,
+        };
+        let target = cache_home.path().join("guide.md");
+
+        // Nothing cached yet
+        assert!(!extract_file_from_cached_zip(&request, "docs/guide.md", &target).unwrap());
+
+        let zip_path = repos_cache_dir().unwrap().join(cached_zip_name(&request));
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        writer
+            .start_file("repo-main/docs/guide.md", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"# Guide\n").unwrap();
+        writer.finish().unwrap();
+
+        assert!(extract_file_from_cached_zip(&request, "docs/guide.md", &target).unwrap());
+        assert_eq!(fs::read(&target).unwrap(), b"# Guide\n");
+        assert!(!extract_file_from_cached_zip(&request, "docs/missing.md", &target).unwrap());
+
+        unsafe {
+            env::remove_var("XDG_CACHE_HOME");
+        }
+    }
+
+    #[test]
+    fn test_entry_in_request_stops_at_path_boundaries() {
+        let mut request = RequestInfo {
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            branch: "main".to_string(),
+            path: "docs".to_string(),
+            has_trailing_slash: false,
+            kind: RequestKind::Tree,
+        };
+        let prefix = "repo-main/";
+        assert!(entry_in_request(&request, prefix, "repo-main/docs/"));
+        assert!(entry_in_request(
+            &request,
+            prefix,
+            "repo-main/docs/guide.md"
+        ));
+        assert!(!entry_in_request(&request, prefix, "repo-main/docs-old/"));
+        assert!(!entry_in_request(
+            &request,
+            prefix,
+            "repo-main/docs-old/a.md"
+        ));
+        assert!(!entry_in_request(&request, prefix, "repo-main/docs.md"));
+
+        request.path = "README.md".to_string();
+        request.kind = RequestKind::Blob;
+        assert!(entry_in_request(&request, prefix, "repo-main/README.md"));
+        assert!(!entry_in_request(
+            &request,
+            prefix,
+            "repo-main/README.md.bak"
+        ));
+
+        request.path.clear();
+        request.kind = RequestKind::Tree;
+        assert!(entry_in_request(&request, prefix, "repo-main/"));
+        assert!(entry_in_request(&request, prefix, "repo-main/src/lib.rs"));
+    }
+
+    #[test]
+    fn test_copy_entry_reports_bytes() {
+        let data = vec![7u8; 600 * 1024];
+        let mut output = Vec::new();
+        let mut progress = DownloadProgress::new(1, data.len() as u64, None);
+        let bar = ProgressBar::hidden();
+
+        copy_entry(&mut &data[..], &mut output, "big.bin", &mut progress, &bar).unwrap();
+        assert_eq!(output, data);
+        assert_eq!(bar.position(), data.len() as u64);
+        // Whole steps are counted as they are written, the rest when the file completes
+        assert_eq!(progress.downloaded_bytes, 2 * PROGRESS_STEP);
+        progress.record_download("big.bin", Path::new("big.bin"), Some(data.len() as u64));
+        assert_eq!(progress.downloaded_bytes, data.len() as u64);
+    }
+}
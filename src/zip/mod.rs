@@ -1,3 +1,5 @@
 mod archive;
+mod ranged;
+mod tarball;
 
-pub use archive::download_via_zip;
+pub use archive::{download_many_via_zip, download_via_zip, extract_file_from_cached_zip};
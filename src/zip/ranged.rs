@@ -0,0 +1,518 @@
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::anyhow;
+use log::{debug, info};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, Response, StatusCode};
+
+use super::tarball::{entry_repo_path, matches_request};
+use crate::error::{Context, GdlError, Result};
+use crate::http::{StreamLimits, StreamWatch, TransientStatus, next_chunk};
+use crate::progress::format_bytes;
+use crate::rate_limit::RateLimitTracker;
+use crate::types::RequestInfo;
+
+/// Bytes read from the end of the archive: the end of central directory record plus
+/// the longest possible archive comment
+const TAIL_SIZE: u64 = 22 + u16::MAX as u64;
+
+/// Entry ranges closer than this are fetched with a single request
+const MERGE_GAP: u64 = 64 * 1024;
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+
+/// Location of the central directory inside the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CentralDirectory {
+    offset: u64,
+    size: u64,
+}
+
+/// What the end of central directory record says about the central directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Eocd {
+    Classic(CentralDirectory),
+    /// The real values are in the ZIP64 record at this offset
+    Zip64 {
+        record_offset: u64,
+    },
+}
+
+/// Sends range requests for one archive; ranges are `[start, end)`
+struct RangeFetcher<'a> {
+    client: &'a Client,
+    url: &'a str,
+    token: Option<&'a str>,
+    rate_limit: &'a RateLimitTracker,
+    limits: &'a StreamLimits,
+}
+
+impl RangeFetcher<'_> {
+    async fn request(&self, range: String) -> Result<Response> {
+        let mut req = self.client.get(self.url).header(RANGE, range);
+        if let Some(token) = self.token {
+            req = req.header("Authorization", format!("token {}", token));
+        }
+        let response = req
+            .send()
+            .await
+            .with_context(|| format!("failed to send request to {}", self.url))?;
+        self.rate_limit.record_headers(response.headers()).await;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let message = format!("failed to fetch zip range: HTTP {}", status);
+        Err(match TransientStatus::check(status) {
+            Some(transient) => anyhow::Error::new(transient).context(message).into(),
+            None => GdlError::from_status(status, message.clone())
+                .unwrap_or_else(|| anyhow!(message).into()),
+        })
+    }
+
+    /// Stream `[start, end)` of the archive into `file` at the same offset
+    async fn copy_range(&self, file: &mut File, start: u64, end: u64) -> Result<u64> {
+        let response = self.request(format!("bytes={}-{}", start, end - 1)).await?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(anyhow!(
+                "server ignored the range request for {} (status {})",
+                self.url,
+                response.status()
+            )
+            .into());
+        }
+
+        file.seek(SeekFrom::Start(start))
+            .context("failed to seek in sparse zip archive")?;
+        let mut stream = response.bytes_stream();
+        let mut watch = StreamWatch::new(self.limits);
+        let mut written = 0u64;
+        while let Some(chunk) = next_chunk(&mut stream, &mut watch)
+            .await
+            .with_context(|| format!("failed to download range of {}", self.url))?
+        {
+            file.write_all(&chunk)
+                .context("failed to write sparse zip archive")?;
+            written += chunk.len() as u64;
+        }
+        if written != end - start {
+            return Err(anyhow!(
+                "received {} of {} bytes for a range of {}",
+                written,
+                end - start,
+                self.url
+            )
+            .into());
+        }
+        Ok(written)
+    }
+}
+
+/// Build a sparse copy of the repository's zip archive at `dest` that holds only the
+/// central directory and the entries matching the request, fetched with HTTP range
+/// requests. Everything else reads as zeros, which is fine as long as only matching
+/// entries are opened.
+///
+/// Returns `false` when the server does not support range requests; the caller then
+/// downloads the whole archive.
+pub(super) async fn fetch_sparse_zip(
+    client: &Client,
+    url: &str,
+    token: Option<&str>,
+    rate_limit: &RateLimitTracker,
+    request: &RequestInfo,
+    dest: &Path,
+    limits: &StreamLimits,
+) -> Result<bool> {
+    let fetcher = RangeFetcher {
+        client,
+        url,
+        token,
+        rate_limit,
+        limits,
+    };
+
+    let response = fetcher.request(format!("bytes=-{}", TAIL_SIZE)).await?;
+    let total = match response.status() {
+        StatusCode::PARTIAL_CONTENT => response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range_total),
+        _ => None,
+    };
+    let Some(total) = total else {
+        debug!("{} does not support range requests", url);
+        return Ok(false);
+    };
+    let tail = response
+        .bytes()
+        .await
+        .with_context(|| format!("failed to read the end of {}", url))?;
+    let tail_start = total - tail.len() as u64;
+
+    let mut file =
+        File::create(dest).with_context(|| format!("failed to create {}", dest.display()))?;
+    file.set_len(total)
+        .with_context(|| format!("failed to size {}", dest.display()))?;
+    file.seek(SeekFrom::Start(tail_start))
+        .and_then(|_| file.write_all(&tail))
+        .with_context(|| format!("failed to write {}", dest.display()))?;
+    let mut fetched = tail.len() as u64;
+
+    let directory = match find_eocd(&tail, tail_start)? {
+        Eocd::Classic(directory) => directory,
+        Eocd::Zip64 { record_offset } => {
+            let record = match record_offset.checked_sub(tail_start) {
+                Some(start) if start as usize + 56 <= tail.len() => {
+                    tail[start as usize..start as usize + 56].to_vec()
+                }
+                _ => {
+                    fetched += fetcher
+                        .copy_range(&mut file, record_offset, record_offset + 56)
+                        .await?;
+                    read_back(&mut file, record_offset, 56)?
+                }
+            };
+            parse_zip64_eocd(&record)?
+        }
+    };
+
+    let directory_end = directory.offset + directory.size;
+    if directory_end > total {
+        return Err(anyhow!("zip central directory lies outside the archive").into());
+    }
+    if directory.offset < tail_start {
+        fetched += fetcher
+            .copy_range(&mut file, directory.offset, directory_end.min(tail_start))
+            .await?;
+    }
+    let central = read_back(&mut file, directory.offset, directory.size)?;
+
+    let entries = parse_central_directory(&central)?;
+    let ranges = plan_ranges(&entries, directory.offset, |name| {
+        entry_repo_path(Path::new(name)).is_some_and(|path| matches_request(request, &path))
+    });
+    debug!(
+        "Fetching {} range(s) of {} for {}",
+        ranges.len(),
+        url,
+        request.path
+    );
+    for (start, end) in ranges {
+        // Parts already read along with the tail need no request
+        let end = end.min(tail_start);
+        if start < end {
+            fetched += fetcher.copy_range(&mut file, start, end).await?;
+        }
+    }
+
+    file.flush()
+        .with_context(|| format!("failed to write {}", dest.display()))?;
+    info!(
+        "Fetched {} of the {} zip archive with range requests",
+        format_bytes(fetched),
+        format_bytes(total)
+    );
+    Ok(true)
+}
+
+fn read_back(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut buffer = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut buffer))
+        .context("failed to read back sparse zip archive")?;
+    Ok(buffer)
+}
+
+/// Total size from a `Content-Range: bytes start-end/total` header
+fn parse_content_range_total(value: &str) -> Option<u64> {
+    value
+        .strip_prefix("bytes ")?
+        .split_once('/')?
+        .1
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Find the end of central directory record in the last bytes of the archive, which
+/// start at `tail_start`
+fn find_eocd(tail: &[u8], tail_start: u64) -> Result<Eocd> {
+    let position = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&pos| {
+            u32_at(tail, pos) == Some(EOCD_SIGNATURE)
+                && u16_at(tail, pos + 20)
+                    .is_some_and(|comment| pos + 22 + comment as usize == tail.len())
+        })
+        .ok_or_else(|| anyhow!("zip end of central directory record not found"))?;
+
+    let entries = u16_at(tail, position + 10).unwrap_or_default();
+    let size = u32_at(tail, position + 12).unwrap_or_default();
+    let offset = u32_at(tail, position + 16).unwrap_or_default();
+    if entries == u16::MAX || size == u32::MAX || offset == u32::MAX {
+        let locator = position
+            .checked_sub(20)
+            .filter(|&start| u32_at(tail, start) == Some(ZIP64_LOCATOR_SIGNATURE))
+            .ok_or_else(|| anyhow!("zip64 end of central directory locator not found"))?;
+        let record_offset = u64_at(tail, locator + 8).unwrap_or_default();
+        return Ok(Eocd::Zip64 { record_offset });
+    }
+
+    // Archives may be prefixed; the record itself tells where the directory ends
+    let directory_end = tail_start + position as u64;
+    let offset = offset as u64;
+    if offset + size as u64 > directory_end {
+        return Err(anyhow!("zip central directory overlaps its end record").into());
+    }
+    Ok(Eocd::Classic(CentralDirectory {
+        offset,
+        size: size as u64,
+    }))
+}
+
+fn parse_zip64_eocd(record: &[u8]) -> Result<CentralDirectory> {
+    if u32_at(record, 0) != Some(ZIP64_EOCD_SIGNATURE) {
+        return Err(anyhow!("invalid zip64 end of central directory record").into());
+    }
+    match (u64_at(record, 40), u64_at(record, 48)) {
+        (Some(size), Some(offset)) => Ok(CentralDirectory { offset, size }),
+        _ => Err(anyhow!("truncated zip64 end of central directory record").into()),
+    }
+}
+
+/// Name and local header offset of every entry in the central directory
+fn parse_central_directory(data: &[u8]) -> Result<Vec<(String, u64)>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        if u32_at(data, pos) != Some(CENTRAL_HEADER_SIGNATURE) {
+            return Err(anyhow!("invalid zip central directory entry at {}", pos).into());
+        }
+        let (
+            Some(compressed),
+            Some(uncompressed),
+            Some(name_len),
+            Some(extra_len),
+            Some(comment_len),
+            Some(offset),
+        ) = (
+            u32_at(data, pos + 20),
+            u32_at(data, pos + 24),
+            u16_at(data, pos + 28),
+            u16_at(data, pos + 30),
+            u16_at(data, pos + 32),
+            u32_at(data, pos + 42),
+        )
+        else {
+            return Err(anyhow!("truncated zip central directory").into());
+        };
+        let name_start = pos + 46;
+        let extra_start = name_start + name_len as usize;
+        let next = extra_start + extra_len as usize + comment_len as usize;
+        let (Some(name), Some(extra)) = (
+            data.get(name_start..extra_start),
+            data.get(extra_start..extra_start + extra_len as usize),
+        ) else {
+            return Err(anyhow!("truncated zip central directory").into());
+        };
+
+        let mut offset = offset as u64;
+        if offset == u32::MAX as u64 {
+            // ZIP64 extra field: the 64-bit values present are those whose 32-bit
+            // fields are saturated, in this order
+            let skip = [uncompressed, compressed]
+                .iter()
+                .filter(|&&value| value == u32::MAX)
+                .count()
+                * 8;
+            offset = zip64_extra(extra)
+                .and_then(|field| u64_at(field, skip))
+                .ok_or_else(|| anyhow!("missing zip64 offset in central directory"))?;
+        }
+
+        entries.push((String::from_utf8_lossy(name).into_owned(), offset));
+        pos = next;
+    }
+    Ok(entries)
+}
+
+/// Data of the ZIP64 extended information extra field, if present
+fn zip64_extra(mut extra: &[u8]) -> Option<&[u8]> {
+    while let (Some(id), Some(len)) = (u16_at(extra, 0), u16_at(extra, 2)) {
+        let field = extra.get(4..4 + len as usize)?;
+        if id == 0x0001 {
+            return Some(field);
+        }
+        extra = &extra[4 + len as usize..];
+    }
+    None
+}
+
+/// Merge the byte ranges of the entries selected by `wanted` into few requests. Each
+/// entry runs from its local header to the next entry, or to the central directory.
+fn plan_ranges(
+    entries: &[(String, u64)],
+    directory_offset: u64,
+    wanted: impl Fn(&str) -> bool,
+) -> Vec<(u64, u64)> {
+    let mut by_offset: Vec<_> = entries.iter().collect();
+    by_offset.sort_by_key(|(_, offset)| *offset);
+
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for (index, (name, start)) in by_offset.iter().enumerate() {
+        if !wanted(name) {
+            continue;
+        }
+        let end = by_offset
+            .get(index + 1)
+            .map(|(_, next)| *next)
+            .unwrap_or(directory_offset);
+        match ranges.last_mut() {
+            Some(last) if *start <= last.1 + MERGE_GAP => last.1 = last.1.max(end),
+            _ => ranges.push((*start, end)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::RequestKind;
+    use std::io::Read;
+    use zip::write::SimpleFileOptions;
+
+    fn archive(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(
+                    *name,
+                    SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
+                )
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_sparse_zip_reads_planned_ranges() {
+        // Entries are stored, so the unwanted one spans more than the tail and MERGE_GAP
+        let padding = vec![7u8; 200_000];
+        let data = archive(&[
+            ("repo-main/README.md", b"readme".to_vec()),
+            ("repo-main/assets/big.bin", padding),
+            ("repo-main/docs/a.md", b"# A".to_vec()),
+            ("repo-main/docs/b.md", b"# B".to_vec()),
+        ]);
+        let total = data.len() as u64;
+        let tail_start = total.saturating_sub(TAIL_SIZE);
+
+        let Eocd::Classic(directory) = find_eocd(&data[tail_start as usize..], tail_start).unwrap()
+        else {
+            panic!("expected a classic end record");
+        };
+        let central =
+            &data[directory.offset as usize..(directory.offset + directory.size) as usize];
+        let entries = parse_central_directory(central).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0], ("repo-main/README.md".to_string(), 0));
+
+        let request = RequestInfo {
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            branch: "main".to_string(),
+            path: "docs".to_string(),
+            has_trailing_slash: false,
+            kind: RequestKind::Tree,
+        };
+        let ranges = plan_ranges(&entries, directory.offset, |name| {
+            entry_repo_path(Path::new(name)).is_some_and(|path| matches_request(&request, &path))
+        });
+        // Both docs entries are adjacent, so one request covers them
+        assert_eq!(ranges, vec![(entries[2].1, directory.offset)]);
+
+        // Only the tail and the planned ranges are copied; the rest stays zeroed
+        let mut sparse = vec![0u8; data.len()];
+        let copied = ranges
+            .iter()
+            .copied()
+            .chain([(directory.offset.min(tail_start), total)]);
+        for (start, end) in copied {
+            sparse[start as usize..end as usize]
+                .copy_from_slice(&data[start as usize..end as usize]);
+        }
+
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(sparse)).unwrap();
+        let mut contents = String::new();
+        zip.by_name("repo-main/docs/b.md")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "# B");
+        // Entries outside the ranges cannot be read back
+        let unfetched = zip
+            .by_name("repo-main/assets/big.bin")
+            .map(|mut entry| entry.read_to_end(&mut Vec::new()).is_err());
+        assert!(unfetched.unwrap_or(true));
+    }
+
+    #[test]
+    fn test_range_headers_and_records() {
+        assert_eq!(parse_content_range_total("bytes 100-199/5000"), Some(5000));
+        assert_eq!(parse_content_range_total("bytes 0-9/*"), None);
+        assert_eq!(parse_content_range_total("items 0-9/10"), None);
+
+        assert!(find_eocd(b"not a zip archive at all", 0).is_err());
+
+        let mut record = vec![0u8; 56];
+        record[..4].copy_from_slice(&ZIP64_EOCD_SIGNATURE.to_le_bytes());
+        record[40..48].copy_from_slice(&300u64.to_le_bytes());
+        record[48..56].copy_from_slice(&(5u64 << 32).to_le_bytes());
+        assert_eq!(
+            parse_zip64_eocd(&record).unwrap(),
+            CentralDirectory {
+                offset: 5 << 32,
+                size: 300
+            }
+        );
+        assert!(parse_zip64_eocd(&[0; 56]).is_err());
+
+        let mut extra = vec![0x99, 0x99, 2, 0, 7, 7];
+        extra.extend_from_slice(&[0x01, 0x00, 8, 0]);
+        extra.extend_from_slice(&42u64.to_le_bytes());
+        assert_eq!(
+            zip64_extra(&extra).and_then(|field| u64_at(field, 0)),
+            Some(42)
+        );
+    }
+}
@@ -0,0 +1,720 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+use console::style;
+use flate2::read::GzDecoder;
+use log::{debug, info, warn};
+use tokio::sync::mpsc;
+
+use super::archive::{ZipDownloadParams, archive_progress_bar};
+use crate::cli::SymlinkPolicy;
+use crate::download::mtime::apply_commit_times;
+use crate::download::sync::{local_blob_sha, mirror_output, skip_unchanged};
+use crate::download::verify::verify_against_tree;
+use crate::error::{Context, GdlError, Result};
+use crate::filter::select_matching;
+use crate::github::archive_url;
+use crate::github::types::{ContentType, GitHubContent};
+use crate::http::{
+    StreamLimits, StreamWatch, TransientStatus, describe_delay, is_transient, next_chunk,
+};
+use crate::lfs::fetch_lfs_objects;
+use crate::paths::{
+    compute_base_and_default_output, disambiguate_targets, ensure_directory, format_path_for_log,
+    output_root,
+};
+use crate::progress::{DownloadProgress, format_bytes, status};
+use crate::shard::{report_empty_shard, select_shard};
+use crate::types::{EmptyDownload, FileCopyTask, RequestInfo, RequestKind};
+use crate::utils::{
+    create_symlink, remove_symlinks_below, resolve_created_link, resolve_link_target,
+};
+
+/// Response chunks buffered between the download and the extractor
+const STREAM_BUFFER_CHUNKS: usize = 32;
+
+/// One archive entry written to the staging directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StagedEntry {
+    /// Path inside the repository
+    item_path: String,
+    size: u64,
+    /// Target of a symlink entry, which is recorded but not written
+    link_target: Option<String>,
+}
+
+/// Entries of the archive that belong to the request
+#[derive(Debug, Default)]
+struct Staged {
+    entries: Vec<StagedEntry>,
+    /// Whether the requested path exists at all, even without any files
+    path_exists: bool,
+}
+
+/// Download the repository's tar.gz archive and extract the requested entries while
+/// it streams in, so the archive itself never touches the disk (`--stream-archive`)
+pub(super) async fn download_via_tarball(params: ZipDownloadParams) -> Result<()> {
+    let ZipDownloadParams {
+        client,
+        request,
+        url,
+        output,
+        token,
+        rate_limit,
+        force,
+        on_conflict,
+        preserve_mtime,
+        symlinks,
+        shard,
+        filter,
+        lfs,
+        limits,
+        verify,
+        sync,
+        update_only,
+        delete,
+        cache,
+        progress,
+        quiet,
+        sanitize_names,
+        prompt,
+        multi,
+        ..
+    } = params;
+
+    let tarball_url = archive_url(&request, "tar.gz");
+    debug!("Streaming tar.gz archive from {}", tarball_url);
+
+    let treat_as_single_file = request.kind == RequestKind::Blob;
+    let (base_path, default_output_dir) =
+        compute_base_and_default_output(&request, treat_as_single_file, None);
+    let output_dir = output_root(output.unwrap_or(default_output_dir));
+    ensure_directory(&output_dir)?;
+
+    // Entries are staged next to their destination so moving them into place is a
+    // rename, and nothing is overwritten before the overwrite check
+    let staging = tempfile::Builder::new()
+        .prefix(".ghdl-staging-")
+        .tempdir_in(&output_dir)
+        .with_context(|| {
+            format!(
+                "failed to create a staging directory in {}",
+                output_dir.display()
+            )
+        })?;
+
+    status(
+        quiet,
+        format!(
+            "{} {} Streaming tar.gz archive...",
+            style("[1/2]").bold().dim(),
+            style("▼").cyan()
+        ),
+    );
+    let policy = rate_limit.retry;
+    let mut attempt = 1;
+    let staged = loop {
+        let target = StagingTarget {
+            request: &request,
+            dir: staging.path(),
+            preserve_mtime,
+        };
+        let result = stream_tarball(
+            &client,
+            &tarball_url,
+            token.as_deref(),
+            &rate_limit,
+            target,
+            &limits,
+            &multi,
+        )
+        .await;
+
+        match result {
+            Err(err) if is_transient(&err) && attempt <= policy.retries => {
+                let delay = policy.delay(attempt);
+                warn!(
+                    "Archive stream failed ({}), retrying in {} ({}/{})...",
+                    err.root_cause(),
+                    describe_delay(delay),
+                    attempt,
+                    policy.retries
+                );
+                fs::remove_dir_all(staging.path())
+                    .and_then(|_| fs::create_dir(staging.path()))
+                    .with_context(|| {
+                        format!(
+                            "failed to reset staging directory {}",
+                            staging.path().display()
+                        )
+                    })?;
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => break result?,
+        }
+    };
+
+    status(
+        quiet,
+        format!(
+            "{} {} Moving files into place...",
+            style("[2/2]").bold().dim(),
+            style("»").cyan()
+        ),
+    );
+
+    let mut tasks = Vec::new();
+    for entry in staged.entries {
+        let name = Path::new(&entry.item_path)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or(&entry.item_path)
+            .to_string();
+        let content = GitHubContent {
+            name,
+            path: entry.item_path.clone(),
+            url: String::new(),
+            size: Some(entry.size),
+            download_url: None,
+            content_type: ContentType::File,
+            sha: None,
+        };
+        let target_path = output_dir.join(crate::paths::relative_path(
+            &base_path,
+            &content,
+            sanitize_names,
+        )?);
+        let source_path = staging.path().join(&entry.item_path);
+
+        let Some(link_target) = entry.link_target else {
+            tasks.push(FileCopyTask {
+                item_path: entry.item_path,
+                source_path,
+                target_path,
+                size: Some(entry.size),
+                link_target: None,
+            });
+            continue;
+        };
+
+        match symlinks {
+            SymlinkPolicy::Skip => {
+                warn!("Skipping symlink {} in tar.gz archive.", entry.item_path);
+            }
+            SymlinkPolicy::Create
+                if resolve_created_link(&entry.item_path, &link_target, &base_path).is_none() =>
+            {
+                warn!(
+                    "Skipping symlink {} -> {}: target is outside the output directory.",
+                    entry.item_path, link_target
+                );
+            }
+            SymlinkPolicy::Create => tasks.push(FileCopyTask {
+                item_path: entry.item_path,
+                source_path,
+                target_path,
+                size: Some(0),
+                link_target: Some(PathBuf::from(link_target)),
+            }),
+            SymlinkPolicy::Follow => {
+                // Only entries that were staged can be followed
+                let resolved = resolve_link_target(&entry.item_path, &link_target)
+                    .map(|resolved| staging.path().join(resolved))
+                    .filter(|resolved| resolved.is_file());
+                let Some(resolved) = resolved else {
+                    warn!(
+                        "Skipping symlink {} -> {}: target is not a downloaded file.",
+                        entry.item_path, link_target
+                    );
+                    continue;
+                };
+                if let Some(parent) = source_path.parent() {
+                    fs::create_dir_all(parent).with_context(|| {
+                        format!("failed to create directory {}", parent.display())
+                    })?;
+                }
+                let size = fs::copy(&resolved, &source_path).with_context(|| {
+                    format!("failed to copy {} to follow a symlink", resolved.display())
+                })?;
+                tasks.push(FileCopyTask {
+                    item_path: entry.item_path,
+                    source_path,
+                    target_path,
+                    size: Some(size),
+                    link_target: None,
+                });
+            }
+        }
+    }
+
+    if tasks.is_empty() && staged.path_exists && request.kind == RequestKind::Tree {
+        return Err(EmptyDownload::for_request(&request).into());
+    }
+    if tasks.is_empty() {
+        return Err(anyhow!(
+            "No files matched the requested path {} in tar.gz archive",
+            if request.path.is_empty() {
+                "/"
+            } else {
+                &request.path
+            }
+        )
+        .into());
+    }
+
+    let tasks = disambiguate_targets(tasks, |task| &task.item_path);
+    let tasks = select_matching(tasks, filter.as_ref(), |task| &task.item_path);
+    let tasks = select_shard(tasks, shard, |task| &task.item_path);
+    if tasks.is_empty() {
+        report_empty_shard(shard, quiet);
+        return Ok(());
+    }
+
+    let keep: Option<HashSet<PathBuf>> = (delete && !treat_as_single_file)
+        .then(|| tasks.iter().map(|task| task.target_path.clone()).collect());
+    let tasks = if sync || update_only {
+        skip_unchanged(tasks, |task| {
+            task.link_target
+                .is_none()
+                .then(|| local_blob_sha(&task.source_path))
+                .flatten()
+        })
+    } else {
+        tasks
+    };
+    if tasks.is_empty() {
+        return mirror_output(&output_dir, keep.as_ref());
+    }
+
+    // --sync replaces changed files
+    let tasks =
+        crate::overwrite::resolve_conflicts(tasks, on_conflict, force || sync, &prompt, |task| {
+            task.link_target
+                .is_none()
+                .then(|| std::fs::read(&task.source_path).ok())
+                .flatten()
+        })?;
+    if tasks.is_empty() {
+        return mirror_output(&output_dir, keep.as_ref());
+    }
+
+    let total_bytes: u64 = tasks.iter().filter_map(|task| task.size).sum();
+    let mut progress = DownloadProgress::new(tasks.len(), total_bytes, progress);
+    let target_display = if tasks.len() == 1 && treat_as_single_file {
+        format_path_for_log(&tasks[0].target_path)
+    } else {
+        format_path_for_log(&output_dir)
+    };
+    info!(
+        "Downloading from {}/{}:{}:{} into {} (tar.gz stream)",
+        request.owner,
+        request.repo,
+        request.branch,
+        if request.path.is_empty() {
+            "/"
+        } else {
+            &request.path
+        },
+        target_display
+    );
+
+    let mut extracted = Vec::with_capacity(tasks.len());
+    for task in &tasks {
+        // A link left by an earlier --symlinks create run must not redirect the write
+        remove_symlinks_below(&output_dir, &task.target_path)?;
+        if let Some(parent) = task.target_path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create output directory {}", parent.display())
+            })?;
+        }
+        progress.log_start(&task.item_path, &task.target_path, task.size);
+        if let Some(link_target) = &task.link_target {
+            create_symlink(link_target, &task.target_path)?;
+        } else {
+            fs::rename(&task.source_path, &task.target_path).with_context(|| {
+                format!(
+                    "failed to move {} to {}",
+                    task.source_path.display(),
+                    task.target_path.display()
+                )
+            })?;
+            extracted.push((task.item_path.clone(), task.target_path.clone()));
+        }
+        progress.record_download(&task.item_path, &task.target_path, task.size);
+    }
+    progress.finish();
+
+    info!(
+        "Finished downloading {} file(s) ({} total) from {} using a tar.gz stream.",
+        progress.downloaded_files,
+        format_bytes(progress.downloaded_bytes),
+        url
+    );
+
+    if verify {
+        verify_against_tree(
+            &client,
+            &request,
+            token.as_deref(),
+            rate_limit.clone(),
+            cache,
+            &extracted,
+        )
+        .await?;
+    }
+    if lfs {
+        fetch_lfs_objects(&client, &request, token.as_deref(), &extracted, &multi).await?;
+    }
+    if preserve_mtime {
+        apply_commit_times(
+            &client,
+            &request,
+            token.as_deref(),
+            &rate_limit,
+            cache,
+            &extracted,
+        )
+        .await;
+    }
+    mirror_output(&output_dir, keep.as_ref())
+}
+
+/// Where and how matching archive entries are staged
+#[derive(Debug, Clone, Copy)]
+struct StagingTarget<'a> {
+    request: &'a RequestInfo,
+    dir: &'a Path,
+    preserve_mtime: bool,
+}
+
+/// Download the archive once, feeding it to the extractor as it arrives
+async fn stream_tarball(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+    rate_limit: &crate::rate_limit::RateLimitTracker,
+    target: StagingTarget<'_>,
+    limits: &StreamLimits,
+    multi: &indicatif::MultiProgress,
+) -> Result<Staged> {
+    let mut req = client.get(url);
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("token {}", token));
+    }
+    let response = req
+        .send()
+        .await
+        .with_context(|| format!("failed to send request to {}", url))?;
+    rate_limit.record_headers(response.headers()).await;
+
+    if !response.status().is_success() {
+        let message = format!("failed to download tar.gz: HTTP {}", response.status());
+        return Err(match TransientStatus::check(response.status()) {
+            Some(transient) => anyhow::Error::new(transient).context(message).into(),
+            None => GdlError::from_status(response.status(), message.clone())
+                .unwrap_or_else(|| anyhow!(message).into()),
+        });
+    }
+
+    let pb = archive_progress_bar(multi, response.content_length(), "tar.gz archive");
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+
+    let request = target.request.clone();
+    let staging = target.dir.to_path_buf();
+    let preserve_mtime = target.preserve_mtime;
+    let extractor = tokio::task::spawn_blocking(move || {
+        let reader = GzDecoder::new(ChannelReader::new(receiver));
+        extract_entries(reader, &request, &staging, preserve_mtime)
+    });
+
+    let mut stream = response.bytes_stream();
+    let mut watch = StreamWatch::new(limits);
+    let mut downloaded: u64 = 0;
+    let streamed: Result<()> = async {
+        while let Some(chunk) = next_chunk(&mut stream, &mut watch)
+            .await
+            .with_context(|| format!("failed to download chunk from {}", url))?
+        {
+            downloaded += chunk.len() as u64;
+            pb.set_position(downloaded);
+            // The extractor hangs up once the tar archive ends, or when it failed; its
+            // error is reported below
+            if sender.send(chunk).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    drop(sender);
+    pb.finish_and_clear();
+
+    let extracted = extractor
+        .await
+        .map_err(|err| anyhow!("archive extraction panicked: {}", err))?;
+    // A failed download truncates the archive, so its error explains the extractor's
+    streamed?;
+    let staged = extracted?;
+    info!("Streamed tar.gz archive: {}", format_bytes(downloaded));
+    Ok(staged)
+}
+
+/// Blocking reader over the chunks of a response body
+struct ChannelReader<T> {
+    receiver: mpsc::Receiver<T>,
+    current: Option<T>,
+    offset: usize,
+}
+
+impl<T> ChannelReader<T> {
+    fn new(receiver: mpsc::Receiver<T>) -> Self {
+        Self {
+            receiver,
+            current: None,
+            offset: 0,
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for ChannelReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(chunk) = &self.current {
+                let remaining = &chunk.as_ref()[self.offset..];
+                if !remaining.is_empty() {
+                    let count = remaining.len().min(buf.len());
+                    buf[..count].copy_from_slice(&remaining[..count]);
+                    self.offset += count;
+                    return Ok(count);
+                }
+            }
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.current = Some(chunk);
+                    self.offset = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Repository path of an archive entry: the archive's root directory is stripped and
+/// anything that could escape the staging directory is rejected
+pub(super) fn entry_repo_path(path: &Path) -> Option<String> {
+    let mut components = path.components();
+    components.next()?;
+    let mut parts = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Whether `item_path` is the requested file, or lies in the requested directory
+pub(super) fn matches_request(request: &RequestInfo, item_path: &str) -> bool {
+    if request.path.is_empty() {
+        return true;
+    }
+    if request.kind == RequestKind::Blob {
+        return item_path == request.path;
+    }
+    let prefix = request.path.trim_end_matches('/');
+    item_path == prefix
+        || item_path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Write the regular files of the archive that match the request into `staging`,
+/// and record its symlinks
+fn extract_entries<R: Read>(
+    reader: R,
+    request: &RequestInfo,
+    staging: &Path,
+    preserve_mtime: bool,
+) -> Result<Staged> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_mtime(preserve_mtime);
+
+    let mut staged = Staged::default();
+    for entry in archive.entries().context("failed to read tar.gz archive")? {
+        let mut entry = entry.context("failed to read tar.gz archive entry")?;
+        let path = entry
+            .path()
+            .context("invalid path in tar.gz archive")?
+            .into_owned();
+        let Some(item_path) = entry_repo_path(&path) else {
+            continue;
+        };
+        if !matches_request(request, &item_path) {
+            continue;
+        }
+        staged.path_exists = true;
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() {
+            let link_target = entry
+                .link_name()
+                .context("invalid symlink in tar.gz archive")?
+                .map(|target| target.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            staged.entries.push(StagedEntry {
+                item_path,
+                size: 0,
+                link_target: Some(link_target),
+            });
+            continue;
+        }
+        if !entry_type.is_file() {
+            continue;
+        }
+
+        let target = staging.join(&item_path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {}", parent.display()))?;
+        }
+        let size = entry.size();
+        entry
+            .unpack(&target)
+            .with_context(|| format!("failed to extract {} from tar.gz", item_path))?;
+        debug!("Staged {} from tar.gz archive", item_path);
+        staged.entries.push(StagedEntry {
+            item_path,
+            size,
+            link_target: None,
+        });
+    }
+    Ok(staged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    fn tarball() -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        let mut add = |path: &str, contents: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_700_000_000);
+            header.set_cksum();
+            builder.append_data(&mut header, path, contents).unwrap();
+        };
+        add("repo-main/README.md", b"readme");
+        add("repo-main/docs/guide.md", b"# Guide");
+        add("repo-main/docs2/other.md", b"other");
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        builder
+            .append_link(&mut header, "repo-main/docs/latest.md", "guide.md")
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn request(path: &str, kind: RequestKind) -> RequestInfo {
+        RequestInfo {
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            branch: "main".to_string(),
+            path: path.to_string(),
+            has_trailing_slash: false,
+            kind,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_streamed_entries() {
+        let staging = tempfile::tempdir().unwrap();
+        let (sender, receiver) = mpsc::channel(4);
+        let data = tarball();
+        let docs = request("docs", RequestKind::Tree);
+        let root = staging.path().to_path_buf();
+        let extractor = tokio::task::spawn_blocking(move || {
+            extract_entries(
+                GzDecoder::new(ChannelReader::new(receiver)),
+                &docs,
+                &root,
+                true,
+            )
+        });
+        // Split the archive into arbitrary chunks, as the network would
+        for chunk in data.chunks(7) {
+            // Past the tar end marker only gzip's trailer remains, which is not read
+            if sender.send(chunk.to_vec()).await.is_err() {
+                break;
+            }
+        }
+        drop(sender);
+
+        let staged = extractor.await.unwrap().unwrap();
+        assert!(staged.path_exists);
+        assert_eq!(
+            staged.entries,
+            vec![
+                StagedEntry {
+                    item_path: "docs/guide.md".to_string(),
+                    size: 7,
+                    link_target: None,
+                },
+                StagedEntry {
+                    item_path: "docs/latest.md".to_string(),
+                    size: 0,
+                    link_target: Some("guide.md".to_string()),
+                },
+            ]
+        );
+        let guide = staging.path().join("docs/guide.md");
+        assert_eq!(fs::read(&guide).unwrap(), b"# Guide");
+        assert!(!staging.path().join("docs2").exists());
+        assert!(!staging.path().join("README.md").exists());
+    }
+
+    #[test]
+    fn test_entry_filtering() {
+        assert_eq!(
+            entry_repo_path(Path::new("repo-main/docs/a.md")).as_deref(),
+            Some("docs/a.md")
+        );
+        assert_eq!(entry_repo_path(Path::new("repo-main/")), None);
+        assert_eq!(entry_repo_path(Path::new("repo-main/../escape")), None);
+
+        let whole = request("", RequestKind::Tree);
+        let docs = request("docs", RequestKind::Tree);
+        let readme = request("README.md", RequestKind::Blob);
+        assert!(matches_request(&whole, "src/main.rs"));
+        assert!(matches_request(&docs, "docs/a.md"));
+        assert!(!matches_request(&docs, "docs2/a.md"));
+        assert!(matches_request(&readme, "README.md"));
+        assert!(!matches_request(&readme, "README.md.bak"));
+
+        let staging = tempfile::tempdir().unwrap();
+        let staged = extract_entries(
+            GzDecoder::new(&tarball()[..]),
+            &readme,
+            staging.path(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(staged.entries.len(), 1);
+        assert_eq!(
+            fs::read(staging.path().join("README.md")).unwrap(),
+            b"readme"
+        );
+    }
+}
@@ -48,6 +48,184 @@ fn rejects_invalid_url() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn reads_urls_from_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = assert_cmd::Command::cargo_bin("ghdl")?;
+    cmd.args(["--offline", "--input-file", "-"])
+        .write_stdin("# vendored docs\n\nhttps://github.com/owner/repo/issues/1\n");
+    cmd.assert()
+        .failure()
+        .code(predicate::eq(2))
+        .stderr(contains("URL must be either"));
+    Ok(())
+}
+
+#[test]
+fn unsupported_url_exits_with_invalid_url_status() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--offline", "https://github.com/owner/repo/issues/1"]);
+    cmd.assert()
+        .failure()
+        .code(predicate::eq(2))
+        .stderr(contains("URL must be either"));
+    Ok(())
+}
+
+#[test]
+fn exhausted_api_budget_exits_with_rate_limit_status() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path())
+        .args(["--strategy", "api", "--max-api-calls", "0", "-o"])
+        .arg(dir.path().join("out"))
+        .arg("https://github.com/owner/repo/tree/main/docs");
+    cmd.assert()
+        .failure()
+        .code(predicate::eq(5))
+        .stderr(contains("--max-api-calls"));
+    Ok(())
+}
+
+#[test]
+fn sends_api_requests_to_api_base() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader, Write};
+
+    // A mock API answering every request with a 404
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let (requests, received) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request_line = String::new();
+            let _ = BufReader::new(&stream).read_line(&mut request_line);
+            let _ = requests.send(request_line);
+            let body = r#"{"message":"Not Found"}"#;
+            let _ = write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path())
+        .env("GHDL_API_BASE", &base)
+        .args(["--strategy", "api", "--no-cache", "-o"])
+        .arg(dir.path().join("out"))
+        .arg("https://github.com/owner/repo/tree/main/docs");
+    // A repository the API cannot find is an error, not an empty download
+    cmd.assert().code(3);
+    assert!(
+        received
+            .try_iter()
+            .any(|line| line.starts_with("GET /repos/owner/repo/"))
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn skips_created_links_leaving_a_subpath_download() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader, Write};
+
+    // A mock API serving `docs/` with a link inside it and one leaving it via `../`
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let listing = format!(
+        r#"[{{"name":"a.md","path":"docs/a.md","url":"","size":5,"download_url":"{base}/raw/a.md","type":"file","sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0"}},
+           {{"name":"inside","path":"docs/inside","url":"","size":4,"download_url":null,"type":"symlink","sha":"insidesha"}},
+           {{"name":"x","path":"docs/x","url":"","size":6,"download_url":null,"type":"symlink","sha":"xsha"}}]"#
+    );
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request_line = String::new();
+            let _ = BufReader::new(&stream).read_line(&mut request_line);
+            let path = request_line.split(' ').nth(1).unwrap_or_default();
+            let path = path.split('?').next().unwrap_or_default();
+            let (status, body) = match path {
+                "/repos/owner/repo/commits/main" => {
+                    ("200 OK", "0123456789abcdef0123456789abcdef01234567")
+                }
+                "/repos/owner/repo/contents/docs" => ("200 OK", listing.as_str()),
+                "/repos/owner/repo/git/trees/main:docs" => (
+                    "200 OK",
+                    r#"{"tree":[{"path":"a.md","mode":"100644","type":"blob","sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","size":5}],"truncated":false}"#,
+                ),
+                "/repos/owner/repo/git/blobs/insidesha" => ("200 OK", "a.md"),
+                "/repos/owner/repo/git/blobs/xsha" => ("200 OK", "../src"),
+                "/raw/a.md" => ("200 OK", "hello"),
+                _ => ("404 Not Found", r#"{"message":"Not Found"}"#),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+
+    let dir = tempfile::tempdir()?;
+    let out = dir.path().join("out");
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path())
+        .env("GHDL_API_BASE", &base)
+        .args([
+            "--strategy",
+            "api",
+            "--no-cache",
+            "--symlinks",
+            "create",
+            "-o",
+        ])
+        .arg(&out)
+        .arg("https://github.com/owner/repo/tree/main/docs");
+    cmd.assert()
+        .success()
+        .stderr(contains("target is outside the output directory"));
+    assert_eq!(
+        std::fs::read_link(out.join("inside"))?,
+        std::path::Path::new("a.md")
+    );
+    assert!(std::fs::symlink_metadata(out.join("x")).is_err());
+    Ok(())
+}
+
+#[test]
+fn rejects_non_numeric_git_depth() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args([
+        "--git-depth",
+        "all",
+        "--git-filter",
+        "tree:0",
+        "https://github.com/owner/repo",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid value 'all' for '--git-depth <N>'"));
+    Ok(())
+}
+
+#[test]
+fn rejects_keep_git_with_zip_strategy() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args([
+        "--keep-git",
+        "--strategy",
+        "zip",
+        "https://github.com/owner/repo",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("--keep-git clones the repository with git"));
+    Ok(())
+}
+
 #[test]
 fn accepts_clear_cache_without_url() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("ghdl")?;
@@ -198,3 +376,900 @@ fn accepts_token_flag() -> Result<(), Box<dyn std::error::Error>> {
         .stderr(predicate::str::contains("token").not());
     Ok(())
 }
+
+#[test]
+fn accepts_preserve_mtime_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--preserve-mtime");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("preserve-mtime").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_fail_on_empty_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--fail-on-empty");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("fail-on-empty").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_symlinks_flag() -> Result<(), Box<dyn std::error::Error>> {
+    for policy in ["follow", "create", "skip"] {
+        let mut cmd = Command::cargo_bin("ghdl")?;
+        cmd.arg("--symlinks").arg(policy);
+        cmd.arg("https://github.com/invalid/test");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("symlinks").not());
+    }
+    Ok(())
+}
+
+#[test]
+fn accepts_lfs_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--lfs");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unexpected argument").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_limit_rate_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--limit-rate").arg("500K");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("limit-rate").not());
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--limit-rate").arg("fast");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--limit-rate"));
+    Ok(())
+}
+
+#[test]
+fn ephemeral_leaves_cache_untouched() -> Result<(), Box<dyn std::error::Error>> {
+    let cache = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", cache.path());
+    cmd.arg("--ephemeral");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unexpected argument").not());
+    assert_eq!(std::fs::read_dir(cache.path())?.count(), 0);
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--ephemeral", "--offline"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn accepts_retry_flags() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args([
+        "--retries",
+        "0",
+        "--retry-delay",
+        "10",
+        "--retry-jitter",
+        "0",
+    ]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("retry").not());
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--retry-jitter", "2"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("between 0 and 1"));
+    Ok(())
+}
+
+#[test]
+fn accepts_timeout_flags() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--connect-timeout", "5", "--timeout", "600"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("timeout").not());
+    Ok(())
+}
+
+#[test]
+fn validates_proxy_url() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--proxy", "socks5://127.0.0.1:9"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid --proxy").not());
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--proxy", "not a url"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid --proxy URL"));
+    Ok(())
+}
+
+#[test]
+fn validates_ca_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let bundle = dir.path().join("ca.pem");
+    std::fs::write(&bundle, "not a certificate\n")?;
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--cacert").arg(&bundle);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("no certificates found"));
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--insecure");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("verification is disabled"));
+    Ok(())
+}
+
+#[test]
+fn accepts_speed_limit_flags() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--speed-limit", "10K", "--speed-time", "15"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("speed").not());
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--speed-time", "15"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--speed-limit"));
+    Ok(())
+}
+
+#[test]
+fn accepts_stream_archive_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--strategy", "zip", "--stream-archive", "--retries", "0"]);
+    cmd.arg("https://github.com/invalid/test/tree/main");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unexpected argument").not());
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--stream-archive", "--offline"]);
+    cmd.arg("https://github.com/invalid/test/tree/main");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn accepts_chunk_flags() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--chunks", "8", "--chunk-threshold", "16MiB"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("chunk").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_stall_timeout_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--stall-timeout").arg("5");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("stall-timeout").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_cache_ttl_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--cache-ttl").arg("0");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cache-ttl").not());
+    Ok(())
+}
+
+#[test]
+fn writes_log_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("ghdl.log");
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--offline")
+        .arg("--log-file")
+        .arg(&log)
+        .arg("https://github.com/owner/repo/issues/1");
+    cmd.assert().failure().code(predicate::eq(2));
+    assert!(std::fs::read_to_string(&log)?.contains("GitHub API request(s) sent"));
+    Ok(())
+}
+
+#[test]
+fn rejects_invalid_config_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let config = dir.path().join("config.toml");
+    std::fs::write(&config, "cache_ttl = \"soon\"\n")?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("GHDL_CONFIG", &config);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid config file"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn reports_failing_token_cmd() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let config = dir.path().join("config.toml");
+    std::fs::write(&config, "[providers.github]\ntoken_cmd = \"exit 1\"\n")?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("GHDL_CONFIG", &config)
+        .env_remove("GITHUB_TOKEN")
+        .env_remove("GH_TOKEN");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("token_cmd `exit 1` exited"));
+    Ok(())
+}
+
+#[test]
+fn accepts_gh_auth_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("GH_CONFIG_DIR", dir.path())
+        .env("PATH", dir.path())
+        .env_remove("GITHUB_TOKEN")
+        .env_remove("GH_TOKEN");
+    cmd.args(["--gh-auth", "--retries", "0"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("gh-auth").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_cache_max_size_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--cache-max-size").arg("2GiB");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cache-max-size").not());
+    Ok(())
+}
+
+#[test]
+fn rejects_invalid_cache_max_size() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--cache-max-size").arg("2 parsecs");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown size unit"));
+    Ok(())
+}
+
+#[test]
+fn offline_fails_without_cached_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path());
+    cmd.args(["--offline", "--strategy", "zip"]);
+    cmd.arg("https://github.com/owner/repo/tree/main/docs");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("offline: no cached zip archive"));
+    Ok(())
+}
+
+#[test]
+fn accepts_explain_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--explain");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("explain").not());
+    Ok(())
+}
+
+#[test]
+fn explain_conflicts_with_json_progress_on_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--explain", "--progress", "json"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(contains("--progress-file"));
+
+    // Progress events written elsewhere cannot interleave with the decisions
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--explain", "--progress", "json", "--progress-file"])
+        .arg(dir.path().join("progress.jsonl"))
+        .arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(contains("--progress-file").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_tree_flag() -> Result<(), Box<dyn std::error::Error>> {
+    for arg in ["--tree", "--tree=3"] {
+        let mut cmd = Command::cargo_bin("ghdl")?;
+        cmd.arg(arg);
+        cmd.arg("https://github.com/invalid/test");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("tree").not());
+    }
+    Ok(())
+}
+
+#[test]
+fn accepts_listing_parallel_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--listing-parallel").arg("16");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("listing-parallel").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_git_transport_flag() -> Result<(), Box<dyn std::error::Error>> {
+    for transport in ["https", "ssh"] {
+        let mut cmd = Command::cargo_bin("ghdl")?;
+        cmd.arg("--git-transport").arg(transport);
+        cmd.arg("https://github.com/invalid/test");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("git-transport").not());
+    }
+    Ok(())
+}
+
+#[test]
+fn accepts_shard_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--shard").arg("2/4");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--shard").not());
+    Ok(())
+}
+
+#[test]
+fn rejects_invalid_shard() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--shard").arg("5/4");
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "shard index must be between 1 and 4",
+    ));
+    Ok(())
+}
+
+#[test]
+fn local_repo_requires_git_repository() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--local-repo").arg(dir.path());
+    cmd.arg("https://github.com/owner/repo/tree/main/docs");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a git repository"));
+    Ok(())
+}
+
+#[test]
+fn accepts_verify_tag_flag() -> Result<(), Box<dyn std::error::Error>> {
+    for mode in ["warn", "require"] {
+        let mut cmd = Command::cargo_bin("ghdl")?;
+        cmd.arg("--verify-tag").arg(mode);
+        cmd.arg("https://github.com/invalid/test/tree/v1.0");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("verify-tag").not());
+    }
+    Ok(())
+}
+
+#[test]
+fn accepts_sync_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--sync").arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("sync").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_jobs_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--offline", "--jobs", "3"])
+        .arg("https://github.com/owner/repo/issues/1")
+        .arg("https://github.com/owner/repo/issues/2");
+    cmd.assert()
+        .failure()
+        .code(predicate::eq(2))
+        .stderr(contains("URL must be either"));
+    Ok(())
+}
+
+#[test]
+fn rejects_unknown_on_conflict_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--on-conflict", "merge"])
+        .arg("https://github.com/owner/repo/tree/main/docs");
+    cmd.assert()
+        .failure()
+        .stderr(contains("possible values: skip, overwrite, backup, rename"));
+    Ok(())
+}
+
+#[test]
+fn accepts_update_only_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--offline", "--update-only"])
+        .arg("https://github.com/owner/repo/issues/1");
+    cmd.assert()
+        .failure()
+        .code(predicate::eq(2))
+        .stderr(contains("URL must be either"));
+    Ok(())
+}
+
+#[test]
+fn accepts_notify_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    // An empty PATH leaves no notifier to run, which must not change the outcome
+    cmd.env("PATH", "")
+        .args(["--offline", "--notify"])
+        .arg("https://github.com/owner/repo/issues/1");
+    cmd.assert()
+        .failure()
+        .code(predicate::eq(2))
+        .stderr(contains("URL must be either"));
+    Ok(())
+}
+
+#[test]
+fn keep_going_summarizes_failed_urls() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--offline", "--keep-going"])
+        .arg("https://github.com/owner/repo/issues/1")
+        .arg("https://github.com/owner/repo/issues/2");
+    cmd.assert()
+        .failure()
+        .code(predicate::eq(2))
+        .stderr(contains("0 of 2 URL(s) downloaded"))
+        .stderr(contains("issues/2: URL must be either"));
+    Ok(())
+}
+
+#[test]
+fn accepts_provenance_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--provenance")
+        .arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("provenance").not());
+    Ok(())
+}
+
+#[test]
+fn delete_requires_sync() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--delete").arg("https://github.com/invalid/test");
+    cmd.assert().failure().stderr(contains("--sync"));
+    Ok(())
+}
+
+#[test]
+fn watch_rejects_unknown_interval_unit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--watch", "5d", "https://github.com/invalid/test"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("unknown interval unit"));
+    Ok(())
+}
+
+#[test]
+fn locked_requires_lockfile() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.current_dir(dir.path())
+        .env("XDG_CACHE_HOME", dir.path())
+        .args(["--locked", "https://github.com/owner/repo/tree/main/docs"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("failed to read lockfile gdl.lock"));
+    Ok(())
+}
+
+#[test]
+fn install_requires_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.current_dir(dir.path())
+        .env("XDG_CACHE_HOME", dir.path())
+        .arg("install");
+    cmd.assert()
+        .failure()
+        .stderr(contains("failed to read manifest gdl.toml"));
+    Ok(())
+}
+
+#[test]
+fn accepts_verify_switches() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--no-verify", "--verify", "--no-verify"]);
+    cmd.arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("verify").not());
+    Ok(())
+}
+
+#[test]
+fn accepts_no_sanitize_names_flag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("--no-sanitize-names")
+        .arg("https://github.com/invalid/test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("sanitize").not());
+    Ok(())
+}
+
+#[test]
+fn audit_reports_lfs_pointers() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(
+        dir.path().join("model.bin"),
+        "version https://git-lfs.github.com/spec/v1\n\
+         oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n\
+         size 12345\n",
+    )?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("audit").arg(dir.path());
+    cmd.assert()
+        .success()
+        .stderr(contains("model.bin").and(contains("--lfs")));
+    Ok(())
+}
+
+#[test]
+fn audit_accepts_complete_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("README.md"), "# hello\n")?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("audit").arg(dir.path());
+    cmd.assert()
+        .success()
+        .stderr(contains("No external references found"));
+    Ok(())
+}
+
+#[test]
+fn cache_ls_lists_sections() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path()).args(["cache", "ls"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("responses").and(contains("repos")));
+    Ok(())
+}
+
+#[test]
+fn cache_clear_accepts_blobs_section() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path())
+        .args(["cache", "clear", "--blobs"]);
+    cmd.assert().success();
+    Ok(())
+}
+
+#[test]
+fn stats_shows_cache_and_telemetry() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path()).arg("stats");
+    cmd.assert().success().stdout(contains("blobs"));
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_STATE_HOME", dir.path())
+        .env("XDG_CONFIG_HOME", dir.path())
+        .args(["stats", "--telemetry"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("nothing recorded yet"));
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_STATE_HOME", dir.path())
+        .env("XDG_CONFIG_HOME", dir.path())
+        .args(["stats", "--telemetry", "--upload"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("no telemetry_endpoint is configured"));
+    Ok(())
+}
+
+#[test]
+fn falls_back_when_cache_dir_is_unwritable() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let blocker = dir.path().join("not-a-dir");
+    std::fs::write(&blocker, b"")?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", blocker.join("cache"))
+        .env("TMPDIR", dir.path())
+        .env("USER", "tester")
+        .arg("stats");
+    cmd.assert()
+        .success()
+        .stdout(contains("ghdl-cache-tester"))
+        .stderr(contains("is not writable"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn refuses_shared_fallback_cache_dir() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let blocker = dir.path().join("not-a-dir");
+    std::fs::write(&blocker, b"")?;
+    let shared = dir.path().join("ghdl-cache-tester");
+    std::fs::create_dir(&shared)?;
+    std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o777))?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", blocker.join("cache"))
+        .env("TMPDIR", dir.path())
+        .env("USER", "tester")
+        .arg("stats");
+    cmd.assert()
+        .stderr(contains("is not a private directory of ours"));
+    Ok(())
+}
+
+#[test]
+fn cache_rm_rejects_invalid_repository() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path())
+        .args(["cache", "rm", "not-a-repo"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("expected a repository as OWNER/REPO"));
+    Ok(())
+}
+
+#[test]
+fn auth_login_requires_a_token() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["auth", "login", "--with-token"])
+        .stdin(std::process::Stdio::null());
+    cmd.assert().failure().stderr(contains("no token given"));
+    Ok(())
+}
+
+#[test]
+fn auth_login_web_requires_client_id() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let config = dir.path().join("config.toml");
+    std::fs::write(&config, "")?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("GHDL_CONFIG", &config)
+        .args(["auth", "login", "--web"]);
+    cmd.assert().failure().stderr(contains("oauth_client_id"));
+    Ok(())
+}
+
+#[test]
+fn auth_check_reports_missing_token() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let config = dir.path().join("config.toml");
+    std::fs::write(&config, "")?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("GHDL_CONFIG", &config)
+        .env("NETRC", dir.path().join("netrc"))
+        .env_remove("GITHUB_TOKEN")
+        .env_remove("GH_TOKEN")
+        .args(["auth", "check"]);
+    cmd.assert().failure().stderr(contains("no token found"));
+    Ok(())
+}
+
+#[test]
+fn reads_flags_from_environment() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("GHDL_OFFLINE", "yes")
+        .args(["--no-cache", "https://github.com/invalid/test"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("GHDL_STRATEGY", "carrier-pigeon")
+        .arg("https://github.com/invalid/test");
+    cmd.assert().failure().stderr(contains("carrier-pigeon"));
+    Ok(())
+}
+
+#[test]
+fn get_subcommand_downloads_like_bare_urls() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["get", "--offline", "https://github.com/invalid/test"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("default branch of invalid/test is unknown"));
+
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("get");
+    cmd.assert().failure().stderr(contains("<URL>"));
+    Ok(())
+}
+
+#[test]
+fn ls_rejects_unknown_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["ls", "--format", "yaml", "https://github.com/invalid/test"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid value 'yaml'"));
+    Ok(())
+}
+
+#[test]
+fn rate_rejects_unknown_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["rate", "--format", "yaml"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid value 'yaml'"));
+    Ok(())
+}
+
+#[test]
+fn info_requires_a_url() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.arg("info");
+    cmd.assert().failure().stderr(contains("<URL>"));
+    Ok(())
+}
+
+#[test]
+fn dry_run_conflicts_with_local_repo() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args([
+        "--dry-run",
+        "--local-repo",
+        ".",
+        "https://github.com/invalid/test",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn quiet_still_reports_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path());
+    cmd.args([
+        "-q",
+        "--no-progress",
+        "--offline",
+        "https://github.com/invalid/test",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("offline"))
+        .stderr(contains("completed successfully").not());
+    Ok(())
+}
+
+#[test]
+fn log_format_json_emits_json_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.env("XDG_CACHE_HOME", dir.path())
+        .env("RUST_LOG", "debug");
+    cmd.args([
+        "--log-format",
+        "json",
+        "--offline",
+        "https://github.com/invalid/test/tree/main/src",
+    ]);
+    let output = cmd.assert().failure().get_output().stderr.clone();
+    let stderr = String::from_utf8(output)?;
+    let records: Vec<_> = stderr
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .collect();
+    assert!(!records.is_empty(), "no JSON log records in {stderr}");
+    for record in records {
+        let value: serde_json::Value = serde_json::from_str(record)?;
+        assert!(value["level"].is_string() && value["message"].is_string());
+    }
+    Ok(())
+}
+
+#[test]
+fn progress_rejects_unknown_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args(["--progress", "xml", "https://github.com/invalid/test"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("invalid value 'xml'"));
+    Ok(())
+}
+
+#[test]
+fn checksums_conflicts_with_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("ghdl")?;
+    cmd.args([
+        "--checksums",
+        "SHA256SUMS",
+        "--dry-run",
+        "https://github.com/invalid/test",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("cannot be used with"));
+    Ok(())
+}